@@ -0,0 +1,135 @@
+//! Annotations placed by the user over the image.
+//!
+//! Annotations are anchored in image pixel coordinates,
+//! so that they follow the image through panning and zooming.
+
+/// A point in image pixel coordinates (x to the right, y downwards)
+pub type Point = (f64, f64);
+
+/// A single annotation on the image
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    /// a text label, starting at the given position
+    Text { position: Point, text: String },
+    /// an arrow pointing at `tip`
+    Arrow { tip: Point, tail: Point },
+    /// a distance measurement between two points
+    Ruler { start: Point, end: Point },
+    /// connected line segments, closed into a polygon if `closed`
+    Polyline { points: Vec<Point>, closed: bool },
+    /// an ellipse around `center` with the given semi-axes,
+    /// the first one at `rotation` radians from the x axis
+    Ellipse {
+        center: Point,
+        radii: (f64, f64),
+        rotation: f64,
+    },
+}
+
+/// The unit in which lengths are displayed
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum LengthUnit {
+    Millimeter,
+    Centimeter,
+}
+
+impl LengthUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            LengthUnit::Millimeter => "mm",
+            LengthUnit::Centimeter => "cm",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "mm" => Some(LengthUnit::Millimeter),
+            "cm" => Some(LengthUnit::Centimeter),
+            _ => None,
+        }
+    }
+}
+
+/// How measurements are displayed
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct MeasurementFormat {
+    pub unit: LengthUnit,
+    /// the number of decimal places
+    pub decimals: usize,
+}
+
+impl Default for MeasurementFormat {
+    fn default() -> Self {
+        MeasurementFormat {
+            unit: LengthUnit::Millimeter,
+            decimals: 1,
+        }
+    }
+}
+
+impl MeasurementFormat {
+    /// Format a length given in millimeters
+    pub fn format_length(&self, mm: f64) -> String {
+        let value = match self.unit {
+            LengthUnit::Millimeter => mm,
+            LengthUnit::Centimeter => mm / 10.,
+        };
+        format!("{:.*} {}", self.decimals, value, self.unit.symbol())
+    }
+
+    /// Format an area given in square millimeters
+    pub fn format_area(&self, mm2: f64) -> String {
+        let value = match self.unit {
+            LengthUnit::Millimeter => mm2,
+            LengthUnit::Centimeter => mm2 / 100.,
+        };
+        format!("{:.*} {}²", self.decimals, value, self.unit.symbol())
+    }
+}
+
+/// The length of a ruler in millimeters,
+/// given the row and column spacing of the image (mm).
+pub fn ruler_length(start: Point, end: Point, (row_spacing, column_spacing): (f64, f64)) -> f64 {
+    ((end.0 - start.0) * column_spacing).hypot((end.1 - start.1) * row_spacing)
+}
+
+/// The two ends of the barbs of an arrow head of the given length,
+/// both starting at the tip.
+pub fn arrow_head(tip: Point, tail: Point, length: f64) -> [Point; 2] {
+    let angle = (tail.1 - tip.1).atan2(tail.0 - tip.0);
+    let spread = std::f64::consts::FRAC_PI_6;
+    [angle - spread, angle + spread].map(|a| (tip.0 + length * a.cos(), tip.1 + length * a.sin()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn format_measurements() {
+        // 3 by 4 pixels of 1 by 0.5 mm
+        let length = ruler_length((0., 0.), (4., 3.), (1., 0.5));
+        assert!((length - 3.605551).abs() < 1e-6);
+
+        let mut format = MeasurementFormat::default();
+        assert_eq!(format.format_length(length), "3.6 mm");
+        format.decimals = 3;
+        assert_eq!(format.format_length(length), "3.606 mm");
+        format.unit = LengthUnit::Centimeter;
+        format.decimals = 2;
+        assert_eq!(format.format_length(length), "0.36 cm");
+        assert_eq!(format.format_area(250.), "2.50 cm²");
+    }
+
+    #[wasm_bindgen_test]
+    fn arrow_head_points_back_to_tail() {
+        // arrow pointing to the left
+        let [a, b] = arrow_head((0., 0.), (10., 0.), 2.);
+        // both barbs go back towards the tail, one on each side
+        assert!(a.0 > 0. && b.0 > 0.);
+        assert!((a.0 - b.0).abs() < 1e-9);
+        assert!((a.1 + b.1).abs() < 1e-9);
+        assert!((a.0.hypot(a.1) - 2.).abs() < 1e-9);
+    }
+}
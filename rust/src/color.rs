@@ -0,0 +1,376 @@
+//! Color management of color images.
+//!
+//! Canvases can hold image data in sRGB or in the wider Display P3 color space.
+//! Color images whose ICC profile describes Display P3
+//! (and whose ColorSpace attribute does not state otherwise)
+//! are kept in that color space where the browser supports it,
+//! instead of having their values taken as sRGB.
+
+use dicom::{dictionary_std::tags, object::InMemDicomObject};
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::ImageData;
+
+/// A color space of image data and canvases
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum DisplayColorSpace {
+    /// the default color space of the web
+    #[default]
+    Srgb,
+    /// the wide gamut color space of most recent displays
+    DisplayP3,
+}
+
+impl DisplayColorSpace {
+    /// The name of the color space in the canvas API
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayColorSpace::Srgb => "srgb",
+            DisplayColorSpace::DisplayP3 => "display-p3",
+        }
+    }
+
+    /// The color space with the given name in the canvas API, if supported
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "srgb" => Some(DisplayColorSpace::Srgb),
+            "display-p3" => Some(DisplayColorSpace::DisplayP3),
+            _ => None,
+        }
+    }
+
+    /// The color space which best matches the description of an ICC profile,
+    /// sRGB unless it is the description of the Display P3 profile.
+    ///
+    /// Other P3 profiles (such as DCI-P3, with its different white point)
+    /// are not Display P3 and are shown as sRGB.
+    pub fn from_profile_description(description: &str) -> Self {
+        if description.trim().eq_ignore_ascii_case("Display P3") {
+            DisplayColorSpace::DisplayP3
+        } else {
+            DisplayColorSpace::Srgb
+        }
+    }
+
+    /// The color space of a defined term of the ColorSpace attribute,
+    /// if known.
+    ///
+    /// None of the defined terms is Display P3,
+    /// and Adobe RGB and ROMM RGB cannot be kept on a canvas,
+    /// so all of them are shown as sRGB.
+    pub fn from_dicom_color_space(term: &str) -> Option<Self> {
+        match term.trim() {
+            "SRGB" | "ADOBERGB" | "ROMMRGB" => Some(DisplayColorSpace::Srgb),
+            _ => None,
+        }
+    }
+
+    /// The options for creating a 2D canvas context in this color space,
+    /// also used as the settings of image data
+    pub fn context_options(self) -> JsValue {
+        let options = js_sys::Object::new();
+        // unsupported options are ignored by the browser
+        let _ = js_sys::Reflect::set(
+            &options,
+            &JsValue::from_str("colorSpace"),
+            &JsValue::from_str(self.name()),
+        );
+        options.into()
+    }
+}
+
+/// A big endian 32-bit number at the given position
+fn be32(bytes: &[u8], at: usize) -> Option<usize> {
+    let bytes = bytes.get(at..at.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+/// Read the description of an ICC profile (its `desc` tag),
+/// either as ASCII text (version 2)
+/// or as the first of its localized UTF-16 texts (version 4).
+pub fn icc_profile_description(profile: &[u8]) -> Option<String> {
+    // the tag table follows the 128-byte header
+    // no more entries than fit in the profile, whatever the declared count
+    let count = be32(profile, 128)?.min(profile.len().saturating_sub(132) / 12);
+    let (offset, size) = (0..count).find_map(|i| {
+        let entry = i.checked_mul(12)?.checked_add(132)?;
+        if profile.get(entry..entry.checked_add(4)?)? == b"desc" {
+            Some((be32(profile, entry + 4)?, be32(profile, entry + 8)?))
+        } else {
+            None
+        }
+    })?;
+    let tag = profile.get(offset..offset.checked_add(size)?)?;
+
+    let text = match tag.get(..4)? {
+        b"desc" => {
+            let len = be32(tag, 8)?;
+            String::from_utf8_lossy(tag.get(12..len.checked_add(12)?)?).into_owned()
+        }
+        b"mluc" => {
+            // the first record: language, country, length, and offset
+            let (len, offset) = (be32(tag, 20)?, be32(tag, 24)?);
+            let text: Vec<u16> = tag
+                .get(offset..offset.checked_add(len)?)?
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&text)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// The color space in which the color pixel data of the object
+/// should be shown, according to its ColorSpace attribute
+/// or else the description of its ICC profile.
+///
+/// Profiles other than Display P3 (such as Adobe RGB)
+/// cannot be kept on a canvas and are shown as sRGB.
+pub fn display_color_space_of(obj: &InMemDicomObject) -> DisplayColorSpace {
+    let term = obj
+        .element_opt(tags::COLOR_SPACE)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.to_str().ok());
+    if let Some(term) = term {
+        if let Some(color_space) = DisplayColorSpace::from_dicom_color_space(&term) {
+            gloo_console::debug!("Color space", term.as_ref(), "shown as", color_space.name());
+            return color_space;
+        }
+        gloo_console::warn!(
+            "Unknown color space",
+            term.as_ref(),
+            "- reading the ICC profile"
+        );
+    }
+
+    let profile = obj
+        .element_opt(tags::ICC_PROFILE)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.to_bytes().ok());
+    let description = match profile {
+        Some(profile) => icc_profile_description(&profile),
+        None => return DisplayColorSpace::Srgb,
+    };
+    match description {
+        Some(description) => {
+            let color_space = DisplayColorSpace::from_profile_description(&description);
+            gloo_console::debug!("ICC profile", &description, "shown as", color_space.name());
+            color_space
+        }
+        None => {
+            gloo_console::warn!("Could not read the description of the ICC profile");
+            DisplayColorSpace::Srgb
+        }
+    }
+}
+
+/// Whether image data can be in a color space other than sRGB
+pub fn imagedata_color_spaces_supported() -> bool {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("ImageData"))
+        .and_then(|imagedata| js_sys::Reflect::get(&imagedata, &JsValue::from_str("prototype")))
+        .and_then(|prototype| js_sys::Reflect::has(&prototype, &JsValue::from_str("colorSpace")))
+        .unwrap_or(false)
+}
+
+/// The color space of the image data, sRGB if not stated
+pub fn color_space_of_imagedata(imagedata: &ImageData) -> DisplayColorSpace {
+    js_sys::Reflect::get(imagedata, &JsValue::from_str("colorSpace"))
+        .ok()
+        .and_then(|name| name.as_string())
+        .and_then(|name| DisplayColorSpace::from_name(&name))
+        .unwrap_or_default()
+}
+
+/// Create image data from RGBA values in the given color space,
+/// falling back to sRGB if the browser does not support it.
+pub fn new_imagedata(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    color_space: DisplayColorSpace,
+) -> Result<ImageData, JsValue> {
+    if color_space == DisplayColorSpace::Srgb || !imagedata_color_spaces_supported() {
+        if color_space != DisplayColorSpace::Srgb {
+            gloo_console::warn!(
+                "Image data in",
+                color_space.name(),
+                "is not supported, using sRGB"
+            );
+        }
+        return ImageData::new_with_u8_clamped_array_and_sh(Clamped(rgba), width, height);
+    }
+
+    let constructor = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("ImageData"))?
+        .dyn_into::<js_sys::Function>()?;
+    let data = js_sys::Uint8ClampedArray::from(rgba);
+    let args = js_sys::Array::of4(
+        &data,
+        &JsValue::from(width),
+        &JsValue::from(height),
+        &color_space.context_options(),
+    );
+    js_sys::Reflect::construct(&constructor, &args)?.dyn_into()
+}
+
+/// A correction of color casts,
+/// applied to each channel of the RGB values of color images
+/// as `value * gain + offset`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorBalance {
+    /// the multipliers of the red, green, and blue channels
+    pub gain: [f64; 3],
+    /// the values added to the red, green, and blue channels, out of 255
+    pub offset: [f64; 3],
+}
+
+impl Default for ColorBalance {
+    fn default() -> Self {
+        ColorBalance {
+            gain: [1.; 3],
+            offset: [0.; 3],
+        }
+    }
+}
+
+impl ColorBalance {
+    /// Whether the colors are shown as they are
+    pub fn is_identity(self) -> bool {
+        self == ColorBalance::default()
+    }
+
+    /// Balance the channels of RGBA values, leaving alpha as it is.
+    pub fn apply(self, rgba: &mut [u8]) {
+        if self.is_identity() {
+            return;
+        }
+        for px in rgba.chunks_exact_mut(4) {
+            for c in 0..3 {
+                let value = px[c] as f64 * self.gain[c] + self.offset[c];
+                px[c] = value.round().clamp(0., 255.) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// A profile with only a description tag
+    fn profile_with_description(tag: &[u8]) -> Vec<u8> {
+        let mut profile = vec![0; 128];
+        profile.extend_from_slice(&1_u32.to_be_bytes());
+        profile.extend_from_slice(b"desc");
+        profile.extend_from_slice(&144_u32.to_be_bytes());
+        profile.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        profile.extend_from_slice(tag);
+        profile
+    }
+
+    #[wasm_bindgen_test]
+    fn read_icc_profile_descriptions() {
+        // version 2 text description
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&11_u32.to_be_bytes());
+        tag.extend_from_slice(b"Display P3\0");
+        let description = icc_profile_description(&profile_with_description(&tag));
+        assert_eq!(description.as_deref(), Some("Display P3"));
+        assert_eq!(
+            DisplayColorSpace::from_profile_description("Display P3"),
+            DisplayColorSpace::DisplayP3
+        );
+
+        // version 4 localized description
+        let text: Vec<u8> = "sRGB IEC61966-2.1"
+            .encode_utf16()
+            .flat_map(|c| c.to_be_bytes())
+            .collect();
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&1_u32.to_be_bytes());
+        tag.extend_from_slice(&12_u32.to_be_bytes());
+        tag.extend_from_slice(b"enUS");
+        tag.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&28_u32.to_be_bytes());
+        tag.extend_from_slice(&text);
+        let description = icc_profile_description(&profile_with_description(&tag));
+        assert_eq!(description.as_deref(), Some("sRGB IEC61966-2.1"));
+        assert_eq!(
+            DisplayColorSpace::from_profile_description("sRGB IEC61966-2.1"),
+            DisplayColorSpace::Srgb
+        );
+
+        // other P3 profiles are not Display P3
+        for description in ["DCI-P3", "P3 DCI (Theater)", "Display P3 + something"] {
+            assert_eq!(
+                DisplayColorSpace::from_profile_description(description),
+                DisplayColorSpace::Srgb
+            );
+        }
+
+        // truncated
+        assert_eq!(icc_profile_description(&[0; 130]), None);
+
+        // a tag count far beyond the size of the profile
+        let mut profile = vec![0; 128];
+        profile.extend_from_slice(&u32::MAX.to_be_bytes());
+        profile.extend_from_slice(&[0; 24]);
+        assert_eq!(icc_profile_description(&profile), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn color_space_attribute_comes_first() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        // a Display P3 profile
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&11_u32.to_be_bytes());
+        tag.extend_from_slice(b"Display P3\0");
+        let mut obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::ICC_PROFILE,
+            VR::OB,
+            PrimitiveValue::from(profile_with_description(&tag)),
+        )]);
+        assert_eq!(display_color_space_of(&obj), DisplayColorSpace::DisplayP3);
+
+        // the attribute states otherwise
+        obj.put(DataElement::new(
+            tags::COLOR_SPACE,
+            VR::CS,
+            PrimitiveValue::from("SRGB"),
+        ));
+        assert_eq!(display_color_space_of(&obj), DisplayColorSpace::Srgb);
+
+        // unknown terms leave it to the profile
+        obj.put(DataElement::new(
+            tags::COLOR_SPACE,
+            VR::CS,
+            PrimitiveValue::from("SOMETHING"),
+        ));
+        assert_eq!(display_color_space_of(&obj), DisplayColorSpace::DisplayP3);
+    }
+
+    #[wasm_bindgen_test]
+    fn balance_color_channels() {
+        let original = [10, 128, 250, 255, 0, 64, 200, 128];
+        let mut rgba = original;
+        ColorBalance::default().apply(&mut rgba);
+        assert_eq!(rgba, original);
+
+        let balance = ColorBalance {
+            gain: [2., 1., 0.5],
+            offset: [0., -70., 10.],
+        };
+        balance.apply(&mut rgba);
+        // clamped to the range of 8-bit values, alpha untouched
+        assert_eq!(rgba, [20, 58, 135, 255, 0, 0, 110, 128]);
+    }
+}
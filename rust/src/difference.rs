@@ -0,0 +1,231 @@
+//! Differences between two co-registered monochrome images,
+//! to highlight what changed between a baseline and a follow-up.
+
+use std::borrow::Cow;
+
+use dicom::core::DicomValue;
+use dicom::{dictionary_std::tags, object::DefaultDicomObject};
+use snafu::prelude::*;
+use web_sys::ImageData;
+
+use crate::imaging::{
+    frame_samples, image_dimensions_of, modality_of, write_to_imagedata, Result, StoredBits,
+};
+
+/// The color of the most negative difference
+const NEGATIVE_COLOR: [f64; 3] = [59., 76., 192.];
+/// The color of no difference
+const NEUTRAL_COLOR: [f64; 3] = [255., 255., 255.];
+/// The color of the most positive difference
+const POSITIVE_COLOR: [f64; 3] = [180., 4., 38.];
+
+/// The values of a monochrome frame in modality units,
+/// after applying the modality LUT or the rescale slope and intercept.
+pub fn rescaled_samples_of(obj: &DefaultDicomObject, frame: u32) -> Result<Vec<f64>> {
+    let modality = modality_of(obj)?;
+    Ok(stored_values_of(obj, frame)?
+        .into_iter()
+        .map(|x| modality.apply(x as f64))
+        .collect())
+}
+
+/// The stored values of a monochrome frame,
+/// without any overlay bits and sign extended if signed.
+pub fn stored_values_of(obj: &DefaultDicomObject, frame: u32) -> Result<Vec<i32>> {
+    sampled_stored_values_of(obj, frame, 1)
+}
+
+/// The stored values of a monochrome frame
+/// on a grid of every `stride` rows and columns, row by row,
+/// as in [`stored_values_of`].
+///
+/// Only the sampled pixels are read.
+pub fn sampled_stored_values_of(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    stride: u32,
+) -> Result<Vec<i32>> {
+    let (columns, rows) = image_dimensions_of(obj)?;
+    let frame_len = columns as usize * rows as usize;
+    let samples_per_pixel = match obj
+        .element_opt(tags::SAMPLES_PER_PIXEL)
+        .whatever_context("Could not fetch SamplesPerPixel")?
+    {
+        Some(elem) => elem
+            .to_int::<u16>()
+            .whatever_context("SamplesPerPixel is not a number")?,
+        None => 1,
+    };
+    ensure_whatever!(
+        samples_per_pixel == 1,
+        "Only monochrome images can be compared"
+    );
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+    let signed = match obj
+        .element_opt(tags::PIXEL_REPRESENTATION)
+        .whatever_context("Could not fetch PixelRepresentation")?
+    {
+        Some(elem) => {
+            elem.to_int::<u16>()
+                .whatever_context("PixelRepresentation is not a number")?
+                == 1
+        }
+        None => false,
+    };
+    let stored_bits = StoredBits::of(obj)?;
+
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    if matches!(pixel_data.value(), DicomValue::PixelSequence { .. }) {
+        whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
+    }
+    let (columns, stride) = (columns as usize, stride.max(1) as usize);
+    let grid = (0..rows as usize)
+        .step_by(stride)
+        .flat_map(move |row| (0..columns).step_by(stride).map(move |x| row * columns + x));
+    match bits_allocated {
+        8 => {
+            let samples = pixel_data
+                .to_bytes()
+                .whatever_context("Could not read PixelData as a sequence of 8-bit integers")?;
+            let samples = frame_samples(&samples[..], frame, frame_len)?;
+            Ok(grid
+                .map(|i| stored_value(stored_bits, signed, samples[i] as u16))
+                .collect())
+        }
+        16 => {
+            let samples: Cow<[u16]> = pixel_data
+                .uint16_slice()
+                .map(Cow::from)
+                .or_else(|_| pixel_data.to_multi_int::<u16>().map(Cow::Owned))
+                .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?;
+            let samples = frame_samples(&samples[..], frame, frame_len)?;
+            Ok(grid
+                .map(|i| stored_value(stored_bits, signed, samples[i]))
+                .collect())
+        }
+        _ => whatever!("Unsupported BitsAllocated {} :(", bits_allocated),
+    }
+}
+
+/// The stored value of a sample, sign extended if signed
+pub fn stored_value(stored_bits: StoredBits, signed: bool, x: u16) -> i32 {
+    let value = stored_bits.value(x) as i32;
+    let bits = stored_bits.bits_stored as i32;
+    if signed && value >= 1 << (bits - 1) {
+        value - (1 << bits)
+    } else {
+        value
+    }
+}
+
+/// The per-pixel difference of a frame of the follow-up image
+/// minus a frame of the baseline image, in modality units.
+///
+/// Both images must have the same number of rows and columns.
+/// Returns the differences along with the image width and height.
+pub fn difference_of(
+    (baseline, baseline_frame): (&DefaultDicomObject, u32),
+    (followup, followup_frame): (&DefaultDicomObject, u32),
+) -> Result<(Vec<f64>, (u32, u32))> {
+    let baseline_size = image_dimensions_of(baseline)?;
+    let followup_size = image_dimensions_of(followup)?;
+    ensure_whatever!(
+        baseline_size == followup_size,
+        "Cannot compare images of different sizes ({}x{} and {}x{})",
+        baseline_size.0,
+        baseline_size.1,
+        followup_size.0,
+        followup_size.1
+    );
+
+    let before = rescaled_samples_of(baseline, baseline_frame)?;
+    let after = rescaled_samples_of(followup, followup_frame)?;
+    let differences = after.iter().zip(&before).map(|(a, b)| a - b).collect();
+    Ok((differences, followup_size))
+}
+
+/// The color of a difference in a diverging color map
+/// centered at zero,
+/// from blue for `-range` through white to red for `range`.
+pub fn diverging_color(difference: f64, range: f64) -> [u8; 3] {
+    let t = if range > 0. {
+        (difference / range).clamp(-1., 1.)
+    } else {
+        0.
+    };
+    let end = if t < 0. {
+        NEGATIVE_COLOR
+    } else {
+        POSITIVE_COLOR
+    };
+    let t = t.abs();
+    let mut color = [0; 3];
+    for (c, (neutral, end)) in color.iter_mut().zip(NEUTRAL_COLOR.iter().zip(end)) {
+        *c = (neutral + (end - neutral) * t).round() as u8;
+    }
+    color
+}
+
+/// Map the differences to RGBA values through the diverging color map,
+/// spanning the largest absolute difference.
+pub fn difference_to_rgba(differences: &[f64]) -> Vec<u8> {
+    let range = differences.iter().fold(0., |max: f64, d| max.max(d.abs()));
+    differences
+        .iter()
+        .flat_map(|&d| {
+            let [r, g, b] = diverging_color(d, range);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// Render the difference of a follow-up frame minus a baseline frame
+/// through the diverging color map,
+/// reusing the given image data if it has the same size.
+pub fn difference_to_imagedata(
+    baseline: (&DefaultDicomObject, u32),
+    followup: (&DefaultDicomObject, u32),
+    imagedata: &mut Option<ImageData>,
+) -> Result<ImageData> {
+    let (differences, (width, height)) = difference_of(baseline, followup)?;
+    write_to_imagedata(imagedata, &difference_to_rgba(&differences), width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn diverging_color_map() {
+        assert_eq!(diverging_color(0., 100.), [255, 255, 255]);
+        assert_eq!(diverging_color(-100., 100.), [59, 76, 192]);
+        assert_eq!(diverging_color(250., 100.), [180, 4, 38]);
+        // no differences at all
+        assert_eq!(diverging_color(0., 0.), [255, 255, 255]);
+
+        let rgba = difference_to_rgba(&[-2., 0., 1.]);
+        assert_eq!(&rgba[..4], &[59, 76, 192, 255]);
+        assert_eq!(&rgba[4..8], &[255, 255, 255, 255]);
+        assert_eq!(rgba[8], 218);
+    }
+
+    #[wasm_bindgen_test]
+    fn sign_extend_stored_values() {
+        let stored_bits = StoredBits {
+            bits_stored: 12,
+            high_bit: 11,
+        };
+        assert_eq!(stored_value(stored_bits, false, 0x0FFF), 4095);
+        assert_eq!(stored_value(stored_bits, true, 0x0FFF), -1);
+        assert_eq!(stored_value(stored_bits, true, 0x07FF), 2047);
+        // overlay bits are ignored
+        assert_eq!(stored_value(stored_bits, true, 0xF800), -2048);
+    }
+}
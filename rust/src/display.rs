@@ -0,0 +1,419 @@
+//! How the gray levels of monochrome images are shown.
+//!
+//! After windowing, the gray levels can be inverted
+//! and mapped to colors through a colormap.
+//! Overlays are painted afterwards, so they keep their own colors.
+//! These options are exported to JavaScript as part of [`DisplaySettings`],
+//! along with the other settings of how the image is displayed.
+
+use wasm_bindgen::prelude::*;
+
+use crate::color::ColorBalance;
+use crate::parametric::Diverging;
+use crate::{Background, ScaleMode, Smoothing, ZoomAnchor, ZoomOptions};
+
+/// A mapping from gray levels to colors
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum Colormap {
+    /// shades of gray (the default)
+    #[default]
+    Gray,
+    /// black through red and yellow to white
+    Hot,
+    /// gray with a blue tint, as in bone scans
+    Bone,
+}
+
+impl Colormap {
+    /// The colormap with the given name
+    /// (`"gray"`, `"hot"`, or `"bone"`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gray" => Some(Colormap::Gray),
+            "hot" => Some(Colormap::Hot),
+            "bone" => Some(Colormap::Bone),
+            _ => None,
+        }
+    }
+
+    /// The name of the colormap
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Gray => "gray",
+            Colormap::Hot => "hot",
+            Colormap::Bone => "bone",
+        }
+    }
+
+    /// The color of a gray level
+    pub fn color_of(self, y: u8) -> [u8; 3] {
+        // a linear ramp from 0 to 255 over a part of the gray levels
+        let ramp = |from: u32, to: u32| -> u8 {
+            let y = y as u32;
+            if y <= from {
+                0
+            } else if y >= to {
+                255
+            } else {
+                ((y - from) * 255 / (to - from)) as u8
+            }
+        };
+        match self {
+            Colormap::Gray => [y, y, y],
+            Colormap::Hot => [ramp(0, 96), ramp(96, 192), ramp(192, 255)],
+            // gray mixed with hot in reverse
+            Colormap::Bone => {
+                let mix = |tint: u8| ((7 * y as u32 + tint as u32) / 8) as u8;
+                let [r, g, b] = Colormap::Hot.color_of(y);
+                [mix(b), mix(g), mix(r)]
+            }
+        }
+    }
+}
+
+/// How windowed gray levels are shown
+#[derive(Debug, Default, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct DisplayOptions {
+    /// whether the gray levels are inverted
+    pub invert: bool,
+    /// the colors of the gray levels
+    pub colormap: Colormap,
+}
+
+impl DisplayOptions {
+    /// Whether the gray levels are shown as they are
+    pub fn is_identity(self) -> bool {
+        self == DisplayOptions::default()
+    }
+
+    /// The gray level shown for a windowed gray level, inverted if asked
+    pub fn gray_level_of(self, y: u8) -> u8 {
+        if self.invert {
+            0xFF - y
+        } else {
+            y
+        }
+    }
+
+    /// Invert and map the gray levels of RGBA values to colors,
+    /// reading each gray level from the red channel.
+    pub fn apply(self, rgba: &mut [u8]) {
+        if self.is_identity() {
+            return;
+        }
+        for px in rgba.chunks_exact_mut(4) {
+            px[..3].copy_from_slice(&self.colormap.color_of(self.gray_level_of(px[0])));
+        }
+    }
+}
+
+/// The settings of how the image is displayed,
+/// to be changed together with [`apply_display_settings`](crate::apply_display_settings).
+///
+/// New settings start from the defaults:
+/// not inverted, gray colormap, fitted to the canvas, smoothed,
+/// and letterboxed in black.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DisplaySettings {
+    pub(crate) display: DisplayOptions,
+    pub(crate) color_balance: ColorBalance,
+    pub(crate) diverging: Option<Diverging>,
+    pub(crate) scale_mode: ScaleMode,
+    pub(crate) smoothing: Smoothing,
+    pub(crate) background: Background,
+    pub(crate) zoom: ZoomOptions,
+    pub(crate) full_range: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            display: DisplayOptions::default(),
+            color_balance: ColorBalance::default(),
+            diverging: None,
+            scale_mode: ScaleMode::Fit,
+            smoothing: Smoothing::On,
+            background: Background::default(),
+            zoom: ZoomOptions::default(),
+            full_range: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DisplaySettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> DisplaySettings {
+        DisplaySettings::default()
+    }
+
+    /// Whether the gray levels of monochrome images are inverted
+    pub fn invert(&self) -> bool {
+        self.display.invert
+    }
+
+    pub fn set_invert(&mut self, invert: bool) {
+        self.display.invert = invert;
+    }
+
+    /// Whether monochrome images are windowed linearly
+    /// over the full range of values of each frame,
+    /// instead of with the window level of the file
+    pub fn full_range(&self) -> bool {
+        self.full_range
+    }
+
+    pub fn set_full_range(&mut self, full_range: bool) {
+        self.full_range = full_range;
+    }
+
+    /// The colormap of monochrome images
+    /// (`"gray"`, `"hot"`, or `"bone"`)
+    pub fn colormap(&self) -> String {
+        self.display.colormap.name().to_string()
+    }
+
+    pub fn set_colormap(&mut self, colormap: &str) -> Result<(), JsValue> {
+        self.display.colormap = Colormap::from_name(colormap).ok_or_else(|| {
+            JsValue::from_str("Colormap should be \"gray\", \"hot\", or \"bone\"")
+        })?;
+        Ok(())
+    }
+
+    /// The multipliers of the red, green, and blue channels of color images
+    pub fn color_gains(&self) -> Vec<f64> {
+        self.color_balance.gain.to_vec()
+    }
+
+    /// The values added to the red, green, and blue channels of color images,
+    /// out of 255
+    pub fn color_offsets(&self) -> Vec<f64> {
+        self.color_balance.offset.to_vec()
+    }
+
+    /// Correct the color cast of color images
+    /// by multiplying each of their red, green, and blue values by a gain
+    /// and adding an offset, out of 255.
+    /// Gains of 1 and offsets of 0 leave the colors as they are.
+    pub fn set_color_balance(
+        &mut self,
+        red_gain: f64,
+        green_gain: f64,
+        blue_gain: f64,
+        red_offset: f64,
+        green_offset: f64,
+        blue_offset: f64,
+    ) -> Result<(), JsValue> {
+        let gain = [red_gain, green_gain, blue_gain];
+        let offset = [red_offset, green_offset, blue_offset];
+        if !gain.iter().all(|g| g.is_finite() && *g >= 0.) || !offset.iter().all(|o| o.is_finite())
+        {
+            return Err(JsValue::from_str(
+                "Gains should be non-negative numbers and offsets numbers",
+            ));
+        }
+        self.color_balance = ColorBalance { gain, offset };
+        Ok(())
+    }
+
+    /// Go back to showing the colors as they are
+    pub fn clear_color_balance(&mut self) {
+        self.color_balance = ColorBalance::default();
+    }
+
+    /// The value at the center of the diverging color map
+    /// of parametric maps, if they are shown through it
+    pub fn diverging_center(&self) -> Option<f64> {
+        self.diverging.map(|diverging| diverging.center)
+    }
+
+    /// The distance from the center
+    /// to either end of the diverging color map of parametric maps,
+    /// if they are shown through it
+    pub fn diverging_range(&self) -> Option<f64> {
+        self.diverging.map(|diverging| diverging.range)
+    }
+
+    /// Show parametric maps through a diverging color map
+    /// from blue for `center - range`
+    /// through white at `center` to red for `center + range`,
+    /// instead of windowing them.
+    pub fn set_diverging(&mut self, center: f64, range: f64) -> Result<(), JsValue> {
+        if !center.is_finite() || !(range.is_finite() && range > 0.) {
+            return Err(JsValue::from_str(
+                "Center should be a number and range a positive number",
+            ));
+        }
+        self.diverging = Some(Diverging { center, range });
+        Ok(())
+    }
+
+    /// Go back to windowing parametric maps
+    pub fn clear_diverging(&mut self) {
+        self.diverging = None;
+    }
+
+    /// How the image is scaled to the canvas
+    /// (`"fit"` or `"actual-size"`)
+    pub fn scale_mode(&self) -> String {
+        self.scale_mode.name().to_string()
+    }
+
+    pub fn set_scale_mode(&mut self, scale_mode: &str) -> Result<(), JsValue> {
+        self.scale_mode = ScaleMode::from_name(scale_mode)
+            .ok_or_else(|| JsValue::from_str("Scale mode should be \"fit\" or \"actual-size\""))?;
+        Ok(())
+    }
+
+    /// The interpolation applied when scaling the image
+    /// (`"on"`, `"off"`, `"horizontal"`, or `"vertical"`)
+    pub fn smoothing(&self) -> String {
+        self.smoothing.name().to_string()
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: &str) -> Result<(), JsValue> {
+        self.smoothing = Smoothing::from_name(smoothing).ok_or_else(|| {
+            JsValue::from_str(
+                "Smoothing should be \"on\", \"off\", \"horizontal\", or \"vertical\"",
+            )
+        })?;
+        Ok(())
+    }
+
+    /// What is shown around the image where it does not cover the canvas
+    /// (`"letterbox"`, `"gradient"`, or `"stretch"`)
+    pub fn background(&self) -> String {
+        self.background.name().to_string()
+    }
+
+    /// Show a solid color around the image (`"letterbox"`, in black),
+    /// a subtle gradient (`"gradient"`),
+    /// or stretch the image to fill the canvas
+    /// regardless of its aspect ratio (`"stretch"`)
+    pub fn set_background(&mut self, background: &str) -> Result<(), JsValue> {
+        let background = Background::from_name(background).ok_or_else(|| {
+            JsValue::from_str("Background should be \"letterbox\", \"gradient\", or \"stretch\"")
+        })?;
+        // keep the color of the letterbox
+        if self.background.name() != background.name() {
+            self.background = background;
+        }
+        Ok(())
+    }
+
+    /// The red, green, and blue values of the letterbox around the image,
+    /// if the background is a letterbox
+    pub fn letterbox_color(&self) -> Option<Vec<u8>> {
+        match self.background {
+            Background::Letterbox(color) => Some(color.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Show a solid color around the image
+    pub fn set_letterbox_color(&mut self, red: u8, green: u8, blue: u8) {
+        self.background = Background::Letterbox([red, green, blue]);
+    }
+
+    /// The factor by which each notch of the mouse wheel zooms in or out
+    /// while holding Ctrl
+    pub fn zoom_wheel_factor(&self) -> f64 {
+        self.zoom.wheel_factor
+    }
+
+    pub fn set_zoom_wheel_factor(&mut self, factor: f64) -> Result<(), JsValue> {
+        if !(factor.is_finite() && factor > 1.) {
+            return Err(JsValue::from_str(
+                "Zoom wheel factor should be a number greater than 1",
+            ));
+        }
+        self.zoom.wheel_factor = factor;
+        Ok(())
+    }
+
+    /// The smallest zoom level
+    pub fn min_zoom(&self) -> f64 {
+        self.zoom.min
+    }
+
+    /// The largest zoom level
+    pub fn max_zoom(&self) -> f64 {
+        self.zoom.max
+    }
+
+    /// Limit the zoom level between `min` and `max`,
+    /// where 1 fits the image to the canvas (or shows it at actual size)
+    pub fn set_zoom_limits(&mut self, min: f64, max: f64) -> Result<(), JsValue> {
+        if !(min.is_finite() && max.is_finite() && min > 0. && min <= max) {
+            return Err(JsValue::from_str(
+                "Zoom limits should be positive numbers, the minimum not above the maximum",
+            ));
+        }
+        self.zoom.min = min;
+        self.zoom.max = max;
+        Ok(())
+    }
+
+    /// Where zooming with the mouse wheel is anchored
+    /// (`"cursor"` or `"center"`)
+    pub fn zoom_anchor(&self) -> String {
+        self.zoom.anchor.name().to_string()
+    }
+
+    pub fn set_zoom_anchor(&mut self, anchor: &str) -> Result<(), JsValue> {
+        self.zoom.anchor = ZoomAnchor::from_name(anchor)
+            .ok_or_else(|| JsValue::from_str("Zoom anchor should be \"cursor\" or \"center\""))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn invert_and_map_gray_levels() {
+        let mut rgba = [0, 0, 0, 255, 200, 200, 200, 255];
+        DisplayOptions::default().apply(&mut rgba);
+        assert_eq!(rgba, [0, 0, 0, 255, 200, 200, 200, 255]);
+
+        let inverted = DisplayOptions {
+            invert: true,
+            ..Default::default()
+        };
+        inverted.apply(&mut rgba);
+        assert_eq!(rgba, [255, 255, 255, 255, 55, 55, 55, 255]);
+
+        // hot goes from black to white through red and yellow
+        assert_eq!(Colormap::Hot.color_of(0), [0, 0, 0]);
+        assert_eq!(Colormap::Hot.color_of(96), [255, 0, 0]);
+        assert_eq!(Colormap::Hot.color_of(192), [255, 255, 0]);
+        assert_eq!(Colormap::Hot.color_of(255), [255, 255, 255]);
+        // bone is tinted blue
+        let [r, g, b] = Colormap::Bone.color_of(128);
+        assert!(b > g && g >= r);
+
+        for colormap in [Colormap::Gray, Colormap::Hot, Colormap::Bone] {
+            assert_eq!(Colormap::from_name(colormap.name()), Some(colormap));
+        }
+        assert_eq!(Colormap::from_name("rainbow"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn change_display_settings() {
+        let mut settings = DisplaySettings::new();
+        settings.set_colormap("hot").unwrap();
+        assert_eq!(settings.colormap(), "hot");
+        assert!(settings.set_colormap("rainbow").is_err());
+        assert!(settings.set_zoom_limits(4., 2.).is_err());
+
+        // choosing the letterbox again keeps its color
+        settings.set_letterbox_color(32, 32, 32);
+        settings.set_background("letterbox").unwrap();
+        assert_eq!(settings.letterbox_color(), Some(vec![32, 32, 32]));
+        settings.set_background("gradient").unwrap();
+        assert_eq!(settings.letterbox_color(), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! Display filters applied to rendered images.
+//!
+//! These only change how the image looks on screen
+//! and are not meant for diagnostic use.
+
+/// The parameters of an unsharp mask,
+/// which enhances edges by adding back the difference
+/// between the image and a blurred copy of itself
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UnsharpMask {
+    /// how much of the difference is added back (0 for no effect)
+    pub amount: f64,
+    /// the standard deviation of the Gaussian blur in pixels
+    pub radius: f64,
+}
+
+impl Default for UnsharpMask {
+    fn default() -> Self {
+        UnsharpMask {
+            amount: 0.8,
+            radius: 1.5,
+        }
+    }
+}
+
+/// The largest accepted blur radius in pixels
+pub const MAX_UNSHARP_RADIUS: f64 = 10.;
+
+/// The normalized 1D Gaussian kernel with the given standard deviation,
+/// covering three standard deviations on each side
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let half = (sigma * 3.).ceil().max(1.) as i64;
+    let kernel: Vec<f64> = (-half..=half)
+        .map(|x| (-((x * x) as f64) / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|k| k / sum).collect()
+}
+
+/// Convolve a single channel image with the kernel
+/// along one axis, extending the image at its edges.
+fn convolve(
+    src: &[f64],
+    width: usize,
+    height: usize,
+    kernel: &[f64],
+    horizontal: bool,
+) -> Vec<f64> {
+    let half = (kernel.len() / 2) as i64;
+    let mut out = vec![0.; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = kernel
+                .iter()
+                .enumerate()
+                .map(|(i, k)| {
+                    let offset = i as i64 - half;
+                    let (sx, sy) = if horizontal {
+                        ((x as i64 + offset).clamp(0, width as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + offset).clamp(0, height as i64 - 1) as usize)
+                    };
+                    k * src[sy * width + sx]
+                })
+                .sum();
+        }
+    }
+    out
+}
+
+/// The luma of an RGB pixel (BT.601)
+fn luma_of(px: &[u8]) -> f64 {
+    0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64
+}
+
+/// Sharpen the RGBA samples of an image in place with an unsharp mask.
+///
+/// Only the luma is sharpened, so that color images do not get
+/// colored fringes along their edges:
+/// the change in luma of each pixel is added to all three channels,
+/// which leaves its chroma (as in YCbCr) as it was.
+/// Gray images are sharpened the same as channel by channel.
+///
+/// The Gaussian blur is applied as two separable passes.
+/// Alpha is left untouched.
+pub fn unsharp_mask(rgba: &mut [u8], width: u32, height: u32, mask: UnsharpMask) {
+    let (width, height) = (width as usize, height as usize);
+    if mask.amount <= 0. || mask.radius <= 0. || width == 0 || height == 0 {
+        return;
+    }
+    debug_assert_eq!(rgba.len(), width * height * 4);
+
+    let kernel = gaussian_kernel(mask.radius.min(MAX_UNSHARP_RADIUS));
+    let luma: Vec<f64> = rgba.chunks_exact(4).map(luma_of).collect();
+    let blurred = convolve(&luma, width, height, &kernel, true);
+    let blurred = convolve(&blurred, width, height, &kernel, false);
+
+    for ((px, y), blurred) in rgba.chunks_exact_mut(4).zip(luma).zip(blurred) {
+        let delta = mask.amount * (y - blurred);
+        for c in &mut px[..3] {
+            *c = (*c as f64 + delta).round().clamp(0., 255.) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// A 4x1 image with a step from dark to bright gray
+    fn step_edge() -> Vec<u8> {
+        [64, 64, 192, 192]
+            .iter()
+            .flat_map(|&x| [x, x, x, 255])
+            .collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn unsharp_mask_amount_zero_is_no_op() {
+        let mut rgba = step_edge();
+        unsharp_mask(
+            &mut rgba,
+            4,
+            1,
+            UnsharpMask {
+                amount: 0.,
+                radius: 1.,
+            },
+        );
+        assert_eq!(rgba, step_edge());
+    }
+
+    #[wasm_bindgen_test]
+    fn unsharp_mask_enhances_edges() {
+        let mut rgba = step_edge();
+        unsharp_mask(
+            &mut rgba,
+            4,
+            1,
+            UnsharpMask {
+                amount: 1.,
+                radius: 1.,
+            },
+        );
+        // darker on the dark side and brighter on the bright side
+        assert!(rgba[4] < 64);
+        assert!(rgba[8] > 192);
+        // alpha is kept
+        assert!(rgba.chunks(4).all(|px| px[3] == 255));
+    }
+
+    #[wasm_bindgen_test]
+    fn unsharp_mask_keeps_chroma() {
+        // a step between two shades of the same reddish hue
+        let step: Vec<u8> = [
+            [100, 50, 60],
+            [100, 50, 60],
+            [180, 130, 140],
+            [180, 130, 140],
+        ]
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 255])
+        .collect();
+        let mut rgba = step.clone();
+        unsharp_mask(
+            &mut rgba,
+            4,
+            1,
+            UnsharpMask {
+                amount: 1.,
+                radius: 1.,
+            },
+        );
+        // the edge is enhanced
+        assert!(luma_of(&rgba[4..8]) < luma_of(&step[4..8]));
+        assert!(luma_of(&rgba[8..12]) > luma_of(&step[8..12]));
+        // without changing the differences between channels
+        for px in rgba.chunks(4) {
+            assert_eq!(px[0] as i32 - px[1] as i32, 50);
+            assert_eq!(px[2] as i32 - px[1] as i32, 10);
+        }
+    }
+}
@@ -0,0 +1,747 @@
+//! Helper module for patient space geometry of images.
+
+use dicom::{core::Tag, dictionary_std::tags, object::InMemDicomObject};
+use snafu::prelude::*;
+
+use crate::imaging::{image_dimensions_of, string_of, Result};
+
+/// The position and orientation of an image plane in patient space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImagePlane {
+    /// the patient position of the center of the first pixel (mm)
+    pub position: [f64; 3],
+    /// the direction cosines of a row (towards increasing columns)
+    pub row_direction: [f64; 3],
+    /// the direction cosines of a column (towards increasing rows)
+    pub column_direction: [f64; 3],
+    /// the distance between adjacent rows and between adjacent columns (mm)
+    pub spacing: (f64, f64),
+    pub rows: u32,
+    pub columns: u32,
+}
+
+/// The plane of an image shown in another view,
+/// whose intersection with the displayed image is drawn as a reference line
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReferencePlane {
+    /// a patient position on the plane (mm)
+    pub position: [f64; 3],
+    /// the normal vector of the plane
+    pub normal: [f64; 3],
+}
+
+impl ReferencePlane {
+    /// The plane of an image from its ImagePositionPatient
+    /// and ImageOrientationPatient (row then column direction cosines).
+    ///
+    /// Returns `None` if the directions are parallel.
+    pub fn from_position_orientation(position: [f64; 3], orientation: [f64; 6]) -> Option<Self> {
+        let normal = cross(
+            [orientation[0], orientation[1], orientation[2]],
+            [orientation[3], orientation[4], orientation[5]],
+        );
+        if dot(normal, normal) < 1e-12 {
+            return None;
+        }
+        Some(ReferencePlane { position, normal })
+    }
+}
+
+impl From<&ImagePlane> for ReferencePlane {
+    fn from(plane: &ImagePlane) -> Self {
+        ReferencePlane {
+            position: plane.position,
+            normal: plane.normal(),
+        }
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add_scaled(a: [f64; 3], b: [f64; 3], k: f64) -> [f64; 3] {
+    [a[0] + b[0] * k, a[1] + b[1] * k, a[2] + b[2] * k]
+}
+
+impl ImagePlane {
+    /// the normal vector of the plane
+    pub fn normal(&self) -> [f64; 3] {
+        cross(self.row_direction, self.column_direction)
+    }
+
+    /// The patient position (mm) of a point in image pixel coordinates
+    /// (column, row), the top left corner of the image being at (0, 0).
+    pub fn to_patient(&self, (x, y): (f64, f64)) -> [f64; 3] {
+        let (row_spacing, column_spacing) = self.spacing;
+        // the position is the center of the first pixel
+        let p = add_scaled(
+            self.position,
+            self.row_direction,
+            (x - 0.5) * column_spacing,
+        );
+        add_scaled(p, self.column_direction, (y - 0.5) * row_spacing)
+    }
+
+    /// Project a patient position (mm) onto this image,
+    /// returning its image pixel coordinates (column, row)
+    /// and its distance from the plane (mm).
+    pub fn to_pixel(&self, point: [f64; 3]) -> ((f64, f64), f64) {
+        let (row_spacing, column_spacing) = self.spacing;
+        let d = sub(point, self.position);
+        let x = dot(d, self.row_direction) / column_spacing + 0.5;
+        let y = dot(d, self.column_direction) / row_spacing + 0.5;
+        ((x, y), dot(d, self.normal()).abs())
+    }
+
+    /// Compute the line where the plane of `other`
+    /// intersects this image,
+    /// as a pair of (column, row) pixel coordinates
+    /// clipped to the bounds of this image.
+    ///
+    /// Returns `None` if the planes are parallel
+    /// or if the line does not cross this image.
+    pub fn reference_line(&self, other: &ImagePlane) -> Option<[(f64, f64); 2]> {
+        self.line_of(&other.into())
+    }
+
+    /// Compute the line where a reference plane intersects this image,
+    /// like [`ImagePlane::reference_line`].
+    pub fn line_of(&self, other: &ReferencePlane) -> Option<[(f64, f64); 2]> {
+        let n = other.normal;
+        let (row_spacing, column_spacing) = self.spacing;
+
+        // line equation in pixel space: a * x + b * y + c = 0
+        let a = dot(n, self.row_direction) * column_spacing;
+        let b = dot(n, self.column_direction) * row_spacing;
+        let c = dot(n, sub(self.position, other.position));
+
+        if a.abs() < 1e-6 && b.abs() < 1e-6 {
+            return None;
+        }
+
+        let w = self.columns as f64;
+        let h = self.rows as f64;
+        let mut points: Vec<(f64, f64)> = Vec::with_capacity(4);
+        let mut push = |p: (f64, f64)| {
+            let inside = p.0 >= -1e-6 && p.0 <= w + 1e-6 && p.1 >= -1e-6 && p.1 <= h + 1e-6;
+            let seen = points
+                .iter()
+                .any(|q| (q.0 - p.0).abs() < 1e-6 && (q.1 - p.1).abs() < 1e-6);
+            if inside && !seen {
+                points.push(p);
+            }
+        };
+
+        if b.abs() >= 1e-6 {
+            // intersections with left and right edges
+            push((0., -c / b));
+            push((w, -(a * w + c) / b));
+        }
+        if a.abs() >= 1e-6 {
+            // intersections with top and bottom edges
+            push((-c / a, 0.));
+            push((-(b * h + c) / a, h));
+        }
+
+        match points[..] {
+            [p1, p2, ..] => Some([p1, p2]),
+            _ => None,
+        }
+    }
+}
+
+/// The FrameOfReferenceUID of an object, if any.
+///
+/// Positions in patient space are only comparable
+/// between images of the same frame of reference.
+pub fn frame_of_reference_uid_of(obj: &InMemDicomObject) -> Option<String> {
+    string_of(obj, tags::FRAME_OF_REFERENCE_UID)
+}
+
+/// Whether images of the given frames of reference can be spatially linked,
+/// which requires both to be known and the same.
+pub fn same_frame_of_reference(a: Option<&str>, b: Option<&str>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+/// How a resolved pixel spacing relates to the patient anatomy
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Calibration {
+    /// Spacing in the patient,
+    /// as given by PixelSpacing in cross-sectional modalities
+    Patient,
+    /// Spacing in the patient,
+    /// calibrated against an object of known size
+    /// (PixelSpacingCalibrationType FIDUCIAL)
+    Fiducial,
+    /// Spacing in the patient,
+    /// estimated by correcting for geometric magnification
+    /// (PixelSpacingCalibrationType GEOMETRY)
+    Geometry,
+    /// Spacing in the patient of unknown calibration
+    /// (PixelSpacing in projection radiography without a calibration type)
+    Unknown,
+    /// Spacing at the front plane of the detector,
+    /// not corrected for magnification (ImagerPixelSpacing)
+    Detector,
+    /// Nominal spacing of a scanned film (NominalScannedPixelSpacing)
+    Nominal,
+    /// Spacing in the patient,
+    /// as given by the calibration of an ultrasound region
+    /// (SequenceOfUltrasoundRegions)
+    UltrasoundRegion,
+}
+
+impl Calibration {
+    /// A message warning about the accuracy of measurements
+    /// made with this spacing, if they are not exact.
+    pub fn warning(self) -> Option<&'static str> {
+        match self {
+            Calibration::Patient | Calibration::Fiducial | Calibration::UltrasoundRegion => None,
+            Calibration::Geometry => {
+                Some("Pixel spacing was estimated from the geometric magnification.")
+            }
+            Calibration::Unknown => Some("Pixel spacing calibration is not specified."),
+            Calibration::Detector => Some(
+                "Pixel spacing is measured at the detector and is not corrected for magnification.",
+            ),
+            Calibration::Nominal => Some("Pixel spacing is the nominal spacing of a scanned film."),
+        }
+    }
+
+    /// Whether the spacing applies to the patient anatomy,
+    /// as opposed to a geometric spacing at the detector or film
+    pub fn is_at_patient(self) -> bool {
+        !matches!(self, Calibration::Detector | Calibration::Nominal)
+    }
+}
+
+/// Modalities of projection radiography,
+/// in which spacing at the detector and in the patient differ
+const PROJECTION_MODALITIES: &[&str] = &["CR", "DX", "MG", "IO", "RF", "RG", "XA", "PX"];
+
+/// The code of centimeters in PhysicalUnitsXDirection and PhysicalUnitsYDirection
+const ULTRASOUND_UNITS_CM: u16 = 3;
+
+fn spacing_of(obj: &InMemDicomObject, tag: dicom::core::Tag) -> Option<(f64, f64)> {
+    let values = obj.element_opt(tag).ok()??.to_multi_float64().ok()?;
+    match values[..] {
+        [row_spacing, column_spacing, ..] if row_spacing > 0. && column_spacing > 0. => {
+            Some((row_spacing, column_spacing))
+        }
+        _ => None,
+    }
+}
+
+/// The pixel spacing of the first ultrasound region
+/// calibrated in centimeters along both axes,
+/// as a pair of row spacing and column spacing (mm).
+fn ultrasound_region_spacing(regions: &[InMemDicomObject]) -> Option<(f64, f64)> {
+    regions.iter().find_map(|region| {
+        let units = |tag| region.element_opt(tag).ok()??.to_int::<u16>().ok();
+        let delta = |tag| region.element_opt(tag).ok()??.to_float64().ok();
+        if units(tags::PHYSICAL_UNITS_X_DIRECTION)? != ULTRASOUND_UNITS_CM
+            || units(tags::PHYSICAL_UNITS_Y_DIRECTION)? != ULTRASOUND_UNITS_CM
+        {
+            return None;
+        }
+        let column_spacing = delta(tags::PHYSICAL_DELTA_X)?.abs() * 10.;
+        let row_spacing = delta(tags::PHYSICAL_DELTA_Y)?.abs() * 10.;
+        if row_spacing > 0. && column_spacing > 0. {
+            Some((row_spacing, column_spacing))
+        } else {
+            None
+        }
+    })
+}
+
+/// Resolve the pixel spacing of an image
+/// as a pair of row spacing and column spacing (mm)
+/// along with how it is calibrated.
+/// This is the spacing which all measurements should use.
+///
+/// The attributes considered depend on the modality, in order of priority:
+///
+/// - projection radiography (CR, DX, MG, ...):
+///   PixelSpacing (calibrated as per PixelSpacingCalibrationType),
+///   then ImagerPixelSpacing (at the detector),
+///   then NominalScannedPixelSpacing (of the scanned film);
+/// - ultrasound (US):
+///   PixelSpacing,
+///   then the first region of SequenceOfUltrasoundRegions
+///   calibrated in centimeters;
+/// - secondary capture (SC) and other images without a modality:
+///   PixelSpacing, then NominalScannedPixelSpacing;
+/// - other modalities (cross-sectional imaging such as CT and MR):
+///   PixelSpacing only.
+///
+/// Use [`Calibration::is_at_patient`] to tell
+/// spacing in the patient from geometric spacing at the detector.
+pub fn pixel_spacing(obj: &InMemDicomObject) -> Option<(f64, f64, Calibration)> {
+    let modality = obj
+        .element_opt(tags::MODALITY)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.to_str().ok())
+        .map(|modality| modality.trim().to_string())
+        .unwrap_or_default();
+
+    if modality == "US" {
+        if let Some((row, column)) = spacing_of(obj, tags::PIXEL_SPACING) {
+            return Some((row, column, Calibration::Patient));
+        }
+        let regions = obj
+            .element_opt(tags::SEQUENCE_OF_ULTRASOUND_REGIONS)
+            .ok()??
+            .items()?;
+        return ultrasound_region_spacing(regions)
+            .map(|(row, column)| (row, column, Calibration::UltrasoundRegion));
+    }
+
+    if modality.is_empty() || modality == "SC" || modality == "OT" {
+        return spacing_of(obj, tags::PIXEL_SPACING)
+            .map(|(row, column)| (row, column, Calibration::Patient))
+            .or_else(|| {
+                spacing_of(obj, tags::NOMINAL_SCANNED_PIXEL_SPACING)
+                    .map(|(row, column)| (row, column, Calibration::Nominal))
+            });
+    }
+
+    if !PROJECTION_MODALITIES.contains(&modality.as_str()) {
+        return spacing_of(obj, tags::PIXEL_SPACING)
+            .map(|(row, column)| (row, column, Calibration::Patient));
+    }
+
+    if let Some((row, column)) = spacing_of(obj, tags::PIXEL_SPACING) {
+        let calibration_type = string_of(obj, tags::PIXEL_SPACING_CALIBRATION_TYPE);
+        let calibration = match calibration_type.as_deref() {
+            Some("FIDUCIAL") => Calibration::Fiducial,
+            Some("GEOMETRY") => Calibration::Geometry,
+            _ => Calibration::Unknown,
+        };
+        return Some((row, column, calibration));
+    }
+
+    if let Some((row, column)) = spacing_of(obj, tags::IMAGER_PIXEL_SPACING) {
+        return Some((row, column, Calibration::Detector));
+    }
+
+    spacing_of(obj, tags::NOMINAL_SCANNED_PIXEL_SPACING)
+        .map(|(row, column)| (row, column, Calibration::Nominal))
+}
+
+/// The first item of a functional group sequence of a frame
+/// of an enhanced multi-frame object,
+/// from its per-frame functional groups,
+/// or else from the shared functional groups.
+pub fn functional_group_of(
+    obj: &InMemDicomObject,
+    frame: u32,
+    sequence: Tag,
+) -> Option<&InMemDicomObject> {
+    let group_in = |groups: Tag, index: usize| {
+        obj.element_opt(groups)
+            .ok()??
+            .items()?
+            .get(index)?
+            .element_opt(sequence)
+            .ok()??
+            .items()?
+            .first()
+    };
+    group_in(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE, frame as usize)
+        .or_else(|| group_in(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE, 0))
+}
+
+/// The object holding an attribute of a frame:
+/// the functional group of the frame in the given sequence
+/// if it has the attribute, or else the object itself.
+fn frame_attribute_source<'a>(
+    obj: &'a InMemDicomObject,
+    frame: u32,
+    sequence: Tag,
+    tag: Tag,
+) -> &'a InMemDicomObject {
+    functional_group_of(obj, frame, sequence)
+        .filter(|group| matches!(group.element_opt(tag), Ok(Some(_))))
+        .unwrap_or(obj)
+}
+
+/// Resolve the pixel spacing of a frame
+/// as a pair of row spacing and column spacing (mm)
+/// along with how it is calibrated.
+///
+/// Frames of enhanced multi-frame objects
+/// take the spacing in their PixelMeasuresSequence
+/// (per frame, or else shared),
+/// which may differ from one frame to the next.
+/// Otherwise, the spacing is resolved as in [`pixel_spacing`].
+pub fn frame_pixel_spacing(obj: &InMemDicomObject, frame: u32) -> Option<(f64, f64, Calibration)> {
+    functional_group_of(obj, frame, tags::PIXEL_MEASURES_SEQUENCE)
+        .and_then(|measures| spacing_of(measures, tags::PIXEL_SPACING))
+        .map(|(row, column)| (row, column, Calibration::Patient))
+        .or_else(|| pixel_spacing(obj))
+}
+
+fn vec3_of(values: &[f64], offset: usize) -> [f64; 3] {
+    [values[offset], values[offset + 1], values[offset + 2]]
+}
+
+/// Read the image plane geometry of a frame of an object,
+/// if it declares its position and orientation in patient space.
+///
+/// Frames of enhanced multi-frame objects
+/// take their position, orientation, and spacing
+/// from the PlanePositionSequence, PlaneOrientationSequence,
+/// and PixelMeasuresSequence of their functional groups
+/// (per frame, or else shared),
+/// falling back to the attributes of the object itself.
+pub fn image_plane_of(obj: &InMemDicomObject, frame: u32) -> Result<Option<ImagePlane>> {
+    let attribute_of =
+        |sequence, tag| frame_attribute_source(obj, frame, sequence, tag).element_opt(tag);
+    let position = attribute_of(tags::PLANE_POSITION_SEQUENCE, tags::IMAGE_POSITION_PATIENT)
+        .whatever_context("Could not fetch ImagePositionPatient")?;
+    let orientation = attribute_of(
+        tags::PLANE_ORIENTATION_SEQUENCE,
+        tags::IMAGE_ORIENTATION_PATIENT,
+    )
+    .whatever_context("Could not fetch ImageOrientationPatient")?;
+    let spacing = attribute_of(tags::PIXEL_MEASURES_SEQUENCE, tags::PIXEL_SPACING)
+        .whatever_context("Could not fetch PixelSpacing")?;
+
+    let (position, orientation, spacing) = match (position, orientation, spacing) {
+        (Some(position), Some(orientation), Some(spacing)) => (position, orientation, spacing),
+        _ => return Ok(None),
+    };
+
+    let position = position
+        .to_multi_float64()
+        .whatever_context("ImagePositionPatient is not a list of numbers")?;
+    let orientation = orientation
+        .to_multi_float64()
+        .whatever_context("ImageOrientationPatient is not a list of numbers")?;
+    let spacing = spacing
+        .to_multi_float64()
+        .whatever_context("PixelSpacing is not a list of numbers")?;
+
+    ensure_whatever!(
+        position.len() >= 3,
+        "ImagePositionPatient should have 3 values"
+    );
+    ensure_whatever!(
+        orientation.len() >= 6,
+        "ImageOrientationPatient should have 6 values"
+    );
+    ensure_whatever!(spacing.len() >= 2, "PixelSpacing should have 2 values");
+
+    let (columns, rows) = image_dimensions_of(obj)?;
+
+    Ok(Some(ImagePlane {
+        position: vec3_of(&position, 0),
+        row_direction: vec3_of(&orientation, 0),
+        column_direction: vec3_of(&orientation, 3),
+        spacing: (spacing[0], spacing[1]),
+        rows,
+        columns,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// An object with the given modality and decimal string attributes,
+    /// with multiple values separated by backslashes
+    fn obj_with(modality: &str, attributes: &[(dicom::core::Tag, &str)]) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter(
+            std::iter::once(DataElement::new(
+                tags::MODALITY,
+                VR::CS,
+                PrimitiveValue::from(modality),
+            ))
+            .chain(attributes.iter().map(|&(tag, value)| {
+                DataElement::new(
+                    tag,
+                    VR::DS,
+                    PrimitiveValue::Strs(value.split('\\').map(String::from).collect()),
+                )
+            })),
+        )
+    }
+
+    #[wasm_bindgen_test]
+    fn resolve_pixel_spacing_per_modality() {
+        // cross-sectional imaging ignores projection attributes
+        let ct = obj_with(
+            "CT",
+            &[
+                (tags::PIXEL_SPACING, "0.5\\0.6"),
+                (tags::IMAGER_PIXEL_SPACING, "0.1\\0.1"),
+            ],
+        );
+        assert_eq!(pixel_spacing(&ct), Some((0.5, 0.6, Calibration::Patient)));
+        let mr = obj_with("MR", &[(tags::IMAGER_PIXEL_SPACING, "0.1\\0.1")]);
+        assert_eq!(pixel_spacing(&mr), None);
+
+        // projection radiography prefers calibrated spacing
+        let mut dx = obj_with(
+            "DX",
+            &[
+                (tags::PIXEL_SPACING, "0.14\\0.14"),
+                (tags::IMAGER_PIXEL_SPACING, "0.15\\0.15"),
+            ],
+        );
+        assert_eq!(pixel_spacing(&dx), Some((0.14, 0.14, Calibration::Unknown)));
+        dx.put(DataElement::new(
+            tags::PIXEL_SPACING_CALIBRATION_TYPE,
+            VR::CS,
+            PrimitiveValue::from("FIDUCIAL"),
+        ));
+        assert_eq!(
+            pixel_spacing(&dx),
+            Some((0.14, 0.14, Calibration::Fiducial))
+        );
+
+        // then spacing at the detector
+        let mg = obj_with("MG", &[(tags::IMAGER_PIXEL_SPACING, "0.07\\0.07")]);
+        let spacing = pixel_spacing(&mg);
+        assert_eq!(spacing, Some((0.07, 0.07, Calibration::Detector)));
+        assert!(!spacing.unwrap().2.is_at_patient());
+
+        // then the nominal spacing of scanned film
+        let cr = obj_with("CR", &[(tags::NOMINAL_SCANNED_PIXEL_SPACING, "0.2\\0.2")]);
+        assert_eq!(pixel_spacing(&cr), Some((0.2, 0.2, Calibration::Nominal)));
+        let sc = obj_with("SC", &[(tags::NOMINAL_SCANNED_PIXEL_SPACING, "0.2\\0.2")]);
+        assert_eq!(pixel_spacing(&sc), Some((0.2, 0.2, Calibration::Nominal)));
+
+        // invalid spacing is ignored
+        let ct = obj_with("CT", &[(tags::PIXEL_SPACING, "0\\0.5")]);
+        assert_eq!(pixel_spacing(&ct), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn resolve_ultrasound_region_spacing() {
+        let region = |units: u16, delta_x: f64, delta_y: f64| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(
+                    tags::PHYSICAL_UNITS_X_DIRECTION,
+                    VR::US,
+                    PrimitiveValue::from(units),
+                ),
+                DataElement::new(
+                    tags::PHYSICAL_UNITS_Y_DIRECTION,
+                    VR::US,
+                    PrimitiveValue::from(units),
+                ),
+                DataElement::new(
+                    tags::PHYSICAL_DELTA_X,
+                    VR::FD,
+                    PrimitiveValue::from(delta_x),
+                ),
+                DataElement::new(
+                    tags::PHYSICAL_DELTA_Y,
+                    VR::FD,
+                    PrimitiveValue::from(delta_y),
+                ),
+            ])
+        };
+
+        // a Doppler region in cm/s is skipped
+        let regions = [region(7, 0.5, 0.5), region(3, 0.02, -0.03)];
+        let (row, column) = ultrasound_region_spacing(&regions).unwrap();
+        assert!((row - 0.3).abs() < 1e-9);
+        assert!((column - 0.2).abs() < 1e-9);
+        assert!(Calibration::UltrasoundRegion.is_at_patient());
+
+        assert_eq!(ultrasound_region_spacing(&[region(4, 0.1, 0.1)]), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn link_only_the_same_frame_of_reference() {
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::FRAME_OF_REFERENCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.4\0"),
+        )]);
+        let uid = frame_of_reference_uid_of(&obj);
+        assert_eq!(uid.as_deref(), Some("1.2.3.4"));
+
+        assert!(same_frame_of_reference(uid.as_deref(), Some("1.2.3.4")));
+        assert!(!same_frame_of_reference(uid.as_deref(), Some("1.2.3.5")));
+        // unknown frames of reference are never linked
+        assert!(!same_frame_of_reference(uid.as_deref(), None));
+        assert!(!same_frame_of_reference(None, None));
+    }
+
+    #[wasm_bindgen_test]
+    fn map_points_between_planes() {
+        // axial slice at z = -25, 256x256 pixels of 0.5 mm
+        let axial = ImagePlane {
+            position: [0., -50., -25.],
+            row_direction: [1., 0., 0.],
+            column_direction: [0., 1., 0.],
+            spacing: (0.5, 0.5),
+            rows: 256,
+            columns: 256,
+        };
+        // sagittal image at x = 10, 100x100 pixels of 1 mm
+        let sagittal = ImagePlane {
+            position: [10., -50., 0.],
+            row_direction: [0., 1., 0.],
+            column_direction: [0., 0., -1.],
+            spacing: (1., 1.),
+            rows: 100,
+            columns: 100,
+        };
+
+        let point = axial.to_patient((20.5, 100.5));
+        assert_eq!(point, [10., 0., -25.]);
+        let ((x, y), distance) = axial.to_pixel(point);
+        assert!((x - 20.5).abs() < 1e-9 && (y - 100.5).abs() < 1e-9);
+        assert!(distance < 1e-9);
+
+        // the same point on the sagittal image
+        let ((x, y), distance) = sagittal.to_pixel(point);
+        assert!((x - 50.5).abs() < 1e-9 && (y - 25.5).abs() < 1e-9);
+        assert!(distance < 1e-9);
+
+        // a point off the plane
+        let (_, distance) = axial.to_pixel([10., 0., -20.]);
+        assert!((distance - 5.).abs() < 1e-9);
+    }
+
+    #[wasm_bindgen_test]
+    fn reference_line_of_axial_on_coronal() {
+        // coronal image, 100x100 pixels of 1 mm, covering x in [0, 100], z in [0, -100]
+        let coronal = ImagePlane {
+            position: [0., 0., 0.],
+            row_direction: [1., 0., 0.],
+            column_direction: [0., 0., -1.],
+            spacing: (1., 1.),
+            rows: 100,
+            columns: 100,
+        };
+        // axial slice at z = -25
+        let axial = ImagePlane {
+            position: [0., -50., -25.],
+            row_direction: [1., 0., 0.],
+            column_direction: [0., 1., 0.],
+            spacing: (0.5, 0.5),
+            rows: 256,
+            columns: 256,
+        };
+
+        let [p1, p2] = coronal.reference_line(&axial).unwrap();
+        assert!((p1.1 - 25.).abs() < 1e-9);
+        assert!((p2.1 - 25.).abs() < 1e-9);
+        assert!((p1.0 - p2.0).abs() > 99.);
+
+        // parallel planes do not intersect
+        assert_eq!(axial.reference_line(&axial), None);
+
+        // the same slice given by its position and orientation
+        let reference =
+            ReferencePlane::from_position_orientation([0., -50., -25.], [1., 0., 0., 0., 1., 0.])
+                .unwrap();
+        assert_eq!(coronal.line_of(&reference), Some([p1, p2]));
+        assert_eq!(
+            ReferencePlane::from_position_orientation([0.; 3], [1., 0., 0., 1., 0., 0.]),
+            None
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn geometry_of_each_frame() {
+        use dicom::core::{DicomValue, Length};
+
+        let ds = |tag, value: &str| {
+            DataElement::new(
+                tag,
+                VR::DS,
+                PrimitiveValue::Strs(value.split('\\').map(String::from).collect()),
+            )
+        };
+        let sequence = |tag, items: Vec<InMemDicomObject>| {
+            DataElement::new(
+                tag,
+                VR::SQ,
+                DicomValue::new_sequence(items, Length::UNDEFINED),
+            )
+        };
+        let frame = |spacing: &str, position: &str| {
+            InMemDicomObject::from_element_iter([
+                sequence(
+                    tags::PIXEL_MEASURES_SEQUENCE,
+                    vec![InMemDicomObject::from_element_iter([ds(
+                        tags::PIXEL_SPACING,
+                        spacing,
+                    )])],
+                ),
+                sequence(
+                    tags::PLANE_POSITION_SEQUENCE,
+                    vec![InMemDicomObject::from_element_iter([ds(
+                        tags::IMAGE_POSITION_PATIENT,
+                        position,
+                    )])],
+                ),
+            ])
+        };
+        let shared = InMemDicomObject::from_element_iter([sequence(
+            tags::PLANE_ORIENTATION_SEQUENCE,
+            vec![InMemDicomObject::from_element_iter([ds(
+                tags::IMAGE_ORIENTATION_PATIENT,
+                "1\\0\\0\\0\\1\\0",
+            )])],
+        )]);
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("MR")),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(64_u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(64_u16)),
+            ds(tags::PIXEL_SPACING, "2\\2"),
+            sequence(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE, vec![shared]),
+            sequence(
+                tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE,
+                vec![frame("0.5\\0.5", "0\\0\\0"), frame("0.8\\0.6", "0\\0\\5")],
+            ),
+        ]);
+
+        assert_eq!(
+            frame_pixel_spacing(&obj, 0),
+            Some((0.5, 0.5, Calibration::Patient))
+        );
+        assert_eq!(
+            frame_pixel_spacing(&obj, 1),
+            Some((0.8, 0.6, Calibration::Patient))
+        );
+        // frames beyond the functional groups keep the spacing of the object
+        assert_eq!(
+            frame_pixel_spacing(&obj, 2),
+            Some((2., 2., Calibration::Patient))
+        );
+
+        let first = image_plane_of(&obj, 0).unwrap().unwrap();
+        let second = image_plane_of(&obj, 1).unwrap().unwrap();
+        assert_eq!(first.spacing, (0.5, 0.5));
+        assert_eq!(second.spacing, (0.8, 0.6));
+        assert_eq!(second.position, [0., 0., 5.]);
+        assert_eq!(second.row_direction, [1., 0., 0.]);
+        // the same pixel lies further away on the frame with larger pixels
+        assert_eq!(first.to_patient((10.5, 0.5)), [5., 0., 0.]);
+        assert_eq!(second.to_patient((10.5, 0.5)), [6., 0., 5.]);
+    }
+}
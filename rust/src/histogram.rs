@@ -0,0 +1,203 @@
+//! Histograms and dynamic range of the values of a frame.
+//!
+//! Large images are subsampled on a regular grid,
+//! so that binning stays fast
+//! while the histogram keeps the same overall shape.
+//! The dynamic range takes all values into account.
+
+/// The largest number of pixels binned into a histogram,
+/// above which the pixels are subsampled
+pub const MAX_HISTOGRAM_SAMPLES: u64 = 1 << 18;
+
+/// The stride along both rows and columns
+/// with which the pixels of a frame of the given size are sampled,
+/// so that at most [`MAX_HISTOGRAM_SAMPLES`] pixels are binned.
+///
+/// The sampled pixels only depend on the size of the frame.
+pub fn sampling_stride((width, height): (u32, u32)) -> u32 {
+    let samples = |stride: u32| {
+        (width as u64).div_ceil(stride as u64) * (height as u64).div_ceil(stride as u64)
+    };
+    let pixels = width as u64 * height as u64;
+    let mut stride = ((pixels as f64 / MAX_HISTOGRAM_SAMPLES as f64).sqrt().ceil() as u32).max(1);
+    // partial rows and columns may leave a few samples too many
+    while samples(stride) > MAX_HISTOGRAM_SAMPLES {
+        stride += 1;
+    }
+    stride
+}
+
+/// A histogram of values with bins of equal width
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// the smallest sampled value
+    pub min: f64,
+    /// the largest sampled value
+    pub max: f64,
+    /// the number of sampled values in each bin
+    pub counts: Vec<u32>,
+    /// the stride along rows and columns with which pixels were sampled
+    pub stride: u32,
+}
+
+/// Bin the values of a frame of the given size (row by row)
+/// into the given number of bins
+/// between the smallest and the largest sampled value.
+///
+/// Large frames are sampled with [`sampling_stride`],
+/// so the counts add up to the number of sampled pixels,
+/// and the extremes may be missed.
+pub fn histogram_of(values: &[f64], (width, height): (u32, u32), bins: usize) -> Histogram {
+    let stride = sampling_stride((width, height));
+    let sampled: Vec<f64> = values
+        .chunks_exact(width as usize)
+        .take(height as usize)
+        .step_by(stride as usize)
+        .flat_map(|row| row.iter().step_by(stride as usize).copied())
+        .collect();
+    histogram_of_sampled(&sampled, stride, bins)
+}
+
+/// Bin values already sampled with the given stride
+/// into the given number of bins
+/// between the smallest and the largest of them.
+pub fn histogram_of_sampled(sampled: &[f64], stride: u32, bins: usize) -> Histogram {
+    let (min, max) = sampled
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &x| {
+            (min.min(x), max.max(x))
+        });
+    let mut counts = vec![0; bins.max(1)];
+    if sampled.is_empty() {
+        return Histogram {
+            min: 0.,
+            max: 0.,
+            counts,
+            stride,
+        };
+    }
+
+    let last = counts.len() - 1;
+    let bin_width = (max - min) / counts.len() as f64;
+    for &x in sampled {
+        let bin = if bin_width > 0. {
+            (((x - min) / bin_width) as usize).min(last)
+        } else {
+            0
+        };
+        counts[bin] += 1;
+    }
+
+    Histogram {
+        min,
+        max,
+        counts,
+        stride,
+    }
+}
+
+/// The values actually present in a frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DynamicRange {
+    /// the smallest and largest stored values
+    pub stored: (i32, i32),
+    /// the smallest and largest values in modality units
+    pub rescaled: (f64, f64),
+    /// the number of distinct stored values
+    pub distinct: usize,
+}
+
+/// Find the dynamic range of the stored values of a frame,
+/// leaving out the values within the padding range (inclusive),
+/// and mapping them to modality units with `rescale`.
+///
+/// Returns `None` if there are no values other than padding.
+pub fn dynamic_range_of(
+    stored: &[i32],
+    padding: Option<(i32, i32)>,
+    rescale: impl Fn(i32) -> f64,
+) -> Option<DynamicRange> {
+    let is_padding = |x: i32| padding.map_or(false, |(first, last)| (first..=last).contains(&x));
+    let (min, max) =
+        stored
+            .iter()
+            .copied()
+            .filter(|&x| !is_padding(x))
+            .fold(None, |range, x| match range {
+                Some((min, max)) => Some((i32::min(min, x), i32::max(max, x))),
+                None => Some((x, x)),
+            })?;
+
+    // stored values span at most 16 bits
+    let mut present = vec![false; (max - min) as usize + 1];
+    for &x in stored {
+        if !is_padding(x) {
+            present[(x - min) as usize] = true;
+        }
+    }
+
+    let (mut distinct, mut rescaled) = (0, (f64::INFINITY, f64::NEG_INFINITY));
+    for (i, _) in present.iter().enumerate().filter(|(_, &present)| present) {
+        distinct += 1;
+        // modality LUTs are not always increasing
+        let y = rescale(min + i as i32);
+        rescaled = (rescaled.0.min(y), rescaled.1.max(y));
+    }
+
+    Some(DynamicRange {
+        stored: (min, max),
+        rescaled,
+        distinct,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn bounded_sampling_stride() {
+        assert_eq!(sampling_stride((512, 512)), 1);
+        for size in [(4096, 4096), (3000, 2500), (43_000, 7), (1, 1_000_000)] {
+            let stride = sampling_stride(size);
+            let samples = size.0.div_ceil(stride) as u64 * size.1.div_ceil(stride) as u64;
+            assert!(samples <= MAX_HISTOGRAM_SAMPLES, "{:?}", size);
+            // not much coarser than needed
+            assert!(samples > MAX_HISTOGRAM_SAMPLES / 8, "{:?}", size);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn bin_sampled_values() {
+        let values = [0., 1., 2., 3., 4., 5., 6., 7., 8., 10.];
+        let histogram = histogram_of(&values, (5, 2), 5);
+        assert_eq!(histogram.stride, 1);
+        assert_eq!((histogram.min, histogram.max), (0., 10.));
+        assert_eq!(histogram.counts, vec![2, 2, 2, 2, 2]);
+
+        // all values the same
+        let histogram = histogram_of(&[3.; 4], (2, 2), 4);
+        assert_eq!(histogram.counts, vec![4, 0, 0, 0]);
+    }
+
+    #[wasm_bindgen_test]
+    fn dynamic_range_without_padding() {
+        // CT values with padding outside of the field of view
+        let stored = [-2000, -2000, 0, 24, 24, 1000, 3000, -2000];
+        let rescale = |x: i32| x as f64 - 1024.;
+
+        let range = dynamic_range_of(&stored, Some((-2000, -2000)), rescale).unwrap();
+        assert_eq!(range.stored, (0, 3000));
+        assert_eq!(range.rescaled, (-1024., 1976.));
+        assert_eq!(range.distinct, 4);
+
+        // the padding counts when it is not declared
+        let range = dynamic_range_of(&stored, None, rescale).unwrap();
+        assert_eq!(range.stored, (-2000, 3000));
+        assert_eq!(range.distinct, 5);
+
+        // nothing but padding
+        assert_eq!(dynamic_range_of(&[5, 6], Some((0, 10)), rescale), None);
+    }
+}
@@ -1,15 +1,26 @@
 //! Helper module for working with DICOM and imaging data.
 
 use std::borrow::Cow;
+use std::io::Read;
+use std::rc::Rc;
 
 use dicom::{
+    core::Tag,
+    core::{DataElement, DicomValue, PrimitiveValue, VR},
     dictionary_std::tags,
-    object::{file::ReadPreamble, DefaultDicomObject, OpenFileOptions}, core::DicomValue,
+    object::{file::ReadPreamble, DefaultDicomObject, InMemDicomObject, OpenFileOptions},
 };
+use js_sys::Uint8ClampedArray;
 use snafu::prelude::*;
-use wasm_bindgen::{Clamped, JsValue};
+use wasm_bindgen::{Clamped, JsCast, JsValue};
 use web_sys::ImageData;
 
+use crate::color::{display_color_space_of, new_imagedata, ColorBalance};
+use crate::display::DisplayOptions;
+use crate::overlay::{draw_overlays, OverlayLayer};
+use crate::parametric::{check_float_frame, has_float_pixel_data};
+use crate::rle::{is_rle_lossless, native_frame_of};
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(whatever, display("{}", message))]
@@ -18,6 +29,10 @@ pub enum Error {
         #[snafu(source(from(Box<dyn std::error::Error>, Some)))]
         source: Option<Box<dyn std::error::Error>>,
     },
+    #[snafu(display("This object contains no image pixel data"))]
+    NoPixelData,
+    #[snafu(display("Could not determine the image dimensions: {}", reason))]
+    InvalidDimensions { reason: String },
     #[snafu(display("{:?}", value))]
     Js { value: JsValue },
 }
@@ -26,6 +41,9 @@ impl From<Error> for JsValue {
     fn from(e: Error) -> Self {
         match e {
             Error::Other { message, .. } => JsValue::from_str(&message),
+            e @ (Error::NoPixelData | Error::InvalidDimensions { .. }) => {
+                JsValue::from_str(&e.to_string())
+            }
             Error::Js { value } => value,
         }
     }
@@ -40,361 +58,4891 @@ pub struct WindowLevel {
     pub center: f64,
 }
 
-#[inline]
-pub fn byte_data_to_dicom_obj(byte_data: &[u8]) -> Result<dicom::object::DefaultDicomObject> {
-    OpenFileOptions::new()
-        .read_preamble(ReadPreamble::Always)
-        .from_reader(byte_data)
-        .whatever_context("Failed to read DICOM data")
+/// How much the window level changes per arrow key press
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct KeyWindowLevelSteps {
+    /// the step of each key press
+    pub step: f64,
+    /// the step of each key press while holding Shift
+    pub large_step: f64,
 }
 
-pub fn window_level_of(obj: &DefaultDicomObject) -> Result<Option<WindowLevel>> {
-    let ww = obj
-        .element_opt(tags::WINDOW_WIDTH)
-        .whatever_context("Could not get attribute WindowWidth")?;
-
-    let wc = obj
-        .element_opt(tags::WINDOW_CENTER)
-        .whatever_context("Could not get attribute WindowCenter")?;
-
-    match (ww, wc) {
-        (Some(ww), Some(wc)) => {
-            let ww = ww
-                .to_float64()
-                .whatever_context("Could not read WindowWidth as a number")?;
-            let wc = wc
-                .to_float64()
-                .whatever_context("Could not read WindowCenter as a number")?;
-
-            Ok(Some(WindowLevel {
-                width: ww,
-                center: wc,
-            }))
+impl Default for KeyWindowLevelSteps {
+    fn default() -> Self {
+        KeyWindowLevelSteps {
+            step: 1.,
+            large_step: 10.,
         }
-        _ => Ok(None),
     }
 }
 
-pub fn obj_to_imagedata(obj: &DefaultDicomObject, y_samples: &mut Vec<u8>, lut: &mut Option<Vec<u8>>) -> Result<ImageData> {
-    let photometric_interpretation = obj
-        .element(tags::PHOTOMETRIC_INTERPRETATION)
-        .whatever_context("Could not fetch PhotometricInterpretation")?
-        .to_str()
-        .whatever_context("Could not read PhotometricInterpretation as a string")?;
-
-    let width = obj
-        .element(tags::COLUMNS)
-        .whatever_context("Could not fetch Columns")?
-        .to_int::<u32>()
-        .whatever_context("Columns is not an integer")?;
-    let height = obj
-        .element(tags::ROWS)
-        .whatever_context("Could not fetch Rows")?
-        .to_int::<u32>()
-        .whatever_context("Rows is not an integer")?;
-
-    match photometric_interpretation.as_ref() {
-        "MONOCHROME1" => {
-            if lut.is_none() {
-                gloo_console::debug!("Creating monochrome2 LUT");
-                *lut = Some(simple_pixel_data_lut(obj)?);
-            }
-
-            let lut = lut.as_ref().unwrap().as_ref();
-            convert_monochrome_to_y_values(y_samples, obj, Monochrome::Monochrome1, lut)?;
+impl KeyWindowLevelSteps {
+    /// The change of the window width and center (in this order)
+    /// for an arrow key, with the larger step if asked:
+    /// up/down change the center, left/right change the width.
+    ///
+    /// Returns `None` for any other key.
+    pub fn change_of(self, key: &str, large: bool) -> Option<(f64, f64)> {
+        let step = if large { self.large_step } else { self.step };
+        match key {
+            "ArrowUp" => Some((0., step)),
+            "ArrowDown" => Some((0., -step)),
+            "ArrowRight" => Some((step, 0.)),
+            "ArrowLeft" => Some((-step, 0.)),
+            _ => None,
         }
-        "MONOCHROME2" => {
-            if lut.is_none() {
-                gloo_console::debug!("Creating monochrome2 LUT");
-                *lut = Some(simple_pixel_data_lut(obj)?);
-            }
+    }
+}
 
-            let lut = lut.as_ref().unwrap().as_ref();
-            convert_monochrome_to_y_values(y_samples, obj, Monochrome::Monochrome2, lut)?;
-        }
-        "RGB" => return convert_rgb_to_imagedata(obj, width, height),
-        pi => whatever!("Unsupported photometric interpretation {}, sorry. :(", pi),
+/// An explicit VOI lookup table, as in the VOILUTSequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiLut {
+    /// the (rescaled) value mapped to the first entry
+    pub first_mapped: i32,
+    /// the number of bits of each entry
+    pub bits: u16,
+    /// whether the entries are signed
+    pub signed: bool,
+    pub entries: Vec<i32>,
+}
+
+impl VoiLut {
+    /// map a rescaled value to an output value in [0, 255]
+    pub fn apply(&self, x: f64) -> f64 {
+        let i = (x.round() as i64 - self.first_mapped as i64)
+            .clamp(0, self.entries.len() as i64 - 1) as usize;
+        let max = ((1_u32 << self.bits) - 1) as f64;
+        // signed entries start from the most negative value
+        let offset = if self.signed {
+            (1_u32 << (self.bits - 1)) as f64
+        } else {
+            0.
+        };
+        (self.entries[i] as f64 + offset) * 255. / max
     }
+}
 
-    ImageData::new_with_u8_clamped_array_and_sh(Clamped(y_samples), width, height)
-        .map_err(|value| Error::Js { value })
+/// An explicit presentation lookup table, as in the PresentationLUTSequence,
+/// mapping the output of the VOI stage to display values
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentationLut {
+    /// the number of bits of each entry
+    pub bits: u16,
+    pub entries: Vec<i32>,
 }
 
-/// create a simple LUT which maps a 16-bit image
-pub fn simple_pixel_data_lut(obj: &DefaultDicomObject) -> Result<Vec<u8>> {
-    let window_level = window_level_of(obj)?.whatever_context("The given image does not provide window levels :(")?;
-    simple_pixel_data_lut_with(obj, window_level)
+impl PresentationLut {
+    /// map a VOI output value in [0, 255] to a display value in [0, 255],
+    /// spreading the VOI output over all entries
+    pub fn apply(&self, x: f64) -> f64 {
+        let last = self.entries.len() - 1;
+        let i = (x / LUT_OUTPUT_MAX * last as f64)
+            .round()
+            .clamp(0., last as f64) as usize;
+        let max = ((1_u32 << self.bits) - 1) as f64;
+        (self.entries[i] as f64 * LUT_OUTPUT_MAX / max).clamp(0., LUT_OUTPUT_MAX)
+    }
 }
-/// create a simple LUT which maps a 16-bit image
-/// using the given window level
-pub fn simple_pixel_data_lut_with(
-    obj: &DefaultDicomObject,
-    window_level: WindowLevel,
-) -> Result<Vec<u8>> {
-    let bits_stored = obj
-        .element(tags::BITS_STORED)
-        .whatever_context("Could not fetch BitsStored")?
-        .to_int::<u16>()
-        .whatever_context("BitsStored is not a number")?;
 
-    let mut lut = vec![0; 1 << bits_stored];
+/// The number of entries of a presentation LUT of the user
+/// once spread over the output of the VOI stage
+pub const CUSTOM_LUT_LEN: usize = 256;
 
-    update_pixel_data_lut_with(&mut lut, obj, window_level)?;
+/// The largest number of entries read from a presentation LUT of the user
+const MAX_CUSTOM_LUT_ENTRIES: usize = 65_536;
 
-    Ok(lut)
+/// How the entries of a presentation LUT of the user are stored in its file
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum LutFileFormat {
+    /// written as text and separated by commas, semicolons, or whitespace
+    /// (such as a CSV file of a single row or column)
+    Text,
+    /// stored as one byte each
+    Binary,
 }
 
-/// create a simple LUT which maps a 16-bit image
-/// using the given window level parameters.
-/// 
-/// `lut` must have the correct size for the given object
-/// (`1 << bits_stored`).
-pub fn update_pixel_data_lut_with(
-    lut: &mut [u8],
-    obj: &DefaultDicomObject,
-    window_level: WindowLevel,
-) -> Result<()> {
-    debug_assert!(lut.len() >= 256);
+impl LutFileFormat {
+    /// The format with the given name (`"text"` or `"binary"`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LutFileFormat::Text),
+            "binary" => Some(LutFileFormat::Binary),
+            _ => None,
+        }
+    }
+}
 
-    let rescale_slope = if let Some(elem) = obj
-        .element_opt(tags::RESCALE_SLOPE)
-        .whatever_context("Could not fetch RescaleSlope")?
-    {
-        elem.to_float64()
-            .whatever_context("RescaleSlope is not a number")?
+/// Read a presentation LUT supplied by the user from the bytes of a file
+/// in the given format, to replace that of the object.
+///
+/// The entries are display values from 0 to 255.
+/// Like [`PresentationLut::apply`],
+/// the entries are spread over the output of the VOI stage,
+/// giving one display value for each of its output values.
+pub fn custom_presentation_lut_of(
+    bytes: &[u8],
+    format: LutFileFormat,
+) -> Result<[u8; CUSTOM_LUT_LEN]> {
+    let entries: Vec<i32> = if format == LutFileFormat::Text {
+        std::str::from_utf8(bytes)
+            .whatever_context("The LUT file is not text")?
+            .split(|c: char| c == ',' || c == ';' || c.is_ascii_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|entry| (0..=255).contains(entry))
+                    .with_whatever_context(|| {
+                        format!("LUT entry {:?} is not a value from 0 to 255", value)
+                    })
+            })
+            .collect::<Result<_>>()?
     } else {
-        1.0
+        bytes.iter().map(|&b| b as i32).collect()
     };
+    ensure_whatever!(
+        (2..=MAX_CUSTOM_LUT_ENTRIES).contains(&entries.len()),
+        "The LUT has {} entries, it should have between 2 and {}",
+        entries.len(),
+        MAX_CUSTOM_LUT_ENTRIES
+    );
 
-    let rescale_intercept = if let Some(elem) = obj
-        .element_opt(tags::RESCALE_INTERCEPT)
-        .whatever_context("Could not fetch RescaleSlope")?
-    {
-        elem.to_float64()
-            .whatever_context("RescaleSlope is not a number")?
-    } else {
-        0.0
+    let presentation = PresentationLut { bits: 8, entries };
+    let mut lut = [0; CUSTOM_LUT_LEN];
+    for (x, y) in lut.iter_mut().enumerate() {
+        *y = presentation.apply(x as f64).round() as u8;
+    }
+    Ok(lut)
+}
+
+/// Read the number of entries, the first mapped value,
+/// and the number of bits of each entry from a LUTDescriptor
+pub fn lut_descriptor_of(descriptor: &[i32]) -> Result<(usize, i32, u16)> {
+    let (num_entries, first_mapped, bits) = match *descriptor {
+        [num_entries, first_mapped, bits] => (num_entries, first_mapped, bits),
+        _ => whatever!("LUTDescriptor should have 3 values"),
     };
+    ensure_whatever!(
+        (1..=16).contains(&bits),
+        "Unsupported LUT entry size of {} bits",
+        bits
+    );
+    // 0 means 2^16 entries
+    let num_entries = match num_entries {
+        0 => 65_536,
+        n => n as usize,
+    };
+    Ok((num_entries, first_mapped, bits as u16))
+}
 
-    let voi_lut_function = if let Some(elem) = obj
-        .element_opt(tags::VOILUT_FUNCTION)
-        .whatever_context("Could not fetch VOILUTFunction")?
-    {
-        elem.to_str()
-            .whatever_context("VOILUTFunction is not a string")?
-            .to_string()
+/// Read the entries of an explicit lookup table from its LUTData,
+/// given the number of entries and bits per entry of its LUTDescriptor.
+///
+/// The data may be stored as US or OW, or as raw bytes,
+/// with 8-bit entries either one per 16-bit word or two per word
+/// (the first in the low byte).
+/// Data stored as SS has signed entries.
+/// Returns the entries and whether they are signed.
+pub fn lut_entries_of(
+    value: &DicomValue<InMemDicomObject>,
+    num_entries: usize,
+    bits: u16,
+) -> Result<(Vec<i32>, bool)> {
+    let (words, signed): (Vec<u16>, bool) = match value {
+        DicomValue::Primitive(PrimitiveValue::U8(bytes)) => {
+            if bits <= 8 && bytes.len() == num_entries {
+                // one byte per entry
+                return Ok((bytes.iter().map(|&x| x as i32).collect(), false));
+            }
+            let words = bytes
+                .chunks_exact(2)
+                .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                .collect();
+            (words, false)
+        }
+        DicomValue::Primitive(PrimitiveValue::U16(values)) => (values.to_vec(), false),
+        DicomValue::Primitive(PrimitiveValue::I16(values)) => {
+            (values.iter().map(|&x| x as u16).collect(), true)
+        }
+        value => (
+            value
+                .to_multi_int::<u16>()
+                .whatever_context("LUTData is not a list of numbers")?,
+            false,
+        ),
+    };
+    let words = if bits <= 8 && words.len() * 2 == num_entries {
+        words
+            .iter()
+            .flat_map(|&word| [word & 0xFF, word >> 8])
+            .collect()
     } else {
-        "LINEAR".to_string()
+        words
     };
+    ensure_whatever!(!words.is_empty(), "LUTData is empty");
 
-    if voi_lut_function != "LINEAR"
-        && voi_lut_function != "LINEAR_EXACT"
-        && voi_lut_function != "SIGMOID"
-    {
-        whatever!("Unsupported VOI LUT function {}", &voi_lut_function);
-    }
-
-    for (i, y) in lut.iter_mut().enumerate() {
-        let x = i as f64;
-        // rescale
-        let x = x * rescale_slope + rescale_intercept;
-        // window
-        let x = apply_window_level(x, &voi_lut_function, window_level);
-        *y = x as u8;
-    }
+    let mask = ((1_u32 << bits) - 1) as i32;
+    let entries = words
+        .into_iter()
+        .map(|word| {
+            let entry = word as i32 & mask;
+            if signed && entry >= 1 << (bits - 1) {
+                entry - (1 << bits)
+            } else {
+                entry
+            }
+        })
+        .collect();
+    Ok((entries, signed))
+}
 
-    Ok(())
+/// An explicit modality lookup table, as in the ModalityLUTSequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModalityLut {
+    /// the stored value mapped to the first entry
+    pub first_mapped: i32,
+    pub entries: Vec<i32>,
 }
 
-fn apply_window_level(x: f64, voi_lut_function: &str, window_level: WindowLevel) -> f64 {
-    let WindowLevel {
-        width: ww,
-        center: wc,
-    } = window_level;
+impl ModalityLut {
+    /// Build a modality LUT from its LUTDescriptor and LUTData.
+    ///
+    /// The first mapped value is read as signed
+    /// if the pixel data is signed,
+    /// even when the descriptor was encoded as unsigned.
+    pub fn from_descriptor(descriptor: &[i32], entries: Vec<i32>, signed: bool) -> Result<Self> {
+        let (_, first_mapped, _) = lut_descriptor_of(descriptor)?;
+        let first_mapped = if signed && first_mapped >= 0x8000 {
+            first_mapped - 0x10000
+        } else {
+            first_mapped
+        };
+        ensure_whatever!(!entries.is_empty(), "LUTData is empty");
+        Ok(ModalityLut {
+            first_mapped,
+            entries,
+        })
+    }
 
-    match voi_lut_function {
-        "LINEAR_EXACT" => window_level_linear_exact(x, ww, wc),
-        "SIGMOID" => window_level_sigmoid(x, ww, wc),
-        "LINEAR" => window_level_linear(x, ww, wc),
-        _ => panic!("Unsupported VOI LUT function {}", voi_lut_function),
+    /// map a stored value to an output value,
+    /// clamping values outside of the table to its first or last entry
+    pub fn apply(&self, x: f64) -> f64 {
+        let i = (x.round() as i64 - self.first_mapped as i64)
+            .clamp(0, self.entries.len() as i64 - 1) as usize;
+        self.entries[i] as f64
     }
 }
 
-fn window_level_linear(x: f64, ww: f64, wc: f64) -> f64 {
-    debug_assert!(ww >= 1.);
-
-    // C.11.2.1.2.1
-    let min = wc - (ww - 1.) / 2.;
-    let max = wc - 0.5 + (ww - 1.) / 2.;
+/// The modality transformation from stored values to output units
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modality {
+    /// a linear rescale
+    Rescale { slope: f64, intercept: f64 },
+    /// an explicit lookup table
+    Lut(ModalityLut),
+}
 
-    if x <= min {
-        // if (x <= c - (w-1) / 2), then y = ymin
-        0.
-    } else if x > max {
-        // else if (x > c - 0.5 + (w-1) /2), then y = ymax
-        255.
-    } else {
-        // else y = ((x - (c - 0.5)) / (w-1) + 0.5) * (ymax- ymin) + ymin
-        ((x - (wc - 0.5)) / (ww - 1.) + 0.5) * 255.
+impl Modality {
+    /// map a stored value to an output value
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Modality::Rescale { slope, intercept } => x * slope + intercept,
+            Modality::Lut(lut) => lut.apply(x),
+        }
     }
 }
 
-fn window_level_linear_exact(value: f64, ww: f64, wc: f64) -> f64 {
-    debug_assert!(ww >= 0.);
+/// A value of interest (VOI) transformation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Voi {
+    /// window level parameters, applied with the object's VOI LUT function
+    Window(WindowLevel),
+    /// an explicit lookup table
+    Lut(VoiLut),
+}
 
-    // C.11.2.1.3.2
+/// A named VOI transformation declared in the object
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiPreset {
+    pub name: String,
+    pub voi: Voi,
+}
 
-    let min = wc - ww / 2.;
-    let max = wc + ww / 2.;
+/// Information about lossy compression previously applied to an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossyCompression {
+    /// the compression ratio, if known
+    pub ratio: Option<f64>,
+    /// the compression method, if known
+    pub method: Option<String>,
+}
 
-    if value <= min {
-        // if (x <= c - w/2), then y = ymin
-        0.
-    } else if value > max {
-        // else if (x > c + w/2), then y = ymax
-        255.
-    } else {
-        // else y = ((x - c) / w + 0.5) * (ymax - ymin) + ymin
-        ((value - wc) / ww + 0.5) * 255.
+impl std::fmt::Display for LossyCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("This image was lossy compressed")?;
+        match (&self.method, self.ratio) {
+            (Some(method), Some(ratio)) => write!(f, " ({}, ratio {:.1}:1)", method, ratio)?,
+            (Some(method), None) => write!(f, " ({})", method)?,
+            (None, Some(ratio)) => write!(f, " (ratio {:.1}:1)", ratio)?,
+            (None, None) => {}
+        }
+        f.write_str(" and should not be used for primary diagnosis.")
     }
 }
 
-fn window_level_sigmoid(value: f64, ww: f64, wc: f64) -> f64 {
-    assert!(ww >= 1.);
+/// UIDs of transfer syntaxes which are (or may be) lossy
+const LOSSY_TRANSFER_SYNTAXES: &[&str] = &[
+    // JPEG Baseline (Process 1)
+    "1.2.840.10008.1.2.4.50",
+    // JPEG Extended (Process 2 & 4)
+    "1.2.840.10008.1.2.4.51",
+    // JPEG-LS Lossy (Near-Lossless)
+    "1.2.840.10008.1.2.4.81",
+    // JPEG 2000 Image Compression
+    "1.2.840.10008.1.2.4.91",
+    // JPEG 2000 Part 2 Multi-component Image Compression
+    "1.2.840.10008.1.2.4.93",
+    // MPEG2 and MPEG-4 family
+    "1.2.840.10008.1.2.4.100",
+    "1.2.840.10008.1.2.4.101",
+    "1.2.840.10008.1.2.4.102",
+    "1.2.840.10008.1.2.4.103",
+    "1.2.840.10008.1.2.4.104",
+    "1.2.840.10008.1.2.4.105",
+    "1.2.840.10008.1.2.4.106",
+    // HEVC/H.265
+    "1.2.840.10008.1.2.4.107",
+    "1.2.840.10008.1.2.4.108",
+];
 
-    // C.11.2.1.3.1
+/// Transfer syntaxes in which pixel data is stored natively
+const NATIVE_TRANSFER_SYNTAXES: &[&str] = &[
+    // Implicit VR Little Endian
+    "1.2.840.10008.1.2",
+    // Explicit VR Little Endian
+    "1.2.840.10008.1.2.1",
+    // Deflated Explicit VR Little Endian
+    "1.2.840.10008.1.2.1.99",
+    // Explicit VR Big Endian (retired)
+    "1.2.840.10008.1.2.2",
+];
+
+/// The names of the transfer syntaxes by UID
+const TRANSFER_SYNTAX_NAMES: &[(&str, &str)] = &[
+    ("1.2.840.10008.1.2", "Implicit VR Little Endian"),
+    ("1.2.840.10008.1.2.1", "Explicit VR Little Endian"),
+    (
+        "1.2.840.10008.1.2.1.99",
+        "Deflated Explicit VR Little Endian",
+    ),
+    ("1.2.840.10008.1.2.2", "Explicit VR Big Endian"),
+    ("1.2.840.10008.1.2.4.50", "JPEG Baseline (Process 1)"),
+    ("1.2.840.10008.1.2.4.51", "JPEG Extended (Process 2 & 4)"),
+    (
+        "1.2.840.10008.1.2.4.57",
+        "JPEG Lossless, Non-Hierarchical (Process 14)",
+    ),
+    (
+        "1.2.840.10008.1.2.4.70",
+        "JPEG Lossless, Non-Hierarchical, First-Order Prediction",
+    ),
+    ("1.2.840.10008.1.2.4.80", "JPEG-LS Lossless"),
+    ("1.2.840.10008.1.2.4.81", "JPEG-LS Lossy (Near-Lossless)"),
+    ("1.2.840.10008.1.2.4.90", "JPEG 2000 (Lossless Only)"),
+    ("1.2.840.10008.1.2.4.91", "JPEG 2000"),
+    (
+        "1.2.840.10008.1.2.4.92",
+        "JPEG 2000 Part 2 Multi-component (Lossless Only)",
+    ),
+    ("1.2.840.10008.1.2.4.93", "JPEG 2000 Part 2 Multi-component"),
+    ("1.2.840.10008.1.2.4.100", "MPEG2 Main Profile / Main Level"),
+    ("1.2.840.10008.1.2.4.101", "MPEG2 Main Profile / High Level"),
+    (
+        "1.2.840.10008.1.2.4.102",
+        "MPEG-4 AVC/H.264 High Profile / Level 4.1",
+    ),
+    (
+        "1.2.840.10008.1.2.4.103",
+        "MPEG-4 AVC/H.264 BD-compatible High Profile / Level 4.1",
+    ),
+    (
+        "1.2.840.10008.1.2.4.104",
+        "MPEG-4 AVC/H.264 High Profile / Level 4.2 For 2D Video",
+    ),
+    (
+        "1.2.840.10008.1.2.4.105",
+        "MPEG-4 AVC/H.264 High Profile / Level 4.2 For 3D Video",
+    ),
+    (
+        "1.2.840.10008.1.2.4.106",
+        "MPEG-4 AVC/H.264 Stereo High Profile / Level 4.2",
+    ),
+    (
+        "1.2.840.10008.1.2.4.107",
+        "HEVC/H.265 Main Profile / Level 5.1",
+    ),
+    (
+        "1.2.840.10008.1.2.4.108",
+        "HEVC/H.265 Main 10 Profile / Level 5.1",
+    ),
+    (
+        "1.2.840.10008.1.2.4.201",
+        "High-Throughput JPEG 2000 (Lossless Only)",
+    ),
+    (
+        "1.2.840.10008.1.2.4.202",
+        "High-Throughput JPEG 2000 with RPCL Options (Lossless Only)",
+    ),
+    ("1.2.840.10008.1.2.4.203", "High-Throughput JPEG 2000"),
+    ("1.2.840.10008.1.2.5", "RLE Lossless"),
+];
+
+/// The name of a transfer syntax, if known
+pub fn transfer_syntax_name(uid: &str) -> Option<&'static str> {
+    let uid = uid.trim_end_matches(|c| c == '\0' || c == ' ');
+    TRANSFER_SYNTAX_NAMES
+        .iter()
+        .find(|(known, _)| *known == uid)
+        .map(|(_, name)| *name)
+}
 
-    255. / (1. + f64::exp(-4. * (value - wc) / ww))
+/// The transfer syntax of the object as read from its file meta group:
+/// its UID and its name, or "unknown".
+pub fn transfer_syntax_of(obj: &DefaultDicomObject) -> (String, &'static str) {
+    let uid = obj
+        .meta()
+        .transfer_syntax()
+        .trim_end_matches(|c| c == '\0' || c == ' ')
+        .to_string();
+    let name = transfer_syntax_name(&uid).unwrap_or("unknown");
+    (uid, name)
 }
 
+/// How the pixel data of an object is encoded
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
-pub enum Monochrome {
-    Monochrome1,
-    Monochrome2,
+pub enum PixelDataEncoding {
+    /// as plain sample values
+    Native,
+    /// as a sequence of (usually compressed) fragments
+    Encapsulated,
 }
 
-pub fn convert_monochrome_to_y_values(
-    y_values: &mut Vec<u8>,
-    obj: &DefaultDicomObject,
-    monochrome: Monochrome,
-    lut: &[u8],
-) -> Result<()> {
+/// Reconcile the pixel data encoding declared by the transfer syntax
+/// with the one actually found in PixelData.
+///
+/// The actual encoding always wins,
+/// since that is what the pixel data has to be read as,
+/// but a warning is returned if the two disagree.
+pub fn reconcile_pixel_data_encoding(
+    transfer_syntax: &str,
+    encapsulated: bool,
+) -> (PixelDataEncoding, Option<String>) {
+    let transfer_syntax = transfer_syntax.trim_end_matches(|c| c == '\0' || c == ' ');
+    let declared_native = NATIVE_TRANSFER_SYNTAXES.contains(&transfer_syntax);
 
-    let bits_allocated = obj
-        .element(tags::BITS_ALLOCATED)
-        .whatever_context("Could not fetch BitsAllocated")?
-        .to_int::<u16>()
-        .whatever_context("BitsAllocated is not a number")?;
+    match (declared_native, encapsulated) {
+        (true, false) => (PixelDataEncoding::Native, None),
+        (false, true) => (PixelDataEncoding::Encapsulated, None),
+        (true, true) => (
+            PixelDataEncoding::Encapsulated,
+            Some(format!(
+                "The transfer syntax {} declares native pixel data, but it is encapsulated.",
+                transfer_syntax
+            )),
+        ),
+        (false, false) => (
+            PixelDataEncoding::Native,
+            Some(format!(
+                "The transfer syntax {} declares encapsulated pixel data, but it is native. Reading it as native.",
+                transfer_syntax
+            )),
+        ),
+    }
+}
 
+/// Determine how the pixel data of the object is encoded,
+/// warning if the transfer syntax says otherwise.
+pub fn pixel_data_encoding_of(
+    obj: &DefaultDicomObject,
+) -> Result<(PixelDataEncoding, Option<String>)> {
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    let encapsulated = matches!(pixel_data.value(), DicomValue::PixelSequence { .. });
+    Ok(reconcile_pixel_data_encoding(
+        obj.meta().transfer_syntax(),
+        encapsulated,
+    ))
+}
 
-    match bits_allocated {
-        8 => {
-            let samples = obj
-                .element(tags::PIXEL_DATA)
-                .whatever_context("Could not fetch PixelData")?;
+#[inline]
+pub fn byte_data_to_dicom_obj(byte_data: &[u8]) -> Result<dicom::object::DefaultDicomObject> {
+    read_dicom_obj(byte_data, ReadPreamble::Always, ReadExtent::Full)
+}
 
-            if matches!(samples.value(), DicomValue::PixelSequence { .. }) {
-                whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
-            }
+/// Which parts of a DICOM file are read
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum ReadExtent {
+    /// the whole data set
+    Full,
+    /// the attributes before PixelData,
+    /// so as to keep the metadata of large images
+    /// without holding their pixel data in memory
+    Metadata,
+}
 
-            let samples = samples
-                .to_bytes()
-                .whatever_context("Could not read PixelData as a sequence of 8-bit integers")?;
-    
-            if samples.len() * 4 != y_values.len() {
-                y_values.resize(samples.len() * 4, 255);
-            }
+fn read_dicom_obj(
+    byte_data: &[u8],
+    read_preamble: ReadPreamble,
+    extent: ReadExtent,
+) -> Result<DefaultDicomObject> {
+    let options = OpenFileOptions::new().read_preamble(read_preamble);
+    let options = match extent {
+        ReadExtent::Full => options,
+        ReadExtent::Metadata => options.read_until(tags::PIXEL_DATA),
+    };
+    options
+        .from_reader(byte_data)
+        .whatever_context("Failed to read DICOM data")
+}
 
-            for (y, x) in y_values.chunks_mut(4).zip(samples.iter().copied()) {
-                let x = lut[x as usize];
+/// The magic bytes at the start of gzip data
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-                let x = if monochrome == Monochrome::Monochrome1 {
-                    0xFF - x
-                } else {
-                    x
-                };
+/// Decompress the bytes of a file if they are gzip data
+/// (as in `.dcm.gz` files), or else keep them as they are.
+///
+/// This is unrelated to the Deflated Explicit VR Little Endian
+/// transfer syntax, in which only the data set is compressed.
+pub fn gunzip_if_compressed(byte_data: &[u8]) -> Result<Cow<[u8]>> {
+    gunzip_with_limit(byte_data, MAX_DECOMPRESSED_LEN)
+}
 
-                y[3] = 255;
-                y[0] = x;
-                y[1] = x;
-                y[2] = x;
-            }
-        }
-        16 => {
-            let samples = obj
-                .element(tags::PIXEL_DATA)
-                .whatever_context("Could not fetch PixelData")?;
+/// The largest size of the decompressed data of a gzipped file,
+/// beyond which it is taken as a decompression bomb
+const MAX_DECOMPRESSED_LEN: usize = 1 << 30;
 
-            if matches!(samples.value(), DicomValue::PixelSequence { .. }) {
-                whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
-            }
+fn gunzip_with_limit(byte_data: &[u8], limit: usize) -> Result<Cow<[u8]>> {
+    if !byte_data.starts_with(&GZIP_MAGIC) {
+        return Ok(Cow::Borrowed(byte_data));
+    }
+    let mut decompressed = Vec::new();
+    flate2::read::MultiGzDecoder::new(byte_data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .whatever_context("Failed to decompress gzip data")?;
+    ensure_whatever!(
+        decompressed.len() <= limit,
+        "Gzip data decompresses to more than {} bytes",
+        limit
+    );
+    Ok(Cow::Owned(decompressed))
+}
 
-            let samples: Cow<[u16]> = samples
-                .uint16_slice()
-                .map(Cow::from)
-                .or_else(|_| {
-                    samples
-                        .to_multi_int::<u16>()
-                        .map(Cow::Owned)
-                })
-                .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?;
+/// Read a DICOM file from its bytes,
+/// retrying in a more lenient way if the file is not conformant.
+/// Gzip-compressed files are decompressed first.
+///
+/// Returns the object
+/// and a description of the leniency applied to read it, if any.
+pub fn byte_data_to_dicom_obj_lenient(
+    byte_data: &[u8],
+    extent: ReadExtent,
+) -> Result<(DefaultDicomObject, Option<&'static str>)> {
+    let byte_data = gunzip_if_compressed(byte_data)?;
+    let byte_data = &*byte_data;
+    let e = match read_dicom_obj(byte_data, ReadPreamble::Always, extent) {
+        Ok(obj) => return Ok((obj, None)),
+        Err(e) => e,
+    };
 
-            if samples.len() * 4 != y_values.len() {
-                y_values.resize(samples.len() * 4, 255);
-            }
+    // file without the 128-byte preamble
+    if byte_data.starts_with(b"DICM") {
+        if let Ok(obj) = read_dicom_obj(byte_data, ReadPreamble::Never, extent) {
+            return Ok((obj, Some("the file has no preamble")));
+        }
+    }
 
-            let x_mask = lut.len() - 1;
+    // file meta group with a wrong group length
+    if let Some(fixed) = fix_meta_group_length(byte_data) {
+        if let Ok(obj) = read_dicom_obj(&fixed, ReadPreamble::Always, extent) {
+            return Ok((obj, Some("the file meta group length was corrected")));
+        }
+    }
 
-            for (y, x) in y_values.chunks_mut(4).zip(samples.iter().copied()) {
-                let x = lut[x as usize & x_mask];
+    Err(e)
+}
 
-                let x = if monochrome == Monochrome::Monochrome1 {
-                    0xFF - x
-                } else {
-                    x
-                };
+/// Recalculate the group length of the file meta group
+/// (expected to be in explicit VR little endian after the preamble),
+/// returning a copy of the data with the corrected length
+/// if it differs from the declared one.
+pub fn fix_meta_group_length(byte_data: &[u8]) -> Option<Vec<u8>> {
+    if byte_data.get(128..132)? != b"DICM" {
+        return None;
+    }
+    // (0002,0000) UL 4
+    let group_length_header = [0x02, 0x00, 0x00, 0x00, b'U', b'L', 0x04, 0x00];
+    if byte_data.get(132..140)? != group_length_header {
+        return None;
+    }
+    let declared = u32::from_le_bytes(byte_data.get(140..144)?.try_into().ok()?);
 
-                y[3] = 255;
-                y[0] = x;
-                y[1] = x;
-                y[2] = x;
-            }
-        }
-        _ => {
-            whatever!("Unsupported BitsAllocated {} :(", bits_allocated);
+    // walk the remaining meta group elements
+    let start = 144;
+    let mut offset = start;
+    while byte_data.get(offset..offset + 2)? == [0x02, 0x00] {
+        let vr = byte_data.get(offset + 4..offset + 6)?;
+        let (header_len, value_len) = match vr {
+            b"OB" | b"OD" | b"OF" | b"OL" | b"OV" | b"OW" | b"SQ" | b"SV" | b"UC" | b"UN"
+            | b"UR" | b"UT" | b"UV" => (
+                12,
+                u32::from_le_bytes(byte_data.get(offset + 8..offset + 12)?.try_into().ok()?),
+            ),
+            _ => (
+                8,
+                u16::from_le_bytes(byte_data.get(offset + 6..offset + 8)?.try_into().ok()?) as u32,
+            ),
+        };
+        offset = offset
+            .checked_add(header_len)?
+            .checked_add(value_len as usize)?;
+        if offset > byte_data.len() {
+            return None;
         }
-    };
+    }
 
-    Ok(())
+    let actual = u32::try_from(offset - start).ok()?;
+    if actual == declared {
+        return None;
+    }
+
+    let mut fixed = byte_data.to_vec();
+    fixed[140..144].copy_from_slice(&actual.to_le_bytes());
+    Some(fixed)
 }
 
-pub fn convert_rgb_to_imagedata(
-    obj: &DefaultDicomObject,
-    width: u32,
-    height: u32,
-) -> Result<ImageData> {
-    let samples_per_pixel = obj
-        .element(tags::SAMPLES_PER_PIXEL)
-        .whatever_context("Could not fetch SamplesPerPixel")?
-        .to_int::<u16>()
-        .whatever_context("SamplesPerPixel is not an integer")?;
+/// The modalities whose objects may declare their display parameters
+/// in the DetectorInformationSequence instead of at the top level
+const NESTED_DISPLAY_MODALITIES: [&str; 2] = ["DX", "CR"];
 
-    if samples_per_pixel != 3 {
-        whatever!("Expected 3 samples per pixel, got {}", samples_per_pixel);
+/// The item in which a Digital X-Ray or Computed Radiography object
+/// declares its display parameters if not at the top level:
+/// the first item of its DetectorInformationSequence.
+///
+/// Returns `None` for objects of other modalities.
+fn nested_display_item_of(obj: &InMemDicomObject) -> Result<Option<&InMemDicomObject>> {
+    let modality = obj
+        .element_opt(tags::MODALITY)
+        .whatever_context("Could not fetch Modality")?
+        .and_then(|elem| elem.to_str().ok())
+        .map(|modality| modality.trim().to_string());
+    if !matches!(modality, Some(modality) if NESTED_DISPLAY_MODALITIES.contains(&modality.as_str()))
+    {
+        return Ok(None);
+    }
+    Ok(obj
+        .element_opt(tags::DETECTOR_INFORMATION_SEQUENCE)
+        .whatever_context("Could not fetch DetectorInformationSequence")?
+        .and_then(|elem| elem.items())
+        .and_then(|items| items.first()))
+}
+
+/// The window level declared in the object, looked up in this order:
+///
+/// 1. the first values of WindowWidth and WindowCenter at the top level;
+/// 2. for DX and CR objects,
+///    those in the first item of the DetectorInformationSequence.
+///
+/// Returns `None` if neither declares one.
+pub fn window_level_of(obj: &InMemDicomObject) -> Result<Option<WindowLevel>> {
+    if let Some(window_level) = window_level_in(obj)? {
+        return Ok(Some(window_level));
+    }
+    match nested_display_item_of(obj)? {
+        Some(item) => window_level_in(item),
+        None => Ok(None),
+    }
+}
+
+/// The first values of WindowWidth and WindowCenter in a data set,
+/// without looking into its sequences
+fn window_level_in(obj: &InMemDicomObject) -> Result<Option<WindowLevel>> {
+    let ww = obj
+        .element_opt(tags::WINDOW_WIDTH)
+        .whatever_context("Could not get attribute WindowWidth")?;
+
+    let wc = obj
+        .element_opt(tags::WINDOW_CENTER)
+        .whatever_context("Could not get attribute WindowCenter")?;
+
+    match (ww, wc) {
+        (Some(ww), Some(wc)) => {
+            let ww = first_number_of(ww.value())
+                .whatever_context("Could not read WindowWidth as a number")?;
+            let wc = first_number_of(wc.value())
+                .whatever_context("Could not read WindowCenter as a number")?;
+
+            Ok(Some(WindowLevel {
+                width: ww,
+                center: wc,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The window level of a frame of an enhanced multi-frame object,
+/// from the FrameVOILUTSequence of its per-frame functional groups,
+/// or else of the shared functional groups.
+///
+/// Returns `None` if neither declares one.
+pub fn frame_window_level_of(obj: &InMemDicomObject, frame: u32) -> Result<Option<WindowLevel>> {
+    let window_level_in = |tag: Tag, index: usize| -> Result<Option<WindowLevel>> {
+        let group = match obj
+            .element_opt(tag)
+            .whatever_context("Could not fetch functional groups")?
+            .and_then(|elem| elem.items())
+            .and_then(|items| items.get(index))
+        {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+        match group
+            .element_opt(tags::FRAME_VOILUT_SEQUENCE)
+            .whatever_context("Could not fetch FrameVOILUTSequence")?
+            .and_then(|elem| elem.items())
+            .and_then(|items| items.first())
+        {
+            Some(voi) => window_level_in(voi),
+            None => Ok(None),
+        }
+    };
+
+    match window_level_in(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE, frame as usize)? {
+        Some(window_level) => Ok(Some(window_level)),
+        None => window_level_in(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE, 0),
+    }
+}
+
+/// The largest number of pixels per frame accepted for display
+/// (256 MiB of RGBA image data)
+pub const MAX_PIXELS_PER_FRAME: u64 = 1 << 26;
+
+/// Read the dimensions of the image as (columns, rows),
+/// whichever VR Columns and Rows were stored with.
+///
+/// Fails with [`Error::InvalidDimensions`]
+/// if either is missing, not a positive integer,
+/// or if a frame would have more than [`MAX_PIXELS_PER_FRAME`] pixels.
+pub fn image_dimensions_of(obj: &InMemDicomObject) -> Result<(u32, u32)> {
+    let dimension = |tag: Tag, name: &str| -> Result<u32> {
+        let elem = obj
+            .element_opt(tag)
+            .ok()
+            .flatten()
+            .with_context(|| InvalidDimensionsSnafu {
+                reason: format!("{} is missing", name),
+            })?;
+        let value = first_number_of(elem.value())
+            .filter(|&value| value >= 1. && value <= u32::MAX as f64 && value.fract() == 0.)
+            .with_context(|| InvalidDimensionsSnafu {
+                reason: format!("{} is not a positive integer", name),
+            })?;
+        Ok(value as u32)
+    };
+    let columns = dimension(tags::COLUMNS, "Columns")?;
+    let rows = dimension(tags::ROWS, "Rows")?;
+    ensure!(
+        columns as u64 * rows as u64 <= MAX_PIXELS_PER_FRAME,
+        InvalidDimensionsSnafu {
+            reason: format!("{} by {} pixels is too large to display", columns, rows),
+        }
+    );
+    Ok((columns, rows))
+}
+
+/// Read the first value of a numeric attribute as a number,
+/// whichever numeric VR it was stored with
+/// (DS, IS, US, SS, UL, SL, FL, FD).
+fn first_number_of(value: &DicomValue<InMemDicomObject>) -> Option<f64> {
+    match value {
+        DicomValue::Primitive(PrimitiveValue::Str(_) | PrimitiveValue::Strs(_)) => value
+            .to_str()
+            .ok()?
+            .split('\\')
+            .next()?
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .parse()
+            .ok(),
+        DicomValue::Primitive(
+            PrimitiveValue::U16(_)
+            | PrimitiveValue::I16(_)
+            | PrimitiveValue::U32(_)
+            | PrimitiveValue::I32(_)
+            | PrimitiveValue::U64(_)
+            | PrimitiveValue::I64(_)
+            | PrimitiveValue::F32(_)
+            | PrimitiveValue::F64(_),
+        ) => value.to_multi_float64().ok()?.first().copied(),
+        _ => None,
+    }
+}
+
+/// Collect all VOI presets declared in the object:
+/// the window levels,
+/// labeled by WindowCenterWidthExplanation when available,
+/// followed by the lookup tables of the VOILUTSequence,
+/// labeled by LUTExplanation when available.
+pub fn voi_presets_of(obj: &DefaultDicomObject) -> Result<Vec<VoiPreset>> {
+    let mut presets = window_presets_of(obj)?;
+
+    let luts = voi_luts_of(obj)?;
+    let offset = presets.len();
+    presets.extend(
+        luts.into_iter()
+            .enumerate()
+            .map(|(i, (explanation, lut))| VoiPreset {
+                name: explanation.unwrap_or_else(|| format!("Preset {}", offset + i + 1)),
+                voi: Voi::Lut(lut),
+            }),
+    );
+
+    Ok(presets)
+}
+
+/// Collect the explicit lookup tables in the VOILUTSequence
+/// along with their explanations.
+pub fn voi_luts_of(obj: &DefaultDicomObject) -> Result<Vec<(Option<String>, VoiLut)>> {
+    let items = match obj
+        .element_opt(tags::VOILUT_SEQUENCE)
+        .whatever_context("Could not fetch VOILUTSequence")?
+        .and_then(|elem| elem.items())
+    {
+        Some(items) => items,
+        None => return Ok(Vec::new()),
+    };
+
+    items
+        .iter()
+        .map(|item| {
+            let descriptor = item
+                .element(tags::LUT_DESCRIPTOR)
+                .whatever_context("Could not fetch LUTDescriptor")?
+                .to_multi_int::<i32>()
+                .whatever_context("LUTDescriptor is not a list of numbers")?;
+            let (num_entries, first_mapped, bits) = lut_descriptor_of(&descriptor)?;
+
+            let data = item
+                .element(tags::LUT_DATA)
+                .whatever_context("Could not fetch LUTData")?;
+            let (entries, signed) = lut_entries_of(data.value(), num_entries, bits)?;
+
+            let explanation = item
+                .element_opt(tags::LUT_EXPLANATION)
+                .ok()
+                .flatten()
+                .and_then(|elem| elem.to_str().ok())
+                .map(|explanation| explanation.trim().to_string())
+                .filter(|explanation| !explanation.is_empty());
+
+            Ok((
+                explanation,
+                VoiLut {
+                    first_mapped,
+                    bits,
+                    signed,
+                    entries,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The explicit presentation lookup table
+/// in the first item of the PresentationLUTSequence, if any
+pub fn presentation_lut_of(obj: &InMemDicomObject) -> Result<Option<PresentationLut>> {
+    let item = match obj
+        .element_opt(tags::PRESENTATION_LUT_SEQUENCE)
+        .whatever_context("Could not fetch PresentationLUTSequence")?
+        .and_then(|elem| elem.items())
+        .and_then(|items| items.first())
+    {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+
+    let descriptor = item
+        .element(tags::LUT_DESCRIPTOR)
+        .whatever_context("Could not fetch LUTDescriptor")?
+        .to_multi_int::<i32>()
+        .whatever_context("LUTDescriptor is not a list of numbers")?;
+    let (num_entries, _, bits) = lut_descriptor_of(&descriptor)?;
+    let data = item
+        .element(tags::LUT_DATA)
+        .whatever_context("Could not fetch LUTData")?;
+    let (entries, _) = lut_entries_of(data.value(), num_entries, bits)?;
+    ensure_whatever!(!entries.is_empty(), "The presentation LUT has no entries");
+    Ok(Some(PresentationLut { bits, entries }))
+}
+
+/// Collect all window level presets declared in the object,
+/// labeled by WindowCenterWidthExplanation when available.
+pub fn window_presets_of(obj: &DefaultDicomObject) -> Result<Vec<VoiPreset>> {
+    let ww = obj
+        .element_opt(tags::WINDOW_WIDTH)
+        .whatever_context("Could not get attribute WindowWidth")?;
+
+    let wc = obj
+        .element_opt(tags::WINDOW_CENTER)
+        .whatever_context("Could not get attribute WindowCenter")?;
+
+    let (ww, wc) = match (ww, wc) {
+        (Some(ww), Some(wc)) => (ww, wc),
+        _ => return Ok(Vec::new()),
+    };
+
+    let widths = ww
+        .to_multi_float64()
+        .whatever_context("Could not read WindowWidth as numbers")?;
+    let centers = wc
+        .to_multi_float64()
+        .whatever_context("Could not read WindowCenter as numbers")?;
+
+    let explanations = obj
+        .element_opt(tags::WINDOW_CENTER_WIDTH_EXPLANATION)
+        .whatever_context("Could not get attribute WindowCenterWidthExplanation")?
+        .and_then(|elem| elem.to_multi_str().ok())
+        .map(|names| names.to_vec())
+        .unwrap_or_default();
+
+    Ok(pair_window_presets(&widths, &centers, &explanations))
+}
+
+/// Pair window widths and centers positionally into presets.
+///
+/// If the number of widths and centers differ,
+/// only the first of each is used.
+pub fn pair_window_presets(
+    widths: &[f64],
+    centers: &[f64],
+    explanations: &[String],
+) -> Vec<VoiPreset> {
+    let count = if widths.len() == centers.len() {
+        widths.len()
+    } else {
+        gloo_console::warn!(
+            "WindowWidth has",
+            widths.len(),
+            "values but WindowCenter has",
+            centers.len(),
+            "values, using only the first of each"
+        );
+        usize::min(1, usize::min(widths.len(), centers.len()))
+    };
+
+    widths
+        .iter()
+        .zip(centers)
+        .take(count)
+        .enumerate()
+        .map(|(i, (&width, &center))| VoiPreset {
+            name: explanations
+                .get(i)
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("Preset {}", i + 1)),
+            voi: Voi::Window(WindowLevel { width, center }),
+        })
+        .collect()
+}
+
+/// Check whether the given object has undergone lossy compression,
+/// either according to the attribute LossyImageCompression
+/// or to the transfer syntax of the file.
+pub fn lossy_compression_of(obj: &DefaultDicomObject) -> Result<Option<LossyCompression>> {
+    let lossy_attribute = if let Some(elem) = obj
+        .element_opt(tags::LOSSY_IMAGE_COMPRESSION)
+        .whatever_context("Could not fetch LossyImageCompression")?
+    {
+        elem.to_str()
+            .whatever_context("LossyImageCompression is not a string")?
+            .trim()
+            == "01"
+    } else {
+        false
+    };
+
+    let lossy_ts = LOSSY_TRANSFER_SYNTAXES.contains(&obj.meta().transfer_syntax());
+
+    if !lossy_attribute && !lossy_ts {
+        return Ok(None);
+    }
+
+    let ratio = obj
+        .element_opt(tags::LOSSY_IMAGE_COMPRESSION_RATIO)
+        .whatever_context("Could not fetch LossyImageCompressionRatio")?
+        .and_then(|elem| elem.to_float64().ok());
+
+    let method = obj
+        .element_opt(tags::LOSSY_IMAGE_COMPRESSION_METHOD)
+        .whatever_context("Could not fetch LossyImageCompressionMethod")?
+        .and_then(|elem| elem.to_str().ok())
+        .map(|method| method.trim().to_string())
+        .filter(|method| !method.is_empty());
+
+    Ok(Some(LossyCompression { ratio, method }))
+}
+
+/// Warnings that the pixel data itself may identify the patient,
+/// according to BurnedInAnnotation and RecognizableVisualFeatures,
+/// which de-identification of the attributes alone does not remove.
+pub fn identifiable_pixels_warnings_of(obj: &InMemDicomObject) -> Vec<&'static str> {
+    let is_yes = |tag| {
+        obj.element_opt(tag)
+            .ok()
+            .flatten()
+            .and_then(|elem| elem.to_str().ok())
+            .map_or(false, |value| value.trim().eq_ignore_ascii_case("YES"))
+    };
+    let mut warnings = Vec::new();
+    if is_yes(tags::BURNED_IN_ANNOTATION) {
+        warnings.push(
+            "This image has annotations burned into its pixel data, \
+             which may identify the patient.",
+        );
+    }
+    if is_yes(tags::RECOGNIZABLE_VISUAL_FEATURES) {
+        warnings.push("This image shows features by which the patient may be recognized.");
+    }
+    warnings
+}
+
+/// How an image is meant to be viewed, as declared by the object
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewingIntent {
+    /// `PresentationIntentType`:
+    /// `FOR PRESENTATION` or `FOR PROCESSING`
+    pub presentation_intent: Option<String>,
+    /// `RecommendedViewingMode`:
+    /// `NAT` (native) or `SUB` (as a subtraction from a mask)
+    pub recommended_viewing_mode: Option<String>,
+}
+
+impl ViewingIntent {
+    /// Whether the image is meant for processing rather than presentation,
+    /// so that it has no meaningful presentation parameters
+    pub fn for_processing(&self) -> bool {
+        self.presentation_intent.as_deref() == Some("FOR PROCESSING")
+    }
+
+    /// Warnings about how the image is shown compared to its intent
+    pub fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.for_processing() {
+            warnings.push(
+                "This image is meant for processing, not presentation. \
+                 It is shown linearly over its full range of values.",
+            );
+        }
+        if self.recommended_viewing_mode.as_deref() == Some("SUB") {
+            warnings.push(
+                "This image is meant to be viewed subtracted from a mask, \
+                 which is not supported. It is shown as is.",
+            );
+        }
+        warnings
+    }
+}
+
+/// The text value of an attribute without its padding
+/// (surrounding spaces and trailing NULs),
+/// or `None` if it is absent, not text, or empty
+pub fn string_of(obj: &InMemDicomObject, tag: Tag) -> Option<String> {
+    obj.element_opt(tag)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.to_str().ok())
+        .map(|value| {
+            value
+                .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                .to_string()
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// Read how the image is meant to be viewed
+pub fn viewing_intent_of(obj: &InMemDicomObject) -> ViewingIntent {
+    ViewingIntent {
+        presentation_intent: string_of(obj, tags::PRESENTATION_INTENT_TYPE),
+        recommended_viewing_mode: string_of(obj, tags::RECOMMENDED_VIEWING_MODE),
+    }
+}
+
+/// The layout of the pixel data of an object
+/// and how it is converted for display
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PixelFormat {
+    conversion: Conversion,
+    size: (u32, u32),
+    bits_allocated: u16,
+    planar_configuration: Option<u16>,
+}
+
+/// Read the pixel data attributes of the object
+/// and resolve how it is converted for display,
+/// failing if it cannot be shown.
+fn pixel_format_of(
+    obj: &DefaultDicomObject,
+    force_photometric: Option<&'static str>,
+) -> Result<PixelFormat> {
+    check_pixel_data(obj)?;
+
+    let (encoding, _) = pixel_data_encoding_of(obj)?;
+    if encoding == PixelDataEncoding::Encapsulated {
+        let (uid, name) = transfer_syntax_of(obj);
+        whatever!(
+            "Encapsulated pixel data encoding ({}, {}) is not supported at the moment, sorry. :(",
+            name,
+            uid
+        );
+    }
+
+    let photometric_interpretation = obj
+        .element(tags::PHOTOMETRIC_INTERPRETATION)
+        .whatever_context("Could not fetch PhotometricInterpretation")?
+        .to_str()
+        .whatever_context("Could not read PhotometricInterpretation as a string")?;
+
+    let (width, height) = image_dimensions_of(obj)?;
+
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+
+    let samples_per_pixel = if let Some(elem) = obj
+        .element_opt(tags::SAMPLES_PER_PIXEL)
+        .whatever_context("Could not fetch SamplesPerPixel")?
+    {
+        elem.to_int::<u16>()
+            .whatever_context("SamplesPerPixel is not an integer")?
+    } else {
+        1
+    };
+
+    let planar_configuration = obj
+        .element_opt(tags::PLANAR_CONFIGURATION)
+        .whatever_context("Could not fetch PlanarConfiguration")?
+        .map(|elem| elem.to_int::<u16>())
+        .transpose()
+        .whatever_context("PlanarConfiguration is not an integer")?;
+
+    // convert from what the decoded pixel data is in,
+    // which is not always what was stored
+    let photometric_interpretation = match force_photometric {
+        Some(forced) => forced.to_string(),
+        None => decoded_photometric_interpretation(
+            &normalize_photometric_interpretation(&photometric_interpretation),
+            obj.meta().transfer_syntax(),
+        ),
+    };
+
+    let conversion = resolve_conversion(
+        &photometric_interpretation,
+        bits_allocated,
+        samples_per_pixel,
+        planar_configuration.unwrap_or(0),
+    )?;
+
+    Ok(PixelFormat {
+        conversion,
+        size: (width, height),
+        bits_allocated,
+        planar_configuration,
+    })
+}
+
+/// Check that a frame of the object can be shown
+/// without converting any of its samples:
+/// its pixel data must be supported
+/// and long enough to hold the frame.
+///
+/// This lets a new object be rejected
+/// before it replaces the one on display.
+pub fn check_displayable(obj: &DefaultDicomObject, frame: u32) -> Result<()> {
+    if has_float_pixel_data(obj) {
+        return check_float_frame(obj, frame);
+    }
+    if is_rle_lossless(obj) {
+        return check_displayable(&native_frame_of(obj, frame)?, 0);
+    }
+    let format = pixel_format_of(obj, None)?;
+    let frame_len = frame_len_of(obj)?;
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    match pixel_data.value() {
+        DicomValue::Primitive(value) => check_pixel_data_len(
+            value.calculate_byte_len(),
+            frame,
+            frame_len,
+            format.bits_allocated,
+        ),
+        // mismatched encodings are reconciled when decoding
+        _ => Ok(()),
+    }
+}
+
+/// Check that pixel data of the given length in bytes
+/// holds all samples up to the end of the given frame.
+fn check_pixel_data_len(
+    byte_len: usize,
+    frame: u32,
+    frame_len: usize,
+    bits_allocated: u16,
+) -> Result<()> {
+    let needed = (frame as usize + 1) * frame_len * (bits_allocated as usize / 8);
+    ensure_whatever!(
+        byte_len >= needed,
+        "Pixel data is too short ({} bytes) for frame #{} ({} bytes needed)",
+        byte_len,
+        frame,
+        needed
+    );
+    Ok(())
+}
+
+/// Find a frame layout (columns, rows, frames) which fits
+/// the number of pixels in the pixel data,
+/// for when it does not match the declared Columns and Rows.
+///
+/// Returns `None` if the declared layout fits,
+/// leaving less than a row of trailing data.
+/// A multi-frame image which is cut short
+/// keeps its declared Columns and Rows
+/// and is lowered to the whole frames present.
+/// Otherwise, the pixels of each frame are laid out
+/// with the declared number of columns, the declared number of rows,
+/// or in a square, whichever divides them evenly first.
+fn infer_layout(
+    pixels: usize,
+    (columns, rows): (u32, u32),
+    frames: u32,
+) -> Result<Option<(u32, u32, u32)>> {
+    let frame_pixels = columns as usize * rows as usize;
+    let expected = frame_pixels * frames as usize;
+    if pixels >= expected && pixels - expected < columns as usize {
+        return Ok(None);
+    }
+    if frames > 1 && pixels < expected && pixels >= frame_pixels {
+        return Ok(Some((columns, rows, (pixels / frame_pixels) as u32)));
+    }
+
+    let per_frame = pixels / frames as usize;
+    let square = (per_frame as f64).sqrt().round() as usize;
+    let layout = if pixels % frames as usize != 0 || per_frame == 0 {
+        None
+    } else if per_frame % columns as usize == 0 {
+        Some((columns as usize, per_frame / columns as usize))
+    } else if per_frame % rows as usize == 0 {
+        Some((per_frame / rows as usize, rows as usize))
+    } else if square * square == per_frame {
+        Some((square, square))
+    } else {
+        None
+    };
+
+    match layout {
+        // Columns and Rows are unsigned shorts
+        Some((columns, rows))
+            if columns <= u16::MAX as usize
+                && rows <= u16::MAX as usize
+                && columns as u64 * rows as u64 <= MAX_PIXELS_PER_FRAME =>
+        {
+            Ok(Some((columns as u32, rows as u32, frames)))
+        }
+        _ => InvalidDimensionsSnafu {
+            reason: format!(
+                "pixel data holds {} pixels, but {} frame(s) of {} by {} pixels need {}",
+                pixels, frames, columns, rows, expected
+            ),
+        }
+        .fail(),
+    }
+}
+
+/// Check the declared Columns, Rows, and NumberOfFrames of an object
+/// against the length of its native pixel data,
+/// inferring a layout (columns, rows, frames) which fits
+/// if they do not match.
+///
+/// Returns `None` if the declared layout fits,
+/// the pixel data is encapsulated,
+/// or there is no integer pixel data.
+fn inferred_layout_of(obj: &InMemDicomObject) -> Result<Option<(u32, u32, u32)>> {
+    let byte_len = match obj
+        .element_opt(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?
+        .map(|elem| elem.value())
+    {
+        Some(DicomValue::Primitive(value)) => value.calculate_byte_len(),
+        _ => return Ok(None),
+    };
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+    if !matches!(bits_allocated, 8 | 16) {
+        // reported when resolving the pixel format
+        return Ok(None);
+    }
+
+    let dimensions = image_dimensions_of(obj)?;
+    let frames = number_of_frames_of(obj)?;
+    let samples_per_pixel =
+        (frame_len_of(obj)? / (dimensions.0 as usize * dimensions.1 as usize)).max(1);
+    let pixels = byte_len / (bits_allocated as usize / 8) / samples_per_pixel;
+    let expected = dimensions.0 as usize * dimensions.1 as usize * frames as usize;
+
+    let layout = infer_layout(pixels, dimensions, frames);
+    if !matches!(layout, Ok(None)) {
+        gloo_console::warn!(
+            "Declared layout of",
+            frames,
+            "frame(s) of",
+            dimensions.0,
+            "x",
+            dimensions.1,
+            "needs",
+            expected,
+            "pixels, but pixel data holds",
+            pixels
+        );
+    }
+    layout
+}
+
+/// Reconcile the declared Columns, Rows, and NumberOfFrames of an object
+/// with the length of its pixel data,
+/// so that frames are not read with the wrong geometry.
+///
+/// If they do not match but another layout fits,
+/// the declared values are replaced
+/// and a warning for the user is returned.
+/// The object is only copied if it is shared and needs fixing.
+/// Fails if no plausible layout fits the pixel data.
+pub fn reconcile_layout(obj: &mut Rc<DefaultDicomObject>) -> Result<Option<String>> {
+    let (columns, rows, frames) = match inferred_layout_of(obj)? {
+        Some(layout) => layout,
+        None => return Ok(None),
+    };
+    let (declared_columns, declared_rows) = image_dimensions_of(obj)?;
+    let declared_frames = number_of_frames_of(obj)?;
+    let obj = Rc::make_mut(obj);
+    if frames != declared_frames {
+        obj.put(DataElement::new(
+            tags::NUMBER_OF_FRAMES,
+            VR::IS,
+            PrimitiveValue::from(frames.to_string()),
+        ));
+        return Ok(Some(format!(
+            "The pixel data is cut short, \
+            so only {} of the {} declared frames are shown.",
+            frames, declared_frames
+        )));
+    }
+    for (tag, value) in [(tags::COLUMNS, columns), (tags::ROWS, rows)] {
+        obj.put(DataElement::new(
+            tag,
+            VR::US,
+            PrimitiveValue::from(value as u16),
+        ));
+    }
+    Ok(Some(format!(
+        "The declared image size ({} by {} pixels) does not match the pixel data, \
+        so it is shown as {} by {} pixels.",
+        declared_columns, declared_rows, columns, rows
+    )))
+}
+
+/// Check that a bit depth can be read:
+/// 8 or 16 bits allocated, with up to as many bits stored.
+fn check_bit_depth(bits_allocated: u16, bits_stored: u16) -> Result<()> {
+    ensure_whatever!(
+        matches!(bits_allocated, 8 | 16),
+        "BitsAllocated should be 8 or 16, not {}",
+        bits_allocated
+    );
+    ensure_whatever!(
+        (1..=bits_allocated).contains(&bits_stored),
+        "BitsStored should be between 1 and {}, not {}",
+        bits_allocated,
+        bits_stored
+    );
+    Ok(())
+}
+
+/// Replace BitsAllocated and BitsStored of an object
+/// whose header does not match its pixel data,
+/// with HighBit right below the stored bits.
+///
+/// Fails without changing anything
+/// if the pixel data is too short for all frames at that depth.
+pub fn override_bit_depth(
+    obj: &mut DefaultDicomObject,
+    bits_allocated: u16,
+    bits_stored: u16,
+) -> Result<()> {
+    check_bit_depth(bits_allocated, bits_stored)?;
+    let frames = number_of_frames_of(obj)?;
+    let frame_len = frame_len_of(obj)?;
+    let byte_len = match obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?
+        .value()
+    {
+        DicomValue::Primitive(value) => value.calculate_byte_len(),
+        _ => whatever!("Only native pixel data can be read with another bit depth"),
+    };
+    check_pixel_data_len(byte_len, frames - 1, frame_len, bits_allocated)?;
+
+    let repacked = match obj.element(tags::PIXEL_DATA).map(|elem| elem.value()) {
+        Ok(DicomValue::Primitive(value)) => repack_pixel_data(value, bits_allocated),
+        _ => None,
+    };
+    if let Some((vr, value)) = repacked {
+        obj.put(DataElement::new(tags::PIXEL_DATA, vr, value));
+    }
+    for (tag, value) in [
+        (tags::BITS_ALLOCATED, bits_allocated),
+        (tags::BITS_STORED, bits_stored),
+        (tags::HIGH_BIT, bits_stored - 1),
+    ] {
+        obj.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+    }
+    Ok(())
+}
+
+/// Native pixel data held in bytes or words
+/// repacked into samples of the given bits allocated, little endian,
+/// if it is held the other way.
+fn repack_pixel_data(value: &PrimitiveValue, bits_allocated: u16) -> Option<(VR, PrimitiveValue)> {
+    match (value, bits_allocated) {
+        (PrimitiveValue::U8(bytes), 16) => Some((
+            VR::OW,
+            PrimitiveValue::U16(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                    .collect(),
+            ),
+        )),
+        (PrimitiveValue::U16(words), 8) => Some((
+            VR::OB,
+            PrimitiveValue::U8(words.iter().flat_map(|w| w.to_le_bytes()).collect()),
+        )),
+        _ => None,
+    }
+}
+
+/// The bits allocated (8 or 16) which fit the length of native pixel data
+/// when the declared BitsAllocated does not but the other one does,
+/// as in files which store 8-bit samples but declare 16 bits allocated,
+/// or the other way around.
+///
+/// Returns `None` if the declared bits allocated fit,
+/// if neither fits (as with a wrong image size),
+/// or if the pixel data is encapsulated.
+fn inferred_bits_allocated_of(obj: &InMemDicomObject) -> Result<Option<u16>> {
+    let byte_len = match obj
+        .element_opt(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?
+        .map(|elem| elem.value())
+    {
+        Some(DicomValue::Primitive(value)) => value.calculate_byte_len(),
+        _ => return Ok(None),
+    };
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+    let other = match bits_allocated {
+        8 => 16,
+        16 => 8,
+        // reported when resolving the pixel format
+        _ => return Ok(None),
+    };
+
+    let samples = frame_len_of(obj)? * number_of_frames_of(obj)? as usize;
+    // odd lengths are padded to an even length
+    let fits = |bits: u16| {
+        let len = samples * bits as usize / 8;
+        byte_len == len + len % 2
+    };
+    if fits(bits_allocated) || !fits(other) {
+        return Ok(None);
+    }
+    Ok(Some(other))
+}
+
+/// Reconcile the declared BitsAllocated of an object
+/// with the length of its native pixel data,
+/// so that 8-bit samples are not read in pairs as 16-bit samples
+/// or 16-bit samples split into bytes.
+///
+/// If only the other bit depth fits,
+/// the object is read with it (as with [`override_bit_depth`],
+/// with all 16 bits stored when going up from 8 bits)
+/// and a warning for the user is returned.
+/// The object is only copied if it is shared and needs fixing.
+pub fn reconcile_bit_depth(obj: &mut Rc<DefaultDicomObject>) -> Result<Option<String>> {
+    let bits_allocated = match inferred_bits_allocated_of(obj)? {
+        Some(bits_allocated) => bits_allocated,
+        None => return Ok(None),
+    };
+    let declared = if bits_allocated == 8 { 16 } else { 8 };
+    let bits_stored = if bits_allocated > declared {
+        bits_allocated
+    } else {
+        obj.element_opt(tags::BITS_STORED)
+            .ok()
+            .flatten()
+            .and_then(|elem| elem.to_int::<u16>().ok())
+            .map_or(bits_allocated, |bits_stored| {
+                bits_stored.min(bits_allocated)
+            })
+    };
+    gloo_console::warn!(
+        "Pixel data length fits",
+        bits_allocated,
+        "bits allocated, not the declared",
+        declared
+    );
+    override_bit_depth(Rc::make_mut(obj), bits_allocated, bits_stored)?;
+    Ok(Some(format!(
+        "The image declares {} bits allocated, but its pixel data holds {}-bit samples, \
+        so it is read with {} bits allocated.",
+        declared, bits_allocated, bits_allocated
+    )))
+}
+
+pub fn obj_to_imagedata(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    y_samples: &mut Vec<u8>,
+    lut: &mut Option<Vec<u8>>,
+    imagedata: &mut Option<ImageData>,
+    overlays: &[OverlayLayer],
+    options: ConversionOptions,
+) -> Result<ImageData> {
+    let PixelFormat {
+        conversion,
+        size: (width, height),
+        bits_allocated,
+        planar_configuration,
+    } = pixel_format_of(obj, options.force_photometric)?;
+
+    match conversion {
+        Conversion::Monochrome(monochrome) => {
+            if lut.is_none() {
+                gloo_console::debug!("Creating monochrome LUT");
+                *lut = Some(simple_pixel_data_lut(obj, options.lut)?);
+            }
+
+            let lut = lut.as_ref().unwrap().as_ref();
+            convert_monochrome_to_y_values(
+                y_samples,
+                obj,
+                frame,
+                monochrome,
+                lut,
+                options.lut.out_of_range,
+            )?;
+            options.display.apply(y_samples);
+            draw_overlays(
+                y_samples,
+                obj,
+                frame,
+                (width, height),
+                Region::full(width, height),
+                overlays,
+            )?;
+        }
+        Conversion::Color {
+            color_space,
+            bits_allocated,
+            planar,
+        } => {
+            // guess the configuration from the samples if not declared
+            let planar = planar_configuration.map(|_| planar);
+            return convert_color_to_imagedata(
+                obj,
+                frame,
+                (width, height),
+                color_space,
+                bits_allocated,
+                planar,
+                options,
+            );
+        }
+        Conversion::PaletteColor => {
+            return convert_palette_color_to_imagedata(obj, frame, width, height, bits_allocated)
+        }
+    }
+
+    write_to_imagedata(imagedata, y_samples, width, height)
+}
+
+/// The first column of an image of the given width
+/// shown on the right of a split at the given position,
+/// as a fraction of the width from the left
+pub fn split_column(position: f64, width: u32) -> u32 {
+    (position.clamp(0., 1.) * width as f64).round() as u32
+}
+
+/// Replace the pixels of an RGBA image from the given column onwards
+/// with those of another image of the same width.
+pub fn split_rgba(left: &mut [u8], right: &[u8], width: u32, column: u32) {
+    let row_len = width as usize * 4;
+    let start = column.min(width) as usize * 4;
+    for (l, r) in left
+        .chunks_exact_mut(row_len)
+        .zip(right.chunks_exact(row_len))
+    {
+        l[start..].copy_from_slice(&r[start..]);
+    }
+}
+
+/// Show a monochrome frame under another VOI
+/// to the right of the split at the given position
+/// (a fraction of the width from the left),
+/// so that two windows can be compared side by side.
+///
+/// The LUT of the VOI is built if there is none yet,
+/// and left in place to be reused.
+pub fn split_imagedata(
+    imagedata: ImageData,
+    obj: &DefaultDicomObject,
+    frame: u32,
+    voi: &Voi,
+    lut: &mut Option<Vec<u8>>,
+    position: f64,
+    overlays: &[OverlayLayer],
+    options: ConversionOptions,
+) -> Result<ImageData> {
+    if lut.is_none() {
+        *lut = Some(simple_pixel_data_lut_with_voi(obj, voi, options.lut)?);
+    }
+    let other = obj_to_imagedata(
+        obj,
+        frame,
+        &mut Vec::new(),
+        lut,
+        &mut None,
+        overlays,
+        options,
+    )?;
+    let (width, height) = (imagedata.width(), imagedata.height());
+    ensure_whatever!(
+        other.width() == width && other.height() == height,
+        "Both sides of the split should have the same size"
+    );
+    let mut rgba = imagedata.data().0;
+    split_rgba(
+        &mut rgba,
+        &other.data().0,
+        width,
+        split_column(position, width),
+    );
+    write_to_imagedata(&mut Some(imagedata), &rgba, width, height)
+}
+
+/// Check that the object has pixel data to show,
+/// failing with [`Error::NoPixelData`] if it is absent or empty
+/// (such as in metadata-only objects).
+pub fn check_pixel_data(obj: &InMemDicomObject) -> Result<()> {
+    let pixel_data = obj
+        .element_opt(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    match pixel_data.map(|elem| elem.value()) {
+        None => NoPixelDataSnafu.fail(),
+        Some(DicomValue::PixelSequence { fragments, .. }) if fragments.is_empty() => {
+            NoPixelDataSnafu.fail()
+        }
+        Some(DicomValue::Primitive(value)) if value.calculate_byte_len() == 0 => {
+            NoPixelDataSnafu.fail()
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Write RGBA samples to a reusable image data object,
+/// writing into its backing array in place
+/// and only creating a new one when the dimensions change.
+pub fn write_to_imagedata(
+    imagedata: &mut Option<ImageData>,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<ImageData> {
+    ensure_whatever!(
+        rgba.len() == width as usize * height as usize * 4,
+        "Expected {} RGBA samples for a {}x{} image, got {}",
+        width as usize * height as usize * 4,
+        width,
+        height,
+        rgba.len()
+    );
+
+    match imagedata {
+        Some(imagedata) if imagedata.width() == width && imagedata.height() == height => {
+            let data: Uint8ClampedArray = js_sys::Reflect::get(imagedata, &"data".into())
+                .map_err(|value| Error::Js { value })?
+                .unchecked_into();
+            data.copy_from(rgba);
+            Ok(imagedata.clone())
+        }
+        _ => {
+            let new_imagedata =
+                ImageData::new_with_u8_clamped_array_and_sh(Clamped(rgba), width, height)
+                    .map_err(|value| Error::Js { value })?;
+            *imagedata = Some(new_imagedata.clone());
+            Ok(new_imagedata)
+        }
+    }
+}
+
+/// How the pixels of an image are combined when it is made smaller
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum Downsampling {
+    /// pick the nearest source pixel (fast, but aliases fine detail)
+    #[default]
+    Nearest,
+    /// average all source pixels covered by each output pixel
+    Area,
+}
+
+/// Create a smaller version of an RGBA image
+/// so that it fits in a square of `max_size` pixels,
+/// combining source pixels as chosen.
+///
+/// Returns the thumbnail's RGBA samples, width, and height.
+/// Fails if there are fewer samples than pixels in the image.
+pub fn thumbnail_of(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    max_size: u32,
+    downsampling: Downsampling,
+) -> Result<(Vec<u8>, u32, u32)> {
+    ensure_whatever!(
+        rgba.len() >= width as usize * height as usize * 4,
+        "{} RGBA samples are too few for {} by {} pixels",
+        rgba.len(),
+        width,
+        height
+    );
+    let scale = f64::min(
+        max_size as f64 / width as f64,
+        max_size as f64 / height as f64,
+    )
+    .min(1.);
+    let thumb_w = ((width as f64 * scale) as u32).max(1);
+    let thumb_h = ((height as f64 * scale) as u32).max(1);
+
+    // the source pixels [start, end) covered by an output row or column
+    let span = |o: u32, size: u32, thumb_size: u32| -> (usize, usize) {
+        let start = (o as u64 * size as u64 / thumb_size as u64) as usize;
+        let end = ((o as u64 + 1) * size as u64 / thumb_size as u64) as usize;
+        (start, end.max(start + 1))
+    };
+
+    let w = width as usize;
+    let mut out = Vec::with_capacity((thumb_w * thumb_h * 4) as usize);
+    for y in 0..thumb_h {
+        let (y0, y1) = span(y, height, thumb_h);
+        for x in 0..thumb_w {
+            let (x0, x1) = span(x, width, thumb_w);
+            match downsampling {
+                Downsampling::Nearest => {
+                    let i = (y0 * w + x0) * 4;
+                    out.extend_from_slice(&rgba[i..i + 4]);
+                }
+                Downsampling::Area => {
+                    let mut sums = [0_u64; 4];
+                    for src_y in y0..y1 {
+                        for src_x in x0..x1 {
+                            let i = (src_y * w + src_x) * 4;
+                            for (sum, &v) in sums.iter_mut().zip(&rgba[i..i + 4]) {
+                                *sum += v as u64;
+                            }
+                        }
+                    }
+                    let count = ((y1 - y0) * (x1 - x0)) as u64;
+                    out.extend(sums.iter().map(|sum| ((sum + count / 2) / count) as u8));
+                }
+            }
+        }
+    }
+
+    Ok((out, thumb_w, thumb_h))
+}
+
+/// Enlarge an RGBA image by integer factors along each axis,
+/// interpolating linearly between source pixels.
+///
+/// A factor of 1 leaves that axis untouched,
+/// so this can be used to smooth a single axis.
+///
+/// Returns the resampled RGBA samples, width, and height.
+pub fn resample_linear(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    factor_x: u32,
+    factor_y: u32,
+) -> (Vec<u8>, u32, u32) {
+    let out_w = width * factor_x;
+    let out_h = height * factor_y;
+
+    // source position and interpolation weight
+    // of each output column or row
+    let positions = |size: u32, factor: u32| -> Vec<(usize, usize, f64)> {
+        (0..size * factor)
+            .map(|o| {
+                let p = ((o as f64 + 0.5) / factor as f64 - 0.5).clamp(0., (size - 1) as f64);
+                let p0 = p.floor();
+                let p1 = (p0 as u32 + 1).min(size - 1);
+                (p0 as usize, p1 as usize, p - p0)
+            })
+            .collect()
+    };
+    let xs = positions(width, factor_x);
+    let ys = positions(height, factor_y);
+
+    let w = width as usize;
+    let mut out = Vec::with_capacity(out_w as usize * out_h as usize * 4);
+    for &(y0, y1, ty) in &ys {
+        for &(x0, x1, tx) in &xs {
+            for c in 0..4 {
+                let at = |x: usize, y: usize| rgba[(y * w + x) * 4 + c] as f64;
+                let top = at(x0, y0) * (1. - tx) + at(x1, y0) * tx;
+                let bottom = at(x0, y1) * (1. - tx) + at(x1, y1) * tx;
+                out.push((top * (1. - ty) + bottom * ty).round() as u8);
+            }
+        }
+    }
+
+    (out, out_w, out_h)
+}
+
+/// Render a thumbnail of a monochrome image under each of the given presets.
+/// The windowed values are downsampled as chosen.
+///
+/// Returns no previews if the image is not monochrome.
+pub fn preset_previews_of(
+    obj: &DefaultDicomObject,
+    presets: &[VoiPreset],
+    max_size: u32,
+    downsampling: Downsampling,
+    lut_options: LutOptions,
+) -> Result<Vec<ImageData>> {
+    let photometric_interpretation = obj
+        .element(tags::PHOTOMETRIC_INTERPRETATION)
+        .whatever_context("Could not fetch PhotometricInterpretation")?
+        .to_str()
+        .whatever_context("Could not read PhotometricInterpretation as a string")?;
+
+    let monochrome = match monochrome_of(&photometric_interpretation) {
+        Some(monochrome) => monochrome,
+        None => return Ok(Vec::new()),
+    };
+
+    let (width, height) = image_dimensions_of(obj)?;
+
+    let mut y_samples = Vec::new();
+    presets
+        .iter()
+        .map(|preset| {
+            let lut = simple_pixel_data_lut_with_voi(obj, &preset.voi, lut_options)?;
+            convert_monochrome_to_y_values(
+                &mut y_samples,
+                obj,
+                0,
+                monochrome,
+                &lut,
+                lut_options.out_of_range,
+            )?;
+            let (thumb, thumb_w, thumb_h) =
+                thumbnail_of(&y_samples, width, height, max_size, downsampling)?;
+            ImageData::new_with_u8_clamped_array_and_sh(Clamped(&thumb), thumb_w, thumb_h)
+                .map_err(|value| Error::Js { value })
+        })
+        .collect()
+}
+
+/// How windowed values are rounded to 8-bit output values
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum Rounding {
+    /// round to the nearest value, halves away from zero
+    #[default]
+    Nearest,
+    /// round towards zero
+    Truncate,
+    /// round down
+    Floor,
+}
+
+impl Rounding {
+    /// Round a windowed value and clamp it to an 8-bit output value
+    pub fn apply(self, x: f64) -> u8 {
+        let x = match self {
+            Rounding::Nearest => x.round(),
+            Rounding::Truncate => x.trunc(),
+            Rounding::Floor => x.floor(),
+        };
+        x.clamp(0., 255.) as u8
+    }
+}
+
+/// How samples beyond the end of the LUT of monochrome images are shown,
+/// such as with malformed pixel data or a wrong bit depth
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum OutOfRange {
+    /// as the last entry of the LUT
+    #[default]
+    Clamp,
+    /// as the entry of the sample modulo the size of the LUT
+    Wrap,
+    /// as background (black)
+    Background,
+}
+
+impl OutOfRange {
+    /// The handling with the given name
+    /// (`"clamp"`, `"wrap"`, or `"background"`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(OutOfRange::Clamp),
+            "wrap" => Some(OutOfRange::Wrap),
+            "background" => Some(OutOfRange::Background),
+            _ => None,
+        }
+    }
+
+    /// Look up a sample in the LUT,
+    /// or `None` if it is to be shown as background
+    pub fn lookup(self, lut: &[u8], x: usize) -> Option<u8> {
+        match lut.get(x) {
+            Some(&y) => Some(y),
+            None if lut.is_empty() => None,
+            None => match self {
+                OutOfRange::Clamp => lut.last().copied(),
+                OutOfRange::Wrap => Some(lut[x % lut.len()]),
+                OutOfRange::Background => None,
+            },
+        }
+    }
+}
+
+/// Options for building and applying the LUT of monochrome images
+#[derive(Debug, Default, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct LutOptions {
+    pub rounding: Rounding,
+    /// how samples beyond the end of the LUT are shown
+    pub out_of_range: OutOfRange,
+    /// whether windows are applied linearly (`LINEAR_EXACT`),
+    /// whatever the `VOILUTFunction` of the object
+    pub linear: bool,
+    /// a presentation LUT supplied by the user in place of that of the object,
+    /// giving the display value of each output value of the VOI stage
+    pub presentation: Option<[u8; CUSTOM_LUT_LEN]>,
+}
+
+/// Options for converting pixel data to image data
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ConversionOptions {
+    /// how the LUT of monochrome images is built
+    pub lut: LutOptions,
+    /// whether to read color samples with the opposite planar configuration
+    /// to the declared or detected one
+    pub flip_planar: bool,
+    /// the photometric interpretation to read the pixel data with
+    /// instead of the declared one, for debugging
+    pub force_photometric: Option<&'static str>,
+    /// how the gray levels of monochrome images are shown
+    pub display: DisplayOptions,
+    /// the correction of the colors of color images
+    pub color_balance: ColorBalance,
+}
+
+/// create a simple LUT which maps a 16-bit image
+pub fn simple_pixel_data_lut(obj: &DefaultDicomObject, options: LutOptions) -> Result<Vec<u8>> {
+    if let Some(window_level) = window_level_of(obj)? {
+        return simple_pixel_data_lut_with(obj, window_level, options);
+    }
+    // fall back to the first explicit VOI LUT
+    let (_, voi_lut) = voi_luts_of(obj)?
+        .into_iter()
+        .next()
+        .whatever_context("The given image does not provide window levels :(")?;
+    simple_pixel_data_lut_with_voi(obj, &Voi::Lut(voi_lut), options)
+}
+/// create a simple LUT which maps a 16-bit image
+/// using the given window level
+pub fn simple_pixel_data_lut_with(
+    obj: &DefaultDicomObject,
+    window_level: WindowLevel,
+    options: LutOptions,
+) -> Result<Vec<u8>> {
+    simple_pixel_data_lut_with_voi(obj, &Voi::Window(window_level), options)
+}
+
+/// create a simple LUT which maps a 16-bit image
+/// using the given VOI transformation
+pub fn simple_pixel_data_lut_with_voi(
+    obj: &DefaultDicomObject,
+    voi: &Voi,
+    options: LutOptions,
+) -> Result<Vec<u8>> {
+    let bits_stored = obj
+        .element(tags::BITS_STORED)
+        .whatever_context("Could not fetch BitsStored")?
+        .to_int::<u16>()
+        .whatever_context("BitsStored is not a number")?;
+
+    let mut lut = vec![0; 1 << bits_stored];
+
+    update_pixel_data_lut_with_voi(&mut lut, obj, voi, options)?;
+
+    Ok(lut)
+}
+
+/// create a simple LUT which maps a 16-bit image
+/// using the given window level parameters.
+/// 
+/// `lut` must have the correct size for the given object
+/// (`1 << bits_stored`).
+pub fn update_pixel_data_lut_with(
+    lut: &mut [u8],
+    obj: &DefaultDicomObject,
+    window_level: WindowLevel,
+    options: LutOptions,
+) -> Result<()> {
+    update_pixel_data_lut_with_voi(lut, obj, &Voi::Window(window_level), options)
+}
+
+/// create a simple LUT which maps a 16-bit image
+/// using the given VOI transformation.
+///
+/// `lut` must have the correct size for the given object
+/// (`1 << bits_stored`).
+pub fn update_pixel_data_lut_with_voi(
+    lut: &mut [u8],
+    obj: &DefaultDicomObject,
+    voi: &Voi,
+    options: LutOptions,
+) -> Result<()> {
+    debug_assert!(lut.len() >= 256);
+
+    let modality = modality_of(obj)?;
+    let voi_lut_function = voi_lut_function_of(obj, options)?;
+    let presentation = match options.presentation {
+        Some(_) => None,
+        None => presentation_lut_of(obj)?,
+    };
+
+    for (i, y) in lut.iter_mut().enumerate() {
+        let x = i as f64;
+        // rescale or modality LUT
+        let x = modality.apply(x);
+        // window
+        let x = match voi {
+            Voi::Window(window_level) => {
+                apply_window_level(x, &voi_lut_function, *window_level, LUT_OUTPUT_MAX)
+            }
+            Voi::Lut(voi_lut) => voi_lut.apply(x),
+        };
+        // presentation LUT, the user's first
+        let x = match (&options.presentation, &presentation) {
+            (Some(custom), _) => custom[x.round().clamp(0., LUT_OUTPUT_MAX) as usize] as f64,
+            (None, Some(presentation)) => presentation.apply(x),
+            (None, None) => x,
+        };
+        *y = options.rounding.apply(x);
+    }
+
+    Ok(())
+}
+
+/// The function applying window levels to the object:
+/// its `VOILUTFunction` (`LINEAR` if not declared),
+/// or `LINEAR_EXACT` if the options ask for linear windows.
+fn voi_lut_function_of(obj: &InMemDicomObject, options: LutOptions) -> Result<String> {
+    if options.linear {
+        return Ok("LINEAR_EXACT".to_string());
+    }
+
+    let voi_lut_function = if let Some(elem) = obj
+        .element_opt(tags::VOILUT_FUNCTION)
+        .whatever_context("Could not fetch VOILUTFunction")?
+    {
+        elem.to_str()
+            .whatever_context("VOILUTFunction is not a string")?
+            .to_string()
+    } else {
+        "LINEAR".to_string()
+    };
+
+    if voi_lut_function != "LINEAR"
+        && voi_lut_function != "LINEAR_EXACT"
+        && voi_lut_function != "SIGMOID"
+    {
+        whatever!("Unsupported VOI LUT function {}", &voi_lut_function);
+    }
+    Ok(voi_lut_function)
+}
+
+/// The modality transformation of the object:
+/// the first item of the ModalityLUTSequence if present,
+/// or else the rescale slope and intercept.
+pub fn modality_of(obj: &DefaultDicomObject) -> Result<Modality> {
+    let item = obj
+        .element_opt(tags::MODALITY_LUT_SEQUENCE)
+        .whatever_context("Could not fetch ModalityLUTSequence")?
+        .and_then(|elem| elem.items())
+        .and_then(|items| items.first());
+    let item = match item {
+        Some(item) => item,
+        None => {
+            let (slope, intercept) = rescale_of(obj)?;
+            return Ok(Modality::Rescale { slope, intercept });
+        }
+    };
+
+    let descriptor = item
+        .element(tags::LUT_DESCRIPTOR)
+        .whatever_context("Could not fetch LUTDescriptor")?
+        .to_multi_int::<i32>()
+        .whatever_context("LUTDescriptor is not a list of numbers")?;
+    let (num_entries, _, bits) = lut_descriptor_of(&descriptor)?;
+    let data = item
+        .element(tags::LUT_DATA)
+        .whatever_context("Could not fetch LUTData")?;
+    let (entries, _) = lut_entries_of(data.value(), num_entries, bits)?;
+    let signed = match obj
+        .element_opt(tags::PIXEL_REPRESENTATION)
+        .whatever_context("Could not fetch PixelRepresentation")?
+    {
+        Some(elem) => {
+            elem.to_int::<u16>()
+                .whatever_context("PixelRepresentation is not a number")?
+                == 1
+        }
+        None => false,
+    };
+    ModalityLut::from_descriptor(&descriptor, entries, signed).map(Modality::Lut)
+}
+
+/// The rescale slope and intercept of the object,
+/// 1 and 0 if absent.
+///
+/// They are looked up at the top level,
+/// or else, for DX and CR objects which declare neither there,
+/// in the first item of the DetectorInformationSequence.
+///
+/// Only the first value of each is used if there are more.
+/// If either is not a number,
+/// the rescale is ignored (slope 1 and intercept 0)
+/// so that the image can still be displayed,
+/// as told by [`rescale_warning_of`].
+pub fn rescale_of(obj: &InMemDicomObject) -> Result<(f64, f64)> {
+    match declared_rescale_of(obj)? {
+        (Some(rescale_slope), Some(rescale_intercept)) => Ok((rescale_slope, rescale_intercept)),
+        _ => Ok((1.0, 0.0)),
+    }
+}
+
+/// A warning for an object whose rescale slope or intercept
+/// is not a number, and is therefore ignored.
+pub fn rescale_warning_of(obj: &InMemDicomObject) -> Option<&'static str> {
+    match declared_rescale_of(obj) {
+        Ok((Some(_), Some(_))) | Err(_) => None,
+        Ok(_) => {
+            Some("RescaleSlope or RescaleIntercept is not a number, so the rescale was ignored.")
+        }
+    }
+}
+
+/// The rescale slope and intercept as declared, 1 and 0 if absent,
+/// or `None` for each which is not a number
+fn declared_rescale_of(obj: &InMemDicomObject) -> Result<(Option<f64>, Option<f64>)> {
+    let declares_rescale = |obj: &InMemDicomObject| {
+        [tags::RESCALE_SLOPE, tags::RESCALE_INTERCEPT]
+            .into_iter()
+            .any(|tag| matches!(obj.element_opt(tag), Ok(Some(_))))
+    };
+    let obj = match nested_display_item_of(obj)? {
+        Some(item) if !declares_rescale(obj) && declares_rescale(item) => item,
+        _ => obj,
+    };
+
+    let rescale_slope = match obj
+        .element_opt(tags::RESCALE_SLOPE)
+        .whatever_context("Could not fetch RescaleSlope")?
+    {
+        Some(elem) => first_number_of(elem.value()),
+        None => Some(1.0),
+    };
+
+    let rescale_intercept = match obj
+        .element_opt(tags::RESCALE_INTERCEPT)
+        .whatever_context("Could not fetch RescaleIntercept")?
+    {
+        Some(elem) => first_number_of(elem.value()),
+        None => Some(0.0),
+    };
+
+    Ok((rescale_slope, rescale_intercept))
+}
+
+/// The range of stored values which are padding rather than image,
+/// from PixelPaddingValue and PixelPaddingRangeLimit,
+/// or `None` if the object declares no padding.
+pub fn pixel_padding_of(obj: &InMemDicomObject) -> Result<Option<(i32, i32)>> {
+    let signed = obj
+        .element_opt(tags::PIXEL_REPRESENTATION)
+        .whatever_context("Could not fetch PixelRepresentation")?
+        .map(|elem| elem.to_int::<u16>())
+        .transpose()
+        .whatever_context("PixelRepresentation is not a number")?
+        == Some(1);
+    let value = |tag, name: &str| -> Result<Option<i32>> {
+        let value = obj
+            .element_opt(tag)
+            .with_whatever_context(|_| format!("Could not fetch {}", name))?
+            .map(|elem| elem.to_int::<i32>())
+            .transpose()
+            .with_whatever_context(|_| format!("{} is not a number", name))?;
+        // signed values encoded as unsigned
+        Ok(value.map(|x| {
+            if signed && x >= 0x8000 {
+                x - 0x10000
+            } else {
+                x
+            }
+        }))
+    };
+
+    let padding = match value(tags::PIXEL_PADDING_VALUE, "PixelPaddingValue")? {
+        Some(padding) => padding,
+        None => return Ok(None),
+    };
+    Ok(Some(
+        match value(tags::PIXEL_PADDING_RANGE_LIMIT, "PixelPaddingRangeLimit")? {
+            Some(limit) => (padding.min(limit), padding.max(limit)),
+            None => (padding, padding),
+        },
+    ))
+}
+
+/// The largest output value of the LUT of monochrome images (8-bit)
+const LUT_OUTPUT_MAX: f64 = 255.;
+
+/// Window a value with the given VOI LUT function,
+/// into an output value in [0, `ymax`]
+fn apply_window_level(x: f64, voi_lut_function: &str, window_level: WindowLevel, ymax: f64) -> f64 {
+    let WindowLevel {
+        width: ww,
+        center: wc,
+    } = window_level;
+
+    match voi_lut_function {
+        "LINEAR_EXACT" => window_level_linear_exact(x, ww, wc, ymax),
+        "SIGMOID" => window_level_sigmoid(x, ww, wc, ymax),
+        "LINEAR" => window_level_linear(x, ww, wc, ymax),
+        _ => panic!("Unsupported VOI LUT function {}", voi_lut_function),
+    }
+}
+
+fn window_level_linear(x: f64, ww: f64, wc: f64, ymax: f64) -> f64 {
+    debug_assert!(ww >= 1.);
+
+    // C.11.2.1.2.1
+    let min = wc - (ww - 1.) / 2.;
+    let max = wc - 0.5 + (ww - 1.) / 2.;
+
+    if x <= min {
+        // if (x <= c - (w-1) / 2), then y = ymin
+        0.
+    } else if x > max {
+        // else if (x > c - 0.5 + (w-1) /2), then y = ymax
+        ymax
+    } else {
+        // else y = ((x - (c - 0.5)) / (w-1) + 0.5) * (ymax- ymin) + ymin
+        ((x - (wc - 0.5)) / (ww - 1.) + 0.5) * ymax
+    }
+}
+
+fn window_level_linear_exact(value: f64, ww: f64, wc: f64, ymax: f64) -> f64 {
+    debug_assert!(ww >= 0.);
+
+    // C.11.2.1.3.2
+
+    let min = wc - ww / 2.;
+    let max = wc + ww / 2.;
+
+    if value <= min {
+        // if (x <= c - w/2), then y = ymin
+        0.
+    } else if value > max {
+        // else if (x > c + w/2), then y = ymax
+        ymax
+    } else {
+        // else y = ((x - c) / w + 0.5) * (ymax - ymin) + ymin
+        ((value - wc) / ww + 0.5) * ymax
+    }
+}
+
+fn window_level_sigmoid(value: f64, ww: f64, wc: f64, ymax: f64) -> f64 {
+    assert!(ww >= 1.);
+
+    // C.11.2.1.3.1
+
+    ymax / (1. + f64::exp(-4. * (value - wc) / ww))
+}
+
+/// Normalize a photometric interpretation value for comparison,
+/// removing surrounding padding and converting it to upper case.
+pub fn normalize_photometric_interpretation(photometric_interpretation: &str) -> String {
+    photometric_interpretation
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .to_uppercase()
+}
+
+/// The photometric interpretation of pixel data once decoded,
+/// given the (normalized) photometric interpretation of the stored pixel data
+/// and the transfer syntax.
+///
+/// Some codecs already convert the color samples while decompressing,
+/// so converting them again from the stored photometric interpretation
+/// would produce wrong colors:
+///
+/// - JPEG Baseline and Extended (processes 1, 2 and 4):
+///   YBR_FULL_422 and YBR_FULL are decoded to RGB,
+///   as the JPEG decoder applies the color transform.
+/// - JPEG 2000 and High-Throughput JPEG 2000:
+///   YBR_ICT and YBR_RCT are decoded to RGB,
+///   as the decoder inverts the multi-component transform.
+/// - JPEG Lossless, JPEG-LS and RLE Lossless:
+///   decoded as stored, since these do not transform colors.
+/// - Native pixel data is not decoded, so it is read as stored.
+pub fn decoded_photometric_interpretation(
+    photometric_interpretation: &str,
+    transfer_syntax: &str,
+) -> String {
+    let transfer_syntax = transfer_syntax.trim_end_matches(|c| c == '\0' || c == ' ');
+    match (transfer_syntax, photometric_interpretation) {
+        // JPEG Baseline (Process 1), JPEG Extended (Process 2 & 4)
+        ("1.2.840.10008.1.2.4.50" | "1.2.840.10008.1.2.4.51", "YBR_FULL_422" | "YBR_FULL")
+        // JPEG 2000 (lossless only and lossy, Part 2 multi-component)
+        | (
+            "1.2.840.10008.1.2.4.90"
+            | "1.2.840.10008.1.2.4.91"
+            | "1.2.840.10008.1.2.4.92"
+            | "1.2.840.10008.1.2.4.93"
+            // High-Throughput JPEG 2000
+            | "1.2.840.10008.1.2.4.201"
+            | "1.2.840.10008.1.2.4.202"
+            | "1.2.840.10008.1.2.4.203",
+            "YBR_ICT" | "YBR_RCT",
+        ) => "RGB".to_string(),
+        (_, pi) => pi.to_string(),
+    }
+}
+
+/// The pixel data conversion path resolved for an image
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Conversion {
+    /// grayscale through the VOI LUT
+    Monochrome(Monochrome),
+    /// 8-bit or 16-bit color, either interleaved or by plane
+    Color {
+        color_space: ColorSpace,
+        bits_allocated: u16,
+        planar: bool,
+    },
+    /// indexed color through the palette color lookup tables
+    PaletteColor,
+}
+
+/// The color space of multi-sample pixel data
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum ColorSpace {
+    Rgb,
+    /// full range luminance and chrominance,
+    /// as in YBR_FULL and in YBR_ICT (JPEG 2000)
+    YbrFull,
+    /// full range luminance and chrominance,
+    /// with the chrominance subsampled horizontally by 2 (YBR_FULL_422)
+    YbrFull422,
+    /// red, green and blue after an alpha sample (retired).
+    /// The alpha samples would select palette colors,
+    /// which is not supported, so they are ignored.
+    Argb,
+    /// cyan, magenta, yellow and black (retired)
+    Cmyk,
+}
+
+impl ColorSpace {
+    /// The number of samples per pixel
+    pub fn samples_per_pixel(self) -> usize {
+        match self {
+            ColorSpace::Rgb | ColorSpace::YbrFull | ColorSpace::YbrFull422 => 3,
+            ColorSpace::Argb | ColorSpace::Cmyk => 4,
+        }
+    }
+
+    /// Whether some samples are shared by two pixels
+    pub fn is_subsampled(self) -> bool {
+        self == ColorSpace::YbrFull422
+    }
+
+    /// The photometric interpretation of a retired color space,
+    /// `None` if it is still in use
+    pub fn retired_name(self) -> Option<&'static str> {
+        match self {
+            ColorSpace::Rgb | ColorSpace::YbrFull | ColorSpace::YbrFull422 => None,
+            ColorSpace::Argb => Some("ARGB"),
+            ColorSpace::Cmyk => Some("CMYK"),
+        }
+    }
+}
+
+/// The photometric interpretations which pixel data can be converted from
+/// (see [`resolve_conversion`])
+pub const SUPPORTED_PHOTOMETRIC_INTERPRETATIONS: [&str; 9] = [
+    "MONOCHROME1",
+    "MONOCHROME2",
+    "RGB",
+    "YBR_FULL",
+    "YBR_FULL_422",
+    "YBR_ICT",
+    "PALETTE COLOR",
+    "ARGB",
+    "CMYK",
+];
+
+/// Resolve how pixel data should be converted
+/// from the combination of
+/// photometric interpretation, bits allocated,
+/// samples per pixel, and planar configuration.
+///
+/// Combinations not listed here are not supported
+/// and result in an error naming the exact combination.
+pub fn resolve_conversion(
+    photometric_interpretation: &str,
+    bits_allocated: u16,
+    samples_per_pixel: u16,
+    planar_configuration: u16,
+) -> Result<Conversion> {
+    match (
+        photometric_interpretation,
+        bits_allocated,
+        samples_per_pixel,
+        planar_configuration,
+    ) {
+        ("MONOCHROME1", 8 | 16, 1, _) => Ok(Conversion::Monochrome(Monochrome::Monochrome1)),
+        ("MONOCHROME2", 8 | 16, 1, _) => Ok(Conversion::Monochrome(Monochrome::Monochrome2)),
+        ("RGB", 8 | 16, 3, 0 | 1) => Ok(Conversion::Color {
+            color_space: ColorSpace::Rgb,
+            bits_allocated,
+            planar: planar_configuration == 1,
+        }),
+        ("YBR_FULL" | "YBR_ICT", 8 | 16, 3, 0 | 1) => Ok(Conversion::Color {
+            color_space: ColorSpace::YbrFull,
+            bits_allocated,
+            planar: planar_configuration == 1,
+        }),
+        ("YBR_FULL_422", 8 | 16, 3, 0 | 1) => Ok(Conversion::Color {
+            color_space: ColorSpace::YbrFull422,
+            bits_allocated,
+            planar: planar_configuration == 1,
+        }),
+        ("PALETTE COLOR", 8 | 16, 1, _) => Ok(Conversion::PaletteColor),
+        ("ARGB", 8 | 16, 4, 0 | 1) => Ok(Conversion::Color {
+            color_space: ColorSpace::Argb,
+            bits_allocated,
+            planar: planar_configuration == 1,
+        }),
+        ("CMYK", 8 | 16, 4, 0 | 1) => Ok(Conversion::Color {
+            color_space: ColorSpace::Cmyk,
+            bits_allocated,
+            planar: planar_configuration == 1,
+        }),
+        (pi, bits_allocated, samples_per_pixel, planar_configuration) => whatever!(
+            "Unsupported combination of PhotometricInterpretation {}, BitsAllocated {}, SamplesPerPixel {} and PlanarConfiguration {}, sorry. :(",
+            pi,
+            bits_allocated,
+            samples_per_pixel,
+            planar_configuration
+        ),
+    }
+}
+
+/// The layout of the stored bits in each allocated 16-bit sample.
+///
+/// Older objects may embed overlays
+/// in the bits which are allocated but not stored.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct StoredBits {
+    pub bits_stored: u16,
+    pub high_bit: u16,
+}
+
+impl StoredBits {
+    /// Read BitsStored and HighBit from the object.
+    /// HighBit defaults to `BitsStored - 1` when absent,
+    /// and must fall within BitsAllocated.
+    pub fn of(obj: &InMemDicomObject) -> Result<Self> {
+        let bits_stored = obj
+            .element(tags::BITS_STORED)
+            .whatever_context("Could not fetch BitsStored")?
+            .to_int::<u16>()
+            .whatever_context("BitsStored is not a number")?;
+        ensure_whatever!(
+            (1..=16).contains(&bits_stored),
+            "Unsupported BitsStored {}",
+            bits_stored
+        );
+        let high_bit = match obj
+            .element_opt(tags::HIGH_BIT)
+            .whatever_context("Could not fetch HighBit")?
+        {
+            Some(elem) => elem
+                .to_int::<u16>()
+                .whatever_context("HighBit is not a number")?,
+            None => bits_stored - 1,
+        };
+        let bits_allocated = match obj
+            .element_opt(tags::BITS_ALLOCATED)
+            .whatever_context("Could not fetch BitsAllocated")?
+        {
+            Some(elem) => elem
+                .to_int::<u16>()
+                .whatever_context("BitsAllocated is not a number")?
+                .min(16),
+            None => 16,
+        };
+        ensure_whatever!(
+            high_bit + 1 >= bits_stored && high_bit < bits_allocated,
+            "Inconsistent HighBit {} for BitsStored {} and BitsAllocated {}",
+            high_bit,
+            bits_stored,
+            bits_allocated
+        );
+        Ok(StoredBits {
+            bits_stored,
+            high_bit,
+        })
+    }
+
+    fn shift(&self) -> u16 {
+        self.high_bit + 1 - self.bits_stored
+    }
+
+    fn mask(&self) -> u16 {
+        (((1_u32 << self.bits_stored) - 1) as u16) << self.shift()
+    }
+
+    /// The stored value of a sample, without any overlay bits.
+    pub fn value(&self, x: u16) -> u16 {
+        (x & self.mask()) >> self.shift()
+    }
+
+    /// Whether any of the bits outside of the stored range are set.
+    pub fn has_overlay(&self, x: u16) -> bool {
+        x & !self.mask() != 0
+    }
+}
+
+/// The number of frames in the object,
+/// 1 if NumberOfFrames is absent.
+pub fn number_of_frames_of(obj: &InMemDicomObject) -> Result<u32> {
+    let frames = match obj
+        .element_opt(tags::NUMBER_OF_FRAMES)
+        .whatever_context("Could not fetch NumberOfFrames")?
+    {
+        Some(elem) => elem
+            .to_int::<u32>()
+            .whatever_context("NumberOfFrames is not an integer")?,
+        None => 1,
+    };
+    Ok(frames.max(1))
+}
+
+/// The frame rate of cine playback when the object does not recommend one
+pub const DEFAULT_FRAME_RATE: f64 = 15.;
+/// The lowest frame rate accepted from the object
+const MIN_FRAME_RATE: f64 = 1.;
+/// The highest frame rate accepted from the object
+const MAX_FRAME_RATE: f64 = 60.;
+
+/// The frame rate for cine playback of the object (frames per second),
+/// from RecommendedDisplayFrameRate or CineRate.
+///
+/// Absent values fall back to [`DEFAULT_FRAME_RATE`],
+/// and unreasonable ones are clamped to a sane range.
+pub fn cine_frame_rate_of(obj: &InMemDicomObject) -> f64 {
+    let rate = [tags::RECOMMENDED_DISPLAY_FRAME_RATE, tags::CINE_RATE]
+        .into_iter()
+        .find_map(|tag| {
+            obj.element_opt(tag)
+                .ok()
+                .flatten()
+                .and_then(|elem| elem.to_float64().ok())
+        });
+
+    match rate {
+        Some(rate) if rate.is_finite() && rate > 0. => rate.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE),
+        _ => DEFAULT_FRAME_RATE,
+    }
+}
+
+/// The number of samples in each frame of the object,
+/// taking chroma subsampling of native pixel data into account
+pub fn frame_len_of(obj: &InMemDicomObject) -> Result<usize> {
+    let (columns, rows) = image_dimensions_of(obj)?;
+    if is_native_422(obj) {
+        return Ok(subsampled_row_len(columns as usize) * rows as usize);
+    }
+    let samples_per_pixel = match obj
+        .element_opt(tags::SAMPLES_PER_PIXEL)
+        .whatever_context("Could not fetch SamplesPerPixel")?
+    {
+        Some(elem) => elem
+            .to_int::<usize>()
+            .whatever_context("SamplesPerPixel is not an integer")?,
+        None => 1,
+    };
+    Ok(columns as usize * rows as usize * samples_per_pixel)
+}
+
+/// Whether the object has native pixel data
+/// with horizontally subsampled chrominance (YBR_FULL_422).
+///
+/// Encapsulated pixel data is decoded to full resolution.
+fn is_native_422(obj: &InMemDicomObject) -> bool {
+    let photometric_interpretation = obj
+        .element_opt(tags::PHOTOMETRIC_INTERPRETATION)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.to_str().ok())
+        .map(|pi| normalize_photometric_interpretation(&pi));
+    let native = obj
+        .element_opt(tags::PIXEL_DATA)
+        .ok()
+        .flatten()
+        .map_or(false, |elem| {
+            matches!(elem.value(), DicomValue::Primitive(_))
+        });
+    native && photometric_interpretation.as_deref() == Some("YBR_FULL_422")
+}
+
+/// The number of samples in a row of the given width
+/// with horizontally subsampled chrominance:
+/// a luminance sample for each pixel
+/// and two chrominance samples for each pair of pixels
+/// (or for the last pixel of an odd width).
+fn subsampled_row_len(width: usize) -> usize {
+    width + 2 * width.div_ceil(2)
+}
+
+/// Select the samples of one frame
+/// out of the samples of all frames.
+pub fn frame_samples<T>(samples: &[T], frame: u32, frame_len: usize) -> Result<&[T]> {
+    let start = frame as usize * frame_len;
+    let end = start + frame_len;
+    ensure_whatever!(
+        end <= samples.len(),
+        "Frame #{} is out of the bounds of the pixel data",
+        frame
+    );
+    Ok(&samples[start..end])
+}
+
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Monochrome {
+    Monochrome1,
+    Monochrome2,
+}
+
+/// The kind of monochrome image of the given photometric interpretation,
+/// or `None` if it is not monochrome.
+pub fn monochrome_of(photometric_interpretation: &str) -> Option<Monochrome> {
+    match normalize_photometric_interpretation(photometric_interpretation).as_str() {
+        "MONOCHROME1" => Some(Monochrome::Monochrome1),
+        "MONOCHROME2" => Some(Monochrome::Monochrome2),
+        _ => None,
+    }
+}
+
+/// A rectangular region of an image in pixels
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct Region {
+    /// the first column
+    pub x: u32,
+    /// the first row
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The region covering a whole image of the given size
+    pub fn full(width: u32, height: u32) -> Self {
+        Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+}
+
+/// Select the samples of a region
+/// out of the samples of a whole frame of the given width,
+/// one sample per pixel.
+pub fn region_samples<T: Clone>(samples: &[T], width: u32, region: Region) -> Result<Cow<[T]>> {
+    let (width, x, y) = (width as usize, region.x as usize, region.y as usize);
+    let (region_w, region_h) = (region.width as usize, region.height as usize);
+    ensure_whatever!(
+        x + region_w <= width && (y + region_h) * width <= samples.len(),
+        "Region {:?} is out of the bounds of the frame",
+        region
+    );
+    if x == 0 && region_w == width {
+        return Ok(Cow::Borrowed(&samples[y * width..(y + region_h) * width]));
+    }
+    Ok(Cow::Owned(
+        (y..y + region_h)
+            .flat_map(|row| &samples[row * width + x..row * width + x + region_w])
+            .cloned()
+            .collect(),
+    ))
+}
+
+pub fn convert_monochrome_to_y_values(
+    y_values: &mut Vec<u8>,
+    obj: &InMemDicomObject,
+    frame: u32,
+    monochrome: Monochrome,
+    lut: &[u8],
+    out_of_range: OutOfRange,
+) -> Result<()> {
+    let (width, height) = image_dimensions_of(obj)?;
+    convert_monochrome_region_to_y_values(
+        y_values,
+        obj,
+        frame,
+        monochrome,
+        lut,
+        Region::full(width, height),
+        out_of_range,
+    )
+}
+
+/// The length of an RGBA buffer of the given size in pixels,
+/// whatever the layout of the samples it is converted from
+pub fn rgba_len(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 4
+}
+
+/// Resize an RGBA buffer to hold an image of the given size,
+/// keeping it as is if it already does.
+fn resize_rgba(rgba: &mut Vec<u8>, width: u32, height: u32) {
+    let len = rgba_len(width, height);
+    if rgba.len() != len {
+        rgba.resize(len, 255);
+    }
+}
+
+/// The gray level of a stored value through the LUT,
+/// inverted for MONOCHROME1.
+/// Values beyond the end of the LUT are handled as chosen,
+/// the background being black.
+pub fn y_value_of(lut: &[u8], x: u16, monochrome: Monochrome, out_of_range: OutOfRange) -> u8 {
+    match out_of_range.lookup(lut, x as usize) {
+        Some(y) if monochrome == Monochrome::Monochrome1 => 0xFF - y,
+        Some(y) => y,
+        None => 0,
+    }
+}
+
+/// Convert the samples of a region of a monochrome frame
+/// to RGBA values through the LUT,
+/// after shifting the stored bits down from HighBit
+/// and stripping any overlay bits outside of them.
+pub fn convert_monochrome_region_to_y_values(
+    y_values: &mut Vec<u8>,
+    obj: &InMemDicomObject,
+    frame: u32,
+    monochrome: Monochrome,
+    lut: &[u8],
+    region: Region,
+    out_of_range: OutOfRange,
+) -> Result<()> {
+    let frame_len = frame_len_of(obj)?;
+    let (width, _) = image_dimensions_of(obj)?;
+
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+
+    let stored_bits = StoredBits::of(obj)?;
+
+    match bits_allocated {
+        8 => {
+            let samples = obj
+                .element(tags::PIXEL_DATA)
+                .whatever_context("Could not fetch PixelData")?;
+
+            if matches!(samples.value(), DicomValue::PixelSequence { .. }) {
+                whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
+            }
+
+            let samples = samples
+                .to_bytes()
+                .whatever_context("Could not read PixelData as a sequence of 8-bit integers")?;
+    
+            let samples = frame_samples(&samples[..], frame, frame_len)?;
+            let samples = region_samples(samples, width, region)?;
+
+            resize_rgba(y_values, region.width, region.height);
+
+            for (y, x) in y_values.chunks_mut(4).zip(samples.iter().copied()) {
+                let x = y_value_of(lut, stored_bits.value(x as u16), monochrome, out_of_range);
+
+                y[3] = 255;
+                y[0] = x;
+                y[1] = x;
+                y[2] = x;
+            }
+        }
+        16 => {
+            let samples = obj
+                .element(tags::PIXEL_DATA)
+                .whatever_context("Could not fetch PixelData")?;
+
+            if matches!(samples.value(), DicomValue::PixelSequence { .. }) {
+                whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
+            }
+
+            let samples: Cow<[u16]> = samples
+                .uint16_slice()
+                .map(Cow::from)
+                .or_else(|_| {
+                    samples
+                        .to_multi_int::<u16>()
+                        .map(Cow::Owned)
+                })
+                .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?;
+
+            let samples = frame_samples(&samples[..], frame, frame_len)?;
+            let samples = region_samples(samples, width, region)?;
+
+            resize_rgba(y_values, region.width, region.height);
+
+            for (y, x) in y_values.chunks_mut(4).zip(samples.iter().copied()) {
+                let x = y_value_of(lut, stored_bits.value(x), monochrome, out_of_range);
+
+                y[3] = 255;
+                y[0] = x;
+                y[1] = x;
+                y[2] = x;
+            }
+        }
+        _ => {
+            whatever!("Unsupported BitsAllocated {} :(", bits_allocated);
+        }
+    };
+
+    Ok(())
+}
+
+/// Convert RGB or YBR pixel data of 8 or 16 bits per sample to image data,
+/// in the color space of its ICC profile if possible.
+///
+/// If `planar` is `None` (PlanarConfiguration is missing),
+/// the planar configuration is guessed from the samples of the frame.
+/// With `flip_planar` in the options, the samples are read the other way around.
+/// The color balance of the options is applied to the RGB values,
+/// after any conversion from YCbCr.
+pub fn convert_color_to_imagedata(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    (width, height): (u32, u32),
+    color_space: ColorSpace,
+    bits_allocated: u16,
+    planar: Option<bool>,
+    options: ConversionOptions,
+) -> Result<ImageData> {
+    let ConversionOptions {
+        flip_planar,
+        color_balance,
+        ..
+    } = options;
+
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+
+    let samples: Vec<u16> = if bits_allocated == 16 {
+        let stored_bits = StoredBits::of(obj)?;
+        pixel_data
+            .uint16_slice()
+            .map(Cow::from)
+            .or_else(|_| pixel_data.to_multi_int::<u16>().map(Cow::Owned))
+            .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?
+            .iter()
+            .map(|&x| stored_bits.value(x))
+            .collect()
+    } else {
+        // BitsStored may be missing from 8-bit color images
+        let stored_bits = StoredBits::of(obj).ok();
+        pixel_data
+            .to_bytes()
+            .whatever_context("Could not read the bytes of PixelData")?
+            .iter()
+            .map(|&x| stored_bits.map_or(x as u16, |bits| bits.value(x as u16)))
+            .collect()
+    };
+
+    let bits_stored = obj
+        .element_opt(tags::BITS_STORED)
+        .whatever_context("Could not fetch BitsStored")?
+        .map(|elem| elem.to_int::<u16>())
+        .transpose()
+        .whatever_context("BitsStored is not a number")?
+        .unwrap_or(bits_allocated)
+        .clamp(1, bits_allocated);
+
+    if let Some(name) = color_space.retired_name() {
+        gloo_console::debug!(
+            "Converting from the retired photometric interpretation",
+            name
+        );
+    }
+
+    let samples = frame_samples(&samples[..], frame, frame_len_of(obj)?)?;
+    let planar = planar.unwrap_or_else(|| {
+        // only three-sample pixels can be told apart
+        if color_space.samples_per_pixel() != 3 || color_space.is_subsampled() {
+            return false;
+        }
+        let planar = looks_planar(samples, (width, height));
+        gloo_console::debug!(
+            "PlanarConfiguration is missing, reading samples",
+            if planar { "by plane" } else { "interleaved" }
+        );
+        planar
+    });
+    let mut data = if color_space.is_subsampled() {
+        let samples = upsample_chroma_422(samples, (width, height), planar != flip_planar)?;
+        color_to_rgba(&samples, ColorSpace::YbrFull, false, bits_stored)
+    } else {
+        color_to_rgba(samples, color_space, planar != flip_planar, bits_stored)
+    };
+    ensure_whatever!(
+        data.len() == rgba_len(width, height),
+        "Color samples do not add up to {}x{} pixels",
+        width,
+        height
+    );
+    color_balance.apply(&mut data);
+
+    new_imagedata(&data, width, height, display_color_space_of(obj))
+        .map_err(|value| Error::Js { value })
+}
+
+/// The maximum number of pixels compared
+/// when guessing the planar configuration of a frame
+const PLANAR_GUESS_PIXELS: usize = 65_536;
+
+/// Guess whether the three-sample pixels of a frame are stored by plane,
+/// for when PlanarConfiguration is missing.
+///
+/// Reading the samples with the wrong configuration
+/// brings together samples from unrelated parts of the image,
+/// so the configuration in which vertically adjacent pixels
+/// differ the least is the plausible one.
+/// Interleaved samples, the default for native pixel data,
+/// are assumed unless planes are clearly smoother.
+pub fn looks_planar(samples: &[u16], (width, height): (u32, u32)) -> bool {
+    let (width, height) = (width as usize, height as usize);
+    let plane_size = width * height;
+    if height < 2 || samples.len() < plane_size * 3 {
+        return false;
+    }
+
+    let step = (plane_size / PLANAR_GUESS_PIXELS).max(1);
+    let (mut interleaved, mut planar) = (0_u64, 0_u64);
+    for i in (0..plane_size - width).step_by(step) {
+        for c in 0..3 {
+            interleaved += samples[3 * (i + width) + c].abs_diff(samples[3 * i + c]) as u64;
+            let plane = &samples[c * plane_size..];
+            planar += plane[i + width].abs_diff(plane[i]) as u64;
+        }
+    }
+    planar * 2 < interleaved
+}
+
+/// Reconstruct full resolution luminance and chrominance
+/// from samples with horizontally subsampled chrominance,
+/// returning three interleaved samples per pixel.
+///
+/// Interleaved samples come in groups of two luminance samples
+/// followed by the chrominance shared by both pixels (Y Y Cb Cr),
+/// while planar samples have a plane of luminance
+/// followed by a plane of each chrominance at half the width.
+/// With an odd width, the last pixel of each row has chrominance of its own.
+/// Chrominance is repeated for both pixels of a pair,
+/// and never shared across rows.
+pub fn upsample_chroma_422(
+    samples: &[u16],
+    (width, height): (u32, u32),
+    planar: bool,
+) -> Result<Vec<u16>> {
+    let (width, height) = (width as usize, height as usize);
+    let chroma_width = width.div_ceil(2);
+    ensure_whatever!(
+        samples.len() >= subsampled_row_len(width) * height,
+        "Subsampled color samples do not add up to {}x{} pixels",
+        width,
+        height
+    );
+
+    let mut out = Vec::with_capacity(width * height * 3);
+    for row in 0..height {
+        for x in 0..width {
+            let (y, cb, cr) = if planar {
+                let luma_len = width * height;
+                let chroma_len = chroma_width * height;
+                let chroma = row * chroma_width + x / 2;
+                (
+                    samples[row * width + x],
+                    samples[luma_len + chroma],
+                    samples[luma_len + chroma_len + chroma],
+                )
+            } else {
+                let group = row * subsampled_row_len(width) + (x / 2) * 4;
+                // the last group of an odd row has a single luminance sample
+                let lumas = if x / 2 == chroma_width - 1 && width % 2 == 1 {
+                    1
+                } else {
+                    2
+                };
+                (
+                    samples[group + x % 2],
+                    samples[group + lumas],
+                    samples[group + lumas + 1],
+                )
+            };
+            out.extend([y, cb, cr]);
+        }
+    }
+    Ok(out)
+}
+
+/// Convert color pixels of the given bit depth,
+/// with as many samples as the color space has,
+/// to 8-bit RGBA.
+///
+/// The color transform is done at the original precision,
+/// only reducing to 8 bits at the end.
+/// Subsampled chrominance should be reconstructed beforehand
+/// with [`upsample_chroma_422`].
+pub fn color_to_rgba(
+    samples: &[u16],
+    color_space: ColorSpace,
+    planar: bool,
+    bits_stored: u16,
+) -> Vec<u8> {
+    let max = ((1_u32 << bits_stored) - 1) as f64;
+    let half = (1_u32 << (bits_stored - 1)) as f64;
+
+    let to_rgb = |pixel: [f64; 4]| -> [f64; 3] {
+        let [a, b, c, d] = pixel;
+        match color_space {
+            ColorSpace::Rgb => [a, b, c],
+            ColorSpace::YbrFull | ColorSpace::YbrFull422 => {
+                let (y, cb, cr) = (a, b - half, c - half);
+                [
+                    y + 1.402 * cr,
+                    y - 0.344136 * cb - 0.714136 * cr,
+                    y + 1.772 * cb,
+                ]
+            }
+            ColorSpace::Argb => [b, c, d],
+            ColorSpace::Cmyk => {
+                let white = max - d;
+                [a, b, c].map(|ink| (max - ink) * white / max)
+            }
+        }
+    };
+    let to_u8 = |x: f64| (x.clamp(0., max) * 255. / max).round() as u8;
+
+    let pixel = |samples: [u16; 4]| {
+        let [r, g, b] = to_rgb(samples.map(|x| x as f64));
+        [to_u8(r), to_u8(g), to_u8(b), 0xFF]
+    };
+
+    // the output is sized by pixels, not by input samples
+    let n = color_space.samples_per_pixel();
+    let pixels = samples.len() / n;
+    let mut rgba = Vec::with_capacity(pixels * 4);
+    if planar {
+        // samples are laid out as AAA...BBB...CCC...
+        for i in 0..pixels {
+            let mut p = [0; 4];
+            for (c, sample) in p.iter_mut().take(n).enumerate() {
+                *sample = samples[c * pixels + i];
+            }
+            rgba.extend(pixel(p));
+        }
+    } else {
+        for chunk in samples.chunks_exact(n) {
+            let mut p = [0; 4];
+            p[..n].copy_from_slice(chunk);
+            rgba.extend(pixel(p));
+        }
+    }
+    rgba
+}
+
+/// A single channel of a palette color lookup table
+struct PaletteLut {
+    first_mapped: i32,
+    entries: Vec<u8>,
+}
+
+impl PaletteLut {
+    fn get(&self, x: i32) -> u8 {
+        let i = (x - self.first_mapped).clamp(0, self.entries.len() as i32 - 1);
+        self.entries[i as usize]
+    }
+}
+
+fn palette_lut_of(
+    obj: &DefaultDicomObject,
+    descriptor_tag: Tag,
+    data_tag: Tag,
+    color: &str,
+) -> Result<PaletteLut> {
+    let descriptor = obj
+        .element(descriptor_tag)
+        .with_whatever_context(|_| format!("Could not fetch {} palette descriptor", color))?
+        .to_multi_int::<i32>()
+        .with_whatever_context(|_| format!("{} palette descriptor is not a number", color))?;
+
+    let [num_entries, first_mapped, bits] = match descriptor[..] {
+        [num_entries, first_mapped, bits] => [num_entries, first_mapped, bits],
+        _ => whatever!("{} palette descriptor should have 3 values", color),
+    };
+    // 0 means 2^16 entries
+    let num_entries = if num_entries == 0 {
+        65_536
+    } else {
+        num_entries as usize
+    };
+
+    let data = obj
+        .element(data_tag)
+        .with_whatever_context(|_| format!("Could not fetch {} palette data", color))?;
+
+    let entries: Vec<u8> = match bits {
+        8 => {
+            let bytes = data
+                .to_bytes()
+                .with_whatever_context(|_| format!("Could not read {} palette data", color))?;
+            if bytes.len() == num_entries {
+                // 8-bit entries tightly packed
+                bytes.into_owned()
+            } else {
+                // 8-bit entries in 16-bit words
+                palette_bytes_of_words(&palette_words_of(data.value(), &bytes))
+            }
+        }
+        16 => data
+            .to_multi_int::<u16>()
+            .with_whatever_context(|_| format!("Could not read {} palette data", color))?
+            .into_iter()
+            .map(|x| (x >> 8) as u8)
+            .collect(),
+        _ => whatever!("Unsupported {} palette entry size of {} bits", color, bits),
+    };
+
+    if entries.is_empty() {
+        whatever!("{} palette data is empty", color);
+    }
+
+    Ok(PaletteLut {
+        first_mapped,
+        entries,
+    })
+}
+
+/// The 16-bit words of palette data,
+/// decoded from its bytes if it was not read as words
+fn palette_words_of(value: &DicomValue<InMemDicomObject>, bytes: &[u8]) -> Vec<u16> {
+    match value {
+        DicomValue::Primitive(PrimitiveValue::U16(words)) => words.to_vec(),
+        _ => bytes
+            .chunks_exact(2)
+            .map(|word| u16::from_le_bytes([word[0], word[1]]))
+            .collect(),
+    }
+}
+
+/// The 8-bit entries of a palette stored one per 16-bit word,
+/// from the byte which carries them:
+/// the low byte, unless any of the words uses the high byte
+/// (as dcmtk does for such lookup tables).
+fn palette_bytes_of_words(words: &[u16]) -> Vec<u8> {
+    if words.iter().any(|&word| word > 0xFF) {
+        words.iter().map(|&word| (word >> 8) as u8).collect()
+    } else {
+        words.iter().map(|&word| word as u8).collect()
     }
+}
+
+pub fn convert_palette_color_to_imagedata(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    width: u32,
+    height: u32,
+    bits_allocated: u16,
+) -> Result<ImageData> {
+    let red = palette_lut_of(
+        obj,
+        tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        "Red",
+    )?;
+    let green = palette_lut_of(
+        obj,
+        tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::GREEN_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        "Green",
+    )?;
+    let blue = palette_lut_of(
+        obj,
+        tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+        tags::BLUE_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+        "Blue",
+    )?;
 
     let samples = obj
         .element(tags::PIXEL_DATA)
-        .whatever_context("Could not fetch PixelData")?
-        .to_bytes()
-        .whatever_context("Could not read the bytes of PixelData")?;
+        .whatever_context("Could not fetch PixelData")?;
+
+    let indices: Vec<i32> = if bits_allocated == 8 {
+        samples
+            .to_bytes()
+            .whatever_context("Could not read the bytes of PixelData")?
+            .iter()
+            .map(|&x| x as i32)
+            .collect()
+    } else {
+        samples
+            .to_multi_int::<u16>()
+            .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?
+            .into_iter()
+            .map(|x| x as i32)
+            .collect()
+    };
 
-    let data: Vec<u8> = samples
-        .chunks(3)
-        .map(|chunk| <[u8; 3]>::try_from(chunk).unwrap())
-        .flat_map(|[r, g, b]| [r, g, b, 0xFF])
+    let data: Vec<u8> = frame_samples(&indices[..], frame, frame_len_of(obj)?)?
+        .iter()
+        .copied()
+        .flat_map(|x| [red.get(x), green.get(x), blue.get(x), 0xFF])
         .collect();
 
     ImageData::new_with_u8_clamped_array_and_sh(Clamped(&data), width, height)
         .map_err(|value| Error::Js { value })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn arrow_keys_change_window_level() {
+        let steps = KeyWindowLevelSteps::default();
+        assert_eq!(steps.change_of("ArrowUp", false), Some((0., 1.)));
+        assert_eq!(steps.change_of("ArrowLeft", true), Some((-10., 0.)));
+        assert_eq!(steps.change_of("Enter", false), None);
+
+        // as configured
+        let steps = KeyWindowLevelSteps {
+            step: 5.,
+            large_step: 50.,
+        };
+        assert_eq!(steps.change_of("ArrowDown", false), Some((0., -5.)));
+        assert_eq!(steps.change_of("ArrowRight", false), Some((5., 0.)));
+        assert_eq!(steps.change_of("ArrowUp", true), Some((0., 50.)));
+        assert_eq!(steps.change_of("ArrowLeft", true), Some((-50., 0.)));
+    }
+
+    #[wasm_bindgen_test]
+    fn imagedata_is_reused_for_same_size() {
+        let mut pool = None;
+        let first = write_to_imagedata(&mut pool, &[0; 2 * 2 * 4], 2, 2).unwrap();
+        let second = write_to_imagedata(&mut pool, &[255; 2 * 2 * 4], 2, 2).unwrap();
+        assert!(js_sys::Object::is(&first, &second));
+        assert_eq!(second.data().0, vec![255; 2 * 2 * 4]);
+
+        // different size, new image data
+        let third = write_to_imagedata(&mut pool, &[255; 3 * 2 * 4], 3, 2).unwrap();
+        assert!(!js_sys::Object::is(&first, &third));
+        assert_eq!(third.width(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn resolve_supported_conversions() {
+        let mono1 = Conversion::Monochrome(Monochrome::Monochrome1);
+        let mono2 = Conversion::Monochrome(Monochrome::Monochrome2);
+        let rgb = |bits_allocated, planar| Conversion::Color {
+            color_space: ColorSpace::Rgb,
+            bits_allocated,
+            planar,
+        };
+        let ybr = |bits_allocated, planar| Conversion::Color {
+            color_space: ColorSpace::YbrFull,
+            bits_allocated,
+            planar,
+        };
+        let table = [
+            ("MONOCHROME1", 8, 1, 0, mono1),
+            ("MONOCHROME1", 16, 1, 0, mono1),
+            ("MONOCHROME2", 8, 1, 0, mono2),
+            ("MONOCHROME2", 16, 1, 0, mono2),
+            ("RGB", 8, 3, 0, rgb(8, false)),
+            ("RGB", 8, 3, 1, rgb(8, true)),
+            ("RGB", 16, 3, 0, rgb(16, false)),
+            ("YBR_FULL", 8, 3, 0, ybr(8, false)),
+            ("YBR_ICT", 16, 3, 1, ybr(16, true)),
+            ("PALETTE COLOR", 8, 1, 0, Conversion::PaletteColor),
+            ("PALETTE COLOR", 16, 1, 0, Conversion::PaletteColor),
+            (
+                "ARGB",
+                8,
+                4,
+                0,
+                Conversion::Color {
+                    color_space: ColorSpace::Argb,
+                    bits_allocated: 8,
+                    planar: false,
+                },
+            ),
+            (
+                "CMYK",
+                16,
+                4,
+                1,
+                Conversion::Color {
+                    color_space: ColorSpace::Cmyk,
+                    bits_allocated: 16,
+                    planar: true,
+                },
+            ),
+        ];
+
+        for (pi, bits_allocated, samples_per_pixel, planar_configuration, expected) in table {
+            let conversion =
+                resolve_conversion(pi, bits_allocated, samples_per_pixel, planar_configuration)
+                    .unwrap();
+            assert_eq!(
+                conversion, expected,
+                "unexpected conversion for {} ({}, {}, {})",
+                pi, bits_allocated, samples_per_pixel, planar_configuration
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn convert_16_bit_ybr_to_rgba() {
+        // white, mid gray, and red in 16-bit YBR_FULL
+        let samples = [
+            65535, 32768, 32768, //
+            32768, 32768, 32768, //
+            19595, 21710, 65535,
+        ];
+        let rgba = color_to_rgba(&samples, ColorSpace::YbrFull, false, 16);
+        assert_eq!(&rgba[0..4], &[255, 255, 255, 255]);
+        assert_eq!(&rgba[4..8], &[128, 128, 128, 255]);
+
+        let red = &rgba[8..12];
+        assert!(red[0] >= 254, "{:?}", red);
+        assert!(red[1] <= 1, "{:?}", red);
+        assert!(red[2] <= 1, "{:?}", red);
+
+        // the same pixels by plane
+        let planar: Vec<u16> = (0..3)
+            .flat_map(|c| samples.iter().skip(c).step_by(3).copied())
+            .collect();
+        assert_eq!(color_to_rgba(&planar, ColorSpace::YbrFull, true, 16), rgba);
+    }
+
+    #[wasm_bindgen_test]
+    fn convert_argb_to_rgba() {
+        // the alpha sample does not show through
+        let samples = [
+            0, 255, 0, 0, //
+            255, 10, 20, 30,
+        ];
+        let rgba = color_to_rgba(&samples, ColorSpace::Argb, false, 8);
+        assert_eq!(rgba, vec![255, 0, 0, 255, 10, 20, 30, 255]);
+
+        // the same pixels by plane
+        let planar = [0, 255, 255, 10, 0, 20, 0, 30];
+        assert_eq!(color_to_rgba(&planar, ColorSpace::Argb, true, 8), rgba);
+    }
+
+    #[wasm_bindgen_test]
+    fn convert_cmyk_to_rgba() {
+        // white paper, black ink, cyan ink, and half black
+        let samples = [
+            0, 0, 0, 0, //
+            0, 0, 0, 255, //
+            255, 0, 0, 0, //
+            0, 0, 0, 128,
+        ];
+        let rgba = color_to_rgba(&samples, ColorSpace::Cmyk, false, 8);
+        assert_eq!(&rgba[0..4], &[255, 255, 255, 255]);
+        assert_eq!(&rgba[4..8], &[0, 0, 0, 255]);
+        assert_eq!(&rgba[8..12], &[0, 255, 255, 255]);
+        assert_eq!(&rgba[12..16], &[127, 127, 127, 255]);
+    }
+
+    #[wasm_bindgen_test]
+    fn split_images_at_column() {
+        // 3x2 pixels, black on the left and white on the right
+        let mut left = vec![0; rgba_len(3, 2)];
+        let right = vec![255; rgba_len(3, 2)];
+        split_rgba(&mut left, &right, 3, split_column(0.5, 3));
+        let row: Vec<u8> = left[..12].chunks(4).map(|px| px[0]).collect();
+        assert_eq!(row, vec![0, 0, 255]);
+        assert_eq!(&left[..12], &left[12..]);
+
+        assert_eq!(split_column(-1., 3), 0);
+        assert_eq!(split_column(2., 3), 3);
+        // all on the left
+        let mut left = vec![0; rgba_len(3, 2)];
+        split_rgba(&mut left, &right, 3, 3);
+        assert!(left.iter().all(|&x| x == 0));
+    }
+
+    #[wasm_bindgen_test]
+    fn upsample_planar_subsampled_chroma() {
+        // 3x2 pixels: two chrominance samples per row, the last one unshared
+        let luma = [10, 11, 12, 20, 21, 22];
+        let cb = [100, 101, 200, 201];
+        let cr = [150, 151, 250, 251];
+        let planar: Vec<u16> = [&luma[..], &cb, &cr].concat();
+        let expected = vec![
+            10, 100, 150, 11, 100, 150, 12, 101, 151, //
+            20, 200, 250, 21, 200, 250, 22, 201, 251,
+        ];
+        assert_eq!(
+            upsample_chroma_422(&planar, (3, 2), true).unwrap(),
+            expected
+        );
+
+        // the same pixels interleaved (Y Y Cb Cr, then Y Cb Cr at the end of a row)
+        let interleaved = [
+            10, 11, 100, 150, 12, 101, 151, //
+            20, 21, 200, 250, 22, 201, 251,
+        ];
+        assert_eq!(
+            upsample_chroma_422(&interleaved, (3, 2), false).unwrap(),
+            expected
+        );
+        assert_eq!(subsampled_row_len(3), interleaved.len() / 2);
+
+        // too short for the frame
+        assert!(upsample_chroma_422(&planar[..9], (3, 2), true).is_err());
+
+        // gray chrominance gives gray pixels
+        let gray = upsample_chroma_422(&[50, 60, 128, 128], (2, 1), false).unwrap();
+        let rgba = color_to_rgba(&gray, ColorSpace::YbrFull, false, 8);
+        assert_eq!(rgba, vec![50, 50, 50, 255, 60, 60, 60, 255]);
+    }
+
+    #[wasm_bindgen_test]
+    fn size_rgba_buffers_by_output_pixels() {
+        // a reused buffer takes the size of the region, not of the last frame
+        let mut rgba = vec![0; rgba_len(8, 8)];
+        resize_rgba(&mut rgba, 3, 2);
+        assert_eq!(rgba.len(), 3 * 2 * 4);
+
+        // 2x2 pixels from 3 samples each
+        let rgb = [0_u16; 2 * 2 * 3];
+        assert_eq!(
+            color_to_rgba(&rgb, ColorSpace::Rgb, false, 8).len(),
+            rgba_len(2, 2)
+        );
+        // 2x1 pixels from 4 samples each, by plane
+        let cmyk = [0_u16; 2 * 4];
+        assert_eq!(
+            color_to_rgba(&cmyk, ColorSpace::Cmyk, true, 8).len(),
+            rgba_len(2, 1)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn guess_planar_configuration() {
+        // smooth gradients in red and green over a constant blue
+        let (width, height) = (8, 8);
+        let pixels: Vec<[u16; 3]> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| [x as u16 * 30, y as u16 * 30, 100]))
+            .collect();
+        let interleaved: Vec<u16> = pixels.iter().flatten().copied().collect();
+        let planar: Vec<u16> = (0..3)
+            .flat_map(|c| pixels.iter().map(move |pixel| pixel[c]))
+            .collect();
+        assert!(!looks_planar(&interleaved, (width, height)));
+        assert!(looks_planar(&planar, (width, height)));
+
+        // no evidence either way, interleaved by default
+        assert!(!looks_planar(&[100; 8 * 8 * 3], (width, height)));
+    }
+
+    #[wasm_bindgen_test]
+    fn reconcile_mismatched_pixel_data_encoding() {
+        // consistent
+        assert_eq!(
+            reconcile_pixel_data_encoding("1.2.840.10008.1.2.1\0", false),
+            (PixelDataEncoding::Native, None)
+        );
+        assert_eq!(
+            reconcile_pixel_data_encoding("1.2.840.10008.1.2.4.50", true),
+            (PixelDataEncoding::Encapsulated, None)
+        );
+
+        // declared compressed, stored native
+        let (encoding, warning) = reconcile_pixel_data_encoding("1.2.840.10008.1.2.4.50", false);
+        assert_eq!(encoding, PixelDataEncoding::Native);
+        assert!(warning.is_some());
+
+        // declared native, stored encapsulated
+        let (encoding, warning) = reconcile_pixel_data_encoding("1.2.840.10008.1.2", true);
+        assert_eq!(encoding, PixelDataEncoding::Encapsulated);
+        assert!(warning.is_some());
+    }
+
+    #[wasm_bindgen_test]
+    fn select_frame_samples() {
+        let samples: Vec<u16> = (0..12).collect();
+        assert_eq!(frame_samples(&samples[..], 0, 4).unwrap(), &[0, 1, 2, 3]);
+        assert_eq!(frame_samples(&samples[..], 2, 4).unwrap(), &[8, 9, 10, 11]);
+        assert!(frame_samples(&samples[..], 3, 4).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn cine_frame_rates() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let rate = |tag, value: &str| {
+            let obj = InMemDicomObject::from_element_iter([DataElement::new(
+                tag,
+                VR::IS,
+                PrimitiveValue::from(value),
+            )]);
+            cine_frame_rate_of(&obj)
+        };
+
+        // absent
+        assert_eq!(
+            cine_frame_rate_of(&InMemDicomObject::new_empty()),
+            DEFAULT_FRAME_RATE
+        );
+        // valid
+        assert_eq!(rate(tags::RECOMMENDED_DISPLAY_FRAME_RATE, "30"), 30.);
+        assert_eq!(rate(tags::CINE_RATE, "24"), 24.);
+        // out of range
+        assert_eq!(
+            rate(tags::RECOMMENDED_DISPLAY_FRAME_RATE, "0"),
+            DEFAULT_FRAME_RATE
+        );
+        assert_eq!(rate(tags::CINE_RATE, "200"), MAX_FRAME_RATE);
+    }
+
+    #[wasm_bindgen_test]
+    fn round_windowed_values() {
+        assert_eq!(Rounding::Truncate.apply(127.5), 127);
+        assert_eq!(Rounding::Nearest.apply(127.5), 128);
+        assert_eq!(Rounding::Floor.apply(127.5), 127);
+        assert_eq!(Rounding::Nearest.apply(127.4), 127);
+
+        // clamped to the output range
+        assert_eq!(Rounding::Nearest.apply(-3.), 0);
+        assert_eq!(Rounding::Nearest.apply(255.6), 255);
+    }
+
+    #[wasm_bindgen_test]
+    fn select_region_samples() {
+        // 4x3 frame
+        let samples: Vec<u16> = (0..12).collect();
+        let region = Region {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        assert_eq!(
+            &*region_samples(&samples, 4, region).unwrap(),
+            &[5, 6, 9, 10]
+        );
+
+        // whole rows are borrowed
+        let rows = Region {
+            x: 0,
+            y: 2,
+            width: 4,
+            height: 1,
+        };
+        assert!(matches!(
+            region_samples(&samples, 4, rows).unwrap(),
+            Cow::Borrowed(&[8, 9, 10, 11])
+        ));
+
+        assert!(region_samples(&samples, 4, Region::full(4, 4)).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn modality_lut_with_first_mapped_value() {
+        let lut = ModalityLut::from_descriptor(&[3, 100, 16], vec![10, 20, 30], false).unwrap();
+        assert_eq!(lut.first_mapped, 100);
+        // below the first mapped value
+        assert_eq!(lut.apply(0.), 10.);
+        assert_eq!(lut.apply(99.), 10.);
+        assert_eq!(lut.apply(100.), 10.);
+        assert_eq!(lut.apply(101.), 20.);
+        assert_eq!(lut.apply(102.), 30.);
+        // above the last entry
+        assert_eq!(lut.apply(4095.), 30.);
+
+        // an unsigned descriptor of a signed image (-1024)
+        let lut = ModalityLut::from_descriptor(&[2, 0xFC00, 16], vec![0, 1], true).unwrap();
+        assert_eq!(lut.first_mapped, -1024);
+        assert_eq!(Modality::Lut(lut).apply(-1023.), 1.);
+    }
+
+    #[wasm_bindgen_test]
+    fn read_lut_data_encodings() {
+        let (num_entries, first_mapped, bits) = lut_descriptor_of(&[0, -1024, 16]).unwrap();
+        assert_eq!((num_entries, first_mapped, bits), (65_536, -1024, 16));
+        assert!(lut_descriptor_of(&[256, 0, 32]).is_err());
+
+        // 8-bit entries as US, one per word
+        let us = DicomValue::Primitive(PrimitiveValue::U16(vec![0, 128, 255].into()));
+        assert_eq!(
+            lut_entries_of(&us, 3, 8).unwrap(),
+            (vec![0, 128, 255], false)
+        );
+        // 8-bit entries packed two per word
+        let packed = DicomValue::Primitive(PrimitiveValue::U16(vec![0x8000, 0x00FF].into()));
+        assert_eq!(
+            lut_entries_of(&packed, 4, 8).unwrap(),
+            (vec![0, 128, 255, 0], false)
+        );
+
+        // 16-bit entries as OW, left as little endian bytes
+        let ow = DicomValue::Primitive(PrimitiveValue::U8(vec![0x00, 0x00, 0xFF, 0x0F].into()));
+        assert_eq!(lut_entries_of(&ow, 2, 16).unwrap(), (vec![0, 4095], false));
+
+        // signed entries
+        let ss = DicomValue::Primitive(PrimitiveValue::I16(vec![-2048, 0, 2047].into()));
+        let (entries, signed) = lut_entries_of(&ss, 3, 12).unwrap();
+        assert_eq!((&entries[..], signed), (&[-2048, 0, 2047][..], true));
+        let lut = VoiLut {
+            first_mapped: 0,
+            bits: 12,
+            signed,
+            entries,
+        };
+        assert_eq!(lut.apply(0.), 0.);
+        assert_eq!(lut.apply(2.), 255.);
+    }
+
+    #[wasm_bindgen_test]
+    fn read_8_bit_palette_entries_in_words() {
+        let obj = |data: PrimitiveValue| {
+            let meta = dicom::object::meta::FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+                .media_storage_sop_instance_uid("1.2.3.4")
+                .build()
+                .unwrap();
+            let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+            obj.put(DataElement::new(
+                tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+                VR::US,
+                PrimitiveValue::U16(vec![3, 0, 8].into()),
+            ));
+            obj.put(DataElement::new(
+                tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+                VR::OW,
+                data,
+            ));
+            obj
+        };
+        let entries = |obj: &DefaultDicomObject| {
+            palette_lut_of(
+                obj,
+                tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DESCRIPTOR,
+                tags::RED_PALETTE_COLOR_LOOKUP_TABLE_DATA,
+                "Red",
+            )
+            .unwrap()
+            .entries
+        };
+
+        // in the low byte of each word
+        let low = obj(PrimitiveValue::U16(vec![0x0000, 0x0080, 0x00FF].into()));
+        assert_eq!(entries(&low), [0, 128, 255]);
+
+        // in the high byte of each word
+        let high = obj(PrimitiveValue::U16(vec![0x0000, 0x8000, 0xFF00].into()));
+        assert_eq!(entries(&high), [0, 128, 255]);
+
+        // words still held as little endian bytes
+        let bytes = obj(PrimitiveValue::U8(
+            vec![0x00, 0x00, 0x00, 0x80, 0x00, 0xFF].into(),
+        ));
+        assert_eq!(entries(&bytes), [0, 128, 255]);
+
+        // tightly packed
+        let packed = obj(PrimitiveValue::U8(vec![0, 128, 255].into()));
+        assert_eq!(entries(&packed), [0, 128, 255]);
+    }
+
+    #[wasm_bindgen_test]
+    fn force_linear_windows() {
+        use dicom::core::{DataElement, VR};
+
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::VOILUT_FUNCTION,
+            VR::CS,
+            PrimitiveValue::from("SIGMOID"),
+        )]);
+        let linear = LutOptions {
+            linear: true,
+            ..LutOptions::default()
+        };
+        assert_eq!(
+            voi_lut_function_of(&obj, LutOptions::default()).unwrap(),
+            "SIGMOID"
+        );
+        assert_eq!(voi_lut_function_of(&obj, linear).unwrap(), "LINEAR_EXACT");
+        assert_eq!(
+            voi_lut_function_of(&InMemDicomObject::new_empty(), LutOptions::default()).unwrap(),
+            "LINEAR"
+        );
+
+        // the full range of values spans all gray levels
+        let window_level = WindowLevel {
+            width: 3000.,
+            center: 500.,
+        };
+        assert_eq!(
+            apply_window_level(-1000., "LINEAR_EXACT", window_level, LUT_OUTPUT_MAX),
+            0.
+        );
+        assert_eq!(
+            apply_window_level(2000., "LINEAR_EXACT", window_level, LUT_OUTPUT_MAX),
+            LUT_OUTPUT_MAX
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn read_custom_presentation_lut() {
+        // an inverted ramp of 256 entries, one per line
+        let csv: String = (0..=255).rev().map(|y| format!("{}\n", y)).collect();
+        let lut = custom_presentation_lut_of(csv.as_bytes(), LutFileFormat::Text).unwrap();
+        assert!(lut.iter().enumerate().all(|(x, &y)| y as usize == 255 - x));
+
+        // a binary LUT of 3 entries, spread over the output values
+        let lut = custom_presentation_lut_of(&[0, 200, 100], LutFileFormat::Binary).unwrap();
+        assert_eq!(
+            (lut[0], lut[63], lut[64], lut[128], lut[191], lut[192]),
+            (0, 0, 200, 200, 200, 100)
+        );
+        assert_eq!(lut[255], 100);
+
+        // on one row, with a trailing separator
+        let lut = custom_presentation_lut_of(b"10, 20;\r\n", LutFileFormat::Text).unwrap();
+        assert_eq!((lut[0], lut[255]), (10, 20));
+
+        assert!(custom_presentation_lut_of(b"0,256", LutFileFormat::Text).is_err());
+        assert!(custom_presentation_lut_of(b"42", LutFileFormat::Text).is_err());
+        assert!(custom_presentation_lut_of(b"", LutFileFormat::Text).is_err());
+        // text which does not parse is not taken as bytes
+        assert!(custom_presentation_lut_of(b"0\t128\t255 # gamma", LutFileFormat::Text).is_err());
+        assert!(custom_presentation_lut_of(b"0.5, 1.5", LutFileFormat::Text).is_err());
+        // the same bytes as a binary LUT
+        let lut = custom_presentation_lut_of(b"0,1", LutFileFormat::Binary).unwrap();
+        assert_eq!((lut[0], lut[255]), (b'0', b'1'));
+    }
+
+    #[wasm_bindgen_test]
+    fn presentation_lut_after_window() {
+        use dicom::core::{DataElement, Length, VR};
+
+        // a 12-bit gamma of 2 over 256 entries
+        let entries: Vec<u16> = (0..256_u32)
+            .map(|i| (i * i * 4095 / (255 * 255)) as u16)
+            .collect();
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::PRESENTATION_LUT_SEQUENCE,
+            VR::SQ,
+            DicomValue::new_sequence(
+                vec![InMemDicomObject::from_element_iter([
+                    DataElement::new(
+                        tags::LUT_DESCRIPTOR,
+                        VR::US,
+                        PrimitiveValue::U16(vec![256, 0, 12].into()),
+                    ),
+                    DataElement::new(tags::LUT_DATA, VR::OW, PrimitiveValue::U16(entries.into())),
+                ])],
+                Length::UNDEFINED,
+            ),
+        )]);
+        let lut = presentation_lut_of(&obj).unwrap().unwrap();
+        assert_eq!(lut.bits, 12);
+        assert_eq!(lut.entries.len(), 256);
+        assert_eq!(
+            presentation_lut_of(&InMemDicomObject::new_empty()).unwrap(),
+            None
+        );
+
+        let window_level = WindowLevel {
+            width: 1000.,
+            center: 500.,
+        };
+        let display = |x: f64| {
+            let y = apply_window_level(x, "LINEAR", window_level, LUT_OUTPUT_MAX);
+            Rounding::default().apply(lut.apply(y))
+        };
+        // the ends of the window stay in place
+        assert_eq!(display(-100.), 0);
+        assert_eq!(display(1100.), 255);
+        // the middle of the window is darkened to a quarter
+        assert_eq!(display(500.), 64);
+        assert_eq!(display(750.), 143);
+    }
+
+    #[wasm_bindgen_test]
+    fn rescale_of_malformed_values() {
+        use dicom::core::{DataElement, VR};
+
+        let obj = |slope: &str, intercept: &str| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::RESCALE_SLOPE, VR::DS, PrimitiveValue::from(slope)),
+                DataElement::new(
+                    tags::RESCALE_INTERCEPT,
+                    VR::DS,
+                    PrimitiveValue::from(intercept),
+                ),
+            ])
+        };
+        let rescale = |slope: &str, intercept: &str| rescale_of(&obj(slope, intercept)).unwrap();
+
+        assert_eq!(rescale("2", "-1024 "), (2., -1024.));
+        // only the first of multiple values
+        assert_eq!(rescale("0.5\\2", "-1000\\0"), (0.5, -1000.));
+        // garbage falls back to the identity
+        assert_eq!(rescale("abc", "-1024"), (1., 0.));
+        assert_eq!(rescale("1", "??"), (1., 0.));
+        // which is told once, rather than on every lookup
+        assert!(rescale_warning_of(&obj("abc", "-1024")).is_some());
+        assert_eq!(rescale_warning_of(&obj("2", "-1024")), None);
+        // absent
+        assert_eq!(
+            rescale_of(&InMemDicomObject::new_empty()).unwrap(),
+            (1., 0.)
+        );
+        assert_eq!(rescale_warning_of(&InMemDicomObject::new_empty()), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn display_parameters_in_detector_information() {
+        use dicom::core::{DataElement, Length, VR};
+
+        let detector = |modality: &str| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from(modality)),
+                DataElement::new(
+                    tags::DETECTOR_INFORMATION_SEQUENCE,
+                    VR::SQ,
+                    DicomValue::new_sequence(
+                        vec![InMemDicomObject::from_element_iter([
+                            DataElement::new(
+                                tags::WINDOW_WIDTH,
+                                VR::DS,
+                                PrimitiveValue::from("4096"),
+                            ),
+                            DataElement::new(
+                                tags::WINDOW_CENTER,
+                                VR::DS,
+                                PrimitiveValue::from("2048"),
+                            ),
+                            DataElement::new(
+                                tags::RESCALE_SLOPE,
+                                VR::DS,
+                                PrimitiveValue::from("2"),
+                            ),
+                            DataElement::new(
+                                tags::RESCALE_INTERCEPT,
+                                VR::DS,
+                                PrimitiveValue::from("-10"),
+                            ),
+                        ])],
+                        Length::UNDEFINED,
+                    ),
+                ),
+            ])
+        };
+
+        let dx = detector("DX");
+        assert_eq!(
+            window_level_of(&dx).unwrap(),
+            Some(WindowLevel {
+                width: 4096.,
+                center: 2048.
+            })
+        );
+        assert_eq!(rescale_of(&dx).unwrap(), (2., -10.));
+
+        // the top level comes first
+        let mut cr = detector("CR ");
+        cr.put(DataElement::new(
+            tags::WINDOW_WIDTH,
+            VR::DS,
+            PrimitiveValue::from("100"),
+        ));
+        cr.put(DataElement::new(
+            tags::WINDOW_CENTER,
+            VR::DS,
+            PrimitiveValue::from("50"),
+        ));
+        cr.put(DataElement::new(
+            tags::RESCALE_INTERCEPT,
+            VR::DS,
+            PrimitiveValue::from("0"),
+        ));
+        assert_eq!(
+            window_level_of(&cr).unwrap(),
+            Some(WindowLevel {
+                width: 100.,
+                center: 50.
+            })
+        );
+        assert_eq!(rescale_of(&cr).unwrap(), (1., 0.));
+
+        // other modalities are not looked into
+        let ct = detector("CT");
+        assert_eq!(window_level_of(&ct).unwrap(), None);
+        assert_eq!(rescale_of(&ct).unwrap(), (1., 0.));
+    }
+
+    #[wasm_bindgen_test]
+    fn image_dimensions_with_padding() {
+        use dicom::core::{DataElement, VR};
+
+        let dimensions = |columns: PrimitiveValue, rows: PrimitiveValue, vr: VR| {
+            image_dimensions_of(&InMemDicomObject::from_element_iter([
+                DataElement::new(tags::COLUMNS, vr, columns),
+                DataElement::new(tags::ROWS, vr, rows),
+            ]))
+        };
+
+        assert_eq!(
+            dimensions(
+                PrimitiveValue::from(512_u16),
+                PrimitiveValue::from(256_u16),
+                VR::US
+            )
+            .unwrap(),
+            (512, 256)
+        );
+        // unexpected VR, padded with whitespace and null
+        assert_eq!(
+            dimensions(
+                PrimitiveValue::from(" 512 "),
+                PrimitiveValue::from("256\0"),
+                VR::IS
+            )
+            .unwrap(),
+            (512, 256)
+        );
+
+        assert!(matches!(
+            dimensions(
+                PrimitiveValue::from("512"),
+                PrimitiveValue::from("abc"),
+                VR::IS
+            ),
+            Err(Error::InvalidDimensions { .. })
+        ));
+        assert!(matches!(
+            dimensions(
+                PrimitiveValue::from(0_u16),
+                PrimitiveValue::from(256_u16),
+                VR::US
+            ),
+            Err(Error::InvalidDimensions { .. })
+        ));
+        assert!(matches!(
+            dimensions(
+                PrimitiveValue::from(65535_u16),
+                PrimitiveValue::from(65535_u16),
+                VR::US
+            ),
+            Err(Error::InvalidDimensions { .. })
+        ));
+        assert!(matches!(
+            image_dimensions_of(&InMemDicomObject::new_empty()),
+            Err(Error::InvalidDimensions { .. })
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    fn name_transfer_syntaxes() {
+        assert_eq!(
+            transfer_syntax_name("1.2.840.10008.1.2.1\0"),
+            Some("Explicit VR Little Endian")
+        );
+        assert_eq!(
+            transfer_syntax_name("1.2.840.10008.1.2.4.50"),
+            Some("JPEG Baseline (Process 1)")
+        );
+        assert_eq!(transfer_syntax_name("1.2.3.4"), None);
+        // every native and lossy transfer syntax has a name
+        for uid in NATIVE_TRANSFER_SYNTAXES
+            .iter()
+            .chain(LOSSY_TRANSFER_SYNTAXES)
+        {
+            assert!(transfer_syntax_name(uid).is_some(), "{}", uid);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn decoded_photometric_interpretations() {
+        // JPEG baseline applies the color transform
+        assert_eq!(
+            decoded_photometric_interpretation("YBR_FULL_422", "1.2.840.10008.1.2.4.50\0"),
+            "RGB"
+        );
+        // JPEG 2000 inverts the multi-component transform
+        assert_eq!(
+            decoded_photometric_interpretation("YBR_ICT", "1.2.840.10008.1.2.4.91"),
+            "RGB"
+        );
+        assert_eq!(
+            decoded_photometric_interpretation("YBR_RCT", "1.2.840.10008.1.2.4.90"),
+            "RGB"
+        );
+        // RLE and native pixel data are read as stored
+        assert_eq!(
+            decoded_photometric_interpretation("YBR_FULL", "1.2.840.10008.1.2.5"),
+            "YBR_FULL"
+        );
+        assert_eq!(
+            decoded_photometric_interpretation("YBR_FULL", "1.2.840.10008.1.2.1"),
+            "YBR_FULL"
+        );
+        assert_eq!(
+            decoded_photometric_interpretation("MONOCHROME2", "1.2.840.10008.1.2.4.50"),
+            "MONOCHROME2"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn window_level_of_numeric_vrs() {
+        use dicom::core::{DataElement, VR};
+
+        let window_level = |width: PrimitiveValue, center: PrimitiveValue, vr: VR| {
+            let obj = InMemDicomObject::from_element_iter([
+                DataElement::new(tags::WINDOW_WIDTH, vr, width),
+                DataElement::new(tags::WINDOW_CENTER, vr, center),
+            ]);
+            window_level_of(&obj).unwrap()
+        };
+        let expected = Some(WindowLevel {
+            width: 400.,
+            center: 40.,
+        });
+
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from("400\\1500"),
+                PrimitiveValue::from("40 "),
+                VR::DS
+            ),
+            expected
+        );
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from("400"),
+                PrimitiveValue::from("40"),
+                VR::IS
+            ),
+            expected
+        );
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from(400_u16),
+                PrimitiveValue::from(40_u16),
+                VR::US
+            ),
+            expected
+        );
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from(400_i16),
+                PrimitiveValue::from(40_i16),
+                VR::SS
+            ),
+            expected
+        );
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from(400_f32),
+                PrimitiveValue::from(40_f32),
+                VR::FL
+            ),
+            expected
+        );
+        assert_eq!(
+            window_level(
+                PrimitiveValue::from(400_f64),
+                PrimitiveValue::from(40_f64),
+                VR::FD
+            ),
+            expected
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn window_level_of_each_frame() {
+        use dicom::core::{DataElement, Length, VR};
+
+        let sequence = |tag, items: Vec<InMemDicomObject>| {
+            DataElement::new(
+                tag,
+                VR::SQ,
+                DicomValue::new_sequence(items, Length::UNDEFINED),
+            )
+        };
+        let functional_group = |width: &str, center: &str| {
+            InMemDicomObject::from_element_iter([sequence(
+                tags::FRAME_VOILUT_SEQUENCE,
+                vec![InMemDicomObject::from_element_iter([
+                    DataElement::new(tags::WINDOW_WIDTH, VR::DS, PrimitiveValue::from(width)),
+                    DataElement::new(tags::WINDOW_CENTER, VR::DS, PrimitiveValue::from(center)),
+                ])],
+            )])
+        };
+
+        let obj = InMemDicomObject::from_element_iter([
+            sequence(
+                tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE,
+                vec![
+                    functional_group("400", "40"),
+                    functional_group("1500", "-600"),
+                    InMemDicomObject::new_empty(),
+                ],
+            ),
+            sequence(
+                tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE,
+                vec![functional_group("80", "40")],
+            ),
+        ]);
+        let window_level = |frame| {
+            frame_window_level_of(&obj, frame)
+                .unwrap()
+                .map(|wl| (wl.width, wl.center))
+        };
+        assert_eq!(window_level(0), Some((400., 40.)));
+        assert_eq!(window_level(1), Some((1500., -600.)));
+        // falls back to the shared functional groups
+        assert_eq!(window_level(2), Some((80., 40.)));
+
+        assert_eq!(
+            frame_window_level_of(&InMemDicomObject::new_empty(), 0).unwrap(),
+            None
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn fix_wrong_meta_group_length() {
+        let mut data = vec![0; 128];
+        data.extend(b"DICM");
+        // (0002,0000) UL 4, declaring a wrong length of 99
+        data.extend([0x02, 0x00, 0x00, 0x00, b'U', b'L', 0x04, 0x00]);
+        data.extend(99_u32.to_le_bytes());
+        // (0002,0001) OB, 2 bytes
+        data.extend([0x02, 0x00, 0x01, 0x00, b'O', b'B', 0x00, 0x00]);
+        data.extend(2_u32.to_le_bytes());
+        data.extend([0x00, 0x01]);
+        // (0002,0010) UI, 20 bytes
+        data.extend([0x02, 0x00, 0x10, 0x00, b'U', b'I', 0x14, 0x00]);
+        data.extend(b"1.2.840.10008.1.2.1\0");
+        // (0008,0060) CS, start of the main data set
+        data.extend([0x08, 0x00, 0x60, 0x00, b'C', b'S', 0x02, 0x00]);
+        data.extend(b"OT");
+
+        let fixed = fix_meta_group_length(&data).unwrap();
+        assert_eq!(&fixed[140..144], &(14_u32 + 28).to_le_bytes());
+        assert_eq!(&fixed[144..], &data[144..]);
+
+        // nothing to fix the second time
+        assert_eq!(fix_meta_group_length(&fixed), None);
+
+        // a value length past the end of the data is left alone
+        let mut overflowing = data[..144].to_vec();
+        overflowing.extend([0x02, 0x00, 0x01, 0x00, b'O', b'B', 0x00, 0x00]);
+        overflowing.extend(u32::MAX.to_le_bytes());
+        assert_eq!(fix_meta_group_length(&overflowing), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn window_to_other_output_ranges() {
+        let window_level = WindowLevel {
+            width: 401.,
+            center: 40.,
+        };
+        for function in ["LINEAR", "LINEAR_EXACT", "SIGMOID"] {
+            for x in [-300., -100., 0., 40., 120., 500.] {
+                let y = apply_window_level(x, function, window_level, LUT_OUTPUT_MAX);
+                let y10 = apply_window_level(x, function, window_level, 1023.);
+                assert!((y10 - y * 1023. / 255.).abs() < 1e-9, "{} {}", function, x);
+            }
+        }
+
+        // the boundaries map to the ends of the output range
+        assert_eq!(apply_window_level(-161., "LINEAR", window_level, 1023.), 0.);
+        assert_eq!(
+            apply_window_level(240., "LINEAR", window_level, 1023.),
+            1023.
+        );
+        assert_eq!(
+            apply_window_level(-160.5, "LINEAR_EXACT", window_level, 1023.),
+            0.
+        );
+        assert_eq!(
+            apply_window_level(241., "LINEAR_EXACT", window_level, 1023.),
+            1023.
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn decompress_gzipped_files() {
+        use std::io::Write;
+
+        // a small file, in place of a DICOM file
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(gzipped.starts_with(&GZIP_MAGIC));
+
+        let decompressed = gunzip_if_compressed(&gzipped).unwrap();
+        assert!(matches!(decompressed, Cow::Owned(_)));
+        assert_eq!(&*decompressed, &data[..]);
+
+        // uncompressed data is kept as is
+        assert!(matches!(
+            gunzip_if_compressed(&data).unwrap(),
+            Cow::Borrowed(_)
+        ));
+
+        // truncated gzip data
+        assert!(gunzip_if_compressed(&gzipped[..10]).is_err());
+
+        // data decompressing to more than the limit
+        assert!(gunzip_with_limit(&gzipped, 1000).is_ok());
+        assert!(gunzip_with_limit(&gzipped, 999).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn read_metadata_without_pixel_data() {
+        // an explicit VR little endian element with a 2-byte length
+        let element = |group: u16, element: u16, vr: &[u8; 2], value: &[u8]| {
+            let mut data = Vec::new();
+            data.extend(group.to_le_bytes());
+            data.extend(element.to_le_bytes());
+            data.extend(vr);
+            data.extend((value.len() as u16).to_le_bytes());
+            data.extend(value);
+            data
+        };
+
+        let mut meta = Vec::new();
+        // (0002,0001) OB, with a 4-byte length
+        meta.extend([0x02, 0x00, 0x01, 0x00, b'O', b'B', 0x00, 0x00]);
+        meta.extend(2_u32.to_le_bytes());
+        meta.extend([0x00, 0x01]);
+        meta.extend(element(2, 0x0002, b"UI", b"1.2.840.10008.5.1.4.1.1.7\0"));
+        meta.extend(element(2, 0x0003, b"UI", b"1.2.3.4\0"));
+        meta.extend(element(2, 0x0010, b"UI", b"1.2.840.10008.1.2.1\0"));
+
+        let mut data = vec![0; 128];
+        data.extend(b"DICM");
+        data.extend(element(
+            2,
+            0x0000,
+            b"UL",
+            &(meta.len() as u32).to_le_bytes(),
+        ));
+        data.extend(meta);
+        data.extend(element(8, 0x0060, b"CS", b"OT"));
+        data.extend(element(0x0028, 0x0010, b"US", &2_u16.to_le_bytes()));
+        // (7FE0,0010) OW, with a 4-byte length
+        data.extend([0xE0, 0x7F, 0x10, 0x00, b'O', b'W', 0x00, 0x00]);
+        data.extend(4_u32.to_le_bytes());
+        data.extend([1, 2, 3, 4]);
+
+        let (obj, leniency) = byte_data_to_dicom_obj_lenient(&data, ReadExtent::Full).unwrap();
+        assert_eq!(leniency, None);
+        assert!(check_pixel_data(&obj).is_ok());
+
+        let (obj, _) = byte_data_to_dicom_obj_lenient(&data, ReadExtent::Metadata).unwrap();
+        assert_eq!(obj.element(tags::ROWS).unwrap().to_int::<u16>().unwrap(), 2);
+        assert!(matches!(check_pixel_data(&obj), Err(Error::NoPixelData)));
+
+        // the same file, gzipped
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &data).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let (obj, leniency) = byte_data_to_dicom_obj_lenient(&gzipped, ReadExtent::Full).unwrap();
+        assert_eq!(leniency, None);
+        assert!(check_pixel_data(&obj).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn downsample_high_frequency_pattern() {
+        // vertical stripes one pixel wide, alternating black and white
+        let (width, height) = (8, 8);
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let v = if i % 2 == 0 { 0 } else { 255 };
+                [v, v, v, 255]
+            })
+            .collect();
+
+        // nearest neighbor only ever picks the black stripes
+        let (thumb, thumb_w, thumb_h) =
+            thumbnail_of(&rgba, width, height, 4, Downsampling::Nearest).unwrap();
+        assert_eq!((thumb_w, thumb_h), (4, 4));
+        assert!(thumb.chunks_exact(4).all(|p| p == [0, 0, 0, 255]));
+
+        // area averaging shows them as the gray they look like from afar
+        let (thumb, _, _) = thumbnail_of(&rgba, width, height, 4, Downsampling::Area).unwrap();
+        assert!(thumb.chunks_exact(4).all(|p| p == [128, 128, 128, 255]));
+
+        // small images are kept as they are
+        let (thumb, thumb_w, _) =
+            thumbnail_of(&rgba, width, height, 16, Downsampling::Area).unwrap();
+        assert_eq!(thumb_w, width);
+        assert_eq!(thumb, rgba);
+
+        // too few samples for the image
+        assert!(thumbnail_of(&rgba[4..], width, height, 4, Downsampling::Nearest).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn warn_about_identifiable_pixels() {
+        let cs = |tag, value: &str| DataElement::new(tag, VR::CS, PrimitiveValue::from(value));
+
+        let obj = InMemDicomObject::from_element_iter([
+            cs(tags::BURNED_IN_ANNOTATION, "YES "),
+            cs(tags::RECOGNIZABLE_VISUAL_FEATURES, "NO"),
+        ]);
+        let warnings = identifiable_pixels_warnings_of(&obj);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("burned"));
+
+        let obj =
+            InMemDicomObject::from_element_iter([cs(tags::RECOGNIZABLE_VISUAL_FEATURES, "YES")]);
+        let warnings = identifiable_pixels_warnings_of(&obj);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("recognized"));
+
+        assert!(identifiable_pixels_warnings_of(&InMemDicomObject::new_empty()).is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn read_viewing_intent() {
+        let cs = |tag, value: &str| DataElement::new(tag, VR::CS, PrimitiveValue::from(value));
+
+        let intent = viewing_intent_of(&InMemDicomObject::from_element_iter([cs(
+            tags::PRESENTATION_INTENT_TYPE,
+            "FOR PROCESSING",
+        )]));
+        assert!(intent.for_processing());
+        assert_eq!(intent.recommended_viewing_mode, None);
+        assert_eq!(intent.warnings().len(), 1);
+
+        let intent = viewing_intent_of(&InMemDicomObject::from_element_iter([
+            cs(tags::PRESENTATION_INTENT_TYPE, "FOR PRESENTATION"),
+            cs(tags::RECOMMENDED_VIEWING_MODE, "SUB "),
+        ]));
+        assert!(!intent.for_processing());
+        assert_eq!(intent.recommended_viewing_mode.as_deref(), Some("SUB"));
+        assert!(intent.warnings()[0].contains("subtracted"));
+
+        let intent = viewing_intent_of(&InMemDicomObject::new_empty());
+        assert_eq!(intent, ViewingIntent::default());
+        assert!(intent.warnings().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn check_overridden_bit_depths() {
+        assert!(check_bit_depth(16, 12).is_ok());
+        assert!(check_bit_depth(8, 8).is_ok());
+        assert!(check_bit_depth(12, 12).is_err());
+        assert!(check_bit_depth(8, 12).is_err());
+        assert!(check_bit_depth(16, 0).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn override_declared_bit_depth() {
+        let obj = |bits_allocated: u16, pixel_data: PrimitiveValue| {
+            let meta = dicom::object::meta::FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+                .media_storage_sop_instance_uid("1.2.3.4")
+                .build()
+                .unwrap();
+            let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+            for elem in [
+                DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(3_u16)),
+                DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+                DataElement::new(
+                    tags::BITS_ALLOCATED,
+                    VR::US,
+                    PrimitiveValue::from(bits_allocated),
+                ),
+                DataElement::new(
+                    tags::BITS_STORED,
+                    VR::US,
+                    PrimitiveValue::from(bits_allocated),
+                ),
+                DataElement::new(tags::PIXEL_DATA, VR::OB, pixel_data),
+            ] {
+                obj.put(elem);
+            }
+            obj
+        };
+        let int_of =
+            |obj: &DefaultDicomObject, tag| obj.element(tag).unwrap().to_int::<u16>().unwrap();
+
+        // 3x2 bytes declared as 8 bits allocated, read as 12 of 16 bits
+        let bytes: Vec<u8> = (1..=12).collect();
+        let mut words = obj(8, PrimitiveValue::from(bytes.clone()));
+        override_bit_depth(&mut words, 16, 12).unwrap();
+        assert_eq!(int_of(&words, tags::BITS_ALLOCATED), 16);
+        assert_eq!(int_of(&words, tags::BITS_STORED), 12);
+        assert_eq!(int_of(&words, tags::HIGH_BIT), 11);
+        assert_eq!(
+            words.element(tags::PIXEL_DATA).unwrap().value().primitive(),
+            Some(&PrimitiveValue::U16(
+                vec![0x0201, 0x0403, 0x0605, 0x0807, 0x0A09, 0x0C0B].into()
+            ))
+        );
+
+        // too short for 16 bits: nothing changes
+        let mut short = obj(8, PrimitiveValue::from(bytes[..6].to_vec()));
+        assert!(override_bit_depth(&mut short, 16, 16).is_err());
+        assert_eq!(int_of(&short, tags::BITS_ALLOCATED), 8);
+        assert!(short.element(tags::HIGH_BIT).is_err());
+
+        // bytes read in pairs as words, and words split into bytes
+        assert_eq!(
+            repack_pixel_data(&PrimitiveValue::from(vec![0x34_u8, 0x12, 0x78, 0x56]), 16),
+            Some((VR::OW, PrimitiveValue::U16(vec![0x1234, 0x5678].into())))
+        );
+        assert_eq!(
+            repack_pixel_data(&PrimitiveValue::U16(vec![0x1234].into()), 8),
+            Some((VR::OB, PrimitiveValue::from(vec![0x34_u8, 0x12])))
+        );
+        assert_eq!(repack_pixel_data(&PrimitiveValue::from(bytes), 8), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn infer_mislabeled_bit_depth() {
+        let obj = |bits_allocated: u16, frames: &str, pixel_data: PrimitiveValue| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(3_u16)),
+                DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+                DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from(frames)),
+                DataElement::new(
+                    tags::BITS_ALLOCATED,
+                    VR::US,
+                    PrimitiveValue::from(bits_allocated),
+                ),
+                DataElement::new(tags::PIXEL_DATA, VR::OW, pixel_data),
+            ])
+        };
+
+        // declares 16 bits, holds 2 frames of 3x2 bytes
+        let bytes = PrimitiveValue::from(vec![1_u8, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(
+            inferred_bits_allocated_of(&obj(16, "2", bytes.clone())).unwrap(),
+            Some(8)
+        );
+        // declares 8 bits, holds 2 frames of 3x2 words
+        let words = PrimitiveValue::U16(vec![0x1234; 12].into());
+        assert_eq!(
+            inferred_bits_allocated_of(&obj(8, "2", words.clone())).unwrap(),
+            Some(16)
+        );
+
+        // as declared, or with nothing else fitting
+        assert_eq!(
+            inferred_bits_allocated_of(&obj(8, "2", bytes.clone())).unwrap(),
+            None
+        );
+        assert_eq!(
+            inferred_bits_allocated_of(&obj(16, "2", words)).unwrap(),
+            None
+        );
+        let odd = PrimitiveValue::from(vec![0_u8; 10]);
+        assert_eq!(
+            inferred_bits_allocated_of(&obj(16, "2", odd)).unwrap(),
+            None
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn infer_mismatched_layout() {
+        let obj = |columns: u16, rows: u16, bits_allocated: u16, pixel_data: PrimitiveValue| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(columns)),
+                DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(rows)),
+                DataElement::new(
+                    tags::BITS_ALLOCATED,
+                    VR::US,
+                    PrimitiveValue::from(bits_allocated),
+                ),
+                DataElement::new(tags::PIXEL_DATA, VR::OW, pixel_data),
+            ])
+        };
+
+        // declares 4x4, but only holds 4x2
+        let mismatched = obj(4, 4, 8, PrimitiveValue::from(vec![0_u8; 8]));
+        assert_eq!(inferred_layout_of(&mismatched).unwrap(), Some((4, 2, 1)));
+        // declares 3x3, holds a 4x4 square
+        let square = obj(3, 3, 16, PrimitiveValue::U16(vec![0; 16].into()));
+        assert_eq!(inferred_layout_of(&square).unwrap(), Some((4, 4, 1)));
+        // an odd 8-bit frame padded to an even length
+        let padded = obj(3, 3, 8, PrimitiveValue::from(vec![0_u8; 10]));
+        assert_eq!(inferred_layout_of(&padded).unwrap(), None);
+
+        // nothing fits 7 pixels
+        assert!(matches!(
+            inferred_layout_of(&obj(4, 4, 8, PrimitiveValue::from(vec![0_u8; 7]))),
+            Err(Error::InvalidDimensions { .. })
+        ));
+        // frames of different sizes
+        assert!(infer_layout(11, (2, 2), 2).is_err());
+        assert_eq!(infer_layout(12, (2, 2), 2).unwrap(), Some((2, 3, 2)));
+        // truncated to 2 whole frames and part of a third
+        assert_eq!(infer_layout(10, (2, 2), 4).unwrap(), Some((2, 2, 2)));
+        // not even a whole frame
+        assert!(infer_layout(3, (2, 2), 4).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn check_pixel_data_length_of_frames() {
+        // 2 frames of 3x2 pixels at 16 bits
+        let frame_len = 3 * 2;
+        assert!(check_pixel_data_len(24, 0, frame_len, 16).is_ok());
+        assert!(check_pixel_data_len(24, 1, frame_len, 16).is_ok());
+        assert!(check_pixel_data_len(24, 2, frame_len, 16).is_err());
+        // truncated
+        assert!(check_pixel_data_len(10, 0, frame_len, 16).is_err());
+        assert!(check_pixel_data_len(6, 0, frame_len, 8).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn resample_single_axis() {
+        // 2x2 image: black and white columns
+        let rgba = [
+            0, 0, 0, 255, 255, 255, 255, 255, //
+            0, 0, 0, 255, 255, 255, 255, 255,
+        ];
+
+        let (out, w, h) = resample_linear(&rgba, 2, 2, 2, 1);
+        assert_eq!((w, h), (4, 2));
+        let row: Vec<u8> = out[..16].chunks(4).map(|px| px[0]).collect();
+        assert_eq!(row, vec![0, 64, 191, 255]);
+        assert_eq!(&out[..16], &out[16..]);
+
+        // vertical resampling of the same image keeps columns sharp
+        let (out, w, h) = resample_linear(&rgba, 2, 2, 1, 3);
+        assert_eq!((w, h), (2, 6));
+        for px in out.chunks(8) {
+            assert_eq!(px, &rgba[..8]);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn pair_matching_window_presets() {
+        let presets = pair_window_presets(
+            &[400., 1500.],
+            &[40., -600.],
+            &["SOFT TISSUE".to_string(), "LUNG".to_string()],
+        );
+        assert_eq!(
+            presets,
+            vec![
+                VoiPreset {
+                    name: "SOFT TISSUE".to_string(),
+                    voi: Voi::Window(WindowLevel {
+                        width: 400.,
+                        center: 40.
+                    }),
+                },
+                VoiPreset {
+                    name: "LUNG".to_string(),
+                    voi: Voi::Window(WindowLevel {
+                        width: 1500.,
+                        center: -600.
+                    }),
+                },
+            ]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn pair_single_width_multi_center_window_presets() {
+        let presets = pair_window_presets(&[400.], &[40., 300.], &[]);
+        assert_eq!(
+            presets,
+            vec![VoiPreset {
+                name: "Preset 1".to_string(),
+                voi: Voi::Window(WindowLevel {
+                    width: 400.,
+                    center: 40.
+                }),
+            }]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn pair_mismatched_window_presets() {
+        let presets = pair_window_presets(&[400., 1500., 80.], &[40., -600.], &[]);
+        assert_eq!(presets.len(), 1);
+        assert_eq!(
+            presets[0].voi,
+            Voi::Window(WindowLevel {
+                width: 400.,
+                center: 40.
+            })
+        );
+
+        assert!(pair_window_presets(&[], &[40.], &[]).is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn normalize_padded_photometric_interpretation() {
+        assert_eq!(
+            normalize_photometric_interpretation("MONOCHROME2 "),
+            "MONOCHROME2"
+        );
+        assert_eq!(normalize_photometric_interpretation(" rgb\0"), "RGB");
+        assert_eq!(
+            resolve_conversion(
+                &normalize_photometric_interpretation("MONOCHROME2 "),
+                16,
+                1,
+                0
+            )
+            .unwrap(),
+            Conversion::Monochrome(Monochrome::Monochrome2)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn strip_overlay_bits_above_high_bit() {
+        let stored_bits = StoredBits {
+            bits_stored: 12,
+            high_bit: 11,
+        };
+        // overlay in bit 15
+        let x = 0x8000 | 0x0ABC;
+        assert_eq!(stored_bits.value(x), 0x0ABC);
+        assert!(stored_bits.has_overlay(x));
+        assert!(!stored_bits.has_overlay(0x0FFF));
+
+        // stored bits shifted up, overlay in bit 0
+        let stored_bits = StoredBits {
+            bits_stored: 12,
+            high_bit: 15,
+        };
+        let x = 0xABC0 | 0x0001;
+        assert_eq!(stored_bits.value(x), 0x0ABC);
+        assert!(stored_bits.has_overlay(x));
+        assert!(!stored_bits.has_overlay(0xFFF0));
+    }
+
+    #[wasm_bindgen_test]
+    fn shift_samples_by_high_bit() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let obj = |bits_allocated: u16, bits_stored: u16, high_bit: u16, pixel_data| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(1_u16)),
+                DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16)),
+                DataElement::new(
+                    tags::BITS_ALLOCATED,
+                    VR::US,
+                    PrimitiveValue::from(bits_allocated),
+                ),
+                DataElement::new(tags::BITS_STORED, VR::US, PrimitiveValue::from(bits_stored)),
+                DataElement::new(tags::HIGH_BIT, VR::US, PrimitiveValue::from(high_bit)),
+                DataElement::new(tags::PIXEL_DATA, VR::OW, pixel_data),
+            ])
+        };
+        let mut y_values = Vec::new();
+
+        // 6 bits stored from bit 6 down to bit 1,
+        // with an overlay in bit 7
+        let obj_8 = obj(
+            8,
+            6,
+            6,
+            PrimitiveValue::from(vec![0b1111_1110_u8, 0b0000_0010]),
+        );
+        let lut: Vec<u8> = (0..64).map(|x| x * 4).collect();
+        convert_monochrome_to_y_values(
+            &mut y_values,
+            &obj_8,
+            0,
+            Monochrome::Monochrome2,
+            &lut,
+            OutOfRange::Clamp,
+        )
+        .unwrap();
+        assert_eq!(y_values, [252, 252, 252, 255, 4, 4, 4, 255]);
+
+        // 12 bits stored from bit 13 down to bit 2
+        let obj_16 = obj(
+            16,
+            12,
+            13,
+            PrimitiveValue::U16([0x3FFC, 0x0004].into_iter().collect()),
+        );
+        let lut: Vec<u8> = (0..4096).map(|x| (x >> 4) as u8).collect();
+        convert_monochrome_to_y_values(
+            &mut y_values,
+            &obj_16,
+            0,
+            Monochrome::Monochrome2,
+            &lut,
+            OutOfRange::Clamp,
+        )
+        .unwrap();
+        assert_eq!(y_values, [255, 255, 255, 255, 0, 0, 0, 255]);
+
+        // the high bit must fall within the allocated bits
+        let obj_bad = obj(8, 6, 9, PrimitiveValue::from(vec![0_u8, 0]));
+        assert!(StoredBits::of(&obj_bad).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn look_up_samples_beyond_lut() {
+        let lut = [10, 20, 30, 40];
+        for out_of_range in [OutOfRange::Clamp, OutOfRange::Wrap, OutOfRange::Background] {
+            assert_eq!(out_of_range.lookup(&lut, 1), Some(20));
+        }
+        assert_eq!(OutOfRange::Clamp.lookup(&lut, 6), Some(40));
+        assert_eq!(OutOfRange::Wrap.lookup(&lut, 6), Some(30));
+        assert_eq!(OutOfRange::Background.lookup(&lut, 6), None);
+        assert_eq!(OutOfRange::Clamp.lookup(&[], 0), None);
+
+        // the background stays black in MONOCHROME1
+        let m1 = Monochrome::Monochrome1;
+        assert_eq!(y_value_of(&lut, 6, m1, OutOfRange::Clamp), 215);
+        assert_eq!(y_value_of(&lut, 6, m1, OutOfRange::Background), 0);
+        assert_eq!(OutOfRange::default(), OutOfRange::Clamp);
+        assert_eq!(OutOfRange::from_name("wrap"), Some(OutOfRange::Wrap));
+    }
+
+    #[wasm_bindgen_test]
+    fn detect_missing_pixel_data() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.840.10008.5.1.4.1.1.88.11"),
+        )]);
+        assert!(matches!(check_pixel_data(&obj), Err(Error::NoPixelData)));
+
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OW,
+            PrimitiveValue::Empty,
+        )]);
+        assert!(matches!(check_pixel_data(&obj), Err(Error::NoPixelData)));
+
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OW,
+            PrimitiveValue::from(vec![0_u8; 4]),
+        )]);
+        assert!(check_pixel_data(&obj).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn resolve_unsupported_conversion() {
+        let e = resolve_conversion("RGB", 32, 3, 0).unwrap_err();
+        let msg = e.to_string();
+        assert!(msg.contains("RGB"));
+        assert!(msg.contains("BitsAllocated 32"));
+        assert!(msg.contains("SamplesPerPixel 3"));
+        assert!(msg.contains("PlanarConfiguration 0"));
+    }
+
+    #[wasm_bindgen_test]
+    fn resolve_supported_photometric_interpretations() {
+        for pi in SUPPORTED_PHOTOMETRIC_INTERPRETATIONS {
+            let samples_per_pixel = match pi {
+                "PALETTE COLOR" => 1,
+                "ARGB" | "CMYK" => 4,
+                _ if monochrome_of(pi).is_some() => 1,
+                _ => 3,
+            };
+            assert!(
+                resolve_conversion(pi, 8, samples_per_pixel, 0).is_ok(),
+                "{}",
+                pi
+            );
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Conversion of DICOM data sets to the DICOM JSON model
+//! (PS3.18 Annex F).
+
+use dicom::core::value::{PrimitiveValue, Value};
+use dicom::core::{Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::imaging::{Error, Result};
+
+/// Attributes holding bulk pixel data, excluded from the output
+const BULK_PIXEL_DATA: &[Tag] = &[
+    tags::PIXEL_DATA,
+    tags::FLOAT_PIXEL_DATA,
+    tags::DOUBLE_FLOAT_PIXEL_DATA,
+];
+
+fn set(target: &Object, key: &str, value: &JsValue) -> Result<()> {
+    Reflect::set(target, &JsValue::from_str(key), value).map_err(|value| Error::Js { value })?;
+    Ok(())
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Convert a data set to a DICOM JSON object,
+/// leaving out bulk pixel data.
+pub fn to_dicom_json(obj: &InMemDicomObject) -> Result<JsValue> {
+    let out = Object::new();
+
+    for elem in obj {
+        let tag = elem.header().tag;
+        if BULK_PIXEL_DATA.contains(&tag) {
+            continue;
+        }
+
+        let vr = elem.vr();
+        let attribute = Object::new();
+        set(&attribute, "vr", &JsValue::from_str(&vr.to_string()))?;
+
+        match elem.value() {
+            Value::Sequence { items, .. } => {
+                let values: Array = items.iter().map(to_dicom_json).collect::<Result<_>>()?;
+                if values.length() > 0 {
+                    set(&attribute, "Value", &values)?;
+                }
+            }
+            Value::PixelSequence { .. } => {
+                // encapsulated data is bulk data
+            }
+            Value::Primitive(PrimitiveValue::Empty) => {}
+            Value::Primitive(value) => match vr {
+                VR::OB | VR::OD | VR::OF | VR::OL | VR::OV | VR::OW | VR::UN => {
+                    set(
+                        &attribute,
+                        "InlineBinary",
+                        &JsValue::from_str(&base64_encode(&value.to_bytes())),
+                    )?;
+                }
+                VR::AT => {
+                    let values: Array = match value {
+                        PrimitiveValue::Tags(tags) => tags
+                            .iter()
+                            .map(|t| {
+                                JsValue::from_str(&format!("{:04X}{:04X}", t.group(), t.element()))
+                            })
+                            .collect(),
+                        _ => Array::new(),
+                    };
+                    set(&attribute, "Value", &values)?;
+                }
+                VR::DS
+                | VR::FD
+                | VR::FL
+                | VR::IS
+                | VR::SL
+                | VR::SS
+                | VR::SV
+                | VR::UL
+                | VR::US
+                | VR::UV => {
+                    let values: Array = match value.to_multi_float64() {
+                        Ok(numbers) => numbers.into_iter().map(JsValue::from_f64).collect(),
+                        // not parseable as numbers, keep the original text
+                        Err(_) => value
+                            .to_multi_str()
+                            .iter()
+                            .map(|v| JsValue::from_str(v.trim()))
+                            .collect(),
+                    };
+                    set(&attribute, "Value", &values)?;
+                }
+                VR::PN => {
+                    let values = Array::new();
+                    for name in value.to_multi_str().iter() {
+                        let name = name.trim_end_matches(|c: char| c == ' ' || c == '\0');
+                        if name.is_empty() {
+                            values.push(&JsValue::NULL);
+                        } else {
+                            let pn = Object::new();
+                            set(&pn, "Alphabetic", &JsValue::from_str(name))?;
+                            values.push(&pn);
+                        }
+                    }
+                    set(&attribute, "Value", &values)?;
+                }
+                _ => {
+                    let values: Array = value
+                        .to_multi_str()
+                        .iter()
+                        .map(|v| v.trim_end_matches(|c: char| c == ' ' || c == '\0'))
+                        .map(|v| {
+                            if v.is_empty() {
+                                JsValue::NULL
+                            } else {
+                                JsValue::from_str(v)
+                            }
+                        })
+                        .collect();
+                    set(&attribute, "Value", &values)?;
+                }
+            },
+        }
+
+        let key = format!("{:04X}{:04X}", tag.group(), tag.element());
+        set(&out, &key, &attribute)?;
+    }
+
+    Ok(out.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn base64_encodes_with_padding() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -1,27 +1,115 @@
 use wasm_bindgen::prelude::*;
+use web_sys::IdleDeadline;
 use web_sys::ImageData;
+use web_sys::KeyboardEvent;
 use web_sys::MouseEvent;
+use web_sys::WheelEvent;
 
 use std::cell::Cell;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::rc::Rc;
 
 use dicom::object::DefaultDicomObject;
 use gloo_file::Blob;
 use wasm_bindgen::JsCast;
 use web_sys::HtmlElement;
-use web_sys::{self, CanvasRenderingContext2d, HtmlCanvasElement};
+use web_sys::{
+    self, CanvasRenderingContext2d, HtmlCanvasElement, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d,
+};
 
+pub mod annotation;
+pub mod color;
+pub mod difference;
+pub mod display;
+pub mod filter;
+pub mod geometry;
+pub mod histogram;
 pub mod imaging;
+pub mod json;
+pub mod npy;
+pub mod overlay;
+pub mod parametric;
+pub mod preset;
+pub mod probe;
+pub mod rle;
+pub mod sheet;
+pub mod sr;
+pub mod study;
+pub mod tile;
+pub mod viewport;
+pub mod waveform;
 
+use annotation::{arrow_head, ruler_length, Annotation, LengthUnit, MeasurementFormat};
+use color::{color_space_of_imagedata, new_imagedata, ColorBalance, DisplayColorSpace};
+use difference::{difference_to_imagedata, sampled_stored_values_of, stored_values_of};
+use display::{DisplayOptions, DisplaySettings};
+use filter::{unsharp_mask, UnsharpMask, MAX_UNSHARP_RADIUS};
+use geometry::{
+    frame_of_reference_uid_of, frame_pixel_spacing, image_plane_of, same_frame_of_reference,
+    ImagePlane, ReferencePlane,
+};
+use histogram::{dynamic_range_of, DynamicRange};
 use imaging::{
-    byte_data_to_dicom_obj, obj_to_imagedata, update_pixel_data_lut_with, window_level_of,
-    WindowLevel,
+    byte_data_to_dicom_obj_lenient, check_displayable, cine_frame_rate_of,
+    custom_presentation_lut_of, frame_window_level_of, identifiable_pixels_warnings_of,
+    image_dimensions_of, lossy_compression_of, modality_of, monochrome_of, number_of_frames_of,
+    obj_to_imagedata, override_bit_depth, pixel_data_encoding_of, pixel_padding_of,
+    preset_previews_of, reconcile_bit_depth, reconcile_layout, resample_linear, rescale_warning_of,
+    simple_pixel_data_lut, simple_pixel_data_lut_with, simple_pixel_data_lut_with_voi,
+    split_column, split_imagedata, string_of, transfer_syntax_of, update_pixel_data_lut_with,
+    viewing_intent_of, voi_presets_of, window_level_of, ConversionOptions, Downsampling,
+    KeyWindowLevelSteps, LutFileFormat, LutOptions, Monochrome, OutOfRange, ReadExtent, Rounding,
+    ViewingIntent, Voi, VoiLut, VoiPreset, WindowLevel, SUPPORTED_PHOTOMETRIC_INTERPRETATIONS,
+};
+use overlay::{overlay_layers_of, overlay_roi_statistics_of, OverlayLayer, OverlayType};
+use parametric::{
+    float_to_imagedata, float_window_level_of, float_window_step_of, has_float_pixel_data,
+    Diverging,
 };
+use probe::{probe_pixel, probe_text, rescale_unit_of};
+use rle::{
+    inferred_frames_warning, is_rle_lossless, native_frame, DecodedFrames, DEFAULT_DECODED_FRAMES,
+};
+use sheet::{sample_evenly, ContactSheet, MAX_SHEET_COLUMNS, SHEET_THUMBNAIL_SIZES};
+use sr::{is_structured_report, sop_instance_uid_of, sr_measurements_of, SrMeasurement};
+use tile::{tile_region, visible_tiles, TileCache, TileKey, TiledImage};
+use viewport::{Viewport, ViewportMotion, FRAME_DURATION, MOMENTUM_TIMEOUT};
+use waveform::{is_waveform, plot_points, waveform_of, Waveform};
 
-fn clear(context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
-    context.set_fill_style(&JsValue::from_str("#000"));
-    context.fill_rect(0., 0., 640., 640.);
+/// The maximum width and height of each window preset preview
+const PRESET_PREVIEW_SIZE: u32 = 64;
+
+/// How window preset previews are downsampled,
+/// averaging so that fine structures do not alias
+const PRESET_PREVIEW_DOWNSAMPLING: Downsampling = Downsampling::Area;
+
+/// The colors at the top and bottom of the gradient background
+const BACKGROUND_GRADIENT: (&str, &str) = ("#303030", "#000");
+
+/// Paint the whole output canvas with the background around the image
+fn clear(
+    context: &CanvasRenderingContext2d,
+    out_canvas: &HtmlCanvasElement,
+    background: Background,
+) -> Result<(), JsValue> {
+    let (width, height) = (out_canvas.width() as f64, out_canvas.height() as f64);
+    match background {
+        Background::Letterbox([r, g, b]) => {
+            context.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r, g, b)));
+        }
+        Background::Gradient => {
+            let gradient = context.create_linear_gradient(0., 0., 0., height);
+            gradient.add_color_stop(0., BACKGROUND_GRADIENT.0)?;
+            gradient.add_color_stop(1., BACKGROUND_GRADIENT.1)?;
+            context.set_fill_style(&gradient);
+        }
+        // only seen when zoomed out or panned
+        Background::Stretch => context.set_fill_style(&JsValue::from_str("#000")),
+    }
+    context.fill_rect(0., 0., width, height);
     Ok(())
 }
 
@@ -67,35 +155,528 @@ fn set_error_messsage(msg: &str) {
     error_message.set_inner_html(msg);
 }
 
+fn set_warning_message(msg: &str) {
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let warning_message = document.get_element_by_id("warning-message").unwrap();
+    warning_message.set_text_content(Some(msg));
+}
+
 fn render_image_to_canvas(
     imagedata: ImageData,
-    canvas: &HtmlCanvasElement,
-    canvas_context: &CanvasRenderingContext2d,
+    canvas: &InnerCanvas,
     out_canvas: &HtmlCanvasElement,
     out_canvas_context: &CanvasRenderingContext2d,
-) -> Result<(), JsValue> {
-    clear(out_canvas_context)?;
+    placement: Placement,
+    smoothing: Smoothing,
+) -> Result<ImageTransform, JsValue> {
+    out_canvas_context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    clear(out_canvas_context, out_canvas, placement.background)?;
 
     let w = imagedata.width();
     let h = imagedata.height();
+    let transform = image_transform_on(out_canvas, placement, (w, h));
+    let (scale_x, scale_y) = transform.scale;
+    let (x, y) = transform.offset;
 
-    // send to inner canvas with original size
-    canvas.set_width(w);
-    canvas.set_height(h);
-    canvas_context.put_image_data(&imagedata, 0., 0.)?;
-
-    // scale to fit output canvas
-    let scale = if w > h {
-        out_canvas.width() as f64 / w as f64
+    // The canvas can only smooth both axes or none,
+    // so smoothing a single axis is done here by resampling along it
+    // and drawing without canvas smoothing.
+    let resample_factor =
+        |axis_scale: f64| (axis_scale.ceil() as u32).clamp(1, MAX_RESAMPLE_FACTOR);
+    let (factor_x, factor_y) = match smoothing {
+        Smoothing::Horizontal => (resample_factor(scale_x), 1),
+        Smoothing::Vertical => (1, resample_factor(scale_y)),
+        Smoothing::Off | Smoothing::On => (1, 1),
+    };
+    let imagedata = if factor_x > 1 || factor_y > 1 {
+        let data = imagedata.data();
+        let (data, rw, rh) = resample_linear(&data, w, h, factor_x, factor_y);
+        new_imagedata(&data, rw, rh, color_space_of_imagedata(&imagedata))?
     } else {
-        out_canvas.height() as f64 / h as f64
+        imagedata
     };
 
+    // send to inner canvas
+    canvas.put_image_data(&imagedata)?;
+
     // set scaling transformation
-    out_canvas_context.set_transform(scale, 0., 0., scale, 0., 0.)?;
+    out_canvas_context.set_transform(
+        scale_x / factor_x as f64,
+        0.,
+        0.,
+        scale_y / factor_y as f64,
+        x,
+        y,
+    )?;
+    out_canvas_context.set_image_smoothing_enabled(smoothing == Smoothing::On);
 
     // draw contents of inner canvas to outer canvas
-    out_canvas_context.draw_image_with_html_canvas_element(canvas, 0., 0.)?;
+    canvas.draw_onto(out_canvas_context, 0., 0.)?;
+
+    Ok(transform)
+}
+
+/// Where an image of the given size is placed on the output canvas.
+fn image_transform_on(
+    out_canvas: &HtmlCanvasElement,
+    placement: Placement,
+    (w, h): (u32, u32),
+) -> ImageTransform {
+    // scale to the output canvas,
+    // stretching vertically by the pixel aspect ratio
+    // unless stretched to fill it
+    let client_width = out_canvas.client_width();
+    let css_scale = if client_width > 0 {
+        out_canvas.width() as f64 / client_width as f64
+    } else {
+        1.
+    };
+    let viewport = placement.viewport;
+    let (target_w, target_h) = (out_canvas.width() as f64, out_canvas.height() as f64);
+    let (scale_x, scale_y) = placement.base_scales(w, h, target_w, target_h, css_scale);
+    let (scale_x, scale_y) = (scale_x * viewport.zoom, scale_y * viewport.zoom);
+
+    // center the image, then apply panning
+    let x = (target_w - w as f64 * scale_x) / 2. + viewport.pan.0;
+    let y = (target_h - h as f64 * scale_y) / 2. + viewport.pan.1;
+
+    ImageTransform {
+        scale: (scale_x, scale_y),
+        offset: (x, y),
+    }
+}
+
+/// Render a large image one tile at a time,
+/// windowing only the tiles visible on the output canvas
+/// which are not cached yet.
+fn render_tiles_to_canvas(
+    image: &TiledImage,
+    tiles: &mut TileCache<ImageData>,
+    canvas: &InnerCanvas,
+    out_canvas: &HtmlCanvasElement,
+    out_canvas_context: &CanvasRenderingContext2d,
+    placement: Placement,
+) -> Result<ImageTransform, JsValue> {
+    out_canvas_context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    clear(out_canvas_context, out_canvas, placement.background)?;
+
+    let transform = image_transform_on(out_canvas, placement, image.size);
+    let visible = (
+        transform.to_image((0., 0.)),
+        transform.to_image((out_canvas.width() as f64, out_canvas.height() as f64)),
+    );
+    let generation = tiles.sync(image.fingerprint());
+
+    out_canvas_context.set_transform(
+        transform.scale.0,
+        0.,
+        0.,
+        transform.scale.1,
+        transform.offset.0,
+        transform.offset.1,
+    )?;
+    for tile in visible_tiles(image.size, visible) {
+        let region = tile_region(tile, image.size);
+        let key = TileKey { tile, generation };
+        let imagedata = match tiles.get(key) {
+            Some(imagedata) => imagedata,
+            None => {
+                let imagedata = image.window(region)?;
+                tiles.insert(key, imagedata.clone());
+                imagedata
+            }
+        };
+
+        // send to inner canvas, then draw in place
+        canvas.put_image_data(&imagedata)?;
+        canvas.draw_onto(out_canvas_context, region.x as f64, region.y as f64)?;
+    }
+
+    Ok(transform)
+}
+
+/// Render image data directly to the output canvas,
+/// resizing the canvas to the image's native size
+/// and letting CSS scale it to fit the display area.
+fn render_image_to_canvas_directly(
+    imagedata: ImageData,
+    out_canvas: &HtmlCanvasElement,
+    out_canvas_context: &CanvasRenderingContext2d,
+    display_size: (u32, u32),
+    placement: Placement,
+    smoothing: Smoothing,
+) -> Result<ImageTransform, JsValue> {
+    let w = imagedata.width();
+    let h = imagedata.height();
+
+    if out_canvas.width() != w || out_canvas.height() != h {
+        out_canvas.set_width(w);
+        out_canvas.set_height(h);
+    }
+    out_canvas_context.put_image_data(&imagedata, 0., 0.)?;
+
+    // scale to the display area,
+    // stretching vertically by the pixel aspect ratio
+    // unless stretched to fill it
+    let (display_w, display_h) = display_size;
+    let (scale_x, scale_y) = placement.base_scales(w, h, display_w as f64, display_h as f64, 1.);
+    let viewport = placement.viewport;
+
+    out_canvas.set_attribute(
+        "style",
+        &format!(
+            "width: {}px; height: {}px; transform: translate({}px, {}px) scale({}); image-rendering: {}",
+            (w as f64 * scale_x).round(),
+            (h as f64 * scale_y).round(),
+            viewport.pan.0,
+            viewport.pan.1,
+            viewport.zoom,
+            // CSS cannot smooth a single axis, smooth both instead
+            if smoothing == Smoothing::Off {
+                "pixelated"
+            } else {
+                "auto"
+            },
+        ),
+    )?;
+
+    // the canvas holds the image at its native size
+    Ok(ImageTransform::IDENTITY)
+}
+
+/// The color of annotations drawn over the image
+const ANNOTATION_COLOR: &str = "#ffff00";
+/// The length of the head of annotation arrows (canvas pixels)
+const ARROW_HEAD_LENGTH: f64 = 12.;
+
+/// The color of the linked cursor
+const LINKED_CURSOR_COLOR: &str = "#00ff00";
+/// The radius of the linked cursor dot (canvas pixels)
+const LINKED_CURSOR_RADIUS: f64 = 4.;
+/// How far the linked cursor may be from the image plane to be shown (mm)
+const LINKED_CURSOR_TOLERANCE: f64 = 5.;
+/// The color of the reference line of the image in a linked view
+const REFERENCE_LINE_COLOR: &str = "#00ffff";
+
+/// The color of the line between the two sides of a split view
+const SPLIT_LINE_COLOR: &str = "#ffffff";
+/// How close to the split line a drag moves it (canvas pixels)
+const SPLIT_GRAB_DISTANCE: f64 = 6.;
+
+/// The image under another window preset on the right of a split line,
+/// to compare it with the current window on the left
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SplitView {
+    /// the index of the preset shown on the right
+    preset: usize,
+    /// the position of the split line, as a fraction of the image width
+    position: f64,
+}
+
+/// Draw the split line over the rendered image,
+/// labeling the preset shown on each side.
+fn draw_split_line(
+    context: &CanvasRenderingContext2d,
+    split: Option<(&str, f64)>,
+    (width, height): (u32, u32),
+    transform: ImageTransform,
+) -> Result<(), JsValue> {
+    let (name, position) = match split {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let x = split_column(position, width) as f64;
+    let (top_x, top_y) = transform.to_canvas((x, 0.));
+    let (bottom_x, bottom_y) = transform.to_canvas((x, height as f64));
+
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_stroke_style(&JsValue::from_str(SPLIT_LINE_COLOR));
+    context.set_line_width(2.);
+    context.begin_path();
+    context.move_to(top_x, top_y);
+    context.line_to(bottom_x, bottom_y);
+    context.stroke();
+
+    context.set_fill_style(&JsValue::from_str(SPLIT_LINE_COLOR));
+    context.set_font("14px sans-serif");
+    let y = top_y.max(0.) + 18.;
+    context.set_text_align("right");
+    context.fill_text("Current", top_x - 6., y)?;
+    context.set_text_align("left");
+    context.fill_text(name, top_x + 6., y)?;
+    context.set_text_align("start");
+    Ok(())
+}
+
+/// Draw the linked cursor over the rendered image
+/// if it lies on the image plane (within tolerance)
+/// and inside the image.
+fn draw_linked_cursor(
+    context: &CanvasRenderingContext2d,
+    cursor: Option<[f64; 3]>,
+    plane: Option<&ImagePlane>,
+    transform: ImageTransform,
+) -> Result<(), JsValue> {
+    let (cursor, plane) = match (cursor, plane) {
+        (Some(cursor), Some(plane)) => (cursor, plane),
+        _ => return Ok(()),
+    };
+    let ((x, y), distance) = plane.to_pixel(cursor);
+    let inside = x >= 0. && x <= plane.columns as f64 && y >= 0. && y <= plane.rows as f64;
+    if distance > LINKED_CURSOR_TOLERANCE || !inside {
+        return Ok(());
+    }
+
+    let (x, y) = transform.to_canvas((x, y));
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_fill_style(&JsValue::from_str(LINKED_CURSOR_COLOR));
+    context.begin_path();
+    context.arc(x, y, LINKED_CURSOR_RADIUS, 0., std::f64::consts::PI * 2.)?;
+    context.fill();
+    Ok(())
+}
+
+/// Draw the line where the plane of the image in a linked view
+/// crosses the image plane, if they intersect within the image.
+fn draw_reference_line(
+    context: &CanvasRenderingContext2d,
+    reference: Option<&ReferencePlane>,
+    plane: Option<&ImagePlane>,
+    transform: ImageTransform,
+) -> Result<(), JsValue> {
+    let line = match (reference, plane) {
+        (Some(reference), Some(plane)) => plane.line_of(reference),
+        _ => None,
+    };
+    let [start, end] = match line {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+
+    let (start, end) = (transform.to_canvas(start), transform.to_canvas(end));
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_stroke_style(&JsValue::from_str(REFERENCE_LINE_COLOR));
+    context.set_line_width(1.);
+    context.begin_path();
+    context.move_to(start.0, start.1);
+    context.line_to(end.0, end.1);
+    context.stroke();
+    Ok(())
+}
+
+/// Label the photometric interpretation forced onto the image, if any,
+/// in the top left corner of the canvas.
+fn draw_forced_photometric_label(
+    context: &CanvasRenderingContext2d,
+    force_photometric: Option<&str>,
+) -> Result<(), JsValue> {
+    let photometric_interpretation = match force_photometric {
+        Some(photometric_interpretation) => photometric_interpretation,
+        None => return Ok(()),
+    };
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_fill_style(&JsValue::from_str(ANNOTATION_COLOR));
+    context.set_font("16px sans-serif");
+    context.fill_text(&format!("Forced: {}", photometric_interpretation), 8., 20.)
+}
+
+/// The color of waveform plots
+const WAVEFORM_COLOR: &str = "#00ff66";
+
+/// Plot the channels of a waveform on the canvas,
+/// each in a band of its own from top to bottom,
+/// with its label and the duration and sampling frequency of the waveform.
+fn draw_waveform(
+    context: &CanvasRenderingContext2d,
+    (width, height): (f64, f64),
+    waveform: &Waveform,
+) -> Result<(), JsValue> {
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_fill_style(&JsValue::from_str("#000"));
+    context.fill_rect(0., 0., width, height);
+
+    let margin = 8.;
+    let band = (height - 2. * margin) / waveform.channels.len() as f64;
+    context.set_font("14px sans-serif");
+    context.set_line_width(1.);
+    for (i, channel) in waveform.channels.iter().enumerate() {
+        let top = margin + i as f64 * band;
+        let rect = (margin, top + 4., width - 2. * margin, band - 8.);
+        let points = plot_points(&channel.samples, rect);
+
+        context.set_stroke_style(&JsValue::from_str(WAVEFORM_COLOR));
+        context.begin_path();
+        for (j, &(x, y)) in points.iter().enumerate() {
+            if j == 0 {
+                context.move_to(x, y);
+            } else {
+                context.line_to(x, y);
+            }
+        }
+        context.stroke();
+
+        let label = channel
+            .label
+            .clone()
+            .unwrap_or_else(|| format!("Channel {}", i + 1));
+        context.set_fill_style(&JsValue::from_str(ANNOTATION_COLOR));
+        context.fill_text(&label, margin, top + 16.)?;
+    }
+
+    context.set_fill_style(&JsValue::from_str(ANNOTATION_COLOR));
+    context.fill_text(
+        &format!(
+            "{:.2} s, {} Hz",
+            waveform.duration(),
+            waveform.sampling_frequency
+        ),
+        margin,
+        height - margin,
+    )
+}
+
+/// Plot a waveform object on the canvas in place of an image.
+///
+/// The previous object is no longer displayed,
+/// so that nothing renders over the plot.
+/// Returns whether the waveform is now displayed.
+fn show_waveform(state: &Rc<RefCell<State>>, obj: &DefaultDicomObject) -> bool {
+    let waveform = match waveform_of(obj) {
+        Ok(waveform) => waveform,
+        Err(e) => {
+            let msg = format!("Failed to load waveform: {}", e);
+            gloo_console::error!(&msg);
+            set_error_messsage(&msg);
+            return false;
+        }
+    };
+    gloo_console::log!(
+        "Waveform with",
+        waveform.channels.len(),
+        "channels at",
+        waveform.sampling_frequency,
+        "Hz"
+    );
+
+    stop_viewport_motion(state);
+    let mut state = state.borrow_mut();
+    state.dicom_obj = None;
+    state.frame = 0;
+    state.number_of_frames = 1;
+    state.image_transform = None;
+    let size = (
+        state.out_canvas.width() as f64,
+        state.out_canvas.height() as f64,
+    );
+    match draw_waveform(&state.out_canvas_context, size, &waveform) {
+        Ok(()) => {
+            set_error_messsage("");
+            true
+        }
+        Err(e) => {
+            gloo_console::error!("Could not plot the waveform:", e);
+            set_error_messsage("Sorry, could not plot the waveform. :(");
+            false
+        }
+    }
+}
+
+/// Draw the annotations over the rendered image,
+/// mapping them to the canvas with the given transform.
+///
+/// Rulers are labeled with their length in the given format,
+/// or in pixels if the pixel spacing is not known.
+fn draw_annotations(
+    context: &CanvasRenderingContext2d,
+    annotations: &[Annotation],
+    transform: ImageTransform,
+    pixel_spacing: Option<(f64, f64)>,
+    format: MeasurementFormat,
+) -> Result<(), JsValue> {
+    if annotations.is_empty() {
+        return Ok(());
+    }
+
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    context.set_stroke_style(&JsValue::from_str(ANNOTATION_COLOR));
+    context.set_fill_style(&JsValue::from_str(ANNOTATION_COLOR));
+    context.set_line_width(2.);
+    context.set_font("16px sans-serif");
+
+    for annotation in annotations {
+        match annotation {
+            Annotation::Text { position, text } => {
+                let (x, y) = transform.to_canvas(*position);
+                context.fill_text(text, x, y)?;
+            }
+            Annotation::Arrow { tip, tail } => {
+                let tip = transform.to_canvas(*tip);
+                let tail = transform.to_canvas(*tail);
+                context.begin_path();
+                context.move_to(tail.0, tail.1);
+                context.line_to(tip.0, tip.1);
+                for (x, y) in arrow_head(tip, tail, ARROW_HEAD_LENGTH) {
+                    context.move_to(tip.0, tip.1);
+                    context.line_to(x, y);
+                }
+                context.stroke();
+            }
+            Annotation::Ruler { start, end } => {
+                let label = match pixel_spacing {
+                    Some(spacing) => format.format_length(ruler_length(*start, *end, spacing)),
+                    None => format!(
+                        "{:.*} px",
+                        format.decimals,
+                        ruler_length(*start, *end, (1., 1.))
+                    ),
+                };
+                let (start, end) = (transform.to_canvas(*start), transform.to_canvas(*end));
+                context.begin_path();
+                context.move_to(start.0, start.1);
+                context.line_to(end.0, end.1);
+                context.stroke();
+                context.fill_text(&label, end.0 + 6., end.1)?;
+            }
+            Annotation::Polyline { points, closed } => {
+                context.begin_path();
+                for (i, point) in points.iter().enumerate() {
+                    let (x, y) = transform.to_canvas(*point);
+                    if i == 0 {
+                        context.move_to(x, y);
+                    } else {
+                        context.line_to(x, y);
+                    }
+                }
+                if *closed {
+                    context.close_path();
+                }
+                context.stroke();
+            }
+            Annotation::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                // trace the ellipse in image pixels,
+                // but stroke it with the usual line width
+                let ImageTransform { scale, offset } = transform;
+                context.set_transform(scale.0, 0., 0., scale.1, offset.0, offset.1)?;
+                context.begin_path();
+                context.ellipse(
+                    center.0,
+                    center.1,
+                    radii.0,
+                    radii.1,
+                    *rotation,
+                    0.,
+                    std::f64::consts::PI * 2.,
+                )?;
+                context.set_transform(1., 0., 0., 1., 0., 0.)?;
+                context.stroke();
+            }
+        }
+    }
 
     Ok(())
 }
@@ -105,14 +686,52 @@ fn render_obj_to_canvas(state: &RefCell<State>) {
     let State {
         dicom_obj,
         lut,
-        window_level: _,
+        window_level,
         canvas,
-        canvas_context,
         out_canvas,
         out_canvas_context,
         y_samples,
+        imagedata,
+        render_strategy,
+        display_size,
+        pixel_spacing,
+        scale_mode,
+        viewport,
+        background,
+        smoothing,
+        display,
+        color_balance,
+        diverging,
+        overlays,
+        frame,
+        annotations,
+        image_transform,
+        measurement_format,
+        lut_options,
+        flip_planar,
+        force_photometric,
+        sharpen,
+        unsharp_mask,
+        image_plane,
+        linked_cursor,
+        reference_plane,
+        frame_of_reference_uid,
+        tiles,
+        baseline,
+        presets,
+        split,
+        split_lut,
+        decoded_frames,
+        ..
     } = &mut *state;
 
+    let placement = Placement {
+        pixel_spacing: *pixel_spacing,
+        scale_mode: *scale_mode,
+        viewport: *viewport,
+        background: *background,
+    };
+
     let obj = if let Some(obj) = &dicom_obj {
         obj
     } else {
@@ -120,22 +739,200 @@ fn render_obj_to_canvas(state: &RefCell<State>) {
         return;
     };
 
-    match obj_to_imagedata(obj, y_samples, lut) {
-        Ok(imagedata) => {
-            render_image_to_canvas(
-                imagedata,
+    // RLE frames are decoded when shown, into a single frame of native pixel data
+    let native = match native_frame(decoded_frames, obj, *frame) {
+        Ok(native) => native,
+        Err(e) => {
+            let msg = format!("Failed to decode RLE frame: {}", e);
+            gloo_console::error!(&msg);
+            set_error_messsage(&msg);
+            return;
+        }
+    };
+    let obj = native.obj();
+    let frame = &native.frame();
+
+    // the preset shown on the right of the split line, if any,
+    // for monochrome images only as color images have no window
+    let split = split
+        .filter(|_| displayed_monochrome_of(obj, *force_photometric).is_some())
+        .and_then(|split| Some((split.preset, presets.get(split.preset)?, split.position)));
+
+    // large monochrome images are windowed tile by tile,
+    // unless the whole image is needed
+    // or it is read with another photometric interpretation
+    // or decoded one frame at a time
+    let tiling = match (*render_strategy, *smoothing, *sharpen, &baseline) {
+        (RenderStrategy::DoubleCanvas, Smoothing::On | Smoothing::Off, false, None)
+            if force_photometric.is_none() && split.is_none() && !native.is_decoded() =>
+        {
+            TiledImage::tiling_of(obj)
+        }
+        _ => None,
+    };
+
+    let rendered = match tiling {
+        Some((size, monochrome)) => ensure_lut(lut, obj, *lut_options).map(|lut| {
+            out_canvas_context.set_image_smoothing_enabled(*smoothing == Smoothing::On);
+            let image = TiledImage {
+                obj,
+                frame: *frame,
+                size,
+                monochrome,
+                lut,
+                out_of_range: lut_options.out_of_range,
+                overlays,
+                display: *display,
+            };
+            render_tiles_to_canvas(
+                &image,
+                tiles,
                 canvas,
-                canvas_context,
                 out_canvas,
                 out_canvas_context,
+                placement,
             )
-            .map(|_| {
-                set_error_messsage("");
+        }),
+        None => match baseline {
+            // show what changed since the baseline instead
+            Some((baseline, baseline_frame)) => {
+                native_frame(decoded_frames, baseline, *baseline_frame).and_then(|baseline| {
+                    difference_to_imagedata(
+                        (baseline.obj(), baseline.frame()),
+                        (obj, *frame),
+                        imagedata,
+                    )
+                })
+            }
+            // parametric maps are windowed in floating point
+            None if has_float_pixel_data(obj) => {
+                float_to_imagedata(obj, *frame, *window_level, *diverging, *display, imagedata)
+            }
+            None => {
+                let options = ConversionOptions {
+                    lut: *lut_options,
+                    flip_planar: *flip_planar,
+                    force_photometric: *force_photometric,
+                    display: *display,
+                    color_balance: *color_balance,
+                };
+                obj_to_imagedata(obj, *frame, y_samples, lut, imagedata, overlays, options)
+                    .and_then(|imagedata| match split {
+                        Some((index, preset, position)) => {
+                            // the LUT of the preset is kept
+                            // until the preset or the LUT options change
+                            let key = (index, *lut_options);
+                            let mut preset_lut = split_lut
+                                .take()
+                                .filter(|(cached, _)| *cached == key)
+                                .map(|(_, lut)| lut);
+                            let split = split_imagedata(
+                                imagedata,
+                                obj,
+                                *frame,
+                                &preset.voi,
+                                &mut preset_lut,
+                                position,
+                                overlays,
+                                options,
+                            );
+                            *split_lut = preset_lut.map(|lut| (key, lut));
+                            split
+                        }
+                        None => Ok(imagedata),
+                    })
+            }
+        }
+        .map(|imagedata| {
+            let mask = if *sharpen { Some(*unsharp_mask) } else { None };
+            sharpen_imagedata(imagedata, mask).and_then(|imagedata| match render_strategy {
+                RenderStrategy::DoubleCanvas => render_image_to_canvas(
+                    imagedata,
+                    canvas,
+                    out_canvas,
+                    out_canvas_context,
+                    placement,
+                    *smoothing,
+                ),
+                RenderStrategy::SingleCanvas => render_image_to_canvas_directly(
+                    imagedata,
+                    out_canvas,
+                    out_canvas_context,
+                    *display_size,
+                    placement,
+                    *smoothing,
+                ),
             })
-            .unwrap_or_else(|e| {
-                gloo_console::error!("Error rendering image data:", e);
-                set_error_messsage("Sorry, could not render the image data to the screen. :(");
-            });
+        }),
+    };
+
+    match rendered {
+        Ok(outcome) => {
+            outcome
+                .and_then(|transform| {
+                    *image_transform = Some(transform);
+                    draw_annotations(
+                        out_canvas_context,
+                        annotations,
+                        transform,
+                        *pixel_spacing,
+                        *measurement_format,
+                    )?;
+                    // only positions in the same frame of reference are comparable,
+                    // unless the host did not say which one it is in
+                    let cursor = linked_cursor
+                        .as_ref()
+                        .filter(|(_, uid)| {
+                            uid.is_none()
+                                || same_frame_of_reference(
+                                    uid.as_deref(),
+                                    frame_of_reference_uid.as_deref(),
+                                )
+                        })
+                        .map(|(position, _)| *position);
+                    draw_linked_cursor(
+                        out_canvas_context,
+                        cursor,
+                        image_plane.as_ref(),
+                        transform,
+                    )?;
+                    let reference = reference_plane
+                        .as_ref()
+                        .filter(|(_, uid)| {
+                            uid.is_none()
+                                || same_frame_of_reference(
+                                    uid.as_deref(),
+                                    frame_of_reference_uid.as_deref(),
+                                )
+                        })
+                        .map(|(plane, _)| plane);
+                    draw_reference_line(
+                        out_canvas_context,
+                        reference,
+                        image_plane.as_ref(),
+                        transform,
+                    )?;
+                    if let Ok(size) = image_dimensions_of(obj) {
+                        draw_split_line(
+                            out_canvas_context,
+                            split.map(|(_, preset, position)| (preset.name.as_str(), position)),
+                            size,
+                            transform,
+                        )?;
+                    }
+                    draw_forced_photometric_label(out_canvas_context, *force_photometric)
+                })
+                .map(|_| {
+                    set_error_messsage("");
+                })
+                .unwrap_or_else(|e| {
+                    gloo_console::error!("Error rendering image data:", e);
+                    set_error_messsage("Sorry, could not render the image data to the screen. :(");
+                });
+        }
+        Err(e @ imaging::Error::NoPixelData) => {
+            gloo_console::warn!(e.to_string());
+            set_error_messsage(&e.to_string());
         }
         Err(e) => {
             let msg = format!("Failed to render DICOM object: {}", e);
@@ -145,173 +942,4469 @@ fn render_obj_to_canvas(state: &RefCell<State>) {
     }
 }
 
-/// Set up the file drop zone
-fn set_drop_zone(state: Rc<RefCell<State>>, element: &HtmlElement) {
-    let ondrop_callback = Closure::wrap(Box::new(move |event: web_sys::DragEvent| {
-        event.prevent_default();
+/// The LUT of the object, built with the given options if there is none yet.
+fn ensure_lut<'a>(
+    lut: &'a mut Option<Vec<u8>>,
+    obj: &DefaultDicomObject,
+    options: LutOptions,
+) -> imaging::Result<&'a [u8]> {
+    let new_lut = match lut.take() {
+        Some(lut) => lut,
+        None => simple_pixel_data_lut(obj, options)?,
+    };
+    Ok(lut.insert(new_lut))
+}
 
-        let data_transfer = event.data_transfer().expect("no data transfer available");
-        let file_list = data_transfer.files().expect("no files available");
-        let file = file_list.get(0).expect("file list is empty");
+/// Apply the unsharp mask to the rendered image, if any.
+fn sharpen_imagedata(
+    imagedata: ImageData,
+    mask: Option<UnsharpMask>,
+) -> Result<ImageData, JsValue> {
+    let mask = match mask {
+        Some(mask) if mask.amount > 0. => mask,
+        _ => return Ok(imagedata),
+    };
+    let (width, height) = (imagedata.width(), imagedata.height());
+    let mut data = imagedata.data().0;
+    unsharp_mask(&mut data, width, height, mask);
+    new_imagedata(&data, width, height, color_space_of_imagedata(&imagedata))
+}
 
-        let state = Rc::clone(&state);
-        let blob: Blob = file.into();
-        let file_reader = gloo_file::callbacks::read_as_bytes(&blob, move |outcome| {
-            let data = outcome.expect("failed to get data");
+/// Display a newly loaded DICOM object,
+/// resetting all state specific to the previous one.
+///
+/// The object is checked before anything is replaced,
+/// so that the previous image stays on display if it cannot be shown.
+/// Returns whether the object is now displayed.
+fn load_dicom_obj(
+    state: &Rc<RefCell<State>>,
+    mut dicom_obj: Rc<DefaultDicomObject>,
+    leniency: Option<&'static str>,
+) -> bool {
+    // waveforms are plotted instead of rendered as images
+    if is_waveform(&dicom_obj) {
+        return show_waveform(state, &dicom_obj);
+    }
 
-            let dicom_obj = match byte_data_to_dicom_obj(&data) {
-                Ok(obj) => obj,
-                Err(e) => {
-                    let error_msg = format!("Failed to parse DICOM object: {}", e);
-                    gloo_console::error!(&error_msg);
-                    set_error_messsage(&error_msg);
-                    return;
-                }
-            };
+    let bit_depth_warning = reconcile_bit_depth(&mut dicom_obj).unwrap_or_else(|e| {
+        gloo_console::warn!("Could not check the bit depth against the pixel data:", e);
+        None
+    });
+    let layout_warning = match reconcile_layout(&mut dicom_obj) {
+        Ok(warning) => warning,
+        Err(e) => {
+            let msg = format!("Failed to load DICOM object: {}", e);
+            gloo_console::error!(&msg);
+            set_error_messsage(&msg);
+            return false;
+        }
+    };
+    // decode the first frame into a scratch image before replacing anything,
+    // so that the previous image stays on display if it cannot be shown
+    let window_level = initial_window_level_of(&state.borrow(), &dicom_obj);
+    let options = {
+        let state = state.borrow();
+        ConversionOptions {
+            lut: state.lut_options,
+            display: state.display,
+            color_balance: state.color_balance,
+            ..Default::default()
+        }
+    };
+    let scratch = check_displayable(&dicom_obj, 0)
+        .and_then(|_| first_frame_imagedata(&dicom_obj, window_level, options));
+    let scratch = match scratch {
+        Ok(imagedata) => imagedata,
+        Err(e) => {
+            let msg = format!("Failed to load DICOM object: {}", e);
+            gloo_console::error!(&msg);
+            set_error_messsage(&msg);
+            return false;
+        }
+    };
 
-            {
-                let mut state = state.borrow_mut();
+    let mut warnings = Vec::new();
 
-                // look for window level
-                state.window_level = window_level_of(&dicom_obj).unwrap_or(None);
+    if let Some(leniency) = leniency {
+        warnings.push(format!(
+            "This file is not conformant and was read in lenient mode ({}).",
+            leniency
+        ));
+    }
 
-                state.dicom_obj = Some(dicom_obj);
-                state.lut = None;
-            }
+    // warn about a bit depth or an image size inferred from the pixel data
+    warnings.extend(bit_depth_warning);
+    warnings.extend(layout_warning);
 
-            render_obj_to_canvas(&state);
-        });
+    // warn about pixel data not encoded as declared
+    if let Ok((_, Some(msg))) = pixel_data_encoding_of(&dicom_obj) {
+        warnings.push(msg);
+    }
 
-        std::mem::forget(file_reader);
-    }) as Box<dyn FnMut(_)>);
+    // warn about a rescale which is ignored
+    warnings.extend(rescale_warning_of(&dicom_obj).map(String::from));
 
-    let ondragover_callback = Closure::wrap(Box::new(move |event: web_sys::DragEvent| {
-        event.prevent_default();
-    }) as Box<dyn FnMut(_)>);
+    // warn about RLE frames found without an offset table
+    warnings.extend(inferred_frames_warning(&dicom_obj).map(String::from));
 
-    element.set_ondragover(Some(ondragover_callback.as_ref().unchecked_ref()));
-    element.set_ondrop(Some(ondrop_callback.as_ref().unchecked_ref()));
+    // warn about lossy compressed images
+    match lossy_compression_of(&dicom_obj) {
+        Ok(Some(lossy)) => warnings.push(lossy.to_string()),
+        Ok(None) => {}
+        Err(e) => {
+            gloo_console::warn!("Could not check for lossy compression:", e);
+        }
+    }
 
-    ondrop_callback.forget();
-    ondragover_callback.forget();
-}
+    // warn about pixel data which may identify the patient
+    warnings.extend(
+        identifiable_pixels_warnings_of(&dicom_obj)
+            .into_iter()
+            .map(String::from),
+    );
 
-fn set_window_level_tool(state: Rc<RefCell<State>>, canvas: &HtmlCanvasElement) {
-    let element = canvas;
+    // warn about images not meant to be presented as they are
+    let viewing_intent = viewing_intent_of(&dicom_obj);
+    warnings.extend(viewing_intent.warnings().into_iter().map(String::from));
 
-    let is_dragging_mouse = Rc::new(Cell::new(false));
+    // resolve pixel spacing, warn if not calibrated to the patient
+    let spacing = frame_pixel_spacing(&dicom_obj, 0);
+    if let Some((_, _, calibration)) = spacing {
+        if let Some(msg) = calibration.warning() {
+            warnings.push(msg.to_string());
+        }
+    }
 
-    let dragging = Rc::clone(&is_dragging_mouse);
+    for msg in &warnings {
+        gloo_console::warn!(msg);
+    }
 
-    // on mouse down, dragging = true
-    let onmousedown_callback = Closure::wrap(Box::new(move |_: MouseEvent| {
-        dragging.set(true);
-    }) as Box<dyn FnMut(_)>);
+    stop_cine(state);
+    stop_prefetch(state);
+    stop_viewport_motion(state);
 
-    // on mouse movement, update window levels if dragging
-    let dragging = Rc::clone(&is_dragging_mouse);
-    let onmousemove_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
-        if dragging.get() {
-            let ww = ev.movement_x() as f64;
-            let wc = ev.movement_y() as f64 * 2.;
-            change_window_level(&state, ww, wc);
+    {
+        let mut state = state.borrow_mut();
+
+        // images meant for processing have no meaningful presentation parameters,
+        // so they are shown over their full range unless asked otherwise,
+        // until an image meant for presentation is loaded
+        if viewing_intent.for_processing() {
+            if !state.lut_options.linear {
+                state.lut_options.linear = true;
+                state.full_range_by_intent = true;
+            }
+        } else if state.full_range_by_intent {
+            state.lut_options.linear = false;
+            state.full_range_by_intent = false;
         }
-    }) as Box<dyn FnMut(_)>);
+        state.viewing_intent = viewing_intent;
 
-    // on mouse up, dragging = false
-    let dragging = Rc::clone(&is_dragging_mouse);
-    let onmouseup_callback = Closure::wrap(Box::new(move |_: MouseEvent| {
-        dragging.set(false);
-    }) as Box<dyn FnMut(_)>);
+        // look for window level
+        state.window_level = window_level_of(&dicom_obj).unwrap_or(None);
 
-    element
-        .add_event_listener_with_callback(
-            "mousedown",
-            onmousedown_callback.as_ref().unchecked_ref(),
-        )
-        .unwrap();
-    element
-        .add_event_listener_with_callback(
-            "mousemove",
-            onmousemove_callback.as_ref().unchecked_ref(),
-        )
-        .unwrap();
-    element
-        .add_event_listener_with_callback("mouseup", onmouseup_callback.as_ref().unchecked_ref())
-        .unwrap();
-    element
-        .add_event_listener_with_callback("mouseleave", onmouseup_callback.as_ref().unchecked_ref())
-        .unwrap();
+        state.voi_lut = None;
+        state.presets = voi_presets_of(&dicom_obj).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read window presets:", e);
+            Vec::new()
+        });
+        if state.presets.is_empty() {
+            if let Some(modality) = preset::modality_name_of(&dicom_obj) {
+                state.presets = preset::presets_for_modality(&state.modality_presets, &modality);
+            }
+        }
+        state.preset_previews = None;
+        state.decoded_frames.clear();
+        state.dynamic_ranges.clear();
+        state.split = None;
+        state.split_lut = None;
+        state.tiles = TileCache::default();
+        state.flip_planar = false;
+        state.force_photometric = None;
 
-    onmousedown_callback.forget();
-    onmousemove_callback.forget();
-    onmouseup_callback.forget();
-}
+        state.pixel_spacing =
+            spacing.map(|(row_spacing, column_spacing, _)| (row_spacing, column_spacing));
 
-fn change_window_level(state: &RefCell<State>, rel_ww: f64, rel_wc: f64) {
-    {
-        let mut state = state.borrow_mut();
-        let State {
-            dicom_obj,
-            window_level,
-            lut,
-            ..
-        } = &mut *state;
+        state.viewport = Viewport::default();
 
-        let obj = if let Some(obj) = &dicom_obj {
-            obj
-        } else {
-            // ignore, no DICOM object loaded
-            return;
-        };
+        state.image_plane = image_plane_of(&dicom_obj, 0).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read the image plane:", e);
+            None
+        });
+        state.frame_of_reference_uid = frame_of_reference_uid_of(&dicom_obj);
 
-        // get the current window level
-        let window_level = if let Some(window_level) = window_level {
-            window_level
+        // show the measurements of loaded reports on this image
+        let sop_instance_uid = sop_instance_uid_of(&dicom_obj);
+        state.annotations = state
+            .sr_measurements
+            .iter()
+            .filter(|m| Some(&m.referenced_sop_instance_uid) == sop_instance_uid.as_ref())
+            .flat_map(|m| m.to_annotations())
+            .collect();
+        state.overlays = overlay_layers_of(&dicom_obj).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read overlays:", e);
+            Vec::new()
+        });
+        state.frame = 0;
+        state.window_level_adjusted = false;
+        state.window_level_unit = float_window_step_of(&dicom_obj).unwrap_or(1.);
+        state.number_of_frames = number_of_frames_of(&dicom_obj).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read the number of frames:", e);
+            1
+        });
+
+        state.warnings = warnings;
+        update_warning_message(&state);
+
+        // the image data of the scratch render is reused
+        state.imagedata = Some(scratch);
+        state.lut = None;
+        if has_float_pixel_data(&dicom_obj) {
+            // parametric maps are windowed without a LUT
+            state.window_level = window_level;
+        } else if let Some(window_level) = window_level {
+            match simple_pixel_data_lut_with(&dicom_obj, window_level, state.lut_options) {
+                Ok(lut) => {
+                    state.window_level = Some(window_level);
+                    state.lut = Some(lut);
+                }
+                Err(e) => gloo_console::warn!("Could not apply the window level:", e),
+            }
+        }
+
+        state.dicom_obj = Some(dicom_obj);
+    }
+
+    render_obj_to_canvas(state);
+    update_preset_picker(state);
+    update_overlay_selector(state);
+    update_dynamic_range(state);
+    let full_range = full_range_window(&state.borrow());
+    match full_range {
+        Some(window_level) => set_window_level(state, window_level),
+        None => {
+            let window_level = state.borrow().window_level;
+            notify_window_level_change(state, window_level);
+        }
+    }
+    start_prefetch(state);
+    notify_plane_change(state);
+    true
+}
+
+/// The window level to show a newly loaded object with:
+/// the same window through the series, if enabled and known,
+/// or else the window of the first frame, if it has its own,
+/// or else the default window of its modality or values.
+///
+/// Returns `None` if the object's own window applies.
+fn initial_window_level_of(state: &State, obj: &DefaultDicomObject) -> Option<WindowLevel> {
+    let series_window_level = if state.series_windowing {
+        study::series_of(&state.series, obj).and_then(|series| series.window_level())
+    } else {
+        None
+    };
+    series_window_level
+        .or_else(|| {
+            frame_window_level_of(obj, 0).unwrap_or_else(|e| {
+                gloo_console::warn!("Could not read the window of the first frame:", e);
+                None
+            })
+        })
+        .or_else(|| preset::default_window_level_of(obj, 0, &state.modality_presets))
+        .or_else(|| float_window_level_of(obj, 0))
+}
+
+/// Render the first frame of an object into a scratch image
+/// with the given window level, without touching the state,
+/// to tell whether it can be shown at all.
+fn first_frame_imagedata(
+    obj: &DefaultDicomObject,
+    window_level: Option<WindowLevel>,
+    options: ConversionOptions,
+) -> imaging::Result<ImageData> {
+    let decoded = DecodedFrames::with_capacity(1);
+    let native = native_frame(&decoded, obj, 0)?;
+    let (obj, frame) = (native.obj(), native.frame());
+    if has_float_pixel_data(obj) {
+        return float_to_imagedata(obj, frame, window_level, None, options.display, &mut None);
+    }
+    let mut lut = window_level
+        .and_then(|window_level| simple_pixel_data_lut_with(obj, window_level, options.lut).ok());
+    obj_to_imagedata(
+        obj,
+        frame,
+        &mut Vec::new(),
+        &mut lut,
+        &mut None,
+        &[],
+        options,
+    )
+}
+
+/// The window covering the full range of values of the displayed frame,
+/// if shown in full range and the range is known
+fn full_range_window(state: &State) -> Option<WindowLevel> {
+    if !state.lut_options.linear {
+        return None;
+    }
+    state
+        .dynamic_range
+        .map(|range| study::window_level_covering(range.rescaled))
+}
+
+/// Find the dynamic range of the displayed frame
+/// of a monochrome image, leaving out padding,
+/// and show it in the image information line, if any.
+///
+/// The range of each frame is only worked out the first time it is shown.
+fn update_dynamic_range(state: &RefCell<State>) {
+    let mut st = state.borrow_mut();
+    let frame = st.frame;
+    let range = match (&st.dicom_obj, st.dynamic_ranges.get(&frame)) {
+        (Some(_), Some(range)) => *range,
+        (Some(obj), None) => (|| -> imaging::Result<_> {
+            let native = native_frame(&st.decoded_frames, obj, st.frame)?;
+            let stored = stored_values_of(native.obj(), native.frame())?;
+            let padding = pixel_padding_of(obj)?;
+            let modality = modality_of(obj)?;
+            Ok(dynamic_range_of(&stored, padding, |x| {
+                modality.apply(x as f64)
+            }))
+        })()
+        .unwrap_or_else(|e| {
+            gloo_console::debug!("No dynamic range:", e.to_string());
+            None
+        }),
+        (None, _) => None,
+    };
+    if st.dicom_obj.is_some() {
+        st.dynamic_ranges.insert(frame, range);
+    }
+    st.dynamic_range = range;
+
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    if let Some(info) = document.get_element_by_id("image-info") {
+        let text = range.map(|range| {
+            format!(
+                "Stored values {} to {} ({} distinct), {} to {} in modality units",
+                range.stored.0, range.stored.1, range.distinct, range.rescaled.0, range.rescaled.1
+            )
+        });
+        info.set_text_content(text.as_deref());
+    }
+}
+
+/// Keep the measurements of a Structured Report,
+/// showing them right away if they refer to the displayed image.
+fn add_sr_measurements(state: &RefCell<State>, sr: &DefaultDicomObject) {
+    let measurements = sr_measurements_of(sr);
+    gloo_console::log!(
+        "Structured Report with",
+        measurements.len(),
+        "image measurements"
+    );
+
+    let rerender = {
+        let mut state = state.borrow_mut();
+        let displayed_uid = state
+            .dicom_obj
+            .as_deref()
+            .and_then(|obj| sop_instance_uid_of(obj));
+        let mut rerender = false;
+        for measurement in &measurements {
+            if Some(&measurement.referenced_sop_instance_uid) == displayed_uid.as_ref() {
+                state.annotations.extend(measurement.to_annotations());
+                rerender = true;
+            }
+        }
+        state.sr_measurements.extend(measurements);
+        rerender
+    };
+
+    if rerender {
+        render_obj_to_canvas(state);
+    }
+}
+
+/// Set up the file drop zone.
+///
+/// All dropped files are added to the study browser,
+/// and the first one to be read which can be shown is displayed.
+/// The measurements of Structured Reports are shown
+/// over the images they refer to.
+///
+/// With lazy loading, only the metadata of the other files is kept,
+/// and their pixel data is read again from the file when displayed.
+fn set_drop_zone(state: Rc<RefCell<State>>, element: &HtmlElement) {
+    let ondrop_callback = Closure::wrap(Box::new(move |event: web_sys::DragEvent| {
+        event.prevent_default();
+
+        let data_transfer = event.data_transfer().expect("no data transfer available");
+        let file_list = data_transfer.files().expect("no files available");
+        let file_count = file_list.length();
+        let displayed = Rc::new(Cell::new(false));
+        // files dropped later are displayed instead
+        let token = state.borrow_mut().loads.start();
+
+        for i in 0..file_count {
+            let file = file_list.get(i).expect("file should be in the list");
+            let name = file.name();
+
+            let state = Rc::clone(&state);
+            let displayed = Rc::clone(&displayed);
+            let blob: Blob = file.into();
+            let source = blob.clone();
+            let file_reader = gloo_file::callbacks::read_as_bytes(&blob, move |outcome| {
+                let data = outcome.expect("failed to get data");
+
+                let lazy = state.borrow().lazy_loading && displayed.get();
+                let extent = if lazy {
+                    ReadExtent::Metadata
+                } else {
+                    ReadExtent::Full
+                };
+                let (mut dicom_obj, leniency) = match byte_data_to_dicom_obj_lenient(&data, extent)
+                {
+                    Ok(outcome) => outcome,
+                    Err(e) => {
+                        let error_msg = format!("Failed to parse DICOM object: {}", e);
+                        gloo_console::error!(&name, &error_msg);
+                        if file_count == 1 {
+                            set_error_messsage(&error_msg);
+                        }
+                        return;
+                    }
+                };
+
+                if is_structured_report(&dicom_obj) {
+                    add_sr_measurements(&state, &dicom_obj);
+                    return;
+                }
+
+                if lazy {
+                    match sop_instance_uid_of(&dicom_obj) {
+                        Some(uid) => {
+                            let fingerprint = study::fingerprint_of(&data);
+                            state
+                                .borrow_mut()
+                                .pixel_data_sources
+                                .insert(uid, (source, fingerprint));
+                        }
+                        None => {
+                            gloo_console::warn!(
+                                &name,
+                                "has no SOPInstanceUID, keeping its pixel data"
+                            );
+                            if let Ok((obj, _)) =
+                                byte_data_to_dicom_obj_lenient(&data, ReadExtent::Full)
+                            {
+                                dicom_obj = obj;
+                            }
+                        }
+                    }
+                }
+
+                // the study browser shares the object with the display
+                let dicom_obj = Rc::new(dicom_obj);
+                let added =
+                    study::add_instance(&mut state.borrow_mut().series, Rc::clone(&dicom_obj));
+                match added {
+                    Ok(_) => update_series_browser(&state),
+                    Err(e) => gloo_console::warn!(&name, "Could not add to the study browser:", e),
+                }
+
+                if !displayed.get()
+                    && state.borrow().loads.is_current(token)
+                    && load_dicom_obj(&state, dicom_obj, leniency)
+                {
+                    displayed.set(true);
+                }
+            });
+
+            std::mem::forget(file_reader);
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    let ondragover_callback = Closure::wrap(Box::new(move |event: web_sys::DragEvent| {
+        event.prevent_default();
+    }) as Box<dyn FnMut(_)>);
+
+    element.set_ondragover(Some(ondragover_callback.as_ref().unchecked_ref()));
+    element.set_ondrop(Some(ondrop_callback.as_ref().unchecked_ref()));
+
+    ondrop_callback.forget();
+    ondragover_callback.forget();
+}
+
+/// Set up the mouse tools on the output canvas,
+/// each mouse button being assigned the tool in `State::mouse_tools`.
+fn set_mouse_tools(state: Rc<RefCell<State>>, canvas: &HtmlCanvasElement) {
+    let element = canvas;
+
+    // the tool being dragged, if any
+    let active_tool: Rc<Cell<Option<Tool>>> = Rc::new(Cell::new(None));
+    // where the annotation being placed was started (canvas pixels)
+    let annotation_start: Rc<Cell<Option<(f64, f64)>>> = Rc::new(Cell::new(None));
+    // the time stamp of the last mouse movement (ms)
+    let last_move: Rc<Cell<f64>> = Rc::new(Cell::new(0.));
+    // whether the split line is being dragged
+    let splitting: Rc<Cell<bool>> = Rc::new(Cell::new(false));
+
+    // on mouse down, start dragging with the button's tool
+    let dragging = Rc::clone(&active_tool);
+    let start = Rc::clone(&annotation_start);
+    let canvas_el = canvas.clone();
+    let split_drag = Rc::clone(&splitting);
+    let st = Rc::clone(&state);
+    let onmousedown_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
+        // the split line takes precedence over the tools
+        if ev.button() == 0 && near_split_line(&st, canvas_point_of(&canvas_el, &ev)) {
+            ev.prevent_default();
+            split_drag.set(true);
+            return;
+        }
+        let tool = st
+            .borrow()
+            .mouse_tools
+            .get(ev.button() as usize)
+            .copied()
+            .flatten();
+        if tool.is_some() {
+            // prevent middle button autoscroll
+            ev.prevent_default();
+            // grabbing the image stops it from gliding
+            stop_viewport_motion(&st);
+        }
+        if matches!(tool, Some(Tool::Annotate | Tool::Measure | Tool::ZoomRect)) {
+            start.set(Some(canvas_point_of(&canvas_el, &ev)));
+        }
+        dragging.set(tool);
+    }) as Box<dyn FnMut(_)>);
+
+    // on mouse movement, apply the active tool
+    let dragging = Rc::clone(&active_tool);
+    let start = Rc::clone(&annotation_start);
+    let moved = Rc::clone(&last_move);
+    let split_drag = Rc::clone(&splitting);
+    let canvas_el = canvas.clone();
+    let st = Rc::clone(&state);
+    let onmousemove_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
+        let point = canvas_point_of(&canvas_el, &ev);
+        notify_cursor_move(&st, point);
+        update_pixel_probe(&st, Some(point));
+        if split_drag.get() {
+            move_split_line(&st, point);
+            return;
+        }
+
+        let dx = ev.movement_x() as f64;
+        let dy = ev.movement_y() as f64;
+        let dt = ev.time_stamp() - moved.replace(ev.time_stamp());
+        match dragging.get() {
+            Some(Tool::WindowLevel) => change_window_level(&st, dx, dy * 2.),
+            Some(Tool::Pan) => {
+                change_pan(&st, dx, dy);
+                st.borrow_mut().motion.track_pan(dx, dy, dt);
+            }
+            Some(Tool::Zoom) => change_zoom(&st, f64::powf(1.01, -dy)),
+            Some(Tool::ZoomRect) => {
+                if let Some(start) = start.get() {
+                    draw_zoom_selection(&st, start, point);
+                }
+            }
+            // placed on mouse up
+            Some(Tool::Annotate | Tool::Measure) | None => {}
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    // on mouse up, stop dragging,
+    // letting the image glide if it was released while panning,
+    // or placing an annotation or zooming to the selection
+    // if the mouse was released over the canvas
+    let dragging = Rc::clone(&active_tool);
+    let start = Rc::clone(&annotation_start);
+    let canvas_el = canvas.clone();
+    let onmouseup_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
+        if ev.type_() == "mouseleave" {
+            update_pixel_probe(&state, None);
+        }
+        splitting.set(false);
+        let tool = dragging.replace(None);
+        let start = start.take();
+        if tool == Some(Tool::Pan) {
+            let still = ev.time_stamp() - last_move.get() > MOMENTUM_TIMEOUT;
+            if still || !state.borrow().inertia {
+                state.borrow_mut().motion.velocity = (0., 0.);
+            } else {
+                start_viewport_motion(&state);
+            }
+        }
+        if let (Some(tool @ (Tool::Annotate | Tool::Measure)), Some(start)) = (tool, start) {
+            if ev.type_() == "mouseup" {
+                let end = canvas_point_of(&canvas_el, &ev);
+                place_annotation(&state, tool, start, end);
+            }
+        }
+        if let (Some(Tool::ZoomRect), Some(start)) = (tool, start) {
+            if ev.type_() == "mouseup" {
+                let end = canvas_point_of(&canvas_el, &ev);
+                zoom_to_rect(&state, start, end);
+            } else {
+                // clear the selection
+                render_obj_to_canvas(&state);
+            }
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    // do not open the context menu when dragging with the right button
+    let oncontextmenu_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
+        ev.prevent_default();
+    }) as Box<dyn FnMut(_)>);
+
+    element
+        .add_event_listener_with_callback(
+            "mousedown",
+            onmousedown_callback.as_ref().unchecked_ref(),
+        )
+        .unwrap();
+    element
+        .add_event_listener_with_callback(
+            "mousemove",
+            onmousemove_callback.as_ref().unchecked_ref(),
+        )
+        .unwrap();
+    element
+        .add_event_listener_with_callback("mouseup", onmouseup_callback.as_ref().unchecked_ref())
+        .unwrap();
+    element
+        .add_event_listener_with_callback("mouseleave", onmouseup_callback.as_ref().unchecked_ref())
+        .unwrap();
+    element
+        .add_event_listener_with_callback(
+            "contextmenu",
+            oncontextmenu_callback.as_ref().unchecked_ref(),
+        )
+        .unwrap();
+
+    onmousedown_callback.forget();
+    onmousemove_callback.forget();
+    onmouseup_callback.forget();
+    oncontextmenu_callback.forget();
+}
+
+/// Whether a point on the canvas is close enough to the split line to drag it
+fn near_split_line(state: &RefCell<State>, (x, y): (f64, f64)) -> bool {
+    let state = state.borrow();
+    let (split, transform, obj) = match (&state.split, state.image_transform, &state.dicom_obj) {
+        (Some(split), Some(transform), Some(obj)) => (split, transform, obj),
+        _ => return false,
+    };
+    let (width, height) = match image_dimensions_of(obj) {
+        Ok(size) => size,
+        Err(_) => return false,
+    };
+    let line_x = split_column(split.position, width) as f64;
+    let (canvas_x, top) = transform.to_canvas((line_x, 0.));
+    let (_, bottom) = transform.to_canvas((line_x, height as f64));
+    (x - canvas_x).abs() <= SPLIT_GRAB_DISTANCE && y >= top.min(bottom) && y <= top.max(bottom)
+}
+
+/// Move the split line under a point on the canvas and re-render.
+fn move_split_line(state: &RefCell<State>, point: (f64, f64)) {
+    {
+        let mut st = state.borrow_mut();
+        let width = match st.dicom_obj.as_deref().map(|obj| image_dimensions_of(obj)) {
+            Some(Ok((width, _))) => width as f64,
+            _ => return,
+        };
+        let (x, _) = match st.image_transform {
+            Some(transform) => transform.to_image(point),
+            None => return,
+        };
+        match &mut st.split {
+            Some(split) => split.position = (x / width).clamp(0., 1.),
+            None => return,
+        }
+    }
+    render_obj_to_canvas(state);
+}
+
+/// Compare the current window with a preset in a split view,
+/// or close the split view, and re-render.
+///
+/// The split line starts in the middle, or stays where it was.
+/// Color images have no window to compare,
+/// so the split view stays closed for them.
+fn set_split_preset(state: &RefCell<State>, preset: Option<usize>) {
+    {
+        let mut state = state.borrow_mut();
+        let monochrome = state
+            .dicom_obj
+            .as_deref()
+            .and_then(|obj| displayed_monochrome_of(obj, state.force_photometric))
+            .is_some();
+        if preset.is_some() && !monochrome {
+            gloo_console::warn!("Window presets can only be compared on monochrome images");
+            return;
+        }
+        let position = state.split.map_or(0.5, |split| split.position);
+        state.split = preset
+            .filter(|&preset| preset < state.presets.len())
+            .map(|preset| SplitView { preset, position });
+    }
+    render_obj_to_canvas(state);
+}
+
+/// The position of a mouse event in canvas pixels
+fn canvas_point_of(canvas: &HtmlCanvasElement, ev: &MouseEvent) -> (f64, f64) {
+    let client_width = canvas.client_width();
+    let css_scale = if client_width > 0 {
+        canvas.width() as f64 / client_width as f64
+    } else {
+        1.
+    };
+    (
+        ev.offset_x() as f64 * css_scale,
+        ev.offset_y() as f64 * css_scale,
+    )
+}
+
+/// Call the cursor callback with the patient position
+/// under the given canvas point
+/// and the frame of reference it is in,
+/// if the image declares its plane.
+fn notify_cursor_move(state: &RefCell<State>, point: (f64, f64)) {
+    let (callback, position, frame_of_reference_uid) = {
+        let state = state.borrow();
+        match (
+            &state.on_cursor_move,
+            state.image_plane,
+            state.image_transform,
+        ) {
+            (Some(callback), Some(plane), Some(transform)) => (
+                callback.clone(),
+                plane.to_patient(transform.to_image(point)),
+                state.frame_of_reference_uid.clone(),
+            ),
+            _ => return,
+        }
+    };
+    let [x, y, z] = position;
+    let args = js_sys::Array::of4(
+        &JsValue::from_f64(x),
+        &JsValue::from_f64(y),
+        &JsValue::from_f64(z),
+        &JsValue::from(frame_of_reference_uid),
+    );
+    if let Err(e) = callback.apply(&JsValue::NULL, &args) {
+        gloo_console::error!("Cursor callback failed:", e);
+    }
+}
+
+/// Call the plane callback with the position and orientation
+/// of the displayed image plane in patient space
+/// and the frame of reference it is in,
+/// if the image declares its plane.
+fn notify_plane_change(state: &RefCell<State>) {
+    let (callback, plane, frame_of_reference_uid) = {
+        let state = state.borrow();
+        match (&state.on_plane_change, state.image_plane) {
+            (Some(callback), Some(plane)) => (
+                callback.clone(),
+                plane,
+                state.frame_of_reference_uid.clone(),
+            ),
+            _ => return,
+        }
+    };
+    let orientation: Vec<f64> = plane
+        .row_direction
+        .into_iter()
+        .chain(plane.column_direction)
+        .collect();
+    let args = js_sys::Array::of3(
+        &js_sys::Float64Array::from(&plane.position[..]),
+        &js_sys::Float64Array::from(&orientation[..]),
+        &JsValue::from(frame_of_reference_uid),
+    );
+    if let Err(e) = callback.apply(&JsValue::NULL, &args) {
+        gloo_console::error!("Plane callback failed:", e);
+    }
+}
+
+/// How the pixel data of an object is shown if it is monochrome,
+/// as read with the forced photometric interpretation, if any
+fn displayed_monochrome_of(
+    obj: &DefaultDicomObject,
+    force_photometric: Option<&str>,
+) -> Option<Monochrome> {
+    match force_photometric {
+        Some(forced) => monochrome_of(forced),
+        None => monochrome_of(&string_of(
+            obj,
+            dicom::dictionary_std::tags::PHOTOMETRIC_INTERPRETATION,
+        )?),
+    }
+}
+
+/// Show the values of the pixel under the given canvas point
+/// of a monochrome image
+/// (stored, in modality units, and displayed)
+/// in the pixel probe element, if any,
+/// or clear it if there is no such pixel.
+fn update_pixel_probe(state: &RefCell<State>, point: Option<(f64, f64)>) {
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let element = match document.get_element_by_id("pixel-probe") {
+        Some(element) => element,
+        None => return,
+    };
+
+    let state = state.borrow();
+    let text = match (&state.dicom_obj, &state.lut, state.image_transform, point) {
+        (Some(obj), Some(lut), Some(transform), Some(point)) => {
+            let (x, y) = transform.to_image(point);
+            match displayed_monochrome_of(obj, state.force_photometric) {
+                Some(monochrome) if x >= 0. && y >= 0. => {
+                    native_frame(&state.decoded_frames, obj, state.frame)
+                        .and_then(|native| {
+                            probe_pixel(
+                                native.obj(),
+                                native.frame(),
+                                (x as u32, y as u32),
+                                lut,
+                                monochrome,
+                                state.lut_options.out_of_range,
+                                state.display,
+                            )
+                        })
+                        .unwrap_or_else(|e| {
+                            gloo_console::debug!("Could not probe the pixel:", e.to_string());
+                            None
+                        })
+                        .map(|probe| probe_text(probe, &rescale_unit_of(obj)))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    element.set_text_content(text.as_deref());
+}
+
+/// The distance in canvas pixels under which a drag is taken as a click
+const CLICK_TOLERANCE: f64 = 4.;
+
+/// Add an annotation from a drag between two points on the canvas.
+///
+/// With the measure tool, this is a ruler between the two points.
+/// Otherwise, it is an arrow pointing at where the drag started,
+/// or a text label if the mouse did not move
+/// (asking for the text).
+fn place_annotation(state: &RefCell<State>, tool: Tool, start: (f64, f64), end: (f64, f64)) {
+    let transform = {
+        let state = state.borrow();
+        match (&state.dicom_obj, state.image_transform) {
+            (Some(_), Some(transform)) => transform,
+            _ => return,
+        }
+    };
+
+    let clicked = (end.0 - start.0).hypot(end.1 - start.1) < CLICK_TOLERANCE;
+    let annotation = if tool == Tool::Measure {
+        if clicked {
+            return;
+        }
+        Annotation::Ruler {
+            start: transform.to_image(start),
+            end: transform.to_image(end),
+        }
+    } else if clicked {
+        let window = web_sys::window().expect("no global `window` exists");
+        let text = match window.prompt_with_message("Annotation text") {
+            Ok(Some(text)) if !text.trim().is_empty() => text,
+            _ => return,
+        };
+        Annotation::Text {
+            position: transform.to_image(start),
+            text,
+        }
+    } else {
+        Annotation::Arrow {
+            tip: transform.to_image(start),
+            tail: transform.to_image(end),
+        }
+    };
+
+    state.borrow_mut().annotations.push(annotation);
+    render_obj_to_canvas(state);
+}
+
+/// Remove the most recently placed annotation.
+fn remove_last_annotation(state: &RefCell<State>) {
+    if state.borrow_mut().annotations.pop().is_some() {
+        render_obj_to_canvas(state);
+    }
+}
+
+/// Move the displayed image by the given amount of canvas pixels.
+fn change_pan(state: &RefCell<State>, dx: f64, dy: f64) {
+    {
+        let mut state = state.borrow_mut();
+        if state.dicom_obj.is_none() {
+            return;
+        }
+        state.viewport.pan.0 += dx;
+        state.viewport.pan.1 += dy;
+    }
+
+    render_obj_to_canvas(state);
+}
+
+/// Re-render with the rectangle between two points on the canvas
+/// drawn over the image, as selected for zooming.
+fn draw_zoom_selection(state: &RefCell<State>, start: (f64, f64), end: (f64, f64)) {
+    render_obj_to_canvas(state);
+
+    let state = state.borrow();
+    let context = &state.out_canvas_context;
+    if let Err(e) = context.set_transform(1., 0., 0., 1., 0., 0.) {
+        gloo_console::error!("Could not draw the zoom selection:", e);
+        return;
+    }
+    context.set_stroke_style(&JsValue::from_str(ANNOTATION_COLOR));
+    context.set_line_width(1.);
+    context.stroke_rect(
+        start.0.min(end.0),
+        start.1.min(end.1),
+        (end.0 - start.0).abs(),
+        (end.1 - start.1).abs(),
+    );
+}
+
+/// Zoom and pan so that the rectangle between two points on the canvas
+/// fills the canvas, keeping its aspect ratio,
+/// within the zoom limits.
+///
+/// Selections smaller than a click only clear the selection.
+fn zoom_to_rect(state: &RefCell<State>, start: (f64, f64), end: (f64, f64)) {
+    {
+        let mut state = state.borrow_mut();
+        let (transform, (w, h)) = match (&state.dicom_obj, state.image_transform) {
+            (Some(obj), Some(transform)) => match image_dimensions_of(obj) {
+                Ok(size) => (transform, size),
+                Err(_) => return,
+            },
+            _ => return,
+        };
+        let (rect_w, rect_h) = ((end.0 - start.0).abs(), (end.1 - start.1).abs());
+        if rect_w.hypot(rect_h) >= CLICK_TOLERANCE {
+            let canvas_w = state.out_canvas.width() as f64;
+            let canvas_h = state.out_canvas.height() as f64;
+            let zoom = state
+                .zoom_options
+                .clamp(state.viewport.zoom * f64::min(canvas_w / rect_w, canvas_h / rect_h));
+            let viewport = &mut state.viewport;
+            let factor = zoom / viewport.zoom;
+
+            // the center of the selection goes to the center of the canvas
+            let center = ((start.0 + end.0) / 2., (start.1 + end.1) / 2.);
+            let (x, y) = transform.to_image(center);
+            viewport.zoom = zoom;
+            viewport.pan = (
+                transform.scale.0 * factor * (w as f64 / 2. - x),
+                transform.scale.1 * factor * (h as f64 / 2. - y),
+            );
+            gloo_console::debug!("Zoomed to selection:", zoom);
+        }
+    }
+
+    render_obj_to_canvas(state);
+}
+
+/// Multiply the zoom level of the displayed image by the given factor,
+/// keeping the given point on the canvas (in canvas pixels) in place,
+/// or else zooming about the center of the image.
+///
+/// Zooming about a point is applied at once, even with inertia enabled.
+fn zoom_at(state: &Rc<RefCell<State>>, factor: f64, anchor: Option<(f64, f64)>) {
+    let anchor = match anchor {
+        Some(anchor) => anchor,
+        None => return change_zoom(state, factor),
+    };
+    stop_viewport_motion(state);
+    {
+        let mut state = state.borrow_mut();
+        if state.dicom_obj.is_none() {
+            return;
+        }
+        let center = (
+            state.out_canvas.width() as f64 / 2.,
+            state.out_canvas.height() as f64 / 2.,
+        );
+        let zoom = state.zoom_options.clamp(state.viewport.zoom * factor);
+        state.viewport = state
+            .viewport
+            .zoomed_about(zoom, (anchor.0 - center.0, anchor.1 - center.1));
+    }
+    render_obj_to_canvas(state);
+}
+
+/// Multiply the zoom level of the displayed image by the given factor.
+///
+/// With inertia enabled,
+/// the zoom level eases towards the new level over a few frames.
+fn change_zoom(state: &Rc<RefCell<State>>, factor: f64) {
+    let smooth = {
+        let mut state = state.borrow_mut();
+        if state.dicom_obj.is_none() {
+            return;
+        }
+        if state.inertia {
+            let zoom = state.motion.zoom_target.unwrap_or(state.viewport.zoom);
+            state.motion.zoom_target = Some(state.zoom_options.clamp(zoom * factor));
+        } else {
+            state.viewport.zoom = state.zoom_options.clamp(state.viewport.zoom * factor);
+        }
+        state.inertia
+    };
+
+    if smooth {
+        start_viewport_motion(state);
+    } else {
+        render_obj_to_canvas(state);
+    }
+}
+
+/// Animate the viewport until it comes to rest,
+/// unless it is already being animated.
+fn start_viewport_motion(state: &Rc<RefCell<State>>) {
+    {
+        let mut st = state.borrow_mut();
+        if st.motion.frame.is_some() {
+            return;
+        }
+        st.motion.last_time = None;
+        if st.motion.callback.is_none() {
+            st.motion.callback = Some(viewport_motion_callback(Rc::clone(state)));
+        }
+    }
+
+    request_motion_frame(state);
+}
+
+/// The animation frame callback which moves the viewport
+/// and schedules the next frame while it is still moving.
+fn viewport_motion_callback(st: Rc<RefCell<State>>) -> Closure<dyn FnMut(f64)> {
+    Closure::wrap(Box::new(move |time: f64| {
+        let moving = {
+            let mut state = st.borrow_mut();
+            let State {
+                motion, viewport, ..
+            } = &mut *state;
+            motion.frame = None;
+            let dt = motion.last_time.map_or(FRAME_DURATION, |last| time - last);
+            motion.last_time = Some(time);
+            motion.step(viewport, dt)
+        };
+        render_obj_to_canvas(&st);
+        if moving {
+            request_motion_frame(&st);
+        }
+    }) as Box<dyn FnMut(f64)>)
+}
+
+/// Schedule the next frame of the viewport animation.
+fn request_motion_frame(state: &RefCell<State>) {
+    let mut state = state.borrow_mut();
+    let motion = &mut state.motion;
+    let callback = match &motion.callback {
+        Some(callback) => callback,
+        None => return,
+    };
+    let window = web_sys::window().expect("no global `window` exists");
+    match window.request_animation_frame(callback.as_ref().unchecked_ref()) {
+        Ok(handle) => motion.frame = Some(handle),
+        Err(e) => gloo_console::error!("Could not animate the viewport:", e),
+    }
+}
+
+/// Stop any ongoing inertial panning or zoom transition,
+/// leaving the viewport where it is.
+fn stop_viewport_motion(state: &RefCell<State>) {
+    let mut state = state.borrow_mut();
+    let motion = &mut state.motion;
+    if let Some(handle) = motion.frame.take() {
+        let window = web_sys::window().expect("no global `window` exists");
+        let _ = window.cancel_animation_frame(handle);
+    }
+    motion.velocity = (0., 0.);
+    // settle immediately on the zoom level being eased towards
+    if let Some(zoom) = motion.zoom_target.take() {
+        state.viewport.zoom = zoom;
+    }
+}
+
+/// Set up frame scrolling with the mouse wheel.
+///
+/// By default, scrolling down advances to the next frame,
+/// as in most PACS viewers.
+/// This is reversed when `invert_scroll` is set.
+/// Localizer frames are skipped when `hide_localizers` is set.
+///
+/// While Alt is held, the wheel changes the window level instead:
+/// the center, or the width if Shift is also held,
+/// by `wheel_window_level_steps` per notch,
+/// increasing when scrolling up.
+///
+/// While Ctrl is held (as when pinching a touchpad),
+/// the wheel zooms instead, as per the zoom options.
+fn set_frame_scroll(state: Rc<RefCell<State>>, canvas: &HtmlCanvasElement) {
+    let zoom_canvas = canvas.clone();
+    let onwheel_callback = Closure::wrap(Box::new(move |ev: WheelEvent| {
+        if ev.ctrl_key() {
+            if ev.delta_y() == 0. {
+                return;
+            }
+            ev.prevent_default();
+            let options = state.borrow().zoom_options;
+            let factor = if ev.delta_y() < 0. {
+                options.wheel_factor
+            } else {
+                1. / options.wheel_factor
+            };
+            let anchor = match options.anchor {
+                ZoomAnchor::Cursor => Some(canvas_point_of(&zoom_canvas, &ev)),
+                ZoomAnchor::Center => None,
+            };
+            zoom_at(&state, factor, anchor);
+            return;
+        }
+        if ev.alt_key() {
+            // some browsers turn Shift + wheel into horizontal scrolling
+            let delta = if ev.delta_y() != 0. {
+                ev.delta_y()
+            } else {
+                ev.delta_x()
+            };
+            if delta == 0. {
+                return;
+            }
+            ev.prevent_default();
+            let (center_step, width_step) = state.borrow().wheel_window_level_steps;
+            let sign = if delta < 0. { 1. } else { -1. };
+            if ev.shift_key() {
+                change_window_level(&state, sign * width_step, 0.);
+            } else {
+                change_window_level(&state, 0., sign * center_step);
+            }
+            return;
+        }
+
+        let new_frame = {
+            let state = state.borrow();
+            if state.number_of_frames <= 1 || ev.delta_y() == 0. {
+                return;
+            }
+            ev.prevent_default();
+
+            let forward = (ev.delta_y() > 0.) != state.invert_scroll;
+            let hidden = |frame| match &state.dicom_obj {
+                Some(obj) if state.hide_localizers => study::is_localizer_frame(obj, frame),
+                _ => false,
+            };
+            study::step_frame(state.frame, forward, state.number_of_frames, hidden)
+        };
+        set_frame(&state, new_frame);
+    }) as Box<dyn FnMut(_)>);
+
+    canvas
+        .add_event_listener_with_callback("wheel", onwheel_callback.as_ref().unchecked_ref())
+        .unwrap();
+
+    onwheel_callback.forget();
+}
+
+/// Set up double-clicking the image with the left mouse button
+/// to apply the next window preset or to reset the window level,
+/// as per `State::double_click_action`.
+///
+/// Double clicks are ignored while the left mouse button
+/// annotates or measures, as its clicks place labels there.
+fn set_double_click(state: Rc<RefCell<State>>, canvas: &HtmlCanvasElement) {
+    let ondblclick_callback = Closure::wrap(Box::new(move |ev: MouseEvent| {
+        let (action, tool) = {
+            let state = state.borrow();
+            (state.double_click_action, state.mouse_tools[0])
+        };
+        if ev.button() != 0 || matches!(tool, Some(Tool::Annotate | Tool::Measure)) {
+            return;
+        }
+        ev.prevent_default();
+        match action {
+            DoubleClickAction::NextPreset => apply_next_preset(&state),
+            DoubleClickAction::Reset => reset_window_level(&state),
+            DoubleClickAction::Nothing => {}
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    canvas
+        .add_event_listener_with_callback("dblclick", ondblclick_callback.as_ref().unchecked_ref())
+        .unwrap();
+
+    ondblclick_callback.forget();
+}
+
+/// Display the frame with the given index and re-render.
+///
+/// Frames with their own window level in the functional groups
+/// are shown with it,
+/// unless the window level is locked, adjusted by hand,
+/// or kept through the series.
+/// Their own pixel spacing and plane (if any) are used
+/// for measurements and the linked cursor.
+fn set_frame(state: &Rc<RefCell<State>>, frame: u32) {
+    let frame_window_level = {
+        let mut state = state.borrow_mut();
+        let obj = match &state.dicom_obj {
+            Some(obj) if state.frame != frame && frame < state.number_of_frames => obj,
+            _ => return,
+        };
+        let frame_window_level =
+            if state.window_level_locked || state.window_level_adjusted || state.series_windowing {
+                None
+            } else {
+                frame_window_level_of(obj, frame).unwrap_or_else(|e| {
+                    gloo_console::warn!("Could not read the window of the frame:", e);
+                    None
+                })
+            };
+        let pixel_spacing = frame_pixel_spacing(obj, frame)
+            .map(|(row_spacing, column_spacing, _)| (row_spacing, column_spacing));
+        let image_plane = image_plane_of(obj, frame).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read the image plane of the frame:", e);
+            None
+        });
+        state.pixel_spacing = pixel_spacing;
+        state.image_plane = image_plane;
+        state.frame = frame;
+        gloo_console::debug!("Frame", frame + 1, "of", state.number_of_frames);
+        frame_window_level
+    };
+
+    update_dynamic_range(state);
+
+    let window_level = {
+        let state = state.borrow();
+        full_range_window(&state)
+            .or(frame_window_level)
+            .filter(|window_level| state.window_level != Some(*window_level))
+    };
+    match window_level {
+        Some(window_level) => set_window_level(state, window_level),
+        None => render_obj_to_canvas(state),
+    }
+    notify_plane_change(state);
+
+    // the statistics of regions of interest change with the frame
+    let has_roi = state
+        .borrow()
+        .overlays
+        .iter()
+        .any(|layer| layer.plane.kind == OverlayType::Roi);
+    if has_roi {
+        update_overlay_selector(state);
+    }
+}
+
+/// Start playing the frames of the current object in a loop,
+/// at the frame rate recommended by the object.
+/// Localizer frames are skipped when `hide_localizers` is set.
+fn start_cine(state: &Rc<RefCell<State>>) {
+    stop_cine(state);
+    start_prefetch(state);
+
+    let frame_rate = {
+        let st = state.borrow();
+        let obj = match &st.dicom_obj {
+            Some(obj) if st.number_of_frames > 1 => obj,
+            _ => return,
+        };
+        cine_frame_rate_of(obj)
+    };
+
+    let st = Rc::clone(state);
+    let ontick_callback = Closure::wrap(Box::new(move || {
+        let next_frame = {
+            let state = st.borrow();
+            let hidden = |frame: u32| match &state.dicom_obj {
+                Some(obj) if state.hide_localizers => study::is_localizer_frame(obj, frame),
+                _ => false,
+            };
+            match study::step_frame(state.frame, true, state.number_of_frames, &hidden) {
+                // back to the first frame shown after the last one
+                next if next == state.frame && !hidden(0) => 0,
+                next if next == state.frame => {
+                    study::step_frame(0, true, state.number_of_frames, &hidden)
+                }
+                next => next,
+            }
+        };
+        set_frame(&st, next_frame);
+    }) as Box<dyn FnMut()>);
+
+    let window = web_sys::window().expect("no global `window` exists");
+    match window.set_interval_with_callback_and_timeout_and_arguments_0(
+        ontick_callback.as_ref().unchecked_ref(),
+        (1000. / frame_rate).round() as i32,
+    ) {
+        Ok(handle) => {
+            gloo_console::log!("Cine started at", frame_rate, "fps");
+            state.borrow_mut().cine = Some((handle, ontick_callback));
+        }
+        Err(e) => gloo_console::error!("Could not start cine:", e),
+    }
+}
+
+/// Stop playing the frames of the current object, if playing.
+fn stop_cine(state: &RefCell<State>) {
+    let cine = state.borrow_mut().cine.take();
+    if let Some((handle, _)) = cine {
+        let window = web_sys::window().expect("no global `window` exists");
+        window.clear_interval_with_handle(handle);
+    }
+}
+
+/// The number of RLE frames decoded ahead of the current one by default
+const DEFAULT_PREFETCH_FRAMES: u32 = 16;
+
+/// The largest number of RLE frames decoded ahead of the current one
+const MAX_PREFETCH_FRAMES: u32 = 64;
+
+/// The time left in an idle period
+/// below which no more frames are prefetched in it, in milliseconds
+const PREFETCH_MIN_IDLE_TIME: f64 = 4.;
+
+/// The prefetch of the frames of an RLE object,
+/// decoded on idle time ahead of playback
+#[derive(Debug)]
+struct Prefetch {
+    /// the frames left to decode, next first
+    frames: VecDeque<u32>,
+    /// the handle of the pending idle callback, if any
+    handle: Option<u32>,
+    callback: Closure<dyn FnMut(IdleDeadline)>,
+}
+
+/// Start decoding the frames following the current one
+/// of a multi-frame RLE object on idle time,
+/// up to the prefetch window,
+/// so that playback does not stall on decoding them.
+///
+/// Any previous prefetch is canceled.
+fn start_prefetch(state: &Rc<RefCell<State>>) {
+    stop_prefetch(state);
+
+    let frames: VecDeque<u32> = {
+        let st = state.borrow();
+        match &st.dicom_obj {
+            // frames of objects without a UID are not cached
+            Some(obj)
+                if st.number_of_frames > 1
+                    && is_rle_lossless(obj)
+                    && sop_instance_uid_of(obj).is_some() =>
+            {
+                (1..=st.prefetch_window.min(st.number_of_frames - 1))
+                    .map(|i| (st.frame + i) % st.number_of_frames)
+                    .collect()
+            }
+            _ => return,
+        }
+    };
+    if frames.is_empty() {
+        return;
+    }
+
+    let st = Rc::clone(state);
+    let callback = Closure::wrap(Box::new(move |deadline: IdleDeadline| {
+        prefetch_frames(&st, &deadline);
+    }) as Box<dyn FnMut(IdleDeadline)>);
+    state.borrow_mut().prefetch = Some(Prefetch {
+        frames,
+        handle: None,
+        callback,
+    });
+    request_prefetch(state);
+}
+
+/// Ask for the next idle period to prefetch frames in
+fn request_prefetch(state: &RefCell<State>) {
+    let mut st = state.borrow_mut();
+    let prefetch = match &mut st.prefetch {
+        Some(prefetch) => prefetch,
+        None => return,
+    };
+    let window = web_sys::window().expect("no global `window` exists");
+    match window.request_idle_callback(prefetch.callback.as_ref().unchecked_ref()) {
+        Ok(handle) => prefetch.handle = Some(handle),
+        Err(e) => {
+            gloo_console::debug!("Frames are not prefetched without idle callbacks:", e);
+            prefetch.frames.clear();
+        }
+    }
+}
+
+/// Decode the next frames to prefetch while the idle period lasts,
+/// then ask for another one if any are left.
+fn prefetch_frames(state: &RefCell<State>, deadline: &IdleDeadline) {
+    {
+        let mut st = state.borrow_mut();
+        let State {
+            dicom_obj,
+            decoded_frames,
+            prefetch,
+            ..
+        } = &mut *st;
+        let (obj, prefetch) = match (dicom_obj, prefetch) {
+            (Some(obj), Some(prefetch)) => (obj, prefetch),
+            _ => return,
+        };
+        prefetch.handle = None;
+        while deadline.time_remaining() > PREFETCH_MIN_IDLE_TIME {
+            let frame = match prefetch.frames.pop_front() {
+                Some(frame) => frame,
+                None => break,
+            };
+            if let Err(e) = decoded_frames.get(obj, frame) {
+                gloo_console::warn!("Stopped prefetching frames:", e.to_string());
+                prefetch.frames.clear();
+            }
+        }
+        if prefetch.frames.is_empty() {
+            return;
+        }
+    }
+    request_prefetch(state);
+}
+
+/// Cancel the ongoing prefetch of frames, if any.
+fn stop_prefetch(state: &RefCell<State>) {
+    let prefetch = state.borrow_mut().prefetch.take();
+    if let Some(Prefetch {
+        handle: Some(handle),
+        ..
+    }) = prefetch
+    {
+        let window = web_sys::window().expect("no global `window` exists");
+        window.cancel_idle_callback(handle);
+    }
+}
+
+/// The local storage key of the frame scroll direction setting
+const INVERT_SCROLL_KEY: &str = "simple-dicom-viewer.invert_scroll";
+/// The local storage key of the pan and zoom inertia setting
+const INERTIA_KEY: &str = "simple-dicom-viewer.inertia";
+/// The local storage key of the measurement unit setting
+const MEASUREMENT_UNIT_KEY: &str = "simple-dicom-viewer.measurement_unit";
+/// The local storage key of the measurement decimal places setting
+const MEASUREMENT_DECIMALS_KEY: &str = "simple-dicom-viewer.measurement_decimals";
+
+/// The maximum number of decimal places of measurements
+const MAX_MEASUREMENT_DECIMALS: usize = 6;
+
+/// Read a setting persisted in local storage
+fn load_setting(key: &str) -> Option<String> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(key).ok().flatten())
+}
+
+/// Persist a setting in local storage
+fn save_setting(key: &str, value: &str) {
+    let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+    if let Some(storage) = storage {
+        if let Err(e) = storage.set_item(key, value) {
+            gloo_console::warn!("Could not save setting", key, e);
+        }
+    }
+}
+
+/// Read the persisted frame scroll direction setting
+fn load_invert_scroll() -> bool {
+    load_setting(INVERT_SCROLL_KEY)
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// Change and persist the frame scroll direction setting
+fn set_invert_scroll_setting(state: &RefCell<State>, invert_scroll: bool) {
+    state.borrow_mut().invert_scroll = invert_scroll;
+    save_setting(INVERT_SCROLL_KEY, &invert_scroll.to_string());
+}
+
+/// Read the persisted pan and zoom inertia setting
+fn load_inertia() -> bool {
+    load_setting(INERTIA_KEY)
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+/// Change and persist the pan and zoom inertia setting
+fn set_inertia_setting(state: &RefCell<State>, inertia: bool) {
+    if !inertia {
+        stop_viewport_motion(state);
+        render_obj_to_canvas(state);
+    }
+    state.borrow_mut().inertia = inertia;
+    save_setting(INERTIA_KEY, &inertia.to_string());
+}
+
+/// Read the persisted measurement format settings
+fn load_measurement_format() -> MeasurementFormat {
+    let default = MeasurementFormat::default();
+    MeasurementFormat {
+        unit: load_setting(MEASUREMENT_UNIT_KEY)
+            .and_then(|unit| LengthUnit::from_symbol(&unit))
+            .unwrap_or(default.unit),
+        decimals: load_setting(MEASUREMENT_DECIMALS_KEY)
+            .and_then(|decimals| decimals.parse::<usize>().ok())
+            .map(|decimals| decimals.min(MAX_MEASUREMENT_DECIMALS))
+            .unwrap_or(default.decimals),
+    }
+}
+
+/// Change and persist how measurements are displayed, and re-render.
+fn set_measurement_format_setting(state: &RefCell<State>, format: MeasurementFormat) {
+    state.borrow_mut().measurement_format = format;
+    save_setting(MEASUREMENT_UNIT_KEY, format.unit.symbol());
+    save_setting(MEASUREMENT_DECIMALS_KEY, &format.decimals.to_string());
+    render_obj_to_canvas(state);
+}
+
+/// Set up keyboard shortcuts:
+///
+/// - `A`: toggle the actual size display mode
+/// - `S`: cycle through the smoothing modes
+/// - `V`: invert the gray levels of monochrome images
+/// - `E`: toggle edge enhancement (unsharp mask)
+/// - `O`: show or hide all overlays
+/// - `H`: skip localizer images and frames, or show them again
+/// - `R`: reverse the frame scroll direction
+/// - `I`: toggle pan and zoom inertia
+/// - `C`: start or stop cine playback
+/// - `N`: toggle annotating with the left mouse button
+/// - `M`: toggle measuring distances with the left mouse button
+/// - `Z`: toggle zooming to a rectangle selected with the left mouse button
+/// - `U`: switch the unit of measurements between millimeters and centimeters
+/// - `Backspace`: remove the last annotation
+/// - `D`: download the raw samples of the current frame as a `.npy` file
+///   (with Shift, of all frames)
+/// - `B`: compare the following images to the current one,
+///   or stop comparing
+/// - `W`: toggle keeping the same window level through the series
+/// - `L`: lock the window level while going through frames
+/// - `P`: read color samples with the other planar configuration
+/// - `X`: compare the window level with each window preset in turn
+///   in a split view, then close it
+/// - `0`: reset the window level, zoom, pan, and all display settings
+/// - `F`: with the `data-debug` attribute on the output canvas,
+///   cycle through the supported photometric interpretations
+///   to read the pixel data with
+///
+/// The shortcuts only apply while the viewer has keyboard focus.
+fn set_shortcut_keys(state: Rc<RefCell<State>>, viewer: &HtmlElement) {
+    let onkeydown_callback = Closure::wrap(Box::new(move |ev: KeyboardEvent| {
+        if ev.ctrl_key() || ev.alt_key() || ev.meta_key() {
+            return;
+        }
+
+        match ev.key().as_str() {
+            "a" | "A" => {
+                let mut settings = DisplaySettings::of(&state.borrow());
+                settings.scale_mode = match settings.scale_mode {
+                    ScaleMode::Fit => ScaleMode::ActualSize,
+                    ScaleMode::ActualSize => ScaleMode::Fit,
+                };
+                update_display_settings(&state, settings);
+            }
+            "s" | "S" => {
+                let mut settings = DisplaySettings::of(&state.borrow());
+                settings.smoothing = settings.smoothing.next();
+                gloo_console::log!("Smoothing:", format!("{:?}", settings.smoothing));
+                update_display_settings(&state, settings);
+            }
+            "v" | "V" => {
+                let mut settings = DisplaySettings::of(&state.borrow());
+                settings.display.invert = !settings.display.invert;
+                gloo_console::log!("Inverted:", settings.display.invert);
+                update_display_settings(&state, settings);
+            }
+            "e" | "E" => {
+                let sharpen = !state.borrow().sharpen;
+                gloo_console::log!("Edge enhancement:", sharpen);
+                state.borrow_mut().sharpen = sharpen;
+                render_obj_to_canvas(&state);
+            }
+            "o" | "O" => {
+                {
+                    let mut state = state.borrow_mut();
+                    let visible = !state.overlays.iter().any(|layer| layer.visible);
+                    gloo_console::log!("Overlays:", visible);
+                    for layer in &mut state.overlays {
+                        layer.visible = visible;
+                    }
+                }
+                render_obj_to_canvas(&state);
+                update_overlay_selector(&state);
+            }
+            "h" | "H" => {
+                let hide_localizers = !state.borrow().hide_localizers;
+                gloo_console::log!("Hidden localizers:", hide_localizers);
+                state.borrow_mut().hide_localizers = hide_localizers;
+            }
+            "r" | "R" => {
+                let invert_scroll = !state.borrow().invert_scroll;
+                gloo_console::log!("Inverted frame scrolling:", invert_scroll);
+                set_invert_scroll_setting(&state, invert_scroll);
+            }
+            "i" | "I" => {
+                let inertia = !state.borrow().inertia;
+                gloo_console::log!("Pan and zoom inertia:", inertia);
+                set_inertia_setting(&state, inertia);
+            }
+            "n" | "N" => {
+                let mut state = state.borrow_mut();
+                let tool = match state.mouse_tools[0] {
+                    Some(Tool::Annotate) => Tool::WindowLevel,
+                    _ => Tool::Annotate,
+                };
+                gloo_console::log!("Left mouse button tool:", format!("{:?}", tool));
+                state.mouse_tools[0] = Some(tool);
+            }
+            "m" | "M" => {
+                let mut state = state.borrow_mut();
+                let tool = match state.mouse_tools[0] {
+                    Some(Tool::Measure) => Tool::WindowLevel,
+                    _ => Tool::Measure,
+                };
+                gloo_console::log!("Left mouse button tool:", format!("{:?}", tool));
+                state.mouse_tools[0] = Some(tool);
+            }
+            "z" | "Z" => {
+                let mut state = state.borrow_mut();
+                let tool = match state.mouse_tools[0] {
+                    Some(Tool::ZoomRect) => Tool::WindowLevel,
+                    _ => Tool::ZoomRect,
+                };
+                gloo_console::log!("Left mouse button tool:", format!("{:?}", tool));
+                state.mouse_tools[0] = Some(tool);
+            }
+            "u" | "U" => {
+                let mut format = state.borrow().measurement_format;
+                format.unit = match format.unit {
+                    LengthUnit::Millimeter => LengthUnit::Centimeter,
+                    LengthUnit::Centimeter => LengthUnit::Millimeter,
+                };
+                gloo_console::log!("Measurement unit:", format.unit.symbol());
+                set_measurement_format_setting(&state, format);
+            }
+            "Backspace" => remove_last_annotation(&state),
+            "d" | "D" => {
+                if let Err(e) = download_pixel_data(&state, ev.shift_key()) {
+                    gloo_console::error!("Could not export the pixel data:", e);
+                }
+            }
+            "w" | "W" => {
+                let series_windowing = !state.borrow().series_windowing;
+                gloo_console::log!("Series windowing:", series_windowing);
+                set_series_windowing(&state, series_windowing);
+            }
+            "l" | "L" => {
+                let locked = !state.borrow().window_level_locked;
+                gloo_console::log!("Window level locked:", locked);
+                state.borrow_mut().window_level_locked = locked;
+            }
+            "p" | "P" => {
+                let flip_planar = !state.borrow().flip_planar;
+                gloo_console::log!("Flipped planar configuration:", flip_planar);
+                set_flip_planar(&state, flip_planar);
+            }
+            "f" | "F" if state.borrow().debug => {
+                let force_photometric = next_forced_photometric(state.borrow().force_photometric);
+                gloo_console::log!(
+                    "Forced PhotometricInterpretation:",
+                    force_photometric.unwrap_or("(none)")
+                );
+                set_force_photometric(&state, force_photometric);
+            }
+            "b" | "B" => {
+                if state.borrow().baseline.is_some() {
+                    clear_baseline(&state);
+                } else {
+                    set_baseline(&state);
+                }
+            }
+            "x" | "X" => {
+                // compare with each preset in turn, then close the split view
+                let next = match state.borrow().split {
+                    Some(split) => Some(split.preset + 1),
+                    None => Some(0),
+                };
+                set_split_preset(&state, next);
+            }
+            "0" => reset_all(&state),
+            "c" | "C" => {
+                if state.borrow().cine.is_some() {
+                    stop_cine(&state);
+                } else {
+                    start_cine(&state);
+                }
+            }
+            _ => {}
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    viewer
+        .add_event_listener_with_callback("keydown", onkeydown_callback.as_ref().unchecked_ref())
+        .unwrap();
+
+    onkeydown_callback.forget();
+}
+
+/// Compare the images displayed from now on
+/// to the current frame of the current image,
+/// showing the difference between them.
+fn set_baseline(state: &RefCell<State>) {
+    {
+        let mut state = state.borrow_mut();
+        let baseline = match &state.dicom_obj {
+            Some(obj) => (Rc::clone(obj), state.frame),
+            None => {
+                gloo_console::warn!("No DICOM object loaded");
+                return;
+            }
+        };
+        gloo_console::log!("Comparing to frame", baseline.1, "of the current image");
+        state.baseline = Some(baseline);
+    }
+    render_obj_to_canvas(state);
+}
+
+/// Stop comparing images, showing them as they are.
+fn clear_baseline(state: &RefCell<State>) {
+    gloo_console::log!("Stopped comparing images");
+    state.borrow_mut().baseline = None;
+    render_obj_to_canvas(state);
+}
+
+/// Offer the raw samples of the current frame,
+/// or of all frames,
+/// for download as a `.npy` file.
+fn download_pixel_data(state: &RefCell<State>, all_frames: bool) -> Result<(), JsValue> {
+    let (npy, file_name) = {
+        let state = state.borrow();
+        let obj = state
+            .dicom_obj
+            .as_deref()
+            .ok_or_else(|| JsValue::from_str("No DICOM object loaded"))?;
+        let frame = if all_frames { None } else { Some(state.frame) };
+        let npy = npy::pixel_data_to_npy(obj, frame, &state.decoded_frames)?;
+
+        let name = sop_instance_uid_of(obj).unwrap_or_else(|| "pixel_data".to_string());
+        let file_name = match frame {
+            Some(frame) if state.number_of_frames > 1 => format!("{}_frame{}.npy", name, frame),
+            _ => format!("{}.npy", name),
+        };
+        (npy, file_name)
+    };
+
+    download(&npy, &file_name)
+}
+
+/// Offer the given data for download under the given file name.
+fn download(data: &[u8], file_name: &str) -> Result<(), JsValue> {
+    download_blob(
+        Blob::new_with_options(data, Some("application/octet-stream")),
+        file_name,
+    )
+}
+
+/// Offer the contents of a canvas for download as a PNG image
+/// under the given file name, once it has been encoded.
+fn download_canvas(canvas: &HtmlCanvasElement, file_name: &str) -> Result<(), JsValue> {
+    let file_name = file_name.to_string();
+    let onblob_callback = Closure::once_into_js(move |blob: JsValue| {
+        let downloaded = match blob.dyn_into::<web_sys::Blob>() {
+            Ok(blob) => download_blob(blob.into(), &file_name),
+            Err(_) => Err(JsValue::from_str("Could not encode the image as PNG")),
+        };
+        if let Err(e) = downloaded {
+            gloo_console::error!("Could not download", &file_name, e);
+        }
+    });
+    canvas.to_blob(onblob_callback.unchecked_ref())
+}
+
+/// Offer a blob for download under the given file name.
+fn download_blob(blob: Blob, file_name: &str) -> Result<(), JsValue> {
+    let url = gloo_file::ObjectUrl::from(blob);
+
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let link: HtmlElement = document.create_element("a")?.dyn_into()?;
+    link.set_attribute("href", &url)?;
+    link.set_attribute("download", file_name)?;
+    link.click();
+
+    // release the data once the download has started
+    window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        Closure::once_into_js(move || drop(url)).unchecked_ref(),
+        1000,
+    )?;
+    Ok(())
+}
+
+/// Copy the contents of the canvas to the clipboard as a PNG image,
+/// through the asynchronous Clipboard API.
+///
+/// The clipboard item is given a promise of the image
+/// so that it is written during the user gesture,
+/// as some browsers (such as Safari) require.
+///
+/// The promise is rejected with a message
+/// if the image could not be encoded or written,
+/// such as when writing to the clipboard is not allowed.
+/// Fails right away if the browser cannot copy images.
+fn copy_canvas_to_clipboard(canvas: &HtmlCanvasElement) -> Result<js_sys::Promise, JsValue> {
+    let global = js_sys::global();
+    let clipboard = js_sys::Reflect::get(&global, &JsValue::from_str("navigator"))
+        .and_then(|navigator| js_sys::Reflect::get(&navigator, &JsValue::from_str("clipboard")))
+        .ok()
+        .filter(|clipboard| clipboard.is_object());
+    let write = clipboard
+        .as_ref()
+        .and_then(|clipboard| js_sys::Reflect::get(clipboard, &JsValue::from_str("write")).ok())
+        .and_then(|write| write.dyn_into::<js_sys::Function>().ok());
+    let clipboard_item = js_sys::Reflect::get(&global, &JsValue::from_str("ClipboardItem"))
+        .ok()
+        .and_then(|item| item.dyn_into::<js_sys::Function>().ok());
+    let (clipboard, write, clipboard_item) = match (clipboard, write, clipboard_item) {
+        (Some(clipboard), Some(write), Some(clipboard_item)) => (clipboard, write, clipboard_item),
+        _ => {
+            return Err(JsValue::from_str(
+                "Copying images to the clipboard is not supported by this browser",
+            ))
+        }
+    };
+
+    Ok(js_sys::Promise::new(&mut |resolve, reject| {
+        // the clipboard is written right away, within the user gesture,
+        // with the image data to follow once it is encoded
+        let png = js_sys::Promise::new(&mut |resolve_png, reject_png| {
+            let on_reject = reject_png.clone();
+            let onblob_callback = Closure::once_into_js(move |blob: JsValue| {
+                if blob.is_null() {
+                    let _ = reject_png.call1(
+                        &JsValue::NULL,
+                        &JsValue::from_str("Could not encode the image as PNG"),
+                    );
+                } else {
+                    let _ = resolve_png.call1(&JsValue::NULL, &blob);
+                }
+            });
+            if let Err(e) = canvas.to_blob(onblob_callback.unchecked_ref()) {
+                let _ = on_reject.call1(&JsValue::NULL, &e);
+            }
+        });
+        let items = js_sys::Object::new();
+        let written = js_sys::Reflect::set(&items, &JsValue::from_str("image/png"), &png)
+            .and_then(|_| js_sys::Reflect::construct(&clipboard_item, &js_sys::Array::of1(&items)))
+            .and_then(|item| write.call1(&clipboard, &js_sys::Array::of1(&item)))
+            .and_then(|written| written.dyn_into::<js_sys::Promise>());
+        match written {
+            Ok(written) => {
+                let onwritten_callback = Closure::once(move |_: JsValue| {
+                    let _ = resolve.call0(&JsValue::NULL);
+                });
+                let onfailed_callback = Closure::once(move |e: JsValue| {
+                    let _ = reject.call1(&JsValue::NULL, &clipboard_error(&e));
+                });
+                let _ = written.then2(&onwritten_callback, &onfailed_callback);
+                onwritten_callback.forget();
+                onfailed_callback.forget();
+            }
+            Err(e) => {
+                let _ = reject.call1(&JsValue::NULL, &clipboard_error(&e));
+            }
+        }
+    }))
+}
+
+/// A message explaining why an image could not be copied to the clipboard
+fn clipboard_error(e: &JsValue) -> JsValue {
+    let name = js_sys::Reflect::get(e, &JsValue::from_str("name"))
+        .ok()
+        .and_then(|name| name.as_string());
+    match name.as_deref() {
+        Some("NotAllowedError") => {
+            JsValue::from_str("Permission to write to the clipboard was denied")
+        }
+        _ => JsValue::from_str(&format!(
+            "Could not copy the image to the clipboard: {:?}",
+            e
+        )),
+    }
+}
+
+/// Draw the current frame with the current window level and overlays
+/// onto another canvas, fitted to its size.
+///
+/// The output canvas and its viewport are left as they are.
+fn render_to_target(state: &RefCell<State>, target: &HtmlCanvasElement) -> Result<(), JsValue> {
+    let context = target
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("The target canvas has no 2D context"))?
+        .dyn_into::<CanvasRenderingContext2d>()?;
+
+    let mut state = state.borrow_mut();
+    let State {
+        dicom_obj,
+        lut,
+        canvas,
+        overlays,
+        frame,
+        pixel_spacing,
+        lut_options,
+        flip_planar,
+        force_photometric,
+        display,
+        color_balance,
+        decoded_frames,
+        ..
+    } = &mut *state;
+    let obj = dicom_obj
+        .as_deref()
+        .ok_or_else(|| JsValue::from_str("No DICOM object loaded"))?;
+    let native = native_frame(decoded_frames, obj, *frame)?;
+
+    let imagedata = obj_to_imagedata(
+        native.obj(),
+        native.frame(),
+        &mut Vec::new(),
+        lut,
+        &mut None,
+        overlays,
+        ConversionOptions {
+            lut: *lut_options,
+            flip_planar: *flip_planar,
+            force_photometric: *force_photometric,
+            display: *display,
+            color_balance: *color_balance,
+        },
+    )?;
+    draw_fitted(&imagedata, canvas, target, &context, *pixel_spacing)
+}
+
+/// Draw image data onto a canvas through the given inner canvas,
+/// fitted to its size and centered on a black background.
+fn draw_fitted(
+    imagedata: &ImageData,
+    canvas: &InnerCanvas,
+    target: &HtmlCanvasElement,
+    context: &CanvasRenderingContext2d,
+    pixel_spacing: Option<(f64, f64)>,
+) -> Result<(), JsValue> {
+    let placement = Placement {
+        pixel_spacing,
+        scale_mode: ScaleMode::Fit,
+        viewport: Viewport::default(),
+        background: Background::default(),
+    };
+    let transform = image_transform_on(target, placement, (imagedata.width(), imagedata.height()));
+    canvas.put_image_data(imagedata)?;
+
+    context.set_transform(1., 0., 0., 1., 0., 0.)?;
+    clear(context, target, placement.background)?;
+    context.set_transform(
+        transform.scale.0,
+        0.,
+        0.,
+        transform.scale.1,
+        transform.offset.0,
+        transform.offset.1,
+    )?;
+    canvas.draw_onto(context, 0., 0.)?;
+    context.set_transform(1., 0., 0., 1., 0., 0.)
+}
+
+/// Render thumbnails of the frames of the instances in the series on display
+/// (or only of the image on display if it is not in the study browser)
+/// into a contact sheet,
+/// with the current window level and display settings.
+///
+/// Each frame is reduced to its thumbnail as soon as it is rendered.
+/// Instances without their pixel data (with lazy loading) are left out.
+fn contact_sheet(
+    state: &State,
+    columns: u32,
+    thumbnail_size: u32,
+    max_images: Option<u32>,
+) -> Result<ImageData, JsValue> {
+    let (min_size, max_size) = SHEET_THUMBNAIL_SIZES;
+    if !(1..=MAX_SHEET_COLUMNS).contains(&columns) {
+        return Err(JsValue::from_str(&format!(
+            "Columns should be between 1 and {}",
+            MAX_SHEET_COLUMNS
+        )));
+    }
+    if !(min_size..=max_size).contains(&thumbnail_size) {
+        return Err(JsValue::from_str(&format!(
+            "Thumbnail size should be between {} and {} pixels",
+            min_size, max_size
+        )));
+    }
+    let obj = state
+        .dicom_obj
+        .as_deref()
+        .ok_or_else(|| JsValue::from_str("No DICOM object loaded"))?;
+
+    // the image on display is read in full even if its instance is not
+    let uid = sop_instance_uid_of(obj);
+    let instances: Vec<&DefaultDicomObject> = match study::series_of(&state.series, obj) {
+        Some(series) => series
+            .instances
+            .iter()
+            .map(|instance| {
+                if uid.is_some() && sop_instance_uid_of(instance) == uid {
+                    obj
+                } else {
+                    &**instance
+                }
+            })
+            .collect(),
+        None => vec![obj],
+    };
+    let frames: Vec<(&DefaultDicomObject, u32)> = instances
+        .into_iter()
+        .flat_map(|instance| {
+            let frames = number_of_frames_of(instance).unwrap_or(1);
+            (0..frames).map(move |frame| (instance, frame))
+        })
+        .collect();
+
+    let sampled = sample_evenly(frames.len(), max_images.map(|max| max as usize));
+    let mut sheet = ContactSheet::new(sampled.len(), columns, thumbnail_size).ok_or_else(|| {
+        JsValue::from_str(
+            "The contact sheet would be too large, sample fewer images or use smaller thumbnails",
+        )
+    })?;
+    for i in sampled {
+        let (instance, frame) = frames[i];
+        let added = frame_rgba_of(state, instance, frame)
+            .and_then(|(rgba, width, height)| sheet.push(&rgba, width, height));
+        if let Err(e) = added {
+            gloo_console::warn!("Leaving a frame out of the contact sheet:", e.to_string());
+        }
+    }
+    if sheet.is_empty() {
+        return Err(JsValue::from_str("None of the images could be rendered"));
+    }
+    let (rgba, width, height) = sheet.finish();
+    new_imagedata(&rgba, width, height, DisplayColorSpace::Srgb)
+}
+
+/// Render a frame of an instance without overlays,
+/// with the current window level if any
+/// or else the default one of the instance,
+/// and the current display settings.
+///
+/// Returns its RGBA samples, width, and height.
+fn frame_rgba_of(
+    state: &State,
+    obj: &DefaultDicomObject,
+    frame: u32,
+) -> imaging::Result<(Vec<u8>, u32, u32)> {
+    let window_level = state
+        .window_level
+        .or_else(|| preset::default_window_level_of(obj, frame, &state.modality_presets));
+    let native = native_frame(&state.decoded_frames, obj, frame)?;
+    let (obj, frame) = (native.obj(), native.frame());
+    let imagedata = if has_float_pixel_data(obj) {
+        float_to_imagedata(
+            obj,
+            frame,
+            window_level,
+            state.diverging,
+            state.display,
+            &mut None,
+        )?
+    } else {
+        let mut lut = window_level.and_then(|window_level| {
+            simple_pixel_data_lut_with(obj, window_level, state.lut_options).ok()
+        });
+        let options = ConversionOptions {
+            lut: state.lut_options,
+            display: state.display,
+            ..Default::default()
+        };
+        obj_to_imagedata(
+            obj,
+            frame,
+            &mut Vec::new(),
+            &mut lut,
+            &mut None,
+            &[],
+            options,
+        )?
+    };
+    Ok((imagedata.data().0, imagedata.width(), imagedata.height()))
+}
+
+/// Resize a canvas to the image data and put it there
+fn put_on_canvas(canvas: &HtmlCanvasElement, imagedata: &ImageData) -> Result<(), JsValue> {
+    let context = canvas
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("The canvas has no 2D context"))?
+        .dyn_into::<CanvasRenderingContext2d>()?;
+    canvas.set_width(imagedata.width());
+    canvas.set_height(imagedata.height());
+    context.put_image_data(imagedata, 0., 0.)
+}
+
+/// Apply new display settings and re-render.
+///
+/// Changing the scale mode also resets the zoom and pan.
+fn update_display_settings(state: &RefCell<State>, settings: DisplaySettings) {
+    let full_range_changed = settings.full_range != state.borrow().lut_options.linear;
+    if settings.scale_mode != state.borrow().scale_mode {
+        stop_viewport_motion(state);
+        let mut state = state.borrow_mut();
+        state.scale_mode = settings.scale_mode;
+        state.viewport = Viewport::default();
+        update_warning_message(&state);
+    }
+    {
+        let mut state = state.borrow_mut();
+        state.smoothing = settings.smoothing;
+        state.background = settings.background;
+        state.display = settings.display;
+        state.color_balance = settings.color_balance;
+        state.diverging = settings.diverging;
+        state.zoom_options = settings.zoom;
+        state.viewport.zoom = settings.zoom.clamp(state.viewport.zoom);
+        state.lut_options.linear = settings.full_range;
+        if full_range_changed {
+            state.full_range_by_intent = false;
+        }
+    }
+
+    if full_range_changed {
+        // window over the full range, or go back to the window as loaded
+        let full_range = full_range_window(&state.borrow());
+        match full_range {
+            Some(window_level) => set_window_level(state, window_level),
+            None => reset_window_level(state),
+        }
+        return;
+    }
+    render_obj_to_canvas(state);
+}
+
+/// Show the warnings about the current image and display mode.
+fn update_warning_message(state: &State) {
+    let mut warnings = state.warnings.clone();
+    if state.scale_mode == ScaleMode::ActualSize {
+        if state.pixel_spacing.is_some() {
+            warnings.push(
+                "Actual size is estimated assuming 96 pixels per inch and may be inaccurate."
+                    .to_string(),
+            );
+        } else {
+            warnings
+                .push("Actual size is not available, the pixel spacing is unknown.".to_string());
+        }
+    }
+    set_warning_message(&warnings.join(" "));
+}
+
+/// Set up fine window level adjustment via the arrow keys
+/// while the Ctrl key is held.
+///
+/// Up/down change the window center,
+/// left/right change the window width.
+/// Holding Shift applies a larger step,
+/// as per `State::key_window_level_steps`.
+/// This only applies while the viewer has keyboard focus.
+fn set_window_level_keys(state: Rc<RefCell<State>>, viewer: &HtmlElement) {
+    let onkeydown_callback = Closure::wrap(Box::new(move |ev: KeyboardEvent| {
+        if !ev.ctrl_key() {
+            return;
+        }
+
+        let steps = state.borrow().key_window_level_steps;
+        let (ww, wc) = match steps.change_of(&ev.key(), ev.shift_key()) {
+            Some(change) => change,
+            None => return,
+        };
+
+        ev.prevent_default();
+        change_window_level(&state, ww, wc);
+    }) as Box<dyn FnMut(_)>);
+
+    viewer
+        .add_event_listener_with_callback("keydown", onkeydown_callback.as_ref().unchecked_ref())
+        .unwrap();
+
+    onkeydown_callback.forget();
+}
+
+fn change_window_level(state: &RefCell<State>, rel_ww: f64, rel_wc: f64) {
+    // get the current window level
+    let window_level = if let Some(window_level) = state.borrow().window_level {
+        window_level
+    } else {
+        // ignore, no window level available
+        return;
+    };
+
+    // parametric maps are adjusted in steps scaled to their values
+    let unit = state.borrow().window_level_unit;
+    let new_ww = (window_level.width + rel_ww * unit).max(unit);
+    let new_wc = window_level.center + rel_wc * unit;
+    // keep the adjustment while going through frames
+    state.borrow_mut().window_level_adjusted = true;
+
+    set_window_level(
+        state,
+        WindowLevel {
+            width: new_ww,
+            center: new_wc,
+        },
+    );
+}
+
+/// Replace the current window level, update the LUT, and re-render.
+fn set_window_level(state: &RefCell<State>, new_window_level: WindowLevel) {
+    {
+        let mut state = state.borrow_mut();
+        let State {
+            dicom_obj,
+            window_level,
+            voi_lut,
+            lut,
+            lut_options,
+            ..
+        } = &mut *state;
+
+        let obj = if let Some(obj) = &dicom_obj {
+            obj
+        } else {
+            // ignore, no DICOM object loaded
+            return;
+        };
+
+        // update the window level, replacing any explicit VOI LUT
+        *window_level = Some(new_window_level);
+        *voi_lut = None;
+        gloo_console::debug!(
+            "[WL] updated to",
+            new_window_level.width,
+            ",",
+            new_window_level.center
+        );
+
+        if let Some(lut) = lut {
+            // update the LUT
+            match update_pixel_data_lut_with(lut, obj, new_window_level, *lut_options) {
+                Ok(lut) => lut,
+                Err(e) => {
+                    gloo_console::error!("Failed to update LUT:", e);
+                    return;
+                }
+            }
+        };
+    }
+
+    // update canvas
+    render_obj_to_canvas(state);
+
+    notify_window_level_change(state, Some(new_window_level));
+}
+
+/// Enable or disable keeping the same window level through the series
+/// of the current object, and apply the corresponding window level.
+///
+/// Falls back to the object's own window level
+/// if the range of values of the series is not known.
+fn set_series_windowing(state: &RefCell<State>, enabled: bool) {
+    let window_level = {
+        let mut state = state.borrow_mut();
+        state.series_windowing = enabled;
+        let obj = match &state.dicom_obj {
+            Some(obj) => obj,
+            None => return,
+        };
+        let series_window_level = if enabled {
+            study::series_of(&state.series, obj).and_then(|series| series.window_level())
+        } else {
+            None
+        };
+        if enabled && series_window_level.is_none() {
+            gloo_console::warn!("The range of values of the series is unknown");
+        }
+        series_window_level.or_else(|| window_level_of(obj).unwrap_or(None))
+    };
+
+    if let Some(window_level) = window_level {
+        set_window_level(state, window_level);
+    }
+}
+
+/// Go back to the window level of the current frame as loaded,
+/// dropping any adjustment or preset, and re-render.
+///
+/// Images which declare no window level
+/// get their default LUT rebuilt instead.
+fn reset_window_level(state: &RefCell<State>) {
+    let window_level = {
+        let mut state = state.borrow_mut();
+        state.window_level_adjusted = false;
+        let obj = match &state.dicom_obj {
+            Some(obj) => obj,
+            None => return,
+        };
+        let window_level = frame_window_level_of(obj, state.frame)
+            .unwrap_or(None)
+            .or_else(|| window_level_of(obj).unwrap_or(None))
+            .or_else(|| preset::default_window_level_of(obj, state.frame, &state.modality_presets))
+            .or_else(|| float_window_level_of(obj, state.frame));
+        if window_level.is_none() {
+            state.window_level = None;
+            state.voi_lut = None;
+            state.lut = None;
+        }
+        window_level
+    };
+
+    match window_level {
+        Some(window_level) => set_window_level(state, window_level),
+        None => {
+            render_obj_to_canvas(state);
+            notify_window_level_change(state, None);
+        }
+    }
+}
+
+/// Go back to showing the current image as loaded, re-rendering once:
+/// the window level as loaded, no zoom or pan,
+/// the default display settings (keeping the zoom options),
+/// no edge enhancement, split view, or read-time corrections,
+/// and the full range only if the image is meant for processing.
+///
+/// Overlays, annotations, and the modes of the mouse and keyboard are kept.
+fn reset_all(state: &RefCell<State>) {
+    stop_viewport_motion(state);
+    {
+        let mut state = state.borrow_mut();
+        state.display = DisplayOptions::default();
+        state.color_balance = ColorBalance::default();
+        state.diverging = None;
+        state.scale_mode = ScaleMode::Fit;
+        state.smoothing = Smoothing::On;
+        state.viewport = Viewport::default();
+        state.background = Background::default();
+        state.sharpen = false;
+        state.split = None;
+        state.flip_planar = false;
+        state.force_photometric = None;
+        let for_processing = state.viewing_intent.for_processing();
+        state.lut_options.linear = for_processing;
+        state.full_range_by_intent = for_processing;
+        update_warning_message(&state);
+    }
+    gloo_console::log!("Reset all display settings");
+
+    // the window level is applied last, rendering everything at once
+    let full_range = full_range_window(&state.borrow());
+    match full_range {
+        Some(window_level) => set_window_level(state, window_level),
+        None => reset_window_level(state),
+    }
+}
+
+/// Apply the window preset after the one in use (if any),
+/// or go back to the window level as loaded
+/// after the last preset or if there are none.
+fn apply_next_preset(state: &RefCell<State>) {
+    let next = {
+        let state = state.borrow();
+        let current = state.presets.iter().position(|preset| match &preset.voi {
+            Voi::Window(window_level) => {
+                state.voi_lut.is_none() && state.window_level == Some(*window_level)
+            }
+            Voi::Lut(voi_lut) => state.voi_lut.as_ref() == Some(voi_lut),
+        });
+        let next = current.map_or(0, |i| i + 1);
+        state.presets.get(next).map(|preset| preset.voi.clone())
+    };
+
+    match next {
+        Some(Voi::Window(window_level)) => set_window_level(state, window_level),
+        Some(Voi::Lut(voi_lut)) => set_voi_lut(state, voi_lut),
+        None => reset_window_level(state),
+    }
+}
+
+/// Choose whether color samples are read
+/// with the opposite planar configuration to the declared or detected one,
+/// and re-render.
+fn set_flip_planar(state: &RefCell<State>, flip_planar: bool) {
+    state.borrow_mut().flip_planar = flip_planar;
+    render_obj_to_canvas(state);
+}
+
+/// The photometric interpretation to force after the given one:
+/// each of the supported ones in turn, then the declared one again.
+fn next_forced_photometric(current: Option<&'static str>) -> Option<&'static str> {
+    let next = match current {
+        Some(pi) => SUPPORTED_PHOTOMETRIC_INTERPRETATIONS
+            .iter()
+            .position(|&supported| supported == pi)
+            .map_or(0, |i| i + 1),
+        None => 0,
+    };
+    SUPPORTED_PHOTOMETRIC_INTERPRETATIONS.get(next).copied()
+}
+
+/// Read the pixel data of the current object
+/// with the given photometric interpretation instead of the declared one
+/// (or with the declared one again if `None`),
+/// and re-render.
+fn set_force_photometric(state: &RefCell<State>, force_photometric: Option<&'static str>) {
+    state.borrow_mut().force_photometric = force_photometric;
+    render_obj_to_canvas(state);
+}
+
+/// Read the pixel data of the current object with the given bit depth
+/// instead of the declared one, for files whose header is wrong,
+/// then rebuild everything which depends on it and re-render.
+/// The override lasts until another image is loaded.
+fn force_bit_depth(
+    state: &RefCell<State>,
+    bits_allocated: u16,
+    bits_stored: u16,
+) -> imaging::Result<()> {
+    {
+        let mut st = state.borrow_mut();
+        let State {
+            dicom_obj,
+            window_level,
+            lut,
+            lut_options,
+            tiles,
+            preset_previews,
+            split_lut,
+            overlays,
+            warnings,
+            dynamic_ranges,
+            ..
+        } = &mut *st;
+        let obj = match dicom_obj {
+            Some(obj) => obj,
+            None => return Ok(()),
+        };
+        // the instance cache keeps the object as read
+        override_bit_depth(Rc::make_mut(obj), bits_allocated, bits_stored)?;
+        dynamic_ranges.clear();
+
+        let msg = format!(
+            "The bit depth of this image is overridden (BitsAllocated {}, BitsStored {}).",
+            bits_allocated, bits_stored
+        );
+        gloo_console::warn!(&msg);
+        warnings.push(msg);
+
+        *lut = match window_level {
+            Some(window_level) => Some(simple_pixel_data_lut_with(
+                obj,
+                *window_level,
+                *lut_options,
+            )?),
+            None => None,
+        };
+        *tiles = TileCache::default();
+        *preset_previews = None;
+        *split_lut = None;
+        *overlays = overlay_layers_of(obj).unwrap_or_else(|e| {
+            gloo_console::warn!("Could not read overlays:", e);
+            Vec::new()
+        });
+        update_warning_message(&st);
+    }
+    render_obj_to_canvas(state);
+    update_dynamic_range(state);
+    Ok(())
+}
+
+/// Call the window level change callback registered by the host page, if any,
+/// with `null` for both the width and center if no window level applies.
+///
+/// The state is not borrowed during the call,
+/// so the callback may call back into the viewer.
+fn notify_window_level_change(state: &RefCell<State>, window_level: Option<WindowLevel>) {
+    let callback = state.borrow().on_window_level_change.clone();
+    if let Some(callback) = callback {
+        let (width, center) = match window_level {
+            Some(window_level) => (
+                JsValue::from_f64(window_level.width),
+                JsValue::from_f64(window_level.center),
+            ),
+            None => (JsValue::NULL, JsValue::NULL),
+        };
+        if let Err(e) = callback.call2(&JsValue::NULL, &width, &center) {
+            gloo_console::error!("Window level callback failed:", e);
+        }
+    }
+}
+
+/// Apply an explicit VOI LUT in place of the window level,
+/// rebuild the LUT, and re-render.
+fn set_voi_lut(state: &RefCell<State>, new_voi_lut: VoiLut) {
+    {
+        let mut state = state.borrow_mut();
+        let State {
+            dicom_obj,
+            voi_lut,
+            lut,
+            lut_options,
+            ..
+        } = &mut *state;
+
+        let obj = if let Some(obj) = &dicom_obj {
+            obj
+        } else {
+            // ignore, no DICOM object loaded
+            return;
+        };
+
+        let voi = Voi::Lut(new_voi_lut);
+        match simple_pixel_data_lut_with_voi(obj, &voi, *lut_options) {
+            Ok(new_lut) => *lut = Some(new_lut),
+            Err(e) => {
+                gloo_console::error!("Failed to apply VOI LUT:", e);
+                return;
+            }
+        }
+        if let Voi::Lut(new_voi_lut) = voi {
+            *voi_lut = Some(new_voi_lut);
+        }
+        gloo_console::debug!("[VOI] applied explicit VOI LUT");
+    }
+
+    // update canvas
+    render_obj_to_canvas(state);
+
+    // the VOI LUT takes the place of the window level
+    notify_window_level_change(state, None);
+}
+
+/// The accessible name of the viewer
+const VIEWER_LABEL: &str = "DICOM image viewer";
+
+/// Make the viewer focusable with the keyboard
+/// and describe it to assistive technologies.
+fn set_viewer_accessibility(viewer: &HtmlElement) -> Result<(), JsValue> {
+    viewer.set_tab_index(0);
+    viewer.set_attribute("role", "application")?;
+    viewer.set_attribute("aria-label", VIEWER_LABEL)?;
+    viewer.set_attribute(
+        "aria-keyshortcuts",
+        "A S V E O H R I C N M Z U D B W L P X 0 F Backspace Control+ArrowUp \
+         Control+ArrowDown Control+ArrowLeft Control+ArrowRight",
+    )?;
+
+    // focus the viewer when clicked, so that its shortcuts apply
+    let target = viewer.clone();
+    let onmousedown_callback = Closure::wrap(Box::new(move |_: MouseEvent| {
+        if let Err(e) = target.focus() {
+            gloo_console::warn!("Could not focus the viewer:", e);
+        }
+    }) as Box<dyn FnMut(_)>);
+    viewer.add_event_listener_with_callback(
+        "mousedown",
+        onmousedown_callback.as_ref().unchecked_ref(),
+    )?;
+    onmousedown_callback.forget();
+
+    Ok(())
+}
+
+/// Make a control created by the viewer reachable with the keyboard,
+/// with the given ARIA role and label,
+/// activating it with Enter or Space as if it were clicked.
+fn set_control_accessibility(control: &HtmlElement, role: &str, label: &str) {
+    control.set_tab_index(0);
+    control.set_attribute("role", role).unwrap();
+    control.set_attribute("aria-label", label).unwrap();
+
+    let target = control.clone();
+    let onkeydown_callback = Closure::wrap(Box::new(move |ev: KeyboardEvent| {
+        if matches!(ev.key().as_str(), "Enter" | " ") {
+            ev.prevent_default();
+            target.click();
+        }
+    }) as Box<dyn FnMut(_)>);
+    control
+        .add_event_listener_with_callback("keydown", onkeydown_callback.as_ref().unchecked_ref())
+        .unwrap();
+    onkeydown_callback.forget();
+}
+
+/// Fill the preset picker with a preview of the image under each window preset.
+///
+/// The previews are cached in the state until a new file is loaded.
+fn update_preset_picker(state: &Rc<RefCell<State>>) {
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let picker = document
+        .get_element_by_id("presets")
+        .expect("presets should exist");
+    picker.set_inner_html("");
+
+    let mut st = state.borrow_mut();
+    let State {
+        dicom_obj,
+        presets,
+        preset_previews,
+        lut_options,
+        decoded_frames,
+        ..
+    } = &mut *st;
+
+    let obj = if let Some(obj) = &dicom_obj {
+        obj
+    } else {
+        return;
+    };
+
+    if preset_previews.is_none() {
+        // the previews show the first frame
+        let previews = native_frame(decoded_frames, obj, 0).and_then(|native| {
+            preset_previews_of(
+                native.obj(),
+                presets,
+                PRESET_PREVIEW_SIZE,
+                PRESET_PREVIEW_DOWNSAMPLING,
+                *lut_options,
+            )
+        });
+        match previews {
+            Ok(previews) => *preset_previews = Some(previews),
+            Err(e) => {
+                gloo_console::warn!("Could not render preset previews:", e);
+                return;
+            }
+        }
+    }
+
+    let previews = preset_previews.as_ref().unwrap();
+    for (preset, preview) in presets.iter().zip(previews) {
+        let canvas: HtmlCanvasElement = document
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        canvas.set_width(preview.width());
+        canvas.set_height(preview.height());
+        canvas.set_title(&preset.name);
+        canvas.set_class_name("preset");
+        set_control_accessibility(
+            &canvas,
+            "button",
+            &format!("Apply window preset {}", preset.name),
+        );
+        let context = canvas
+            .get_context("2d")
+            .expect("Could not retrieve 2D context from canvas")
+            .expect("2D context is missing")
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        if let Err(e) = context.put_image_data(preview, 0., 0.) {
+            gloo_console::warn!("Could not draw preset preview:", e);
+        }
+
+        let state = Rc::clone(state);
+        let voi = preset.voi.clone();
+        let onclick_callback = Closure::wrap(Box::new(move |_: MouseEvent| match &voi {
+            Voi::Window(window_level) => set_window_level(&state, *window_level),
+            Voi::Lut(voi_lut) => set_voi_lut(&state, voi_lut.clone()),
+        }) as Box<dyn FnMut(_)>);
+        canvas.set_onclick(Some(onclick_callback.as_ref().unchecked_ref()));
+        onclick_callback.forget();
+
+        picker.append_child(&canvas).unwrap();
+    }
+}
+
+/// List the overlay layers of the current object,
+/// each in its own color,
+/// toggling its visibility when clicked.
+fn update_overlay_selector(state: &Rc<RefCell<State>>) {
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let selector = document
+        .get_element_by_id("overlays")
+        .expect("overlays should exist");
+    // keep the keyboard focus on the same layer after listing them again
+    let focused = document
+        .active_element()
+        .and_then(|element| element.get_attribute("data-layer"));
+    selector.set_inner_html("");
+
+    let st = state.borrow();
+    // regions of interest are listed with their statistics on the current frame
+    let statistics = match &st.dicom_obj {
+        Some(obj) => native_frame(&st.decoded_frames, obj, st.frame)
+            .and_then(|native| {
+                overlay_roi_statistics_of(
+                    native.obj(),
+                    native.frame(),
+                    &st.overlays,
+                    st.pixel_spacing,
+                )
+            })
+            .unwrap_or_else(|e| {
+                gloo_console::warn!("Could not compute the statistics of the overlays:", e);
+                Vec::new()
+            }),
+        None => Vec::new(),
+    };
+    for (i, layer) in st.overlays.iter().enumerate() {
+        let item: HtmlElement = document.create_element("li").unwrap().dyn_into().unwrap();
+        let text = match statistics.get(i).copied().flatten() {
+            Some(stats) => {
+                let format = st.measurement_format;
+                let size = match stats.area {
+                    Some(area) => format.format_area(area),
+                    None => format!("{} px", stats.pixels),
+                };
+                format!(
+                    "{} ({}, mean {:.*})",
+                    layer.name(),
+                    size,
+                    format.decimals,
+                    stats.mean
+                )
+            }
+            None => layer.name(),
+        };
+        item.set_text_content(Some(&text));
+        item.set_class_name(if layer.visible {
+            "overlay"
         } else {
-            // ignore, no window level available
+            "overlay hidden"
+        });
+        let [r, g, b] = layer.color;
+        item.set_attribute("style", &format!("color: rgb({}, {}, {})", r, g, b))
+            .unwrap();
+        set_control_accessibility(&item, "switch", &layer.name());
+        item.set_attribute("aria-checked", &layer.visible.to_string())
+            .unwrap();
+        item.set_attribute("data-layer", &i.to_string()).unwrap();
+
+        let state = Rc::clone(state);
+        let onclick_callback = Closure::wrap(Box::new(move |_: MouseEvent| {
+            if let Some(layer) = state.borrow_mut().overlays.get_mut(i) {
+                layer.visible = !layer.visible;
+            }
+            render_obj_to_canvas(&state);
+            update_overlay_selector(&state);
+        }) as Box<dyn FnMut(_)>);
+        item.set_onclick(Some(onclick_callback.as_ref().unchecked_ref()));
+        onclick_callback.forget();
+
+        selector.append_child(&item).unwrap();
+        if focused.as_deref() == Some(i.to_string().as_str()) {
+            item.focus().unwrap();
+        }
+    }
+}
+
+/// List the loaded series in the study browser,
+/// each of which displays its first instance when clicked.
+fn update_series_browser(state: &Rc<RefCell<State>>) {
+    let window = web_sys::window().expect("no global `window` exists");
+    let document = window.document().expect("should have a document on window");
+    let browser = document
+        .get_element_by_id("series")
+        .expect("series should exist");
+    browser.set_inner_html("");
+
+    let st = state.borrow();
+    for (i, series) in st.series.iter().enumerate() {
+        let item: HtmlElement = document.create_element("li").unwrap().dyn_into().unwrap();
+        item.set_text_content(Some(&series.label()));
+        let mut title = format!(
+            "Study {}\nSeries {}",
+            series.study_instance_uid, series.series_instance_uid
+        );
+        if let Some(uid) = &series.frame_of_reference_uid {
+            title.push_str(&format!("\nFrame of reference {}", uid));
+        }
+        item.set_title(&title);
+        item.set_class_name("series");
+        set_control_accessibility(&item, "button", &format!("Show series {}", series.label()));
+
+        let state = Rc::clone(state);
+        let onclick_callback = Closure::wrap(Box::new(move |_: MouseEvent| {
+            let obj = {
+                let state = state.borrow();
+                state
+                    .series
+                    .get(i)
+                    .and_then(|series| series.first_shown(state.hide_localizers).cloned())
+            };
+            if let Some(obj) = obj {
+                show_instance(&state, obj);
+            }
+        }) as Box<dyn FnMut(_)>);
+        item.set_onclick(Some(onclick_callback.as_ref().unchecked_ref()));
+        onclick_callback.forget();
+
+        browser.append_child(&item).unwrap();
+    }
+}
+
+/// Display an instance of the study browser,
+/// reading its pixel data from its file first
+/// if only its metadata was kept,
+/// unless it was read recently.
+///
+/// Showing another instance or dropping other files
+/// before the file is read supersedes it.
+fn show_instance(state: &Rc<RefCell<State>>, obj: Rc<DefaultDicomObject>) {
+    let token = state.borrow_mut().loads.start();
+    let uid = sop_instance_uid_of(&obj);
+    let source = uid
+        .as_ref()
+        .and_then(|uid| state.borrow().pixel_data_sources.get(uid).cloned());
+    let (uid, (blob, fingerprint)) = match uid.zip(source) {
+        Some(source) => source,
+        None => {
+            load_dicom_obj(state, obj, None);
             return;
+        }
+    };
+
+    let cached = state.borrow_mut().instance_cache.get(&uid, fingerprint);
+    if let Some((obj, leniency)) = cached {
+        load_dicom_obj(state, obj, leniency);
+        return;
+    }
+
+    let state = Rc::clone(state);
+    let file_reader = gloo_file::callbacks::read_as_bytes(&blob, move |outcome| {
+        let loaded = match outcome {
+            Ok(data) => {
+                byte_data_to_dicom_obj_lenient(&data, ReadExtent::Full).map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
         };
+        match loaded {
+            Ok((obj, leniency)) => {
+                let obj = Rc::new(obj);
+                state.borrow_mut().instance_cache.insert(
+                    uid,
+                    fingerprint,
+                    (Rc::clone(&obj), leniency),
+                );
+                if state.borrow().loads.is_current(token) {
+                    load_dicom_obj(&state, obj, leniency);
+                } else {
+                    gloo_console::debug!("Not displaying an instance superseded by a newer load");
+                }
+            }
+            Err(e) => {
+                let error_msg = format!("Failed to read the pixel data: {}", e);
+                gloo_console::error!(&error_msg);
+                set_error_messsage(&error_msg);
+            }
+        }
+    });
+    std::mem::forget(file_reader);
+}
+
+/// The default minimum zoom level
+const MIN_ZOOM: f64 = 0.1;
+/// The default maximum zoom level
+const MAX_ZOOM: f64 = 20.;
+
+/// An interactive tool which can be assigned to a mouse button
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Tool {
+    /// change the window level:
+    /// horizontal movement changes the width,
+    /// vertical movement changes the center
+    WindowLevel,
+    /// move the image
+    Pan,
+    /// zoom in by dragging up, zoom out by dragging down
+    Zoom,
+    /// drag to place an arrow pointing at the starting point,
+    /// or click to place a text label
+    Annotate,
+    /// drag to measure a distance
+    Measure,
+    /// drag to select a rectangle for the view to fit to
+    ZoomRect,
+}
+
+/// What double-clicking the image does
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Default)]
+pub enum DoubleClickAction {
+    /// apply the next window preset,
+    /// going back to the window level as loaded after the last one
+    #[default]
+    NextPreset,
+    /// go back to the window level as loaded
+    Reset,
+    /// nothing
+    Nothing,
+}
+
+impl DoubleClickAction {
+    /// The action with the given name
+    /// (`"next-preset"`, `"reset"`, or `"none"`)
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "next-preset" => Some(DoubleClickAction::NextPreset),
+            "reset" => Some(DoubleClickAction::Reset),
+            "none" => Some(DoubleClickAction::Nothing),
+            _ => None,
+        }
+    }
+
+    /// Obtain the action
+    /// from the `data-double-click` attribute of the output canvas,
+    /// applying the next preset if absent or unknown.
+    fn from_canvas(canvas: &HtmlCanvasElement) -> Self {
+        match canvas.get_attribute("data-double-click") {
+            Some(name) => DoubleClickAction::from_name(&name).unwrap_or_else(|| {
+                gloo_console::warn!("Unknown double click action", name);
+                DoubleClickAction::default()
+            }),
+            None => DoubleClickAction::default(),
+        }
+    }
+}
+
+/// The mapping from image pixel coordinates
+/// to the pixel coordinates of the output canvas
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImageTransform {
+    /// the horizontal and vertical scale
+    pub scale: (f64, f64),
+    /// the canvas position of the image's top left corner
+    pub offset: (f64, f64),
+}
+
+impl ImageTransform {
+    pub const IDENTITY: ImageTransform = ImageTransform {
+        scale: (1., 1.),
+        offset: (0., 0.),
+    };
+
+    pub fn to_canvas(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            x * self.scale.0 + self.offset.0,
+            y * self.scale.1 + self.offset.1,
+        )
+    }
+
+    pub fn to_image(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            (x - self.offset.0) / self.scale.0,
+            (y - self.offset.1) / self.scale.1,
+        )
+    }
+}
+
+/// Where zooming with the mouse wheel is anchored
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum ZoomAnchor {
+    /// the point under the cursor stays in place (the default)
+    Cursor,
+    /// the image zooms about its center
+    Center,
+}
+
+impl ZoomAnchor {
+    /// The zoom anchor with the given name (`"cursor"` or `"center"`)
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cursor" => Some(ZoomAnchor::Cursor),
+            "center" => Some(ZoomAnchor::Center),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ZoomAnchor::Cursor => "cursor",
+            ZoomAnchor::Center => "center",
+        }
+    }
+}
+
+/// How the image zooms, and the limits of its zoom level
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ZoomOptions {
+    /// the factor by which each notch of the mouse wheel zooms in or out
+    pub wheel_factor: f64,
+    /// the smallest zoom level
+    pub min: f64,
+    /// the largest zoom level
+    pub max: f64,
+    /// where zooming with the mouse wheel is anchored
+    pub anchor: ZoomAnchor,
+}
+
+impl Default for ZoomOptions {
+    fn default() -> Self {
+        ZoomOptions {
+            wheel_factor: 1.1,
+            min: MIN_ZOOM,
+            max: MAX_ZOOM,
+            anchor: ZoomAnchor::Cursor,
+        }
+    }
+}
+
+impl ZoomOptions {
+    /// The zoom level within the limits
+    fn clamp(&self, zoom: f64) -> f64 {
+        zoom.clamp(self.min, self.max)
+    }
+}
+
+/// The nominal number of CSS pixels per millimeter (96 per inch)
+const CSS_PIXELS_PER_MM: f64 = 96. / 25.4;
+
+/// How the image is scaled to the canvas before zooming
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum ScaleMode {
+    /// fit the whole image to the canvas
+    Fit,
+    /// display the image in its physical size,
+    /// so that one millimeter on screen is one millimeter in the image,
+    /// if the pixel spacing is known
+    ///
+    /// This assumes the nominal CSS resolution of 96 pixels per inch,
+    /// so the actual size on screen is only an estimate.
+    ActualSize,
+}
+
+impl ScaleMode {
+    /// The scale mode with the given name
+    /// (`"fit"` or `"actual-size"`)
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fit" => Some(ScaleMode::Fit),
+            "actual-size" => Some(ScaleMode::ActualSize),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ScaleMode::Fit => "fit",
+            ScaleMode::ActualSize => "actual-size",
+        }
+    }
+}
+
+/// How the rendered image is placed on its target
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Placement {
+    /// the row and column spacing of the image (mm), if known
+    pub pixel_spacing: Option<(f64, f64)>,
+    /// how the image is scaled before zooming
+    pub scale_mode: ScaleMode,
+    /// the user's zoom and pan
+    pub viewport: Viewport,
+    /// what is shown around the image
+    pub background: Background,
+}
+
+impl Placement {
+    /// the ratio between the height and the width of each pixel
+    fn pixel_aspect_ratio(&self) -> f64 {
+        self.pixel_spacing
+            .map(|(row_spacing, column_spacing)| row_spacing / column_spacing)
+            .unwrap_or(1.)
+    }
+
+    /// The number of target pixels per image column before zooming,
+    /// given the image size, the target size,
+    /// and the number of target pixels per CSS pixel.
+    fn base_scale(&self, w: u32, h: u32, target_w: f64, target_h: f64, css_scale: f64) -> f64 {
+        match (self.scale_mode, self.pixel_spacing) {
+            (ScaleMode::ActualSize, Some((_, column_spacing))) => {
+                column_spacing * CSS_PIXELS_PER_MM * css_scale
+            }
+            _ => {
+                let aspect_h = h as f64 * self.pixel_aspect_ratio();
+                if w as f64 > aspect_h {
+                    target_w / w as f64
+                } else {
+                    target_h / aspect_h
+                }
+            }
+        }
+    }
+
+    /// The number of target pixels per image column and per image row
+    /// before zooming, as with [`Placement::base_scale`].
+    ///
+    /// Images fitted to the target are stretched to fill it
+    /// with the stretch background, ignoring their aspect ratio.
+    fn base_scales(
+        &self,
+        w: u32,
+        h: u32,
+        target_w: f64,
+        target_h: f64,
+        css_scale: f64,
+    ) -> (f64, f64) {
+        let actual_size = self.scale_mode == ScaleMode::ActualSize && self.pixel_spacing.is_some();
+        if self.background == Background::Stretch && !actual_size {
+            (target_w / w as f64, target_h / h as f64)
+        } else {
+            let scale = self.base_scale(w, h, target_w, target_h, css_scale);
+            (scale, scale * self.pixel_aspect_ratio())
+        }
+    }
+}
+
+/// What is shown around the image where it does not cover the canvas
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Background {
+    /// a solid color around the image (black by default)
+    Letterbox([u8; 3]),
+    /// a subtle gradient from dark gray at the top to black at the bottom
+    Gradient,
+    /// no background, the image is stretched to fill the canvas
+    /// regardless of its aspect ratio (unless shown at actual size)
+    Stretch,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Letterbox([0, 0, 0])
+    }
+}
 
-        let new_ww = (window_level.width + rel_ww).max(1.);
-        let new_wc = window_level.center + rel_wc;
+impl Background {
+    /// The kind of background with the given name
+    /// (`"letterbox"`, `"gradient"`, or `"stretch"`),
+    /// letterboxing in black
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "letterbox" => Some(Background::default()),
+            "gradient" => Some(Background::Gradient),
+            "stretch" => Some(Background::Stretch),
+            _ => None,
+        }
+    }
 
-        // update the window level
-        *window_level = WindowLevel {
-            width: new_ww,
-            center: new_wc,
-        };
-        gloo_console::debug!("[WL] updated to", new_ww, ",", new_wc);
+    fn name(self) -> &'static str {
+        match self {
+            Background::Letterbox(_) => "letterbox",
+            Background::Gradient => "gradient",
+            Background::Stretch => "stretch",
+        }
+    }
+}
 
-        if let Some(lut) = lut {
-            // update the LUT
-            match update_pixel_data_lut_with(lut, obj, *window_level) {
-                Ok(lut) => lut,
-                Err(e) => {
-                    gloo_console::error!("Failed to update LUT:", e);
-                    return;
-                }
+/// The maximum factor by which an image is resampled
+/// for smoothing along a single axis
+const MAX_RESAMPLE_FACTOR: u32 = 8;
+
+/// Interpolation applied when the image is scaled
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum Smoothing {
+    /// nearest neighbor in both axes
+    Off,
+    /// smooth in both axes (the default)
+    On,
+    /// smooth horizontally only
+    Horizontal,
+    /// smooth vertically only
+    Vertical,
+}
+
+impl Smoothing {
+    fn next(self) -> Self {
+        match self {
+            Smoothing::On => Smoothing::Off,
+            Smoothing::Off => Smoothing::Horizontal,
+            Smoothing::Horizontal => Smoothing::Vertical,
+            Smoothing::Vertical => Smoothing::On,
+        }
+    }
+
+    /// The smoothing with the given name
+    /// (`"on"`, `"off"`, `"horizontal"`, or `"vertical"`)
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "on" => Some(Smoothing::On),
+            "off" => Some(Smoothing::Off),
+            "horizontal" => Some(Smoothing::Horizontal),
+            "vertical" => Some(Smoothing::Vertical),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Smoothing::On => "on",
+            Smoothing::Off => "off",
+            Smoothing::Horizontal => "horizontal",
+            Smoothing::Vertical => "vertical",
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// The settings currently in use
+    fn of(state: &State) -> Self {
+        DisplaySettings {
+            display: state.display,
+            color_balance: state.color_balance,
+            diverging: state.diverging,
+            scale_mode: state.scale_mode,
+            smoothing: state.smoothing,
+            background: state.background,
+            zoom: state.zoom_options,
+            full_range: state.lut_options.linear,
+        }
+    }
+}
+
+/// The canvas on which images are put at their native size
+/// before being drawn scaled onto the output canvas
+#[derive(Debug)]
+enum InnerCanvas {
+    /// a hidden canvas element in the page
+    Element(HtmlCanvasElement, CanvasRenderingContext2d),
+    /// a canvas detached from the page,
+    /// which does not take part in layout
+    /// and could be drawn from a worker
+    Offscreen(OffscreenCanvas, OffscreenCanvasRenderingContext2d),
+}
+
+impl InnerCanvas {
+    /// Create an offscreen inner canvas in the given color space,
+    /// if the browser supports `OffscreenCanvas`.
+    fn offscreen(color_space: DisplayColorSpace) -> Option<Self> {
+        let supported =
+            js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("OffscreenCanvas"))
+                .unwrap_or(false);
+        if !supported {
+            return None;
+        }
+        let canvas = OffscreenCanvas::new(1, 1).ok()?;
+        let context = canvas
+            .get_context_with_context_options("2d", &color_space.context_options())
+            .ok()??
+            .dyn_into::<OffscreenCanvasRenderingContext2d>()
+            .ok()?;
+        Some(InnerCanvas::Offscreen(canvas, context))
+    }
+
+    /// Resize the canvas to the image data and put it there
+    fn put_image_data(&self, imagedata: &ImageData) -> Result<(), JsValue> {
+        match self {
+            InnerCanvas::Element(canvas, context) => {
+                canvas.set_width(imagedata.width());
+                canvas.set_height(imagedata.height());
+                context.put_image_data(imagedata, 0., 0.)
+            }
+            InnerCanvas::Offscreen(canvas, context) => {
+                canvas.set_width(imagedata.width());
+                canvas.set_height(imagedata.height());
+                context.put_image_data(imagedata, 0., 0.)
+            }
+        }
+    }
+
+    /// Draw the contents of the canvas onto another context
+    /// at the given position, under its current transform
+    fn draw_onto(&self, context: &CanvasRenderingContext2d, x: f64, y: f64) -> Result<(), JsValue> {
+        match self {
+            InnerCanvas::Element(canvas, _) => {
+                context.draw_image_with_html_canvas_element(canvas, x, y)
+            }
+            InnerCanvas::Offscreen(canvas, _) => {
+                context.draw_image_with_offscreen_canvas(canvas, x, y)
             }
+        }
+    }
+}
+
+/// The strategy for presenting rendered images on the page
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum RenderStrategy {
+    /// Put the image data on an inner canvas at its native size,
+    /// then draw it scaled onto the output canvas.
+    ///
+    /// This is the default.
+    DoubleCanvas,
+    /// Put the image data directly on the output canvas,
+    /// which is then scaled to fit via CSS.
+    ///
+    /// Saves one canvas worth of memory and a draw call per render.
+    SingleCanvas,
+}
+
+impl RenderStrategy {
+    /// Obtain the render strategy
+    /// from the `data-render-strategy` attribute of the output canvas
+    /// (`"single"` or `"double"`).
+    fn from_canvas(canvas: &HtmlCanvasElement) -> Self {
+        match canvas.get_attribute("data-render-strategy").as_deref() {
+            Some("single") => RenderStrategy::SingleCanvas,
+            _ => RenderStrategy::DoubleCanvas,
+        }
+    }
+}
+
+/// A still view of a DICOM object on a canvas of the page,
+/// for simple embeds which need no interaction.
+///
+/// The image is fitted to the canvas and shown with its own window level
+/// (or the default one of its modality or values), without overlays.
+/// Unlike the main viewer, any number of these can be created,
+/// each on its own canvas.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Viewer {
+    obj: Rc<DefaultDicomObject>,
+    target: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    canvas: InnerCanvas,
+    frame: u32,
+    window_level: Option<WindowLevel>,
+    /// the most recently shown RLE frames, decoded
+    decoded_frames: DecodedFrames,
+}
+
+#[wasm_bindgen]
+impl Viewer {
+    /// Parse DICOM bytes and show the first frame of the image
+    /// on the canvas with the given ID.
+    ///
+    /// Fails with a message if there is no canvas with that ID,
+    /// or if the bytes cannot be parsed or the image cannot be shown.
+    pub fn from_bytes(bytes: &[u8], canvas_id: &str) -> Result<Viewer, JsValue> {
+        let target = canvas_by_id(canvas_id)?;
+        let context = target
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("The target canvas has no 2D context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        let inner: HtmlCanvasElement = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| JsValue::from_str("No document to create the canvas in"))?
+            .create_element("canvas")?
+            .dyn_into()?;
+        let inner_context = inner
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("The inner canvas has no 2D context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        let (obj, leniency) = byte_data_to_dicom_obj_lenient(bytes, ReadExtent::Full)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse DICOM object: {}", e)))?;
+        let mut obj = Rc::new(obj);
+        if let Some(leniency) = leniency {
+            gloo_console::warn!("The file was read in lenient mode:", leniency);
+        }
+        let loaded = reconcile_bit_depth(&mut obj)
+            .and_then(|warning| {
+                if let Some(warning) = warning {
+                    gloo_console::warn!(warning);
+                }
+                reconcile_layout(&mut obj)
+            })
+            .and_then(|warning| {
+                if let Some(warning) = warning {
+                    gloo_console::warn!(warning);
+                }
+                check_displayable(&obj, 0)
+            });
+        if let Err(e) = loaded {
+            return Err(JsValue::from_str(&format!(
+                "Failed to load DICOM object: {}",
+                e
+            )));
+        }
+
+        let window_level = frame_window_level_of(&obj, 0)
+            .ok()
+            .flatten()
+            .or_else(|| window_level_of(&obj).ok().flatten())
+            .or_else(|| {
+                preset::default_window_level_of(&obj, 0, &preset::default_modality_presets())
+            })
+            .or_else(|| float_window_level_of(&obj, 0));
+        let viewer = Viewer {
+            obj,
+            target,
+            context,
+            canvas: InnerCanvas::Element(inner, inner_context),
+            frame: 0,
+            window_level,
+            decoded_frames: DecodedFrames::default(),
         };
+        viewer.render()?;
+        Ok(viewer)
     }
 
-    // update canvas
-    render_obj_to_canvas(state);
+    /// Draw the image again, such as after the canvas was resized.
+    pub fn render(&self) -> Result<(), JsValue> {
+        let native = native_frame(&self.decoded_frames, &self.obj, self.frame)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode RLE frame: {}", e)))?;
+        let (obj, frame) = (native.obj(), native.frame());
+        let rendered = if has_float_pixel_data(obj) {
+            float_to_imagedata(
+                obj,
+                frame,
+                self.window_level,
+                None,
+                DisplayOptions::default(),
+                &mut None,
+            )
+        } else {
+            self.window_level
+                .map(|window_level| {
+                    simple_pixel_data_lut_with(obj, window_level, LutOptions::default())
+                })
+                .transpose()
+                .and_then(|mut lut| {
+                    obj_to_imagedata(
+                        obj,
+                        frame,
+                        &mut Vec::new(),
+                        &mut lut,
+                        &mut None,
+                        &[],
+                        ConversionOptions::default(),
+                    )
+                })
+        };
+        let imagedata = rendered
+            .map_err(|e| JsValue::from_str(&format!("Failed to render DICOM object: {}", e)))?;
+        let pixel_spacing = frame_pixel_spacing(&self.obj, self.frame)
+            .map(|(row_spacing, column_spacing, _)| (row_spacing, column_spacing));
+        draw_fitted(
+            &imagedata,
+            &self.canvas,
+            &self.target,
+            &self.context,
+            pixel_spacing,
+        )
+    }
+
+    /// The number of frames of the image
+    pub fn number_of_frames(&self) -> u32 {
+        number_of_frames_of(&self.obj).unwrap_or(1)
+    }
+
+    /// Show another frame of the image (from 0)
+    pub fn set_frame(&mut self, frame: u32) -> Result<(), JsValue> {
+        if frame >= self.number_of_frames() {
+            return Err(JsValue::from_str(&format!(
+                "Frame {} is out of range, the image has {} frames",
+                frame,
+                self.number_of_frames()
+            )));
+        }
+        self.frame = frame;
+        self.render()
+    }
+
+    /// Show the image with another window level
+    pub fn set_window_level(&mut self, width: f64, center: f64) -> Result<(), JsValue> {
+        if !(width.is_finite() && width >= 1. && center.is_finite()) {
+            return Err(JsValue::from_str(
+                "Width should be a number of at least 1 and center a number",
+            ));
+        }
+        self.window_level = Some(WindowLevel { width, center });
+        self.render()
+    }
 }
 
 /// The application's global state
 #[derive(Debug)]
 pub struct State {
-    dicom_obj: Option<DefaultDicomObject>,
+    dicom_obj: Option<Rc<DefaultDicomObject>>,
     lut: Option<Vec<u8>>,
     window_level: Option<WindowLevel>,
-    canvas: HtmlCanvasElement,
-    canvas_context: CanvasRenderingContext2d,
+    /// the explicit VOI LUT applied instead of the window level, if any
+    voi_lut: Option<VoiLut>,
+    /// warnings about the current object
+    warnings: Vec<String>,
+    /// all loaded instances, grouped by study and series
+    series: Vec<study::Series>,
+    /// whether only the metadata of dropped files which are not displayed
+    /// is kept in the study browser
+    /// (`data-lazy-loading` attribute of the output canvas)
+    lazy_loading: bool,
+    /// the files of the instances kept without their pixel data,
+    /// with the fingerprint of their bytes, by SOPInstanceUID
+    pixel_data_sources: HashMap<String, (Blob, u64)>,
+    /// the instances most recently read again from their files,
+    /// with the leniency they were read with
+    instance_cache: study::InstanceCache<(Rc<DefaultDicomObject>, Option<&'static str>)>,
+    /// the loads started, of which only the latest is displayed
+    loads: study::LoadGenerations,
+    /// the dynamic range of the displayed frame, if monochrome
+    dynamic_range: Option<DynamicRange>,
+    /// the dynamic range of each frame of the current object shown so far
+    dynamic_ranges: HashMap<u32, Option<DynamicRange>>,
+    /// how the current object is meant to be viewed
+    viewing_intent: ViewingIntent,
+    /// whether full range display was turned on
+    /// because the current object is meant for processing
+    full_range_by_intent: bool,
+    /// the split view comparing the current window with a preset, if any
+    split: Option<SplitView>,
+    /// the LUT of the preset in the split view,
+    /// with the preset index and LUT options it was built with
+    split_lut: Option<((usize, LutOptions), Vec<u8>)>,
+    /// the VOI presets of the current object
+    presets: Vec<VoiPreset>,
+    /// cached previews of the image under each preset
+    preset_previews: Option<Vec<ImageData>>,
+    /// the most recently rendered RLE frames, decoded
+    decoded_frames: DecodedFrames,
+    /// the number of RLE frames decoded ahead of the current one on idle time
+    prefetch_window: u32,
+    /// the ongoing prefetch of RLE frames, if any
+    prefetch: Option<Prefetch>,
+    /// the window presets by modality,
+    /// for images which declare no window of their own
+    modality_presets: Vec<preset::ModalityPreset>,
+    /// where images are put at their native size before being scaled
+    canvas: InnerCanvas,
     out_canvas: HtmlCanvasElement,
     out_canvas_context: CanvasRenderingContext2d,
     /// memory buffer for the output image data
     /// (so that it does not have to be reallocated)
     y_samples: Vec<u8>,
+    /// reusable image data for the output image
+    /// (so that it is only reallocated when the dimensions change)
+    imagedata: Option<ImageData>,
+    /// the row and column spacing of the current image (mm), if known
+    pixel_spacing: Option<(f64, f64)>,
+    /// how the image is scaled to the canvas before zooming
+    scale_mode: ScaleMode,
+    /// the current zoom and pan
+    viewport: Viewport,
+    /// what is shown around the image
+    background: Background,
+    /// how the image zooms, and the limits of its zoom level
+    zoom_options: ZoomOptions,
+    /// whether panning keeps gliding after release
+    /// and zooming eases into the new level
+    inertia: bool,
+    /// the ongoing inertial movement of the viewport
+    motion: ViewportMotion,
+    /// interpolation applied when scaling the image
+    smoothing: Smoothing,
+    /// how the gray levels of monochrome images are shown
+    display: DisplayOptions,
+    /// the correction of the colors of color images
+    color_balance: ColorBalance,
+    /// the diverging color map of parametric maps, if not windowed
+    diverging: Option<Diverging>,
+    /// whether edges are enhanced with the unsharp mask
+    sharpen: bool,
+    /// the parameters of edge enhancement
+    unsharp_mask: UnsharpMask,
+    /// called with the new width and center whenever the window level changes
+    on_window_level_change: Option<js_sys::Function>,
+    /// the position and orientation of the current image in patient space, if known
+    image_plane: Option<ImagePlane>,
+    /// called with the patient position under the mouse cursor
+    on_cursor_move: Option<js_sys::Function>,
+    /// the patient position pointed at in a linked view, if any,
+    /// and the frame of reference it is in, if given
+    linked_cursor: Option<([f64; 3], Option<String>)>,
+    /// called with the position and orientation of the image plane
+    /// whenever it changes
+    on_plane_change: Option<js_sys::Function>,
+    /// the plane of the image in a linked view, if any,
+    /// and the frame of reference it is in, if given
+    reference_plane: Option<(ReferencePlane, Option<String>)>,
+    /// the FrameOfReferenceUID of the current object, if any
+    frame_of_reference_uid: Option<String>,
+    /// whether the window level is kept the same through a series,
+    /// covering the values of all of its instances
+    series_windowing: bool,
+    /// whether the window level is kept while going through frames,
+    /// instead of applying the window level of each frame
+    window_level_locked: bool,
+    /// whether the window level was adjusted by hand since it was loaded
+    /// or last reset, in which case it is kept while going through frames
+    window_level_adjusted: bool,
+    /// the index of the displayed frame
+    frame: u32,
+    /// the number of frames of the current object
+    number_of_frames: u32,
+    /// whether scrolling down goes to the previous frame instead of the next one
+    invert_scroll: bool,
+    /// whether localizer images and frames are skipped
+    /// when going through a series or its frames
+    hide_localizers: bool,
+    /// the annotations placed on the current object
+    annotations: Vec<Annotation>,
+    /// how measurements are displayed
+    measurement_format: MeasurementFormat,
+    /// how the LUT of monochrome images is built
+    lut_options: LutOptions,
+    /// whether color samples of the current object are read
+    /// with the opposite planar configuration
+    flip_planar: bool,
+    /// whether debugging commands are enabled
+    /// (`data-debug` attribute of the output canvas)
+    debug: bool,
+    /// the photometric interpretation forced onto the current object
+    /// for debugging, if any
+    force_photometric: Option<&'static str>,
+    /// the image measurements of all loaded Structured Reports
+    sr_measurements: Vec<SrMeasurement>,
+    /// how the image was last placed on the output canvas
+    image_transform: Option<ImageTransform>,
+    /// the interval handle and callback of the ongoing cine playback, if any
+    cine: Option<(i32, Closure<dyn FnMut()>)>,
+    /// the overlay planes of the current object and how they are shown
+    overlays: Vec<OverlayLayer>,
+    /// the tools assigned to the left, middle, and right mouse buttons
+    mouse_tools: [Option<Tool>; 3],
+    /// how rendered images are presented
+    render_strategy: RenderStrategy,
+    /// the windowed tiles of a large image rendered in tiles
+    tiles: TileCache<ImageData>,
+    /// the image and frame which the displayed image is compared to, if any,
+    /// showing their difference instead of the image itself
+    baseline: Option<(Rc<DefaultDicomObject>, u32)>,
+    /// the size of the area in which the image is displayed
+    display_size: (u32, u32),
+    /// the size of a window level step of the current object,
+    /// 1 except for parametric maps, which are stepped across their values
+    window_level_unit: f64,
+    /// window level steps applied per arrow key press
+    key_window_level_steps: KeyWindowLevelSteps,
+    /// window center and width steps applied per wheel notch while holding Alt
+    wheel_window_level_steps: (f64, f64),
+    /// what double-clicking the image does
+    double_click_action: DoubleClickAction,
+}
+
+thread_local! {
+    /// The application state,
+    /// for access from exported functions
+    static STATE: RefCell<Option<Rc<RefCell<State>>>> = RefCell::new(None);
+}
+
+/// Run the given function with the application state,
+/// or fail if the application was not started.
+fn with_state<T>(f: impl FnOnce(&Rc<RefCell<State>>) -> T) -> Result<T, JsValue> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .as_ref()
+            .map(f)
+            .ok_or_else(|| JsValue::from_str("Viewer is not initialized"))
+    })
+}
+
+/// Export the metadata of the loaded DICOM object
+/// in the DICOM JSON model,
+/// leaving out bulk pixel data.
+#[wasm_bindgen]
+pub fn to_dicom_json() -> Result<JsValue, JsValue> {
+    with_state(|state| {
+        let state = state.borrow();
+        let obj = state
+            .dicom_obj
+            .as_deref()
+            .ok_or_else(|| JsValue::from_str("No DICOM object loaded"))?;
+        json::to_dicom_json(obj).map_err(JsValue::from)
+    })?
+}
+
+/// Register a function to be called with the new window width and center
+/// whenever the window level is changed:
+/// by dragging, by picking a preset, by resetting it,
+/// or by loading an image.
+/// Both are `null` when no window level applies,
+/// as with a VOI LUT preset or an image without a window.
+/// Pass `undefined` or `null` to remove it.
+#[wasm_bindgen]
+pub fn set_on_window_level_change(callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+    with_state(|state| {
+        state.borrow_mut().on_window_level_change = callback;
+    })
+}
+
+/// Register a function to be called with the patient position
+/// (x, y, and z in millimeters)
+/// under the mouse cursor as it moves over the image,
+/// and the FrameOfReferenceUID of the image (or `undefined`),
+/// for linking the cursor to other views.
+/// Only called for images which declare their plane in patient space.
+/// Pass `undefined` or `null` to remove it.
+#[wasm_bindgen]
+pub fn set_on_cursor_move(callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+    with_state(|state| {
+        state.borrow_mut().on_cursor_move = callback;
+    })
+}
+
+/// Show a localizer dot at the given patient position (in millimeters),
+/// typically the cursor position in a linked view.
+/// The dot is only shown if the position lies on the plane of the image,
+/// and if a FrameOfReferenceUID is given,
+/// only on images of the same frame of reference.
+#[wasm_bindgen]
+pub fn set_linked_cursor(
+    x: f64,
+    y: f64,
+    z: f64,
+    frame_of_reference_uid: Option<String>,
+) -> Result<(), JsValue> {
+    with_state(|state| {
+        state.borrow_mut().linked_cursor = Some(([x, y, z], frame_of_reference_uid));
+        render_obj_to_canvas(state);
+    })
+}
+
+/// The FrameOfReferenceUID of the displayed image, if any,
+/// for grouping views which can be spatially linked.
+#[wasm_bindgen]
+pub fn frame_of_reference_uid() -> Result<Option<String>, JsValue> {
+    with_state(|state| state.borrow().frame_of_reference_uid.clone())
+}
+
+/// Hide the localizer dot of a linked view.
+#[wasm_bindgen]
+pub fn clear_linked_cursor() -> Result<(), JsValue> {
+    with_state(|state| {
+        if state.borrow_mut().linked_cursor.take().is_some() {
+            render_obj_to_canvas(state);
+        }
+    })
+}
+
+/// Register a function to be called with the ImagePositionPatient
+/// (3 numbers, in millimeters) and ImageOrientationPatient (6 numbers)
+/// of the displayed image plane
+/// and the FrameOfReferenceUID of the image (or `undefined`)
+/// whenever another image or frame is shown,
+/// for showing its reference line in other views.
+/// Only called for images which declare their plane in patient space.
+/// Pass `undefined` or `null` to remove it.
+#[wasm_bindgen]
+pub fn set_on_plane_change(callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+    with_state(|state| {
+        state.borrow_mut().on_plane_change = callback;
+        notify_plane_change(state);
+    })
+}
+
+/// Show the line where the plane of an image crosses the displayed image,
+/// typically the current slice of a linked view,
+/// from its ImagePositionPatient (3 numbers, in millimeters)
+/// and ImageOrientationPatient (6 numbers).
+/// If a FrameOfReferenceUID is given,
+/// the line is only shown on images of the same frame of reference.
+#[wasm_bindgen]
+pub fn set_reference_plane(
+    position: &[f64],
+    orientation: &[f64],
+    frame_of_reference_uid: Option<String>,
+) -> Result<(), JsValue> {
+    let plane = match (
+        <[f64; 3]>::try_from(position),
+        <[f64; 6]>::try_from(orientation),
+    ) {
+        (Ok(position), Ok(orientation)) => {
+            ReferencePlane::from_position_orientation(position, orientation)
+        }
+        _ => None,
+    }
+    .ok_or_else(|| {
+        JsValue::from_str(
+            "The plane should have a position of 3 numbers \
+             and an orientation of 6 numbers with two distinct directions",
+        )
+    })?;
+    with_state(|state| {
+        state.borrow_mut().reference_plane = Some((plane, frame_of_reference_uid));
+        render_obj_to_canvas(state);
+    })
+}
+
+/// Hide the reference line of a linked view.
+#[wasm_bindgen]
+pub fn clear_reference_plane() -> Result<(), JsValue> {
+    with_state(|state| {
+        if state.borrow_mut().reference_plane.take().is_some() {
+            render_obj_to_canvas(state);
+        }
+    })
+}
+
+/// Set the steps by which the window center and width change
+/// per notch of the mouse wheel while holding Alt
+/// (10 for both by default).
+#[wasm_bindgen]
+pub fn set_wheel_window_level_steps(center_step: f64, width_step: f64) -> Result<(), JsValue> {
+    if !(center_step.is_finite() && center_step > 0. && width_step.is_finite() && width_step > 0.) {
+        return Err(JsValue::from_str(
+            "Window level steps should be positive numbers",
+        ));
+    }
+    with_state(|state| state.borrow_mut().wheel_window_level_steps = (center_step, width_step))
+}
+
+/// Set the steps by which the window center or width change
+/// per press of an arrow key while holding Ctrl,
+/// and while also holding Shift
+/// (1 and 10 by default).
+#[wasm_bindgen]
+pub fn set_key_window_level_steps(step: f64, large_step: f64) -> Result<(), JsValue> {
+    if !(step.is_finite() && step > 0. && large_step.is_finite() && large_step > 0.) {
+        return Err(JsValue::from_str(
+            "Window level steps should be positive numbers",
+        ));
+    }
+    with_state(|state| {
+        state.borrow_mut().key_window_level_steps = KeyWindowLevelSteps { step, large_step }
+    })
+}
+
+/// Choose whether localizer images are skipped
+/// when showing a series from the study browser,
+/// and localizer frames when going through frames.
+/// All images are shown by default.
+#[wasm_bindgen]
+pub fn set_hide_localizers(hide: bool) -> Result<(), JsValue> {
+    with_state(|state| state.borrow_mut().hide_localizers = hide)
+}
+
+/// Set how many frames of multi-frame RLE objects following the current one
+/// are decoded on idle time after loading and when cine starts
+/// (16 by default, 0 to decode frames only when shown).
+/// Frames decoded ahead are kept until other frames replace them.
+#[wasm_bindgen]
+pub fn set_prefetch_window(frames: u32) -> Result<(), JsValue> {
+    if frames > MAX_PREFETCH_FRAMES {
+        return Err(JsValue::from_str(&format!(
+            "The prefetch window should be at most {} frames",
+            MAX_PREFETCH_FRAMES
+        )));
+    }
+    with_state(|state| {
+        stop_prefetch(state);
+        let mut state = state.borrow_mut();
+        state.prefetch_window = frames;
+        state
+            .decoded_frames
+            .set_capacity(DEFAULT_DECODED_FRAMES.max(frames as usize + 1));
+    })
+}
+
+/// Choose whether scrolling down goes to the previous frame
+/// instead of the next one.
+/// The choice is saved for future visits.
+#[wasm_bindgen]
+pub fn set_invert_scroll(invert_scroll: bool) -> Result<(), JsValue> {
+    with_state(|state| set_invert_scroll_setting(state, invert_scroll))
+}
+
+/// Choose whether panning keeps gliding after the mouse is released
+/// and zooming eases into the new level.
+/// Disabled by default.
+/// The choice is saved for future visits.
+#[wasm_bindgen]
+pub fn set_inertia(inertia: bool) -> Result<(), JsValue> {
+    with_state(|state| set_inertia_setting(state, inertia))
+}
+
+/// Download the raw samples of the displayed frame,
+/// or of all frames,
+/// as a NumPy `.npy` file at their original bit depth.
+#[wasm_bindgen]
+pub fn export_pixel_data(all_frames: bool) -> Result<(), JsValue> {
+    with_state(|state| download_pixel_data(state, all_frames))?
+}
+
+/// Draw the current frame, with the current window level and overlays,
+/// onto the canvas with the given ID,
+/// fitted to the canvas and centered on a black background.
+/// The viewer's own canvas is not affected,
+/// and later changes are not drawn there unless this is called again.
+///
+/// Fails if there is no canvas with that ID or no image is loaded.
+#[wasm_bindgen]
+pub fn render_to_canvas(id: &str) -> Result<(), JsValue> {
+    let target = canvas_by_id(id)?;
+    with_state(|state| render_to_target(state, &target))?
+}
+
+/// The canvas with the given ID in the page
+fn canvas_by_id(id: &str) -> Result<HtmlCanvasElement, JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("No document to look for the canvas in"))?;
+    document
+        .get_element_by_id(id)
+        .ok_or_else(|| JsValue::from_str(&format!("There is no element with ID \"{}\"", id)))?
+        .dyn_into::<HtmlCanvasElement>()
+        .map_err(|_| JsValue::from_str(&format!("The element with ID \"{}\" is not a canvas", id)))
+}
+
+/// Draw a contact sheet of the series on display
+/// onto the canvas with the given ID, resizing it to fit:
+/// thumbnails of the frames of its instances in a grid
+/// of the given number of columns,
+/// each fitted in a square of `thumbnail_size` pixels.
+/// Up to `max_images` frames are sampled evenly if given.
+///
+/// Fails if there is no canvas with that ID or no image is loaded.
+#[wasm_bindgen]
+pub fn render_contact_sheet(
+    id: &str,
+    columns: u32,
+    thumbnail_size: u32,
+    max_images: Option<u32>,
+) -> Result<(), JsValue> {
+    let target = canvas_by_id(id)?;
+    let sheet =
+        with_state(|state| contact_sheet(&state.borrow(), columns, thumbnail_size, max_images))??;
+    put_on_canvas(&target, &sheet)
+}
+
+/// Offer a contact sheet of the series on display
+/// for download as a PNG image,
+/// as with [`render_contact_sheet`].
+#[wasm_bindgen]
+pub fn download_contact_sheet(
+    columns: u32,
+    thumbnail_size: u32,
+    max_images: Option<u32>,
+) -> Result<(), JsValue> {
+    let sheet =
+        with_state(|state| contact_sheet(&state.borrow(), columns, thumbnail_size, max_images))??;
+    let canvas: HtmlCanvasElement = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("No document to create the canvas in"))?
+        .create_element("canvas")?
+        .dyn_into()?;
+    put_on_canvas(&canvas, &sheet)?;
+    download_canvas(&canvas, "contact_sheet.png")
+}
+
+/// Copy the image as displayed (with annotations)
+/// to the clipboard as a PNG image,
+/// to paste it into another application.
+///
+/// Returns a promise which is rejected with a message
+/// if the permission to write to the clipboard was denied.
+/// Throws if the browser cannot copy images to the clipboard.
+#[wasm_bindgen]
+pub fn copy_to_clipboard() -> Result<js_sys::Promise, JsValue> {
+    let out_canvas = with_state(|state| state.borrow().out_canvas.clone())?;
+    copy_canvas_to_clipboard(&out_canvas)
+}
+
+/// The histogram of the values of the displayed frame
+/// of a monochrome image in modality units,
+/// with the given number of bins of equal width.
+///
+/// Returns an object with the smallest and largest value (`min`, `max`),
+/// the number of values in each bin (`counts`),
+/// and the stride along rows and columns
+/// with which large frames were sampled (`stride`).
+/// With a stride above 1, the counts only add up to the sampled pixels.
+#[wasm_bindgen]
+pub fn histogram(bins: u32) -> Result<JsValue, JsValue> {
+    with_state(|state| {
+        let state = state.borrow();
+        let obj = state
+            .dicom_obj
+            .as_deref()
+            .ok_or_else(|| JsValue::from_str("No DICOM object loaded"))?;
+        // only the sampled pixels are read and rescaled
+        let stride = histogram::sampling_stride(image_dimensions_of(obj)?);
+        let native = native_frame(&state.decoded_frames, obj, state.frame)?;
+        let modality = modality_of(native.obj())?;
+        let values: Vec<f64> = sampled_stored_values_of(native.obj(), native.frame(), stride)?
+            .into_iter()
+            .map(|x| modality.apply(x as f64))
+            .collect();
+        let histogram = histogram::histogram_of_sampled(&values, stride, bins as usize);
+
+        let out = js_sys::Object::new();
+        js_sys::Reflect::set(&out, &"min".into(), &histogram.min.into())?;
+        js_sys::Reflect::set(&out, &"max".into(), &histogram.max.into())?;
+        js_sys::Reflect::set(
+            &out,
+            &"counts".into(),
+            &js_sys::Uint32Array::from(&histogram.counts[..]),
+        )?;
+        js_sys::Reflect::set(&out, &"stride".into(), &histogram.stride.into())?;
+        Ok(out.into())
+    })?
+}
+
+/// Information about the displayed frame:
+/// its dynamic range, leaving out padding values.
+///
+/// Returns an object with the smallest and largest stored values
+/// (`storedMin`, `storedMax`), the same in modality units
+/// (`rescaledMin`, `rescaledMax`),
+/// the number of distinct stored values (`distinctValues`),
+/// and how the image is meant to be viewed
+/// (`presentationIntent` and `recommendedViewingMode`, or `null` if not declared),
+/// or `null` if the image is not monochrome.
+#[wasm_bindgen]
+pub fn image_info() -> Result<JsValue, JsValue> {
+    with_state(|state| {
+        let state = state.borrow();
+        let range = match state.dynamic_range {
+            Some(range) => range,
+            None => return Ok(JsValue::NULL),
+        };
+        let intent = &state.viewing_intent;
+        let out = js_sys::Object::new();
+        js_sys::Reflect::set(&out, &"storedMin".into(), &range.stored.0.into())?;
+        js_sys::Reflect::set(&out, &"storedMax".into(), &range.stored.1.into())?;
+        js_sys::Reflect::set(&out, &"rescaledMin".into(), &range.rescaled.0.into())?;
+        js_sys::Reflect::set(&out, &"rescaledMax".into(), &range.rescaled.1.into())?;
+        js_sys::Reflect::set(
+            &out,
+            &"distinctValues".into(),
+            &(range.distinct as u32).into(),
+        )?;
+        let string_or_null = |value: &Option<String>| match value {
+            Some(value) => JsValue::from_str(value),
+            None => JsValue::NULL,
+        };
+        js_sys::Reflect::set(
+            &out,
+            &"presentationIntent".into(),
+            &string_or_null(&intent.presentation_intent),
+        )?;
+        js_sys::Reflect::set(
+            &out,
+            &"recommendedViewingMode".into(),
+            &string_or_null(&intent.recommended_viewing_mode),
+        )?;
+        Ok(out.into())
+    })?
+}
+
+/// The transfer syntax of the current file, as read from its file meta group.
+///
+/// Returns an object with the `uid` of the transfer syntax
+/// and its `name` (or `"unknown"`),
+/// or `null` if no object is loaded.
+#[wasm_bindgen]
+pub fn transfer_syntax() -> Result<JsValue, JsValue> {
+    with_state(|state| {
+        let (uid, name) = match &state.borrow().dicom_obj {
+            Some(obj) => transfer_syntax_of(obj),
+            None => return Ok(JsValue::NULL),
+        };
+        let out = js_sys::Object::new();
+        js_sys::Reflect::set(&out, &"uid".into(), &uid.into())?;
+        js_sys::Reflect::set(&out, &"name".into(), &name.into())?;
+        Ok(out.into())
+    })?
+}
+
+/// Keep the same window level through each series,
+/// covering the values of all of its loaded instances
+/// (from SmallestPixelValueInSeries and LargestPixelValueInSeries if present),
+/// so that the presentation does not change between images.
+/// When disabled, or if the range of the series is unknown,
+/// each image is displayed with its own window level.
+#[wasm_bindgen]
+pub fn set_series_window(enabled: bool) -> Result<(), JsValue> {
+    with_state(|state| set_series_windowing(state, enabled))
+}
+
+/// Add a window preset for images of a modality
+/// which declare no window of their own,
+/// replacing the preset of the same modality and name if any.
+/// The default preset of a modality is used to first display its images;
+/// images of modalities without presets
+/// are displayed with a window covering their values.
+/// Applies to the images loaded from now on.
+#[wasm_bindgen]
+pub fn set_modality_preset(
+    modality: &str,
+    name: &str,
+    width: f64,
+    center: f64,
+    is_default: bool,
+) -> Result<(), JsValue> {
+    if !(width > 0.) || !center.is_finite() {
+        return Err(JsValue::from_str("Window width should be positive"));
+    }
+    with_state(|state| {
+        preset::set_modality_preset(
+            &mut state.borrow_mut().modality_presets,
+            preset::ModalityPreset {
+                modality: modality.trim().to_string(),
+                name: name.to_string(),
+                window_level: WindowLevel { width, center },
+                default: is_default,
+            },
+        )
+    })
+}
+
+/// Remove all window presets of a modality,
+/// so that its images are displayed with a window covering their values.
+#[wasm_bindgen]
+pub fn clear_modality_presets(modality: &str) -> Result<(), JsValue> {
+    with_state(|state| {
+        state
+            .borrow_mut()
+            .modality_presets
+            .retain(|preset| preset.modality != modality.trim())
+    })
+}
+
+/// The display settings currently in use
+#[wasm_bindgen]
+pub fn display_settings() -> Result<DisplaySettings, JsValue> {
+    with_state(|state| DisplaySettings::of(&state.borrow()))
+}
+
+/// Apply all of the given display settings at once and re-render.
+#[wasm_bindgen]
+pub fn apply_display_settings(settings: &DisplaySettings) -> Result<(), JsValue> {
+    with_state(|state| update_display_settings(state, *settings))
+}
+
+/// Change a single display setting and re-render,
+/// keeping the others.
+fn change_display_settings(
+    change: impl FnOnce(&mut DisplaySettings) -> Result<(), JsValue>,
+) -> Result<(), JsValue> {
+    with_state(|state| {
+        let mut settings = DisplaySettings::of(&state.borrow());
+        change(&mut settings)?;
+        update_display_settings(state, settings);
+        Ok(())
+    })?
+}
+
+/// Invert the gray levels of monochrome images (not inverted by default),
+/// as with the `V` key.
+#[wasm_bindgen]
+pub fn set_invert(invert: bool) -> Result<(), JsValue> {
+    change_display_settings(|settings| {
+        settings.set_invert(invert);
+        Ok(())
+    })
+}
+
+/// Go back to showing the current image as loaded,
+/// as with the `0` key: the window level as loaded, no zoom or pan,
+/// and the default display settings (keeping the zoom options),
+/// re-rendering once.
+#[wasm_bindgen]
+pub fn reset_all_settings() -> Result<(), JsValue> {
+    with_state(|state| reset_all(state))
+}
+
+/// Window monochrome images linearly over the full range of values of each frame,
+/// leaving out padding, instead of with the window level and VOI LUT function
+/// of the file (off by default).
+/// Turning it off goes back to the window level as loaded.
+#[wasm_bindgen]
+pub fn set_full_range(full_range: bool) -> Result<(), JsValue> {
+    change_display_settings(|settings| {
+        settings.set_full_range(full_range);
+        Ok(())
+    })
+}
+
+/// Show the gray levels of monochrome images through a colormap:
+/// `"gray"` (the default), `"hot"`, or `"bone"`.
+/// Overlays keep their own colors.
+#[wasm_bindgen]
+pub fn set_colormap(colormap: &str) -> Result<(), JsValue> {
+    change_display_settings(|settings| settings.set_colormap(colormap))
+}
+
+/// Show parametric maps (images with floating point pixel data)
+/// through a diverging color map
+/// from blue for `center - range`
+/// through white at `center` to red for `center + range`,
+/// instead of windowing them.
+#[wasm_bindgen]
+pub fn set_diverging_colormap(center: f64, range: f64) -> Result<(), JsValue> {
+    change_display_settings(|settings| settings.set_diverging(center, range))
+}
+
+/// Go back to windowing parametric maps (the default).
+#[wasm_bindgen]
+pub fn clear_diverging_colormap() -> Result<(), JsValue> {
+    change_display_settings(|settings| {
+        settings.clear_diverging();
+        Ok(())
+    })
+}
+
+/// Choose how the image is scaled to the canvas, as with the `A` key:
+/// `"fit"` (the default) or `"actual-size"`.
+/// This also resets the zoom and pan.
+#[wasm_bindgen]
+pub fn set_scale_mode(scale_mode: &str) -> Result<(), JsValue> {
+    change_display_settings(|settings| settings.set_scale_mode(scale_mode))
+}
+
+/// Choose the interpolation applied when scaling the image, as with the `S` key:
+/// `"on"` (the default), `"off"`, `"horizontal"`, or `"vertical"`.
+#[wasm_bindgen]
+pub fn set_smoothing(smoothing: &str) -> Result<(), JsValue> {
+    change_display_settings(|settings| settings.set_smoothing(smoothing))
+}
+
+/// Compare the current window level with a window preset
+/// (by its index in the preset picker) in a split view,
+/// with the preset on the right of a draggable split line,
+/// as with the `X` key.
+/// Pass `null` or an index out of range to close the split view.
+/// The split view does not open on color images.
+#[wasm_bindgen]
+pub fn compare_window_preset(preset: Option<u32>) -> Result<(), JsValue> {
+    with_state(|state| set_split_preset(state, preset.map(|preset| preset as usize)))
+}
+
+/// Move the line of the split view,
+/// as a fraction of the image width from the left (0 to 1).
+#[wasm_bindgen]
+pub fn set_split_position(position: f64) -> Result<(), JsValue> {
+    if !position.is_finite() {
+        return Err(JsValue::from_str("Position should be a number"));
+    }
+    with_state(|state| {
+        if let Some(split) = &mut state.borrow_mut().split {
+            split.position = position.clamp(0., 1.);
+        }
+        render_obj_to_canvas(state);
+    })
+}
+
+/// Keep the current window level while going through frames,
+/// including any manual adjustment,
+/// instead of applying the window level declared for each frame
+/// in the functional groups of enhanced multi-frame images.
+/// Unlocked by default.
+#[wasm_bindgen]
+pub fn set_window_level_lock(locked: bool) -> Result<(), JsValue> {
+    with_state(|state| state.borrow_mut().window_level_locked = locked)
+}
+
+/// Read the color samples of the current image
+/// with the opposite planar configuration
+/// (by plane instead of interleaved, or the other way around),
+/// for images which declare it wrongly
+/// or whose guessed configuration is wrong.
+/// Reset when another image is loaded.
+#[wasm_bindgen]
+pub fn set_flip_planar_configuration(flip: bool) -> Result<(), JsValue> {
+    with_state(|state| set_flip_planar(state, flip))
+}
+
+/// Read the pixel data of the current image
+/// with the given BitsAllocated (8 or 16) and BitsStored
+/// instead of the ones declared in its header,
+/// to recover files whose header does not match their pixel data.
+/// Fails if the pixel data is too short for that depth.
+/// Reset when another image is loaded.
+#[wasm_bindgen]
+pub fn set_bit_depth(bits_allocated: u16, bits_stored: u16) -> Result<(), JsValue> {
+    with_state(|state| force_bit_depth(state, bits_allocated, bits_stored))?
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Compare the images displayed from now on
+/// to the displayed frame of the current image,
+/// showing the signed difference between them
+/// (follow-up minus baseline, in modality units)
+/// through a diverging color map centered at zero.
+/// The images must have the same number of rows and columns.
+#[wasm_bindgen]
+pub fn set_difference_baseline() -> Result<(), JsValue> {
+    with_state(|state| set_baseline(state))
+}
+
+/// Stop comparing images, showing them as they are.
+#[wasm_bindgen]
+pub fn clear_difference_baseline() -> Result<(), JsValue> {
+    with_state(|state| clear_baseline(state))
+}
+
+/// Enable or disable edge enhancement with an unsharp mask,
+/// adding `amount` times the difference between the image
+/// and its Gaussian blur of the given `radius` (in image pixels).
+/// This is a display enhancement, not meant for diagnosis.
+#[wasm_bindgen]
+pub fn set_sharpening(enabled: bool, amount: f64, radius: f64) -> Result<(), JsValue> {
+    if !(amount >= 0. && amount.is_finite()) {
+        return Err(JsValue::from_str("Amount should be a non-negative number"));
+    }
+    if !(radius > 0. && radius <= MAX_UNSHARP_RADIUS) {
+        return Err(JsValue::from_str(&format!(
+            "Radius should be greater than 0 and at most {}",
+            MAX_UNSHARP_RADIUS
+        )));
+    }
+    with_state(|state| {
+        {
+            let mut state = state.borrow_mut();
+            state.sharpen = enabled;
+            state.unsharp_mask = UnsharpMask { amount, radius };
+        }
+        render_obj_to_canvas(state);
+    })
+}
+
+/// Choose how measurements are displayed:
+/// in `"mm"` or `"cm"`, with the given number of decimal places.
+/// The choice is saved for future visits.
+#[wasm_bindgen]
+pub fn set_measurement_format(unit: &str, decimals: usize) -> Result<(), JsValue> {
+    let unit = LengthUnit::from_symbol(unit)
+        .ok_or_else(|| JsValue::from_str("Unit should be \"mm\" or \"cm\""))?;
+    let format = MeasurementFormat {
+        unit,
+        decimals: decimals.min(MAX_MEASUREMENT_DECIMALS),
+    };
+    with_state(|state| set_measurement_format_setting(state, format))
+}
+
+/// Choose what double-clicking the image does:
+/// `"next-preset"` (the default) to cycle through the window presets,
+/// `"reset"` to go back to the window level as loaded,
+/// or `"none"`.
+#[wasm_bindgen]
+pub fn set_double_click_action(action: &str) -> Result<(), JsValue> {
+    let action = DoubleClickAction::from_name(action).ok_or_else(|| {
+        JsValue::from_str("Action should be \"next-preset\", \"reset\", or \"none\"")
+    })?;
+    with_state(|state| state.borrow_mut().double_click_action = action)
+}
+
+/// Choose how windowed values are rounded to 8-bit output values:
+/// `"nearest"` (the default), `"truncate"`, or `"floor"`.
+#[wasm_bindgen]
+pub fn set_lut_rounding(rounding: &str) -> Result<(), JsValue> {
+    let rounding = match rounding {
+        "nearest" => Rounding::Nearest,
+        "truncate" => Rounding::Truncate,
+        "floor" => Rounding::Floor,
+        _ => {
+            return Err(JsValue::from_str(
+                "Rounding should be \"nearest\", \"truncate\", or \"floor\"",
+            ))
+        }
+    };
+    with_state(|state| change_lut_options(state, |options| options.rounding = rounding))
+}
+
+/// Change how the LUT is built,
+/// then rebuild the LUT and the preset previews with the current window and re-render.
+fn change_lut_options(state: &RefCell<State>, f: impl FnOnce(&mut LutOptions)) {
+    {
+        let mut state = state.borrow_mut();
+        let State {
+            dicom_obj,
+            window_level,
+            voi_lut,
+            lut,
+            lut_options,
+            preset_previews,
+            ..
+        } = &mut *state;
+        f(lut_options);
+
+        let voi = match (voi_lut, window_level) {
+            (Some(voi_lut), _) => Some(Voi::Lut(voi_lut.clone())),
+            (None, Some(window_level)) => Some(Voi::Window(*window_level)),
+            (None, None) => None,
+        };
+        *lut = match (dicom_obj, voi) {
+            (Some(obj), Some(voi)) => simple_pixel_data_lut_with_voi(obj, &voi, *lut_options).ok(),
+            // created from the object on the next render
+            _ => None,
+        };
+        *preset_previews = None;
+    }
+    render_obj_to_canvas(state);
+    update_preset_picker(state);
+}
+
+/// Apply a presentation LUT supplied by the user
+/// in place of that of the images (if any),
+/// read from the bytes of a small file in the given format:
+/// display values from 0 to 255, as text separated by commas, semicolons,
+/// or whitespace (`"text"`, such as a CSV file), or as one byte each (`"binary"`).
+/// The entries are spread over the output of the window,
+/// so 256 entries give the display value of each gray level.
+#[wasm_bindgen]
+pub fn set_presentation_lut(bytes: &[u8], format: &str) -> Result<(), JsValue> {
+    let format = LutFileFormat::from_name(format)
+        .ok_or_else(|| JsValue::from_str("Format should be \"text\" or \"binary\""))?;
+    let presentation =
+        custom_presentation_lut_of(bytes, format).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    with_state(|state| {
+        change_lut_options(state, |options| options.presentation = Some(presentation))
+    })
+}
+
+/// Go back to the presentation LUT of the images, if any.
+#[wasm_bindgen]
+pub fn clear_presentation_lut() -> Result<(), JsValue> {
+    with_state(|state| change_lut_options(state, |options| options.presentation = None))
+}
+
+/// Choose how samples beyond the end of the LUT are shown,
+/// such as with malformed pixel data or a wrong bit depth:
+/// `"clamp"` to the last entry (the default),
+/// `"wrap"` around to the first entries,
+/// or `"background"` (black).
+#[wasm_bindgen]
+pub fn set_out_of_range_samples(mode: &str) -> Result<(), JsValue> {
+    let out_of_range = OutOfRange::from_name(mode).ok_or_else(|| {
+        JsValue::from_str("Mode should be \"clamp\", \"wrap\", or \"background\"")
+    })?;
+    with_state(|state| {
+        state.borrow_mut().lut_options.out_of_range = out_of_range;
+        render_obj_to_canvas(state);
+    })
 }
 
 // This is like the `main` function for our Rust webapp.
@@ -327,28 +5420,54 @@ pub fn main_js() -> Result<(), JsValue> {
 
     // fetch canvas
 
-    let canvas = document.get_element_by_id("view_inner").unwrap();
+    let out_canvas = document.get_element_by_id("view").unwrap();
 
-    let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+    let out_canvas: HtmlCanvasElement = out_canvas.dyn_into::<HtmlCanvasElement>().unwrap();
 
-    let context = canvas
-        .get_context("2d")
-        .expect("Could not retrieve 2D context from canvas")
-        .expect("2D context is missing")
-        .dyn_into::<CanvasRenderingContext2d>()
-        .unwrap();
+    // the color space of the canvases, sRGB unless requested
+    let color_space = match out_canvas.get_attribute("data-color-space") {
+        Some(name) => DisplayColorSpace::from_name(&name).unwrap_or_else(|| {
+            gloo_console::warn!("Unsupported canvas color space", name, ", using sRGB");
+            DisplayColorSpace::Srgb
+        }),
+        None => DisplayColorSpace::Srgb,
+    };
 
-    let out_canvas = document.get_element_by_id("view").unwrap();
+    // use an offscreen canvas if requested and supported,
+    // or else the hidden inner canvas
+    let offscreen = if out_canvas.has_attribute("data-offscreen-canvas") {
+        let offscreen = InnerCanvas::offscreen(color_space);
+        if offscreen.is_none() {
+            gloo_console::warn!("OffscreenCanvas is not supported, using the inner canvas");
+        }
+        offscreen
+    } else {
+        None
+    };
+    let canvas = offscreen.unwrap_or_else(|| {
+        let canvas = document.get_element_by_id("view_inner").unwrap();
 
-    let out_canvas: HtmlCanvasElement = out_canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+        let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+
+        let context = canvas
+            .get_context_with_context_options("2d", &color_space.context_options())
+            .expect("Could not retrieve 2D context from canvas")
+            .expect("2D context is missing")
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        InnerCanvas::Element(canvas, context)
+    });
 
     let out_context = out_canvas
-        .get_context("2d")
+        .get_context_with_context_options("2d", &color_space.context_options())
         .expect("Could not retrieve 2D context from canvas")
         .expect("2D context is missing")
         .dyn_into::<CanvasRenderingContext2d>()
         .unwrap();
 
+    let render_strategy = RenderStrategy::from_canvas(&out_canvas);
+    let display_size = (out_canvas.width(), out_canvas.height());
+
     // clear canvas
     reset(&out_context).unwrap();
 
@@ -357,11 +5476,78 @@ pub fn main_js() -> Result<(), JsValue> {
         dicom_obj: None,
         lut: None,
         window_level: None,
+        voi_lut: None,
+        warnings: Vec::new(),
+        series: Vec::new(),
+        lazy_loading: out_canvas.has_attribute("data-lazy-loading"),
+        pixel_data_sources: HashMap::new(),
+        instance_cache: study::InstanceCache::default(),
+        loads: study::LoadGenerations::default(),
+        dynamic_range: None,
+        dynamic_ranges: HashMap::new(),
+        viewing_intent: ViewingIntent::default(),
+        full_range_by_intent: false,
+        presets: Vec::new(),
+        split: None,
+        split_lut: None,
+        preset_previews: None,
+        decoded_frames: DecodedFrames::with_capacity(
+            DEFAULT_DECODED_FRAMES.max(DEFAULT_PREFETCH_FRAMES as usize + 1),
+        ),
+        prefetch_window: DEFAULT_PREFETCH_FRAMES,
+        prefetch: None,
+        modality_presets: preset::default_modality_presets(),
         canvas,
-        canvas_context: context,
         out_canvas: out_canvas.clone(),
         out_canvas_context: out_context,
         y_samples: Vec::new(),
+        imagedata: None,
+        pixel_spacing: None,
+        scale_mode: ScaleMode::Fit,
+        viewport: Viewport::default(),
+        background: Background::default(),
+        zoom_options: ZoomOptions::default(),
+        inertia: load_inertia(),
+        motion: ViewportMotion::default(),
+        smoothing: Smoothing::On,
+        display: DisplayOptions::default(),
+        color_balance: ColorBalance::default(),
+        diverging: None,
+        sharpen: false,
+        unsharp_mask: UnsharpMask::default(),
+        overlays: Vec::new(),
+        series_windowing: false,
+        window_level_locked: false,
+        window_level_adjusted: false,
+        frame: 0,
+        number_of_frames: 1,
+        invert_scroll: load_invert_scroll(),
+        hide_localizers: false,
+        cine: None,
+        annotations: Vec::new(),
+        measurement_format: load_measurement_format(),
+        sr_measurements: Vec::new(),
+        lut_options: LutOptions::default(),
+        flip_planar: false,
+        debug: out_canvas.has_attribute("data-debug"),
+        force_photometric: None,
+        image_transform: None,
+        on_window_level_change: None,
+        image_plane: None,
+        on_cursor_move: None,
+        linked_cursor: None,
+        on_plane_change: None,
+        reference_plane: None,
+        frame_of_reference_uid: None,
+        mouse_tools: [Some(Tool::WindowLevel), Some(Tool::Pan), Some(Tool::Zoom)],
+        render_strategy,
+        tiles: TileCache::default(),
+        baseline: None,
+        display_size,
+        window_level_unit: 1.,
+        key_window_level_steps: KeyWindowLevelSteps::default(),
+        wheel_window_level_steps: (10., 10.),
+        double_click_action: DoubleClickAction::from_canvas(&out_canvas),
     }));
 
     // get drop_zone
@@ -371,9 +5557,21 @@ pub fn main_js() -> Result<(), JsValue> {
         .dyn_into()
         .expect("drop_zone should be an HTML element");
 
+    STATE.with(|st| *st.borrow_mut() = Some(Rc::clone(&state)));
+
     set_drop_zone(Rc::clone(&state), &drop_zone);
 
-    set_window_level_tool(Rc::clone(&state), &out_canvas);
+    set_mouse_tools(Rc::clone(&state), &out_canvas);
+
+    set_frame_scroll(Rc::clone(&state), &out_canvas);
+
+    set_double_click(Rc::clone(&state), &out_canvas);
+
+    set_viewer_accessibility(&out_canvas)?;
+
+    set_window_level_keys(Rc::clone(&state), &out_canvas);
+
+    set_shortcut_keys(Rc::clone(&state), &out_canvas);
 
     Ok(())
 }
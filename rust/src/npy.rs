@@ -0,0 +1,318 @@
+//! Export of the raw pixel samples in the NumPy `.npy` format,
+//! for analysis outside of the viewer.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use dicom::core::{DicomValue, Tag};
+use dicom::{dictionary_std::tags, object::DefaultDicomObject};
+use snafu::prelude::*;
+
+use crate::imaging::{frame_len_of, image_dimensions_of, number_of_frames_of, Result};
+use crate::rle::{is_rle_lossless, native_frame, DecodedFrames, NativeFrame};
+
+/// The magic string at the start of every `.npy` file
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// The alignment of the array data in bytes
+const NPY_ALIGNMENT: usize = 64;
+
+/// The header of a version 1.0 `.npy` file
+/// holding a C-ordered array of the given data type and shape.
+///
+/// The header is padded so that the array data which follows is aligned.
+pub fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        shape => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // magic, version, header length, and a terminating newline
+    let unpadded = NPY_MAGIC.len() + 2 + 2 + dict.len() + 1;
+    let padding = (NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT;
+    dict.extend(std::iter::repeat(' ').take(padding));
+    dict.push('\n');
+
+    let mut header = Vec::with_capacity(unpadded + padding);
+    header.extend_from_slice(NPY_MAGIC);
+    header.extend_from_slice(&[1, 0]);
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+/// Write the native pixel samples of the object as a `.npy` file,
+/// at their original bit depth.
+///
+/// With a frame, only that frame is exported,
+/// with the shape `(rows, columns)`.
+/// Otherwise, all frames are exported,
+/// with the shape `(frames, rows, columns)`.
+/// Color images have an extra axis for the samples of each pixel:
+/// last if interleaved, or before the rows if stored by plane.
+/// Native YBR_FULL_422 pixel data keeps its subsampled chrominance,
+/// with the samples of each row along the last axis
+/// (two luminance samples, then two chrominance samples, per pair of pixels).
+/// RLE frames are decoded one at a time through the given cache.
+pub fn pixel_data_to_npy(
+    obj: &DefaultDicomObject,
+    frame: Option<u32>,
+    decoded: &DecodedFrames,
+) -> Result<Vec<u8>> {
+    let int = |tag: Tag, name: &str| -> Result<Option<usize>> {
+        obj.element_opt(tag)
+            .with_whatever_context(|_| format!("Could not fetch {}", name))?
+            .map(|elem| {
+                elem.to_int::<usize>()
+                    .with_whatever_context(|_| format!("{} is not an integer", name))
+            })
+            .transpose()
+    };
+    let (columns, rows) = image_dimensions_of(obj)?;
+    let (columns, rows) = (columns as usize, rows as usize);
+    let bits_allocated =
+        int(tags::BITS_ALLOCATED, "BitsAllocated")?.whatever_context("Missing BitsAllocated")?;
+    let samples_per_pixel = int(tags::SAMPLES_PER_PIXEL, "SamplesPerPixel")?.unwrap_or(1);
+    // RLE frames are decoded with the samples of each pixel next to each other
+    let planar = int(tags::PLANAR_CONFIGURATION, "PlanarConfiguration")?.unwrap_or(0) == 1
+        && !is_rle_lossless(obj);
+    let signed = int(tags::PIXEL_REPRESENTATION, "PixelRepresentation")?.unwrap_or(0) == 1;
+    let bits_stored = int(tags::BITS_STORED, "BitsStored")?.unwrap_or(bits_allocated);
+
+    let frame_len = frame_len_of(obj)?;
+    // chroma subsampling leaves fewer samples than pixels times samples per pixel
+    let subsampled = frame_len != rows * columns * samples_per_pixel;
+    let number_of_frames = number_of_frames_of(obj)?;
+    let (first_frame, frames) = match frame {
+        Some(frame) => (frame, 1),
+        None => (0, number_of_frames),
+    };
+    let end_frame = first_frame
+        .checked_add(frames)
+        .with_whatever_context(|| format!("Frame #{} is out of bounds", first_frame))?;
+    ensure_whatever!(
+        end_frame <= number_of_frames,
+        "Frame #{} is out of bounds",
+        first_frame
+    );
+    // signed samples are sign extended from their stored bits
+    let descr = match (bits_allocated, signed) {
+        (8, false) => "|u1",
+        (8, true) => "|i1",
+        (16, false) => "<u2",
+        (16, true) => "<i2",
+        (bits_allocated, _) => whatever!(
+            "Exporting pixel data with {} bits allocated is not supported, sorry. :(",
+            bits_allocated
+        ),
+    };
+
+    let mut data = match native_frame(decoded, obj, first_frame)? {
+        // native pixel data is read in one go
+        NativeFrame::Original(obj, _) => {
+            samples_of(obj, first_frame..end_frame, frame_len, bits_allocated)?
+        }
+        // RLE frames are decoded one at a time
+        native => {
+            let mut data = samples_of(native.obj(), 0..1, frame_len, bits_allocated)?;
+            for frame in first_frame + 1..end_frame {
+                let native = native_frame(decoded, obj, frame)?;
+                data.extend(samples_of(native.obj(), 0..1, frame_len, bits_allocated)?);
+            }
+            data
+        }
+    };
+
+    if signed {
+        sign_extend(&mut data, bits_allocated, bits_stored);
+    }
+
+    let mut shape = Vec::with_capacity(4);
+    if frame.is_none() {
+        shape.push(frames as usize);
+    }
+    match (samples_per_pixel, planar) {
+        _ if subsampled => shape.extend([rows, frame_len / rows]),
+        (1, _) => shape.extend([rows, columns]),
+        (samples, true) => shape.extend([samples, rows, columns]),
+        (samples, false) => shape.extend([rows, columns, samples]),
+    }
+
+    let mut npy = npy_header(descr, &shape);
+    npy.extend(data);
+    Ok(npy)
+}
+
+/// The native pixel samples of a range of frames
+/// as little endian bytes
+fn samples_of(
+    obj: &DefaultDicomObject,
+    frames: Range<u32>,
+    frame_len: usize,
+    bits_allocated: usize,
+) -> Result<Vec<u8>> {
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    if matches!(pixel_data.value(), DicomValue::PixelSequence { .. }) {
+        whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
+    }
+    let range = frames.start as usize * frame_len..frames.end as usize * frame_len;
+
+    match bits_allocated {
+        8 => {
+            let samples = pixel_data
+                .to_bytes()
+                .whatever_context("Could not read PixelData as a sequence of 8-bit integers")?;
+            let samples = samples
+                .get(range)
+                .whatever_context("PixelData is shorter than its frames")?;
+            Ok(samples.to_vec())
+        }
+        _ => {
+            let samples: Cow<[u16]> = pixel_data
+                .uint16_slice()
+                .map(Cow::from)
+                .or_else(|_| pixel_data.to_multi_int::<u16>().map(Cow::Owned))
+                .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?;
+            let samples = samples
+                .get(range)
+                .whatever_context("PixelData is shorter than its frames")?;
+            Ok(samples.iter().flat_map(|x| x.to_le_bytes()).collect())
+        }
+    }
+}
+
+/// Extend the sign bit of signed samples (as little endian bytes)
+/// with fewer bits stored than allocated into the unused high bits,
+/// so that they read as the same values at the allocated bit depth.
+fn sign_extend(data: &mut [u8], bits_allocated: usize, bits_stored: usize) {
+    if bits_stored == 0 || bits_stored >= bits_allocated {
+        return;
+    }
+    match bits_allocated {
+        8 => {
+            let shift = 8 - bits_stored as u32;
+            for sample in data.iter_mut() {
+                *sample = (((*sample as i8) << shift) >> shift) as u8;
+            }
+        }
+        16 => {
+            let shift = 16 - bits_stored as u32;
+            for sample in data.chunks_exact_mut(2) {
+                let value = i16::from_le_bytes([sample[0], sample[1]]);
+                sample.copy_from_slice(&((value << shift) >> shift).to_le_bytes());
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn write_npy_header() {
+        let header = npy_header("<u2", &[2, 512, 512]);
+        assert_eq!(header.len() % NPY_ALIGNMENT, 0);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        let len = u16::from_le_bytes([header[8], header[9]]) as usize;
+        assert_eq!(len, header.len() - 10);
+
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(
+            dict.starts_with("{'descr': '<u2', 'fortran_order': False, 'shape': (2, 512, 512), }")
+        );
+        assert!(dict.ends_with(" \n"));
+
+        // one-dimensional shapes are tuples too
+        let header = npy_header("|u1", &[5]);
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dict.contains("'shape': (5,)"));
+    }
+
+    #[wasm_bindgen_test]
+    fn export_subsampled_frames() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        // 2 frames of 3x2 pixels in YBR_FULL_422:
+        // 3 luminance and 2 pairs of chrominance samples per row
+        let meta = dicom::object::meta::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid("1.2.3.4")
+            .build()
+            .unwrap();
+        let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+        let samples: Vec<u8> = (0..28).collect();
+        for elem in [
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(3_u16)),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("2")),
+            DataElement::new(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(3_u16)),
+            DataElement::new(
+                tags::PHOTOMETRIC_INTERPRETATION,
+                VR::CS,
+                PrimitiveValue::from("YBR_FULL_422"),
+            ),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(8_u16)),
+            DataElement::new(tags::BITS_STORED, VR::US, PrimitiveValue::from(8_u16)),
+            DataElement::new(tags::PIXEL_DATA, VR::OB, PrimitiveValue::from(samples)),
+        ] {
+            obj.put(elem);
+        }
+        let decoded = DecodedFrames::default();
+        let dict = |npy: &[u8]| {
+            let len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+            std::str::from_utf8(&npy[10..10 + len]).unwrap().to_string()
+        };
+
+        // the second frame starts after the samples of the first one
+        let npy = pixel_data_to_npy(&obj, Some(1), &decoded).unwrap();
+        assert!(dict(&npy).contains("'shape': (2, 7)"));
+        assert_eq!(&npy[npy.len() - 14..], &(14..28).collect::<Vec<u8>>()[..]);
+
+        let npy = pixel_data_to_npy(&obj, None, &decoded).unwrap();
+        assert!(dict(&npy).contains("'shape': (2, 2, 7)"));
+
+        // no frame that far
+        assert!(pixel_data_to_npy(&obj, Some(2), &decoded).is_err());
+        assert!(pixel_data_to_npy(&obj, Some(u32::MAX), &decoded).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn sign_extend_stored_bits() {
+        // 12 bits stored: -1, -2048, 2047, and 5 with garbage in the high bits
+        let mut data: Vec<u8> = [0x0fffu16, 0x0800, 0x07ff, 0xf005]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        sign_extend(&mut data, 16, 12);
+        let values: Vec<i16> = data
+            .chunks_exact(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]))
+            .collect();
+        assert_eq!(values, vec![-1, -2048, 2047, 5]);
+
+        let mut data = vec![0x7f, 0x3f];
+        sign_extend(&mut data, 8, 7);
+        assert_eq!(data, vec![0xff, 0x3f]);
+
+        // samples using all of their bits are left as they are
+        let mut data = vec![0x0f, 0xf0];
+        sign_extend(&mut data, 16, 16);
+        assert_eq!(data, vec![0x0f, 0xf0]);
+    }
+}
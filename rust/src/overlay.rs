@@ -0,0 +1,430 @@
+//! Overlay planes (repeating groups 60xx) drawn over monochrome images.
+
+use std::borrow::Cow;
+
+use dicom::core::Tag;
+use dicom::{dictionary_std::tags, object::DefaultDicomObject};
+use snafu::prelude::*;
+
+use crate::difference::rescaled_samples_of;
+use crate::imaging::{frame_samples, image_dimensions_of, string_of, Region, Result, StoredBits};
+
+/// Distinct colors assigned to overlay layers, in order
+pub const OVERLAY_COLORS: &[[u8; 3]] = &[
+    [255, 255, 0],
+    [0, 255, 255],
+    [255, 0, 255],
+    [0, 255, 0],
+    [255, 128, 0],
+    [128, 128, 255],
+    [255, 0, 0],
+    [255, 255, 255],
+];
+
+/// Where the bits of an overlay plane are stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverlaySource {
+    /// packed in OverlayData, least significant bit first
+    Packed(Vec<u8>),
+    /// embedded in the given bit of each pixel data sample (retired)
+    Embedded { bit_position: u16 },
+    /// any of the bits of each pixel data sample
+    /// which are allocated but not stored,
+    /// when the object does not describe its overlays
+    OutsideStoredBits(StoredBits),
+}
+
+/// What an overlay plane represents (OverlayType)
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub enum OverlayType {
+    /// graphics or text annotations ("G")
+    Graphics,
+    /// a region of interest, filled in ("R")
+    Roi,
+}
+
+/// A single overlay plane
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayPlane {
+    /// the overlay group (0x6000 to 0x601E)
+    pub group: u16,
+    /// OverlayLabel or OverlayDescription, if any
+    pub label: Option<String>,
+    pub kind: OverlayType,
+    pub rows: u32,
+    pub columns: u32,
+    /// the image row and column of the overlay's first pixel (zero based)
+    pub origin: (i64, i64),
+    pub source: OverlaySource,
+}
+
+/// An overlay plane as displayed
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayLayer {
+    pub plane: OverlayPlane,
+    pub visible: bool,
+    pub color: [u8; 3],
+}
+
+impl OverlayLayer {
+    /// A short description of the layer for the overlay selector
+    pub fn name(&self) -> String {
+        match (&self.plane.label, &self.plane.source) {
+            (Some(label), _) => label.clone(),
+            (None, OverlaySource::OutsideStoredBits(_)) => "Embedded overlay".to_string(),
+            (None, _) => format!("Overlay {:04X}", self.plane.group),
+        }
+    }
+}
+
+/// Whether bit `i` is set in the packed bits (least significant bit first)
+pub fn bit_is_set(data: &[u8], i: usize) -> bool {
+    data.get(i / 8)
+        .map_or(false, |byte| (byte >> (i % 8)) & 1 == 1)
+}
+
+/// Read the overlay planes declared in the object.
+pub fn overlay_planes_of(obj: &DefaultDicomObject) -> Result<Vec<OverlayPlane>> {
+    let mut planes = Vec::new();
+
+    for group in (0x6000..=0x601E).step_by(2) {
+        let rows = match obj
+            .element_opt(Tag(group, 0x0010))
+            .whatever_context("Could not fetch OverlayRows")?
+        {
+            Some(elem) => elem
+                .to_int::<u32>()
+                .whatever_context("OverlayRows is not an integer")?,
+            None => continue,
+        };
+        let columns = obj
+            .element(Tag(group, 0x0011))
+            .whatever_context("Could not fetch OverlayColumns")?
+            .to_int::<u32>()
+            .whatever_context("OverlayColumns is not an integer")?;
+        let origin = match obj
+            .element_opt(Tag(group, 0x0050))
+            .whatever_context("Could not fetch OverlayOrigin")?
+        {
+            Some(elem) => match elem
+                .to_multi_int::<i64>()
+                .whatever_context("OverlayOrigin is not a list of integers")?[..]
+            {
+                // one based
+                [row, column] => (row - 1, column - 1),
+                _ => whatever!("OverlayOrigin should have 2 values"),
+            },
+            None => (0, 0),
+        };
+
+        let source = match obj
+            .element_opt(Tag(group, 0x3000))
+            .whatever_context("Could not fetch OverlayData")?
+        {
+            Some(elem) => OverlaySource::Packed(
+                elem.to_bytes()
+                    .whatever_context("Could not read the bytes of OverlayData")?
+                    .into_owned(),
+            ),
+            None => {
+                let bit_position = obj
+                    .element(Tag(group, 0x0102))
+                    .whatever_context("Missing both OverlayData and OverlayBitPosition")?
+                    .to_int::<u16>()
+                    .whatever_context("OverlayBitPosition is not an integer")?;
+                OverlaySource::Embedded { bit_position }
+            }
+        };
+
+        let label =
+            string_of(obj, Tag(group, 0x1500)).or_else(|| string_of(obj, Tag(group, 0x0022)));
+        let kind = match string_of(obj, Tag(group, 0x0040)).as_deref() {
+            Some("R") => OverlayType::Roi,
+            _ => OverlayType::Graphics,
+        };
+
+        planes.push(OverlayPlane {
+            group,
+            label,
+            kind,
+            rows,
+            columns,
+            origin,
+            source,
+        });
+    }
+
+    Ok(planes)
+}
+
+/// Collect the overlay layers of the object, each with its own color.
+///
+/// Declared overlay planes are visible by default.
+/// If there are none, but 16-bit samples have bits which are not stored,
+/// those bits are offered as a single hidden layer.
+pub fn overlay_layers_of(obj: &DefaultDicomObject) -> Result<Vec<OverlayLayer>> {
+    let mut planes = overlay_planes_of(obj)?;
+    let visible = !planes.is_empty();
+
+    if planes.is_empty() {
+        let bits_allocated = obj
+            .element(tags::BITS_ALLOCATED)
+            .whatever_context("Could not fetch BitsAllocated")?
+            .to_int::<u16>()
+            .whatever_context("BitsAllocated is not a number")?;
+        let stored_bits = StoredBits::of(obj)?;
+        if bits_allocated == 16 && stored_bits.bits_stored < 16 {
+            let (columns, rows) = image_dimensions_of(obj)?;
+            planes.push(OverlayPlane {
+                group: 0x6000,
+                label: None,
+                kind: OverlayType::Graphics,
+                rows,
+                columns,
+                origin: (0, 0),
+                source: OverlaySource::OutsideStoredBits(stored_bits),
+            });
+        }
+    }
+
+    Ok(planes
+        .into_iter()
+        .enumerate()
+        .map(|(i, plane)| OverlayLayer {
+            plane,
+            visible,
+            color: OVERLAY_COLORS[i % OVERLAY_COLORS.len()],
+        })
+        .collect())
+}
+
+/// Paint the visible overlay layers over the RGBA values
+/// of a region of a frame of the given size.
+pub fn draw_overlays(
+    y_values: &mut [u8],
+    obj: &DefaultDicomObject,
+    frame: u32,
+    (width, height): (u32, u32),
+    region: Region,
+    layers: &[OverlayLayer],
+) -> Result<()> {
+    let visible: Vec<_> = layers.iter().filter(|layer| layer.visible).collect();
+    if visible.is_empty() {
+        return Ok(());
+    }
+
+    // embedded overlays need the samples of the frame
+    let samples = embedded_samples_of(obj, visible.iter().map(|layer| &layer.plane))?;
+    let frame_len = width as usize * height as usize;
+    let frame_samples = match &samples {
+        Some(samples) => frame_samples(&samples[..], frame, frame_len)?,
+        None => &[],
+    };
+
+    for layer in visible {
+        let plane = &layer.plane;
+        // only the rows and columns of the plane within the region
+        let rows = (region.y as i64 - plane.origin.0).max(0)
+            ..(region.y as i64 + region.height as i64 - plane.origin.0).min(plane.rows as i64);
+        let columns = (region.x as i64 - plane.origin.1).max(0)
+            ..(region.x as i64 + region.width as i64 - plane.origin.1).min(plane.columns as i64);
+        for row in rows {
+            let y = plane.origin.0 + row;
+            if y < 0 || y >= height as i64 {
+                continue;
+            }
+            for column in columns.clone() {
+                let x = plane.origin.1 + column;
+                if x < 0 || x >= width as i64 {
+                    continue;
+                }
+                let i = (row * plane.columns as i64 + column) as usize;
+                let pixel = (y * width as i64 + x) as usize;
+                let out =
+                    ((y - region.y as i64) * region.width as i64 + x - region.x as i64) as usize;
+                if plane_bit_is_set(plane, i, pixel, frame_samples) {
+                    y_values[out * 4..out * 4 + 3].copy_from_slice(&layer.color);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The 16-bit pixel data samples of all frames,
+/// if any of the planes is embedded in them
+fn embedded_samples_of<'a>(
+    obj: &'a DefaultDicomObject,
+    mut planes: impl Iterator<Item = &'a OverlayPlane>,
+) -> Result<Option<Cow<'a, [u16]>>> {
+    if !planes.any(|plane| !matches!(plane.source, OverlaySource::Packed(_))) {
+        return Ok(None);
+    }
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    pixel_data
+        .uint16_slice()
+        .map(Cow::from)
+        .or_else(|_| pixel_data.to_multi_int::<u16>().map(Cow::Owned))
+        .map(Some)
+        .whatever_context("Could not read PixelData as a sequence of 16-bit integers")
+}
+
+/// Whether the overlay plane is set at its `i`-th bit,
+/// which lies over the given pixel of the frame
+fn plane_bit_is_set(plane: &OverlayPlane, i: usize, pixel: usize, frame_samples: &[u16]) -> bool {
+    match &plane.source {
+        OverlaySource::Packed(data) => bit_is_set(data, i),
+        OverlaySource::Embedded { bit_position } => frame_samples
+            .get(pixel)
+            .map_or(false, |sample| (sample >> bit_position) & 1 == 1),
+        OverlaySource::OutsideStoredBits(stored_bits) => frame_samples
+            .get(pixel)
+            .map_or(false, |&sample| stored_bits.has_overlay(sample)),
+    }
+}
+
+/// Statistics of the pixels within a region of interest
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RoiStatistics {
+    /// the number of image pixels in the region
+    pub pixels: usize,
+    /// the area of the region in mm², if the pixel spacing is known
+    pub area: Option<f64>,
+    /// the mean value of the pixels in modality units
+    pub mean: f64,
+}
+
+/// Compute the statistics of the pixels of a frame of the given size
+/// covered by an overlay plane,
+/// given their values in modality units (row by row).
+///
+/// Returns `None` if the plane covers no pixels of the frame.
+pub fn roi_statistics(
+    plane: &OverlayPlane,
+    values: &[f64],
+    frame_samples: &[u16],
+    (width, height): (u32, u32),
+    pixel_spacing: Option<(f64, f64)>,
+) -> Option<RoiStatistics> {
+    let (mut pixels, mut sum) = (0, 0.);
+    for row in 0..plane.rows as i64 {
+        let y = plane.origin.0 + row;
+        if y < 0 || y >= height as i64 {
+            continue;
+        }
+        for column in 0..plane.columns as i64 {
+            let x = plane.origin.1 + column;
+            if x < 0 || x >= width as i64 {
+                continue;
+            }
+            let i = (row * plane.columns as i64 + column) as usize;
+            let pixel = (y * width as i64 + x) as usize;
+            if plane_bit_is_set(plane, i, pixel, frame_samples) {
+                if let Some(value) = values.get(pixel) {
+                    pixels += 1;
+                    sum += value;
+                }
+            }
+        }
+    }
+    if pixels == 0 {
+        return None;
+    }
+    Some(RoiStatistics {
+        pixels,
+        area: pixel_spacing
+            .map(|(row_spacing, column_spacing)| pixels as f64 * row_spacing * column_spacing),
+        mean: sum / pixels as f64,
+    })
+}
+
+/// Compute the statistics of each region of interest overlay
+/// over a frame of the object, `None` for the other layers.
+pub fn overlay_roi_statistics_of(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    layers: &[OverlayLayer],
+    pixel_spacing: Option<(f64, f64)>,
+) -> Result<Vec<Option<RoiStatistics>>> {
+    let rois = || {
+        layers
+            .iter()
+            .map(|layer| &layer.plane)
+            .filter(|plane| plane.kind == OverlayType::Roi)
+    };
+    if rois().next().is_none() {
+        return Ok(vec![None; layers.len()]);
+    }
+
+    let size = image_dimensions_of(obj)?;
+    let values = rescaled_samples_of(obj, frame)?;
+    let samples = embedded_samples_of(obj, rois())?;
+    let frame_samples = match &samples {
+        Some(samples) => frame_samples(&samples[..], frame, values.len())?,
+        None => &[],
+    };
+    Ok(layers
+        .iter()
+        .map(|layer| match layer.plane.kind {
+            OverlayType::Roi => {
+                roi_statistics(&layer.plane, &values, frame_samples, size, pixel_spacing)
+            }
+            OverlayType::Graphics => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn read_packed_overlay_bits() {
+        let data = [0b0000_0101, 0b1000_0000];
+        let bits: Vec<bool> = (0..16).map(|i| bit_is_set(&data, i)).collect();
+        assert!(bits[0] && !bits[1] && bits[2]);
+        assert!(bits[15]);
+        assert_eq!(bits.iter().filter(|&&b| b).count(), 3);
+        // out of bounds
+        assert!(!bit_is_set(&data, 16));
+    }
+
+    #[wasm_bindgen_test]
+    fn statistics_of_roi_overlay() {
+        // a 2x2 region at the bottom right of a 3x3 image,
+        // with one pixel hanging off the right edge
+        let plane = OverlayPlane {
+            group: 0x6000,
+            label: None,
+            kind: OverlayType::Roi,
+            rows: 2,
+            columns: 3,
+            origin: (1, 1),
+            source: OverlaySource::Packed(vec![0b0001_1111]),
+        };
+        let values = [
+            0., 0., 0., //
+            0., 10., 20., //
+            0., 30., 40.,
+        ];
+        let stats = roi_statistics(&plane, &values, &[], (3, 3), Some((0.5, 2.))).unwrap();
+        assert_eq!(stats.pixels, 4);
+        assert_eq!(stats.area, Some(4.));
+        assert_eq!(stats.mean, 25.);
+
+        // unknown pixel spacing
+        let stats = roi_statistics(&plane, &values, &[], (3, 3), None).unwrap();
+        assert_eq!(stats.area, None);
+
+        // an empty region
+        let plane = OverlayPlane {
+            source: OverlaySource::Packed(vec![0]),
+            ..plane
+        };
+        assert_eq!(roi_statistics(&plane, &values, &[], (3, 3), None), None);
+    }
+}
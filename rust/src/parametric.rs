@@ -0,0 +1,283 @@
+//! Parametric maps stored as floating point pixel data.
+//!
+//! Maps such as diffusion (ADC) or perfusion maps
+//! hold their values in `FloatPixelData` or `DoubleFloatPixelData`
+//! rather than in integer samples.
+//! Their values are windowed in floating point, without a LUT,
+//! or shown through a diverging color map
+//! centered at a meaningful value.
+
+use dicom::core::{DicomValue, PrimitiveValue};
+use dicom::{dictionary_std::tags, object::InMemDicomObject};
+use snafu::prelude::*;
+use web_sys::ImageData;
+
+use crate::difference::diverging_color;
+use crate::display::DisplayOptions;
+use crate::imaging::{image_dimensions_of, write_to_imagedata, Result, WindowLevel};
+
+/// A diverging color map over the values of a parametric map,
+/// from blue for `center - range` through white at `center`
+/// to red for `center + range`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Diverging {
+    pub center: f64,
+    pub range: f64,
+}
+
+/// How the values of a parametric map are turned into colors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FloatMapping {
+    /// windowed into gray levels,
+    /// then shown with the display options
+    Window(WindowLevel, DisplayOptions),
+    /// through a diverging color map
+    Diverging(Diverging),
+}
+
+impl FloatMapping {
+    /// The color of a value
+    fn color_of(&self, value: f64) -> [u8; 3] {
+        match self {
+            // values which are not numbers are shown in black
+            _ if value.is_nan() => [0; 3],
+            FloatMapping::Window(window_level, _) => {
+                let y = if window_level.width > 0. {
+                    (value - window_level.center) / window_level.width + 0.5
+                } else if value < window_level.center {
+                    0.
+                } else {
+                    1.
+                };
+                let y = (y.clamp(0., 1.) * 255.).round() as u8;
+                [y, y, y]
+            }
+            FloatMapping::Diverging(Diverging { center, range }) => {
+                diverging_color(value - center, *range)
+            }
+        }
+    }
+}
+
+/// Whether the object holds its pixels as floating point values
+pub fn has_float_pixel_data(obj: &InMemDicomObject) -> bool {
+    [tags::FLOAT_PIXEL_DATA, tags::DOUBLE_FLOAT_PIXEL_DATA]
+        .into_iter()
+        .any(|tag| matches!(obj.element_opt(tag), Ok(Some(_))))
+}
+
+/// The floating point values of all frames of the object
+fn all_float_values_of(obj: &InMemDicomObject) -> Result<Vec<f64>> {
+    let elem = match obj
+        .element_opt(tags::FLOAT_PIXEL_DATA)
+        .whatever_context("Could not fetch FloatPixelData")?
+    {
+        Some(elem) => elem,
+        None => obj
+            .element(tags::DOUBLE_FLOAT_PIXEL_DATA)
+            .whatever_context("Could not fetch DoubleFloatPixelData")?,
+    };
+    match elem.value() {
+        DicomValue::Primitive(PrimitiveValue::F32(values)) => {
+            Ok(values.iter().map(|&x| x as f64).collect())
+        }
+        DicomValue::Primitive(PrimitiveValue::F64(values)) => Ok(values.to_vec()),
+        DicomValue::Primitive(value) => value
+            .to_multi_float64()
+            .whatever_context("Could not read the floating point pixel data"),
+        _ => whatever!("Floating point pixel data cannot be encapsulated"),
+    }
+}
+
+/// The number of values in a frame of a parametric map
+fn frame_len_of(obj: &InMemDicomObject) -> Result<usize> {
+    let (columns, rows) = image_dimensions_of(obj)?;
+    Ok(columns as usize * rows as usize)
+}
+
+/// Check that a number of floating point values
+/// is enough to hold the given frame.
+fn check_float_len(len: usize, frame: u32, frame_len: usize) -> Result<()> {
+    ensure_whatever!(
+        len >= (frame as usize + 1) * frame_len,
+        "Pixel data is too short ({} values) for frame #{} ({} values per frame)",
+        len,
+        frame,
+        frame_len
+    );
+    Ok(())
+}
+
+/// Check that the floating point pixel data of the object
+/// is long enough to hold the given frame.
+pub fn check_float_frame(obj: &InMemDicomObject, frame: u32) -> Result<()> {
+    check_float_len(all_float_values_of(obj)?.len(), frame, frame_len_of(obj)?)
+}
+
+/// The floating point values of a frame of the object
+pub fn float_values_of(obj: &InMemDicomObject, frame: u32) -> Result<Vec<f64>> {
+    let frame_len = frame_len_of(obj)?;
+    let mut values = all_float_values_of(obj)?;
+    check_float_len(values.len(), frame, frame_len)?;
+    let start = frame as usize * frame_len;
+    values.truncate(start + frame_len);
+    values.drain(..start);
+    Ok(values)
+}
+
+/// The number of window level steps across the values of a parametric map,
+/// as many as across the values of a 12-bit image
+pub const FLOAT_WINDOW_STEPS: f64 = 4096.;
+
+/// The window level step of a parametric map,
+/// so that dragging or pressing keys adjusts its window
+/// at the same pace as the window of a 12-bit image,
+/// whatever the range of its values.
+/// This is also the narrowest window width.
+///
+/// Returns `None` if the object has no floating point pixel data
+/// or all its values are the same.
+pub fn float_window_step_of(obj: &InMemDicomObject) -> Option<f64> {
+    if !has_float_pixel_data(obj) {
+        return None;
+    }
+    let range = window_level_spanning(&all_float_values_of(obj).ok()?)?.width;
+    Some(range / FLOAT_WINDOW_STEPS).filter(|&step| step > 0.)
+}
+
+/// The window level spanning the values of a frame,
+/// ignoring values which are not numbers
+fn window_level_spanning(values: &[f64]) -> Option<WindowLevel> {
+    let (min, max) =
+        values
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold(None, |range: Option<(f64, f64)>, &x| match range {
+                Some((min, max)) => Some((min.min(x), max.max(x))),
+                None => Some((x, x)),
+            })?;
+    Some(WindowLevel {
+        width: max - min,
+        center: (min + max) / 2.,
+    })
+}
+
+/// The window level to first display a frame of a parametric map with
+/// if it declares none: one spanning the values of the frame.
+///
+/// Returns `None` if the object has no floating point pixel data.
+pub fn float_window_level_of(obj: &InMemDicomObject, frame: u32) -> Option<WindowLevel> {
+    if !has_float_pixel_data(obj) {
+        return None;
+    }
+    window_level_spanning(&float_values_of(obj, frame).ok()?)
+}
+
+/// Map floating point values to RGBA values
+pub fn float_to_rgba(values: &[f64], mapping: FloatMapping) -> Vec<u8> {
+    let mut rgba: Vec<u8> = values
+        .iter()
+        .flat_map(|&x| {
+            let [r, g, b] = mapping.color_of(x);
+            [r, g, b, 255]
+        })
+        .collect();
+    if let FloatMapping::Window(_, display) = mapping {
+        display.apply(&mut rgba);
+    }
+    rgba
+}
+
+/// Render a frame of a parametric map,
+/// through the diverging color map if given
+/// or else windowed with the given window level
+/// (or one spanning the values of the frame),
+/// reusing the given image data if it has the same size.
+pub fn float_to_imagedata(
+    obj: &InMemDicomObject,
+    frame: u32,
+    window_level: Option<WindowLevel>,
+    diverging: Option<Diverging>,
+    display: DisplayOptions,
+    imagedata: &mut Option<ImageData>,
+) -> Result<ImageData> {
+    let (width, height) = image_dimensions_of(obj)?;
+    let values = float_values_of(obj, frame)?;
+    let mapping = match diverging {
+        Some(diverging) => FloatMapping::Diverging(diverging),
+        None => {
+            let window_level = window_level
+                .or_else(|| window_level_spanning(&values))
+                .unwrap_or(WindowLevel {
+                    width: 1.,
+                    center: 0.5,
+                });
+            FloatMapping::Window(window_level, display)
+        }
+    };
+    write_to_imagedata(imagedata, &float_to_rgba(&values, mapping), width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, VR};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// A 3x2 parametric map with values between 0 and 1
+    fn float_map() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(3_u16)),
+            DataElement::new(
+                tags::FLOAT_PIXEL_DATA,
+                VR::OF,
+                PrimitiveValue::F32(
+                    [0.125, 0.25, 0.375, 0.5, 0.625, f32::NAN]
+                        .into_iter()
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    #[wasm_bindgen_test]
+    fn render_float_map() {
+        let obj = float_map();
+        assert!(has_float_pixel_data(&obj));
+        let values = float_values_of(&obj, 0).unwrap();
+        assert_eq!(values.len(), 6);
+        assert!(float_values_of(&obj, 1).is_err());
+
+        // windowed in floating point, well below a width of 1
+        let window_level = float_window_level_of(&obj, 0).unwrap();
+        assert_eq!(
+            window_level,
+            WindowLevel {
+                width: 0.5,
+                center: 0.375
+            }
+        );
+        let rgba = float_to_rgba(
+            &values,
+            FloatMapping::Window(window_level, DisplayOptions::default()),
+        );
+        let gray: Vec<u8> = rgba.chunks(4).map(|px| px[0]).collect();
+        assert_eq!(gray, [0, 64, 128, 191, 255, 0]);
+
+        // diverging around a threshold
+        let diverging = Diverging {
+            center: 0.375,
+            range: 0.25,
+        };
+        let rgba = float_to_rgba(&values, FloatMapping::Diverging(diverging));
+        assert_eq!(&rgba[..4], &[59, 76, 192, 255]);
+        assert_eq!(&rgba[8..12], &[255, 255, 255, 255]);
+        assert_eq!(&rgba[16..20], &[180, 4, 38, 255]);
+        // not a number
+        assert_eq!(&rgba[20..], &[0, 0, 0, 255]);
+
+        // stepped in fractions of the range of the values
+        assert_eq!(float_window_step_of(&obj), Some(0.5 / FLOAT_WINDOW_STEPS));
+    }
+}
@@ -0,0 +1,188 @@
+//! Window presets by modality.
+//!
+//! Images which declare no window of their own
+//! are offered the presets of their modality,
+//! and are first displayed with its default preset.
+//! Images of other modalities are first displayed
+//! with a window covering the values of the frame.
+
+use dicom::{dictionary_std::tags, object::DefaultDicomObject};
+
+use crate::difference::stored_values_of;
+use crate::histogram::dynamic_range_of;
+use crate::imaging::{
+    modality_of, pixel_padding_of, string_of, voi_luts_of, window_level_of, Voi, VoiPreset,
+    WindowLevel,
+};
+use crate::study::window_level_covering;
+
+/// A window preset for the images of a modality
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModalityPreset {
+    /// the modality (as in the Modality attribute)
+    pub modality: String,
+    /// the name shown in the preset picker
+    pub name: String,
+    pub window_level: WindowLevel,
+    /// whether images of the modality are first displayed with this preset
+    pub default: bool,
+}
+
+/// The built-in presets by modality
+pub fn default_modality_presets() -> Vec<ModalityPreset> {
+    let preset = |name: &str, width, center, default| ModalityPreset {
+        modality: "CT".to_string(),
+        name: name.to_string(),
+        window_level: WindowLevel { width, center },
+        default,
+    };
+    vec![
+        preset("Soft tissue", 400., 40., true),
+        preset("Lung", 1500., -600., false),
+        preset("Bone", 1800., 400., false),
+        preset("Brain", 80., 40., false),
+    ]
+}
+
+/// Add a preset to the table,
+/// replacing the one of the same modality and name if any.
+///
+/// A new default replaces the previous default of the modality.
+pub fn set_modality_preset(presets: &mut Vec<ModalityPreset>, preset: ModalityPreset) {
+    if preset.default {
+        for other in presets.iter_mut().filter(|p| p.modality == preset.modality) {
+            other.default = false;
+        }
+    }
+    match presets
+        .iter_mut()
+        .find(|p| p.modality == preset.modality && p.name == preset.name)
+    {
+        Some(existing) => *existing = preset,
+        None => presets.push(preset),
+    }
+}
+
+/// The presets of a modality, for the preset picker
+pub fn presets_for_modality(presets: &[ModalityPreset], modality: &str) -> Vec<VoiPreset> {
+    presets
+        .iter()
+        .filter(|p| p.modality == modality)
+        .map(|p| VoiPreset {
+            name: p.name.clone(),
+            voi: Voi::Window(p.window_level),
+        })
+        .collect()
+}
+
+/// The window level to display an image with
+/// when it does not declare one:
+/// the default preset of its modality,
+/// or else the first preset of the modality,
+/// or else a window covering the given range of values, if known.
+fn default_window_level(
+    presets: &[ModalityPreset],
+    modality: Option<&str>,
+    range: Option<(f64, f64)>,
+) -> Option<WindowLevel> {
+    let of_modality = || {
+        presets
+            .iter()
+            .filter(|p| Some(p.modality.as_str()) == modality)
+    };
+    of_modality()
+        .find(|p| p.default)
+        .or_else(|| of_modality().next())
+        .map(|p| p.window_level)
+        .or_else(|| range.map(window_level_covering))
+}
+
+/// The modality of the object, if declared
+pub fn modality_name_of(obj: &DefaultDicomObject) -> Option<String> {
+    string_of(obj, tags::MODALITY)
+}
+
+/// The range of values of a monochrome frame in modality units,
+/// leaving out padding
+fn frame_range_of(obj: &DefaultDicomObject, frame: u32) -> Option<(f64, f64)> {
+    let stored = stored_values_of(obj, frame).ok()?;
+    let padding = pixel_padding_of(obj).ok()?;
+    let modality = modality_of(obj).ok()?;
+    dynamic_range_of(&stored, padding, |x| modality.apply(x as f64)).map(|range| range.rescaled)
+}
+
+/// The window level to first display a frame of the object with
+/// if it declares neither a window level nor a VOI LUT,
+/// from the presets of its modality
+/// or else covering the values of the frame.
+///
+/// Returns `None` if the object has a VOI of its own,
+/// or if the values of the frame cannot be read.
+pub fn default_window_level_of(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    presets: &[ModalityPreset],
+) -> Option<WindowLevel> {
+    if !matches!(window_level_of(obj), Ok(None))
+        || !voi_luts_of(obj).map_or(false, |luts| luts.is_empty())
+    {
+        return None;
+    }
+    let modality = modality_name_of(obj);
+    default_window_level(presets, modality.as_deref(), None)
+        .or_else(|| default_window_level(presets, None, frame_range_of(obj, frame)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn default_window_by_modality() {
+        let mut presets = default_modality_presets();
+        let range = Some((0., 4000.));
+
+        // soft tissue for CT, whatever the values
+        assert_eq!(
+            default_window_level(&presets, Some("CT"), range),
+            Some(WindowLevel {
+                width: 400.,
+                center: 40.
+            })
+        );
+        // the values of images of unknown modality
+        assert_eq!(
+            default_window_level(&presets, Some("XA"), range),
+            Some(WindowLevel {
+                width: 4000.,
+                center: 2000.
+            })
+        );
+        assert_eq!(default_window_level(&presets, None, None), None);
+
+        // editing the table changes the default
+        set_modality_preset(
+            &mut presets,
+            ModalityPreset {
+                modality: "CT".to_string(),
+                name: "Lung".to_string(),
+                window_level: WindowLevel {
+                    width: 1600.,
+                    center: -550.,
+                },
+                default: true,
+            },
+        );
+        assert_eq!(
+            default_window_level(&presets, Some("CT"), range),
+            Some(WindowLevel {
+                width: 1600.,
+                center: -550.
+            })
+        );
+        assert_eq!(presets.iter().filter(|p| p.default).count(), 1);
+        assert_eq!(presets_for_modality(&presets, "CT").len(), 4);
+        assert!(presets_for_modality(&presets, "MR").is_empty());
+    }
+}
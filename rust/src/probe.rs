@@ -0,0 +1,264 @@
+//! Reading the values of the pixel under the cursor.
+//!
+//! The probe shows each stage of the value of a monochrome pixel:
+//! as stored, in modality units after the rescale or modality LUT,
+//! and as displayed after windowing, inversion, and any colormap.
+
+use std::borrow::Cow;
+
+use dicom::core::DicomValue;
+use dicom::{dictionary_std::tags, object::DefaultDicomObject};
+use snafu::prelude::*;
+
+use crate::difference::stored_value;
+use crate::display::{Colormap, DisplayOptions};
+use crate::imaging::{
+    image_dimensions_of, modality_of, string_of, y_value_of, Monochrome, OutOfRange, Result,
+    StoredBits,
+};
+
+/// The values of a single pixel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PixelProbe {
+    /// the stored value, sign extended if signed
+    pub stored: i32,
+    /// the value in modality units
+    pub rescaled: f64,
+    /// the displayed gray level (0 to 255)
+    pub display: u8,
+    /// the displayed color, if gray levels are mapped to colors
+    pub color: Option<[u8; 3]>,
+}
+
+/// The name of the modality units of the object:
+/// its RescaleType if specified,
+/// Hounsfield units for CT,
+/// or else a generic name.
+pub fn rescale_unit_of(obj: &DefaultDicomObject) -> String {
+    match string_of(obj, tags::RESCALE_TYPE) {
+        // "US" stands for unspecified
+        Some(unit) if unit != "US" => unit,
+        _ if string_of(obj, tags::MODALITY).as_deref() == Some("CT") => "HU".to_string(),
+        _ => "value".to_string(),
+    }
+}
+
+/// Read the values of the pixel at the given column and row
+/// of a frame of a monochrome image,
+/// displayed through the given LUT and display options.
+///
+/// Returns `None` if the position is outside of the image.
+pub fn probe_pixel(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    (x, y): (u32, u32),
+    lut: &[u8],
+    monochrome: Monochrome,
+    out_of_range: OutOfRange,
+    options: DisplayOptions,
+) -> Result<Option<PixelProbe>> {
+    let (width, height) = image_dimensions_of(obj)?;
+    if x >= width || y >= height {
+        return Ok(None);
+    }
+    let index = (frame as usize * height as usize + y as usize) * width as usize + x as usize;
+
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+    let signed = obj
+        .element_opt(tags::PIXEL_REPRESENTATION)
+        .whatever_context("Could not fetch PixelRepresentation")?
+        .map(|elem| elem.to_int::<u16>())
+        .transpose()
+        .whatever_context("PixelRepresentation is not a number")?
+        == Some(1);
+    let stored_bits = StoredBits::of(obj)?;
+
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    if matches!(pixel_data.value(), DicomValue::PixelSequence { .. }) {
+        whatever!("Encapsulated pixel data encoding is not supported at the moment, sorry. :(");
+    }
+    let sample = match bits_allocated {
+        8 => {
+            let samples = pixel_data
+                .to_bytes()
+                .whatever_context("Could not read PixelData as a sequence of 8-bit integers")?;
+            let sample = *samples
+                .get(index)
+                .whatever_context("Pixel is out of the bounds of the pixel data")?
+                as u16;
+            sample
+        }
+        16 => {
+            let samples: Cow<[u16]> = pixel_data
+                .uint16_slice()
+                .map(Cow::from)
+                .or_else(|_| pixel_data.to_multi_int::<u16>().map(Cow::Owned))
+                .whatever_context("Could not read PixelData as a sequence of 16-bit integers")?;
+            *samples
+                .get(index)
+                .whatever_context("Pixel is out of the bounds of the pixel data")?
+        }
+        _ => whatever!("Unsupported BitsAllocated {} :(", bits_allocated),
+    };
+
+    let stored = stored_value(stored_bits, signed, sample);
+    // as when windowing the frame
+    let y = y_value_of(lut, stored_bits.value(sample), monochrome, out_of_range);
+    let display = options.gray_level_of(y);
+    Ok(Some(PixelProbe {
+        stored,
+        rescaled: modality_of(obj)?.apply(stored as f64),
+        display,
+        color: match options.colormap {
+            Colormap::Gray => None,
+            colormap => Some(colormap.color_of(display)),
+        },
+    }))
+}
+
+/// A value with no more decimals than needed (up to 2)
+fn format_value(x: f64) -> String {
+    if x.fract() == 0. {
+        format!("{:.0}", x)
+    } else {
+        format!("{:.2}", x)
+    }
+}
+
+/// The probe readout of a pixel, with the name of its modality units
+pub fn probe_text(probe: PixelProbe, unit: &str) -> String {
+    let mut text = format!(
+        "stored: {}, {}: {}, display: {}/255",
+        probe.stored,
+        unit,
+        format_value(probe.rescaled),
+        probe.display
+    );
+    if let Some([r, g, b]) = probe.color {
+        text.push_str(&format!(" (rgb {}, {}, {})", r, g, b));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn format_probe_readout() {
+        let probe = PixelProbe {
+            stored: 1200,
+            rescaled: 176.,
+            display: 210,
+            color: None,
+        };
+        assert_eq!(
+            probe_text(probe, "HU"),
+            "stored: 1200, HU: 176, display: 210/255"
+        );
+
+        let probe = PixelProbe {
+            stored: -3,
+            rescaled: 0.3,
+            display: 0,
+            color: None,
+        };
+        assert_eq!(
+            probe_text(probe, "value"),
+            "stored: -3, value: 0.30, display: 0/255"
+        );
+
+        let probe = PixelProbe {
+            color: Some([255, 128, 0]),
+            ..probe
+        };
+        assert_eq!(
+            probe_text(probe, "value"),
+            "stored: -3, value: 0.30, display: 0/255 (rgb 255, 128, 0)"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn probe_pixel_as_displayed() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        // 2x2 pixels, 12 of 16 bits, rescaled to HU
+        let meta = dicom::object::meta::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+            .media_storage_sop_instance_uid("1.2.3.4")
+            .build()
+            .unwrap();
+        let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+        for elem in [
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16_u16)),
+            DataElement::new(tags::BITS_STORED, VR::US, PrimitiveValue::from(12_u16)),
+            DataElement::new(tags::HIGH_BIT, VR::US, PrimitiveValue::from(11_u16)),
+            DataElement::new(tags::RESCALE_SLOPE, VR::DS, PrimitiveValue::from("1")),
+            DataElement::new(
+                tags::RESCALE_INTERCEPT,
+                VR::DS,
+                PrimitiveValue::from("-1024"),
+            ),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OW,
+                PrimitiveValue::U16([0, 1024, 2048, 0xF000 | 3][..].into()),
+            ),
+        ] {
+            obj.put(elem);
+        }
+        // an identity LUT over the first 256 values, shorter than the samples
+        let lut: Vec<u8> = (0..=255).collect();
+        let probe = |position, out_of_range, options| {
+            probe_pixel(
+                &obj,
+                0,
+                position,
+                &lut,
+                Monochrome::Monochrome2,
+                out_of_range,
+                options,
+            )
+            .unwrap()
+        };
+        let gray = DisplayOptions::default();
+
+        // overlay bits are left out of the stored value
+        let pixel = probe((1, 1), OutOfRange::Clamp, gray).unwrap();
+        assert_eq!(
+            (pixel.stored, pixel.rescaled, pixel.display),
+            (3, -1021., 3)
+        );
+        assert_eq!(pixel.color, None);
+
+        // values beyond the LUT follow the out of range handling
+        assert_eq!(probe((1, 0), OutOfRange::Clamp, gray).unwrap().display, 255);
+        assert_eq!(
+            probe((1, 0), OutOfRange::Background, gray).unwrap().display,
+            0
+        );
+        assert_eq!(probe((1, 0), OutOfRange::Wrap, gray).unwrap().display, 0);
+
+        // as inverted and mapped to colors
+        let inverted = DisplayOptions {
+            invert: true,
+            colormap: Colormap::Hot,
+        };
+        let pixel = probe((0, 0), OutOfRange::Clamp, inverted).unwrap();
+        assert_eq!(pixel.display, 255);
+        assert_eq!(pixel.color, Some(Colormap::Hot.color_of(255)));
+
+        // outside of the image
+        assert_eq!(probe((2, 0), OutOfRange::Clamp, gray), None);
+    }
+}
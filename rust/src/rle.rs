@@ -0,0 +1,663 @@
+//! RLE Lossless pixel data, decoded one frame at a time.
+//!
+//! Each frame of an RLE Lossless object is compressed on its own,
+//! usually in a fragment of its own,
+//! so a frame can be decoded when it is shown
+//! without decoding the others.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use dicom::core::{DataElement, DicomValue, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject};
+use snafu::prelude::*;
+
+use crate::imaging::{image_dimensions_of, number_of_frames_of, Result};
+use crate::sr::sop_instance_uid_of;
+
+/// The UID of the RLE Lossless transfer syntax
+pub const RLE_LOSSLESS: &str = "1.2.840.10008.1.2.5";
+
+/// The transfer syntax of frames once decoded (Explicit VR Little Endian)
+const DECODED_TRANSFER_SYNTAX: &str = "1.2.840.10008.1.2.1\0";
+
+/// The length of the header of an RLE frame:
+/// the number of segments and the offsets of up to 15 segments
+const RLE_HEADER_LEN: usize = 64;
+
+/// Whether the object is encoded in RLE Lossless
+pub fn is_rle_lossless(obj: &DefaultDicomObject) -> bool {
+    obj.meta()
+        .transfer_syntax()
+        .trim_end_matches(|c| c == '\0' || c == ' ')
+        == RLE_LOSSLESS
+}
+
+/// Whether a fragment starts with what looks like the header of an RLE frame:
+/// between 1 and 15 segments, the first right after the header,
+/// and the offsets of the others in order, with no offsets past the last segment.
+fn looks_like_rle_header(fragment: &[u8]) -> bool {
+    if fragment.len() < RLE_HEADER_LEN {
+        return false;
+    }
+    let header: Vec<usize> = fragment[..RLE_HEADER_LEN]
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .collect();
+    let segments = header[0];
+    (1..=15).contains(&segments)
+        && header[1] == RLE_HEADER_LEN
+        && header[1..=segments].windows(2).all(|w| w[0] <= w[1])
+        && header[segments + 1..].iter().all(|&offset| offset == 0)
+}
+
+/// The bytes of a frame of encapsulated RLE pixel data.
+///
+/// Frames are found with the basic offset table if there is one,
+/// and otherwise one fragment is assumed per frame
+/// (or all fragments if there is a single frame).
+/// If there are more fragments than frames and no offset table,
+/// each frame is taken to start at a fragment beginning with an RLE header.
+pub fn frame_fragment<'a>(
+    offset_table: &[u32],
+    fragments: &'a [Vec<u8>],
+    number_of_frames: u32,
+    frame: u32,
+) -> Result<Cow<'a, [u8]>> {
+    ensure_whatever!(
+        frame < number_of_frames,
+        "Frame #{} is out of range, there are {} frames",
+        frame,
+        number_of_frames
+    );
+
+    if offset_table.is_empty() {
+        if fragments.len() == number_of_frames as usize {
+            return Ok(Cow::Borrowed(&fragments[frame as usize]));
+        }
+        if number_of_frames == 1 {
+            return Ok(Cow::Owned(fragments.concat()));
+        }
+
+        let starts: Vec<usize> = (0..fragments.len())
+            .filter(|&i| looks_like_rle_header(&fragments[i]))
+            .collect();
+        ensure_whatever!(
+            starts.len() == number_of_frames as usize && starts.first() == Some(&0),
+            "Cannot tell the fragments of each of {} frames in {} fragments without an offset table",
+            number_of_frames,
+            fragments.len()
+        );
+        let start = starts[frame as usize];
+        let end = starts
+            .get(frame as usize + 1)
+            .copied()
+            .unwrap_or(fragments.len());
+        return match &fragments[start..end] {
+            [fragment] => Ok(Cow::Borrowed(fragment)),
+            parts => Ok(Cow::Owned(parts.concat())),
+        };
+    }
+
+    ensure_whatever!(
+        offset_table.len() == number_of_frames as usize,
+        "The offset table has {} entries for {} frames",
+        offset_table.len(),
+        number_of_frames
+    );
+    let start = offset_table[frame as usize] as usize;
+    let end = offset_table
+        .get(frame as usize + 1)
+        .map(|&offset| offset as usize)
+        .unwrap_or(usize::MAX);
+
+    // offsets count from the first fragment, including each item header
+    let mut position = 0;
+    let mut parts = Vec::new();
+    for fragment in fragments {
+        if position >= start && position < end {
+            parts.push(&fragment[..]);
+        }
+        position += fragment.len() + 8;
+    }
+    match parts[..] {
+        [] => whatever!("No fragment found for frame #{} at offset {}", frame, start),
+        [part] => Ok(Cow::Borrowed(part)),
+        _ => Ok(Cow::Owned(parts.concat())),
+    }
+}
+
+/// A warning for a multi-frame RLE object without an offset table
+/// and with more fragments than frames,
+/// whose frames are told apart by their RLE headers.
+pub fn inferred_frames_warning(obj: &DefaultDicomObject) -> Option<&'static str> {
+    if !is_rle_lossless(obj) {
+        return None;
+    }
+    let number_of_frames = number_of_frames_of(obj).ok()?;
+    match obj.element(tags::PIXEL_DATA).ok()?.value() {
+        DicomValue::PixelSequence {
+            offset_table,
+            fragments,
+        } if offset_table.is_empty()
+            && number_of_frames > 1
+            && fragments.len() != number_of_frames as usize =>
+        {
+            Some("The RLE frames have no offset table, so they were told apart by their headers.")
+        }
+        _ => None,
+    }
+}
+
+/// Decode a PackBits segment into `len` bytes
+fn decode_segment(data: &[u8], len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while out.len() < len && i < data.len() {
+        let n = data[i] as i8;
+        i += 1;
+        match n {
+            // a literal run of n + 1 bytes
+            0..=127 => {
+                let count = n as usize + 1;
+                ensure_whatever!(i + count <= data.len(), "RLE segment ends in a literal run");
+                out.extend_from_slice(&data[i..i + count]);
+                i += count;
+            }
+            // no operation
+            -128 => {}
+            // the next byte repeated 1 - n times
+            _ => {
+                let value = *data
+                    .get(i)
+                    .whatever_context("RLE segment ends in a replicate run")?;
+                out.extend(std::iter::repeat(value).take((1 - n as isize) as usize));
+                i += 1;
+            }
+        }
+    }
+    ensure_whatever!(
+        out.len() >= len,
+        "RLE segment holds {} bytes, {} expected",
+        out.len(),
+        len
+    );
+    out.truncate(len);
+    Ok(out)
+}
+
+/// Decode an RLE frame of the given number of pixels
+/// into native little endian samples, interleaved by pixel.
+///
+/// Each segment holds one byte of one sample of every pixel,
+/// from the most significant byte of the first sample.
+pub fn decode_rle_frame(
+    data: &[u8],
+    pixels: usize,
+    samples_per_pixel: u16,
+    bits_allocated: u16,
+) -> Result<Vec<u8>> {
+    ensure_whatever!(
+        bits_allocated % 8 == 0 && bits_allocated > 0,
+        "Unsupported BitsAllocated {} for RLE",
+        bits_allocated
+    );
+    ensure_whatever!(
+        data.len() >= RLE_HEADER_LEN,
+        "RLE frame is too short for its header"
+    );
+
+    let bytes_per_sample = bits_allocated as usize / 8;
+    let samples_per_pixel = samples_per_pixel as usize;
+    let segments = samples_per_pixel * bytes_per_sample;
+    // the header holds the offsets of up to 15 segments
+    ensure_whatever!(
+        segments <= 15,
+        "RLE frames cannot hold {} segments, only up to 15",
+        segments
+    );
+    let header: Vec<usize> = data[..RLE_HEADER_LEN]
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .collect();
+    ensure_whatever!(
+        header[0] == segments,
+        "RLE frame has {} segments, {} expected",
+        header[0],
+        segments
+    );
+
+    let mut out = vec![0; pixels * segments];
+    for segment in 0..segments {
+        let start = header[segment + 1];
+        let end = if segment + 1 < segments {
+            header[segment + 2]
+        } else {
+            data.len()
+        };
+        ensure_whatever!(
+            start <= end && end <= data.len(),
+            "RLE segment #{} is out of bounds",
+            segment
+        );
+        let bytes = decode_segment(&data[start..end], pixels)?;
+
+        // most significant byte first, stored last in little endian
+        let (sample, byte) = (
+            segment / bytes_per_sample,
+            bytes_per_sample - 1 - segment % bytes_per_sample,
+        );
+        for (pixel, value) in bytes.into_iter().enumerate() {
+            out[(pixel * samples_per_pixel + sample) * bytes_per_sample + byte] = value;
+        }
+    }
+    Ok(out)
+}
+
+/// Decode a frame of RLE pixel data into native pixel data
+pub fn native_pixel_data_of(obj: &InMemDicomObject, frame: u32) -> Result<PrimitiveValue> {
+    let (width, height) = image_dimensions_of(obj)?;
+    let bits_allocated = obj
+        .element(tags::BITS_ALLOCATED)
+        .whatever_context("Could not fetch BitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("BitsAllocated is not a number")?;
+    let samples_per_pixel = match obj
+        .element_opt(tags::SAMPLES_PER_PIXEL)
+        .whatever_context("Could not fetch SamplesPerPixel")?
+    {
+        Some(elem) => elem
+            .to_int::<u16>()
+            .whatever_context("SamplesPerPixel is not an integer")?,
+        None => 1,
+    };
+
+    let pixel_data = obj
+        .element(tags::PIXEL_DATA)
+        .whatever_context("Could not fetch PixelData")?;
+    let fragment = match pixel_data.value() {
+        DicomValue::PixelSequence {
+            offset_table,
+            fragments,
+        } => frame_fragment(offset_table, fragments, number_of_frames_of(obj)?, frame)?,
+        _ => whatever!("RLE pixel data should be encapsulated"),
+    };
+
+    let pixels = width as usize * height as usize;
+    let bytes = decode_rle_frame(&fragment, pixels, samples_per_pixel, bits_allocated)?;
+    match bits_allocated {
+        8 => Ok(PrimitiveValue::U8(bytes.into_iter().collect())),
+        16 => Ok(PrimitiveValue::U16(
+            bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        )),
+        _ => whatever!("Unsupported BitsAllocated {} :(", bits_allocated),
+    }
+}
+
+/// A single frame object holding a frame of an RLE object
+/// decoded into native pixel data, to be shown in its place.
+pub fn native_frame_of(obj: &DefaultDicomObject, frame: u32) -> Result<DefaultDicomObject> {
+    let pixel_data = native_pixel_data_of(obj, frame)?;
+
+    let mut meta = obj.meta().clone();
+    meta.transfer_syntax = DECODED_TRANSFER_SYNTAX.to_string();
+    let mut native = DefaultDicomObject::new_empty_with_meta(meta);
+    for elem in obj.iter() {
+        if elem.header().tag != tags::PIXEL_DATA {
+            native.put(elem.clone());
+        }
+    }
+    let vr = if matches!(pixel_data, PrimitiveValue::U8(_)) {
+        VR::OB
+    } else {
+        VR::OW
+    };
+    native.put(DataElement::new(tags::PIXEL_DATA, vr, pixel_data));
+    native.put(DataElement::new(
+        tags::NUMBER_OF_FRAMES,
+        VR::IS,
+        PrimitiveValue::from("1"),
+    ));
+    // the samples of each pixel are decoded next to each other
+    if obj
+        .element_opt(tags::PLANAR_CONFIGURATION)
+        .ok()
+        .flatten()
+        .is_some()
+    {
+        native.put(DataElement::new(
+            tags::PLANAR_CONFIGURATION,
+            VR::US,
+            PrimitiveValue::from(0_u16),
+        ));
+    }
+    Ok(native)
+}
+
+/// The number of decoded frames kept by default
+pub const DEFAULT_DECODED_FRAMES: usize = 8;
+
+/// A cache of the most recently rendered RLE frames
+/// decoded into single frame objects,
+/// keyed by the SOPInstanceUID of their object and their frame number,
+/// so that a frame is not decoded again each time it is rendered.
+///
+/// Frames of objects without a SOPInstanceUID are not cached.
+#[derive(Debug)]
+pub struct DecodedFrames {
+    frames: RefCell<HashMap<(String, u32), Rc<DefaultDicomObject>>>,
+    /// the keys of the cached frames, least recently used first
+    order: RefCell<VecDeque<(String, u32)>>,
+    capacity: usize,
+}
+
+impl Default for DecodedFrames {
+    fn default() -> Self {
+        DecodedFrames::with_capacity(DEFAULT_DECODED_FRAMES)
+    }
+}
+
+impl DecodedFrames {
+    /// A cache keeping up to the given number of frames
+    pub fn with_capacity(capacity: usize) -> Self {
+        DecodedFrames {
+            frames: RefCell::default(),
+            order: RefCell::default(),
+            capacity,
+        }
+    }
+
+    /// A frame of an RLE object decoded into a single frame object,
+    /// from the cache if it was decoded before.
+    pub fn get(&self, obj: &DefaultDicomObject, frame: u32) -> Result<Rc<DefaultDicomObject>> {
+        let key = match sop_instance_uid_of(obj) {
+            Some(uid) => (uid, frame),
+            None => return Ok(Rc::new(native_frame_of(obj, frame)?)),
+        };
+        let cached = self.frames.borrow().get(&key).cloned();
+        let native = match cached {
+            Some(native) => native,
+            None => {
+                let native = Rc::new(native_frame_of(obj, frame)?);
+                self.frames.borrow_mut().insert(key.clone(), native.clone());
+                native
+            }
+        };
+        self.touch(key);
+        Ok(native)
+    }
+
+    /// Keep up to the given number of frames,
+    /// dropping the least recently used frames beyond it
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let mut order = self.order.borrow_mut();
+        while order.len() > self.capacity {
+            if let Some(old) = order.pop_front() {
+                self.frames.borrow_mut().remove(&old);
+            }
+        }
+    }
+
+    /// Whether the frame of the object with the given UID is cached
+    pub fn contains(&self, uid: &str, frame: u32) -> bool {
+        self.frames.borrow().contains_key(&(uid.to_string(), frame))
+    }
+
+    /// Drop all cached frames
+    pub fn clear(&self) {
+        self.frames.borrow_mut().clear();
+        self.order.borrow_mut().clear();
+    }
+
+    fn touch(&self, key: (String, u32)) {
+        let mut order = self.order.borrow_mut();
+        order.retain(|other| *other != key);
+        order.push_back(key);
+        while order.len() > self.capacity {
+            if let Some(old) = order.pop_front() {
+                self.frames.borrow_mut().remove(&old);
+            }
+        }
+    }
+}
+
+/// A frame of an object as it is rendered
+#[derive(Debug)]
+pub enum NativeFrame<'a> {
+    /// a frame of an object in native pixel data, as it is
+    Original(&'a DefaultDicomObject, u32),
+    /// a frame of an RLE object, decoded into a single frame object
+    Decoded(Rc<DefaultDicomObject>),
+}
+
+impl NativeFrame<'_> {
+    /// The object to render
+    pub fn obj(&self) -> &DefaultDicomObject {
+        match self {
+            NativeFrame::Original(obj, _) => obj,
+            NativeFrame::Decoded(native) => native,
+        }
+    }
+
+    /// The frame of the object to render
+    pub fn frame(&self) -> u32 {
+        match self {
+            NativeFrame::Original(_, frame) => *frame,
+            NativeFrame::Decoded(_) => 0,
+        }
+    }
+
+    /// Whether the frame was decoded from RLE pixel data
+    pub fn is_decoded(&self) -> bool {
+        matches!(self, NativeFrame::Decoded(_))
+    }
+}
+
+/// The object and frame to render for a frame of an object:
+/// frames of RLE objects are decoded (through the cache),
+/// and frames of other objects are rendered as they are.
+pub fn native_frame<'a>(
+    decoded: &DecodedFrames,
+    obj: &'a DefaultDicomObject,
+    frame: u32,
+) -> Result<NativeFrame<'a>> {
+    if is_rle_lossless(obj) {
+        decoded.get(obj, frame).map(NativeFrame::Decoded)
+    } else {
+        Ok(NativeFrame::Original(obj, frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// An RLE frame of 16-bit samples, in two segments
+    fn rle_frame(samples: &[u16]) -> Vec<u8> {
+        let high: Vec<u8> = samples.iter().map(|x| (x >> 8) as u8).collect();
+        let low: Vec<u8> = samples.iter().map(|x| *x as u8).collect();
+        // the high bytes as one replicate run, the low bytes as a literal run
+        assert!(high.iter().all(|&b| b == high[0]));
+        let high = vec![(1 - high.len() as i8) as u8, high[0]];
+        let low: Vec<u8> = [low.len() as u8 - 1].into_iter().chain(low).collect();
+
+        let mut header = [0_u32; 16];
+        header[0] = 2;
+        header[1] = RLE_HEADER_LEN as u32;
+        header[2] = (RLE_HEADER_LEN + high.len()) as u32;
+        header
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .chain(high)
+            .chain(low)
+            .collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn decode_frames_independently() {
+        let frames = [
+            [0x100_u16, 0x101, 0x102, 0x103],
+            [0x200, 0x2ff, 0x220, 0x201],
+            [7, 6, 5, 4],
+        ];
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16_u16)),
+            DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("3")),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OB,
+                DicomValue::new_pixel_sequence(
+                    Default::default(),
+                    frames.iter().map(|f| rle_frame(f)).collect::<Vec<_>>(),
+                ),
+            ),
+        ]);
+
+        // in any order
+        for frame in [2, 0, 1] {
+            let decoded = native_pixel_data_of(&obj, frame).unwrap();
+            assert_eq!(
+                decoded,
+                PrimitiveValue::U16(frames[frame as usize].into_iter().collect())
+            );
+        }
+        assert!(native_pixel_data_of(&obj, 3).is_err());
+
+        // more samples per pixel than the header has room for
+        let frame = rle_frame(&frames[0]);
+        assert!(decode_rle_frame(&frame, 4, 8, 16).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn find_fragments_of_frame() {
+        let fragments = vec![vec![1; 4], vec![2; 2], vec![3; 6]];
+        // one fragment per frame
+        assert_eq!(&*frame_fragment(&[], &fragments, 3, 1).unwrap(), &[2, 2]);
+        // a single frame in all fragments
+        assert_eq!(frame_fragment(&[], &fragments, 1, 0).unwrap().len(), 12);
+        // the first frame spans two fragments, after their item headers
+        let offsets = [0, 4 + 8 + 2 + 8];
+        assert_eq!(
+            &*frame_fragment(&offsets, &fragments, 2, 0).unwrap(),
+            &[1, 1, 1, 1, 2, 2]
+        );
+        assert_eq!(
+            &*frame_fragment(&offsets, &fragments, 2, 1).unwrap(),
+            &[3; 6]
+        );
+        assert!(frame_fragment(&[], &fragments, 2, 0).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn cache_decoded_frames() {
+        let frames = [
+            [0x100_u16, 0x101, 0x102, 0x103],
+            [0x200, 0x2ff, 0x220, 0x201],
+        ];
+        let meta = dicom::object::meta::FileMetaTableBuilder::new()
+            .transfer_syntax(RLE_LOSSLESS)
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid("1.2.3.4")
+            .build()
+            .unwrap();
+        let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+        for elem in [
+            DataElement::new(
+                tags::SOP_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3.4"),
+            ),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16_u16)),
+            DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("2")),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OB,
+                DicomValue::new_pixel_sequence(
+                    Default::default(),
+                    frames.iter().map(|f| rle_frame(f)).collect::<Vec<_>>(),
+                ),
+            ),
+        ] {
+            obj.put(elem);
+        }
+
+        let decoded = DecodedFrames::with_capacity(1);
+        let native = native_frame(&decoded, &obj, 1).unwrap();
+        assert!(native.is_decoded());
+        assert_eq!(native.frame(), 0);
+        assert_eq!(
+            native
+                .obj()
+                .element(tags::PIXEL_DATA)
+                .unwrap()
+                .value()
+                .primitive(),
+            Some(&PrimitiveValue::U16(frames[1].into_iter().collect()))
+        );
+        assert!(decoded.contains("1.2.3.4", 1));
+
+        // the same frame is not decoded again
+        let again = decoded.get(&obj, 1).unwrap();
+        assert!(matches!(native, NativeFrame::Decoded(first) if Rc::ptr_eq(&first, &again)));
+
+        // the least recently used frame is dropped
+        decoded.get(&obj, 0).unwrap();
+        assert!(decoded.contains("1.2.3.4", 0));
+        assert!(!decoded.contains("1.2.3.4", 1));
+        decoded.clear();
+        assert!(!decoded.contains("1.2.3.4", 0));
+    }
+
+    #[wasm_bindgen_test]
+    fn infer_frames_without_offset_table() {
+        let frames = [
+            [0x100_u16, 0x101, 0x102, 0x103],
+            [0x300, 0x3ff, 0x330, 0x301],
+        ];
+        // each frame split in two fragments, the first one after its header
+        let fragments: Vec<Vec<u8>> = frames
+            .iter()
+            .flat_map(|f| {
+                let mut data = rle_frame(f);
+                let rest = data.split_off(RLE_HEADER_LEN + 1);
+                [data, rest]
+            })
+            .collect();
+        assert!(looks_like_rle_header(&fragments[0]));
+        assert!(!looks_like_rle_header(&fragments[1]));
+
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16_u16)),
+            DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("2")),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OB,
+                DicomValue::new_pixel_sequence(Default::default(), fragments.clone()),
+            ),
+        ]);
+        for frame in [1, 0] {
+            let decoded = native_pixel_data_of(&obj, frame).unwrap();
+            assert_eq!(
+                decoded,
+                PrimitiveValue::U16(frames[frame as usize].into_iter().collect())
+            );
+        }
+
+        // fragments which do not start with a header cannot be told apart
+        assert!(frame_fragment(&[], &fragments[1..], 2, 0).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Contact sheets: thumbnails of many images arranged in a grid,
+//! for an overview of a series.
+
+use snafu::prelude::*;
+
+use crate::imaging::{thumbnail_of, Downsampling, Result};
+
+/// The largest number of columns of a contact sheet
+pub const MAX_SHEET_COLUMNS: u32 = 64;
+
+/// The smallest and largest size of each thumbnail, in pixels
+pub const SHEET_THUMBNAIL_SIZES: (u32, u32) = (16, 1024);
+
+/// The largest number of pixels of a contact sheet,
+/// so that it can still be put on a canvas
+const MAX_SHEET_PIXELS: u64 = 8192 * 8192;
+
+/// The space between thumbnails, in pixels
+const SHEET_GAP: u32 = 2;
+
+/// The indices of up to `max` items out of `count`,
+/// spread evenly from the first one.
+pub fn sample_evenly(count: usize, max: Option<usize>) -> Vec<usize> {
+    match max {
+        Some(max) if max < count => (0..max).map(|i| i * count / max).collect(),
+        _ => (0..count).collect(),
+    }
+}
+
+/// The width and height of a contact sheet
+/// of the given number of thumbnails,
+/// or `None` if it would be too large to be shown.
+pub fn sheet_size(count: usize, columns: u32, thumbnail_size: u32) -> Option<(u32, u32)> {
+    let columns = (columns as u64).min(count as u64).max(1);
+    let rows = ((count as u64 + columns - 1) / columns).max(1);
+    let cell = (thumbnail_size + SHEET_GAP) as u64;
+    let (width, height) = (
+        columns * cell + SHEET_GAP as u64,
+        rows * cell + SHEET_GAP as u64,
+    );
+    if width * height > MAX_SHEET_PIXELS {
+        return None;
+    }
+    Some((width as u32, height as u32))
+}
+
+/// A contact sheet being filled with thumbnails
+/// in a grid of the given number of columns,
+/// left to right and top to bottom,
+/// each centered in a square cell of `thumbnail_size` pixels
+/// on a black background.
+///
+/// Each image is reduced to its thumbnail as soon as it is added,
+/// so that only one full image is needed at a time.
+#[derive(Debug)]
+pub struct ContactSheet {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+    columns: u32,
+    thumbnail_size: u32,
+    /// the number of thumbnails it has room for
+    capacity: usize,
+    /// the number of thumbnails added so far
+    count: usize,
+}
+
+impl ContactSheet {
+    /// An empty contact sheet with room for up to `capacity` thumbnails,
+    /// or `None` if it would be too large to be shown.
+    pub fn new(capacity: usize, columns: u32, thumbnail_size: u32) -> Option<Self> {
+        let (width, height) = sheet_size(capacity, columns, thumbnail_size)?;
+        Some(ContactSheet {
+            rgba: [0, 0, 0, 255].repeat(width as usize * height as usize),
+            width,
+            height,
+            columns: columns.min(capacity as u32).max(1),
+            thumbnail_size,
+            capacity,
+            count: 0,
+        })
+    }
+
+    /// Whether no thumbnail was added
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Add an image (RGBA samples, width, and height)
+    /// as a thumbnail in the next cell.
+    ///
+    /// Fails without taking a cell
+    /// if the image has fewer samples than pixels
+    /// or the sheet is full.
+    pub fn push(&mut self, rgba: &[u8], width: u32, height: u32) -> Result<()> {
+        ensure_whatever!(self.count < self.capacity, "The contact sheet is full");
+        let (thumb, thumb_w, thumb_h) =
+            thumbnail_of(rgba, width, height, self.thumbnail_size, Downsampling::Area)?;
+        let cell = self.thumbnail_size + SHEET_GAP;
+        let x = (self.count as u32 % self.columns) * cell
+            + SHEET_GAP
+            + (self.thumbnail_size - thumb_w) / 2;
+        let y = (self.count as u32 / self.columns) * cell
+            + SHEET_GAP
+            + (self.thumbnail_size - thumb_h) / 2;
+        for (row, line) in thumb.chunks_exact(thumb_w as usize * 4).enumerate() {
+            let start = ((y as usize + row) * self.width as usize + x as usize) * 4;
+            self.rgba[start..start + line.len()].copy_from_slice(line);
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// The RGBA samples, width, and height of the sheet,
+    /// cut down to the cells of the thumbnails added
+    /// if there are fewer than it has room for.
+    pub fn finish(self) -> (Vec<u8>, u32, u32) {
+        let (width, height) = sheet_size(self.count, self.columns, self.thumbnail_size)
+            .unwrap_or((self.width, self.height));
+        if (width, height) == (self.width, self.height) {
+            return (self.rgba, width, height);
+        }
+        let rgba = self
+            .rgba
+            .chunks_exact(self.width as usize * 4)
+            .take(height as usize)
+            .flat_map(|line| &line[..width as usize * 4])
+            .copied()
+            .collect();
+        (rgba, width, height)
+    }
+}
+
+/// Arrange images as thumbnails in a contact sheet.
+///
+/// The images are given as RGBA samples, width, and height.
+/// Returns the RGBA samples, width, and height of the sheet,
+/// or `None` if it would be too large to be shown
+/// or an image has fewer samples than pixels.
+pub fn contact_sheet_of(
+    images: &[(Vec<u8>, u32, u32)],
+    columns: u32,
+    thumbnail_size: u32,
+) -> Option<(Vec<u8>, u32, u32)> {
+    let mut sheet = ContactSheet::new(images.len(), columns, thumbnail_size)?;
+    for (rgba, width, height) in images {
+        sheet.push(rgba, *width, *height).ok()?;
+    }
+    Some(sheet.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn arrange_thumbnails_in_grid() {
+        assert_eq!(sample_evenly(3, None), [0, 1, 2]);
+        assert_eq!(sample_evenly(3, Some(5)), [0, 1, 2]);
+        assert_eq!(sample_evenly(10, Some(4)), [0, 2, 5, 7]);
+
+        // three 4x2 images, all white, in two columns of 16-pixel cells
+        let image = (vec![255; 4 * 2 * 4], 4, 2);
+        let (sheet, width, height) = contact_sheet_of(&vec![image; 3], 2, 16).unwrap();
+        assert_eq!((width, height), (2 + 18 * 2, 2 + 18 * 2));
+        assert_eq!(sheet.len(), (width * height * 4) as usize);
+        let at = |x: u32, y: u32| &sheet[((y * width + x) * 4) as usize..][..4];
+        // images keep their size and are centered in their cells
+        assert_eq!(at(2 + 6, 2 + 7), [255; 4]);
+        assert_eq!(at(2 + 9, 2 + 8), [255; 4]);
+        assert_eq!(at(2 + 5, 2 + 7), [0, 0, 0, 255]);
+        assert_eq!(at(2 + 6, 2 + 9), [0, 0, 0, 255]);
+        // the third image starts the second row
+        assert_eq!(at(2 + 6, 20 + 7), [255; 4]);
+        assert_eq!(at(20 + 6, 20 + 7), [0, 0, 0, 255]);
+
+        // too many thumbnails to show at once
+        assert_eq!(sheet_size(100_000, 64, 1024), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn fill_sheet_one_image_at_a_time() {
+        let white = vec![255; 4 * 2 * 4];
+        // room for five in two columns, but only three fit
+        let mut sheet = ContactSheet::new(5, 2, 16).unwrap();
+        assert!(sheet.is_empty());
+        sheet.push(&white, 4, 2).unwrap();
+        assert!(sheet.push(&white[..4], 4, 2).is_err());
+        sheet.push(&white, 4, 2).unwrap();
+        sheet.push(&white, 4, 2).unwrap();
+        assert!(!sheet.is_empty());
+
+        // cut down to two rows, as if made for three images
+        let (rgba, width, height) = sheet.finish();
+        assert_eq!(
+            (rgba.clone(), width, height),
+            contact_sheet_of(&vec![(white.clone(), 4, 2); 3], 2, 16).unwrap()
+        );
+
+        // one row of two, out of room for three
+        let mut sheet = ContactSheet::new(3, 4, 16).unwrap();
+        sheet.push(&rgba, width, height).unwrap();
+        sheet.push(&rgba, width, height).unwrap();
+        let (_, width, height) = sheet.finish();
+        assert_eq!((width, height), (2 + 18 * 2, 2 + 18));
+
+        // no more than it has room for
+        let mut sheet = ContactSheet::new(1, 4, 16).unwrap();
+        sheet.push(&white, 4, 2).unwrap();
+        assert!(sheet.push(&white, 4, 2).is_err());
+    }
+}
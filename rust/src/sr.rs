@@ -0,0 +1,301 @@
+//! Extraction of spatial coordinates from Structured Reports,
+//! to show their measurements over the referenced images.
+
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+use crate::annotation::{Annotation, Point};
+use crate::imaging::string_of;
+
+/// A spatial coordinates (SCOORD) content item of a Structured Report
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrMeasurement {
+    /// the SOP instance UID of the referenced image
+    pub referenced_sop_instance_uid: String,
+    /// POINT, MULTIPOINT, POLYLINE, CIRCLE or ELLIPSE
+    pub graphic_type: String,
+    /// the coordinates in image pixels (column, row)
+    pub points: Vec<Point>,
+    /// the concept name and measured value, if any
+    pub label: Option<String>,
+}
+
+impl SrMeasurement {
+    /// The annotations which show this measurement over the image
+    pub fn to_annotations(&self) -> Vec<Annotation> {
+        let mut annotations = Vec::new();
+        match (self.graphic_type.as_str(), &self.points[..]) {
+            ("POLYLINE", [start, end]) => annotations.push(Annotation::Ruler {
+                start: *start,
+                end: *end,
+            }),
+            // the center and a point on the circle
+            ("CIRCLE", [center, edge]) => {
+                let radius = (edge.0 - center.0).hypot(edge.1 - center.1);
+                annotations.push(Annotation::Ellipse {
+                    center: *center,
+                    radii: (radius, radius),
+                    rotation: 0.,
+                })
+            }
+            // the end points of the major axis, then of the minor axis
+            ("ELLIPSE", [major_start, major_end, minor_start, minor_end]) => {
+                let (dx, dy) = (major_end.0 - major_start.0, major_end.1 - major_start.1);
+                let minor = (minor_end.0 - minor_start.0).hypot(minor_end.1 - minor_start.1);
+                annotations.push(Annotation::Ellipse {
+                    center: (
+                        (major_start.0 + major_end.0) / 2.,
+                        (major_start.1 + major_end.1) / 2.,
+                    ),
+                    radii: (dx.hypot(dy) / 2., minor / 2.),
+                    rotation: dy.atan2(dx),
+                })
+            }
+            ("POLYLINE" | "CIRCLE" | "ELLIPSE", points) if points.len() > 1 => {
+                annotations.push(Annotation::Polyline {
+                    points: points.to_vec(),
+                    closed: self.graphic_type != "POLYLINE",
+                })
+            }
+            (_, points) => {
+                for &point in points {
+                    annotations.push(Annotation::Text {
+                        position: point,
+                        text: "+".to_string(),
+                    });
+                }
+            }
+        }
+        if let (Some(label), Some(&position)) = (&self.label, self.points.first()) {
+            annotations.push(Annotation::Text {
+                position,
+                text: label.clone(),
+            });
+        }
+        annotations
+    }
+}
+
+fn items_of(item: &InMemDicomObject, tag: Tag) -> &[InMemDicomObject] {
+    item.element_opt(tag)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.items())
+        .unwrap_or(&[])
+}
+
+/// The code meaning of the concept name of a content item
+fn concept_name_of(item: &InMemDicomObject) -> Option<String> {
+    items_of(item, tags::CONCEPT_NAME_CODE_SEQUENCE)
+        .first()
+        .and_then(|code| string_of(code, tags::CODE_MEANING))
+}
+
+/// The measured value of a NUM content item, with its unit
+fn measured_value_of(item: &InMemDicomObject) -> Option<String> {
+    let value = items_of(item, tags::MEASURED_VALUE_SEQUENCE).first()?;
+    let number = string_of(value, tags::NUMERIC_VALUE)?;
+    let unit = items_of(value, tags::MEASUREMENT_UNITS_CODE_SEQUENCE)
+        .first()
+        .and_then(|code| string_of(code, tags::CODE_VALUE));
+    Some(match unit {
+        Some(unit) => format!("{} {}", number, unit),
+        None => number,
+    })
+}
+
+/// The label of a content item: its concept name and measured value
+fn label_of(item: &InMemDicomObject) -> Option<String> {
+    match (concept_name_of(item), measured_value_of(item)) {
+        (Some(name), Some(value)) => Some(format!("{}: {}", name, value)),
+        (name, value) => name.or(value),
+    }
+}
+
+/// The SOP instance UID of an object
+pub fn sop_instance_uid_of(obj: &InMemDicomObject) -> Option<String> {
+    string_of(obj, tags::SOP_INSTANCE_UID)
+}
+
+/// Whether the object is a Structured Report document
+pub fn is_structured_report(obj: &InMemDicomObject) -> bool {
+    string_of(obj, tags::MODALITY).as_deref() == Some("SR")
+        || (string_of(obj, tags::VALUE_TYPE).as_deref() == Some("CONTAINER")
+            && !items_of(obj, tags::CONTENT_SEQUENCE).is_empty())
+}
+
+/// Collect all SCOORD content items in the content tree of a Structured Report
+/// which reference an image.
+///
+/// Each is labeled by its parent item if it is a NUM measurement,
+/// or by its own concept name otherwise.
+pub fn sr_measurements_of(obj: &InMemDicomObject) -> Vec<SrMeasurement> {
+    let mut measurements = Vec::new();
+    collect_measurements(obj, None, &mut measurements);
+    measurements
+}
+
+fn collect_measurements(
+    item: &InMemDicomObject,
+    parent_label: Option<String>,
+    out: &mut Vec<SrMeasurement>,
+) {
+    let value_type = string_of(item, tags::VALUE_TYPE);
+
+    if value_type.as_deref() == Some("SCOORD") {
+        if let Some(measurement) = measurement_of(item, parent_label) {
+            out.push(measurement);
+        }
+        return;
+    }
+
+    let label = if value_type.as_deref() == Some("NUM") {
+        label_of(item)
+    } else {
+        None
+    };
+    for child in items_of(item, tags::CONTENT_SEQUENCE) {
+        collect_measurements(child, label.clone(), out);
+    }
+}
+
+fn measurement_of(item: &InMemDicomObject, parent_label: Option<String>) -> Option<SrMeasurement> {
+    let graphic_type = string_of(item, tags::GRAPHIC_TYPE)?;
+    let data = item
+        .element_opt(tags::GRAPHIC_DATA)
+        .ok()
+        .flatten()?
+        .to_multi_float64()
+        .ok()?;
+    let points: Vec<Point> = data.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+    if points.is_empty() {
+        return None;
+    }
+
+    // the referenced image is in a child IMAGE item
+    let referenced_sop_instance_uid = items_of(item, tags::CONTENT_SEQUENCE)
+        .iter()
+        .filter(|child| string_of(child, tags::VALUE_TYPE).as_deref() == Some("IMAGE"))
+        .flat_map(|child| items_of(child, tags::REFERENCED_SOP_SEQUENCE))
+        .find_map(|reference| string_of(reference, tags::REFERENCED_SOP_INSTANCE_UID))?;
+
+    Some(SrMeasurement {
+        referenced_sop_instance_uid,
+        graphic_type,
+        points,
+        label: parent_label.or_else(|| concept_name_of(item)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, DicomValue, Length, PrimitiveValue, VR};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn text(tag: Tag, vr: VR, value: &str) -> DataElement<InMemDicomObject> {
+        DataElement::new(tag, vr, PrimitiveValue::from(value))
+    }
+
+    fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> DataElement<InMemDicomObject> {
+        DataElement::new(
+            tag,
+            VR::SQ,
+            DicomValue::new_sequence(items, Length::UNDEFINED),
+        )
+    }
+
+    fn code(meaning: &str) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([text(tags::CODE_MEANING, VR::LO, meaning)])
+    }
+
+    #[wasm_bindgen_test]
+    fn extract_scoord_under_num() {
+        let image = InMemDicomObject::from_element_iter([
+            text(tags::VALUE_TYPE, VR::CS, "IMAGE"),
+            sequence(
+                tags::REFERENCED_SOP_SEQUENCE,
+                vec![InMemDicomObject::from_element_iter([text(
+                    tags::REFERENCED_SOP_INSTANCE_UID,
+                    VR::UI,
+                    "1.2.3.4",
+                )])],
+            ),
+        ]);
+        let scoord = InMemDicomObject::from_element_iter([
+            text(tags::VALUE_TYPE, VR::CS, "SCOORD"),
+            text(tags::GRAPHIC_TYPE, VR::CS, "POLYLINE"),
+            DataElement::new(
+                tags::GRAPHIC_DATA,
+                VR::FL,
+                PrimitiveValue::F32([10., 20., 30., 40.][..].into()),
+            ),
+            sequence(tags::CONTENT_SEQUENCE, vec![image]),
+        ]);
+        let num = InMemDicomObject::from_element_iter([
+            text(tags::VALUE_TYPE, VR::CS, "NUM"),
+            sequence(tags::CONCEPT_NAME_CODE_SEQUENCE, vec![code("Length")]),
+            sequence(tags::CONTENT_SEQUENCE, vec![scoord]),
+        ]);
+        let sr = InMemDicomObject::from_element_iter([
+            text(tags::MODALITY, VR::CS, "SR"),
+            text(tags::VALUE_TYPE, VR::CS, "CONTAINER"),
+            sequence(tags::CONTENT_SEQUENCE, vec![num]),
+        ]);
+
+        assert!(is_structured_report(&sr));
+        let measurements = sr_measurements_of(&sr);
+        assert_eq!(
+            measurements,
+            vec![SrMeasurement {
+                referenced_sop_instance_uid: "1.2.3.4".to_string(),
+                graphic_type: "POLYLINE".to_string(),
+                points: vec![(10., 20.), (30., 40.)],
+                label: Some("Length".to_string()),
+            }]
+        );
+        assert!(matches!(
+            measurements[0].to_annotations()[0],
+            Annotation::Ruler { .. }
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    fn circles_and_ellipses_to_annotations() {
+        let measurement = |graphic_type: &str, points: Vec<Point>| SrMeasurement {
+            referenced_sop_instance_uid: "1.2.3.4".to_string(),
+            graphic_type: graphic_type.to_string(),
+            points,
+            label: None,
+        };
+
+        let circle = measurement("CIRCLE", vec![(10., 20.), (13., 24.)]);
+        assert_eq!(
+            circle.to_annotations(),
+            vec![Annotation::Ellipse {
+                center: (10., 20.),
+                radii: (5., 5.),
+                rotation: 0.,
+            }]
+        );
+
+        // major axis along y, minor axis along x
+        let ellipse = measurement(
+            "ELLIPSE",
+            vec![(10., 0.), (10., 20.), (6., 10.), (14., 10.)],
+        );
+        match &ellipse.to_annotations()[..] {
+            [Annotation::Ellipse {
+                center,
+                radii,
+                rotation,
+            }] => {
+                assert_eq!(*center, (10., 10.));
+                assert_eq!(*radii, (10., 4.));
+                assert!((rotation - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+            }
+            annotations => panic!("unexpected annotations: {:?}", annotations),
+        }
+    }
+}
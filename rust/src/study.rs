@@ -0,0 +1,564 @@
+//! Grouping of loaded instances by study and series,
+//! and caching of the instances read again from their files.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject};
+use snafu::prelude::*;
+
+use crate::difference::rescaled_samples_of;
+use crate::geometry::functional_group_of;
+use crate::imaging::{modality_of, number_of_frames_of, string_of, Result, WindowLevel};
+
+/// A series of loaded instances
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub study_instance_uid: String,
+    pub series_instance_uid: String,
+    /// the frame of reference of the first instance,
+    /// shared by spatially related series
+    pub frame_of_reference_uid: Option<String>,
+    pub study_description: Option<String>,
+    pub series_description: Option<String>,
+    pub modality: Option<String>,
+    /// the instances in the order in which they were loaded,
+    /// shared with whoever else holds them rather than copied
+    pub instances: Vec<Rc<DefaultDicomObject>>,
+    /// the range of values of the series in modality units,
+    /// if declared by any of its instances
+    declared_range: Option<(f64, f64)>,
+    /// the range of values of the first instances in modality units, if known,
+    /// worked out once the series window level is first asked for
+    ranges: RefCell<Vec<Option<(f64, f64)>>>,
+}
+
+impl Series {
+    /// Add an instance to the series
+    fn push(&mut self, obj: Rc<DefaultDicomObject>) {
+        if self.declared_range.is_none() {
+            self.declared_range = declared_range_of(
+                &obj,
+                tags::SMALLEST_PIXEL_VALUE_IN_SERIES,
+                tags::LARGEST_PIXEL_VALUE_IN_SERIES,
+            );
+        }
+        self.instances.push(obj);
+    }
+
+    /// A window level covering the values of all instances of the series,
+    /// so that it can be kept while going through them.
+    ///
+    /// The range is read from SmallestPixelValueInSeries
+    /// and LargestPixelValueInSeries if declared,
+    /// or else combined from the range of each instance,
+    /// which may need to read its pixel data.
+    /// The range of each instance is kept once worked out.
+    /// Returns `None` if the range of any instance is unknown.
+    pub fn window_level(&self) -> Option<WindowLevel> {
+        let range = match self.declared_range {
+            Some(range) => range,
+            None => {
+                let mut ranges = self.ranges.borrow_mut();
+                let known = ranges.len();
+                ranges.extend(
+                    self.instances[known..]
+                        .iter()
+                        .map(|obj| instance_range_of(obj)),
+                );
+                let ranges = ranges.iter().copied().collect::<Option<Vec<_>>>()?;
+                range_of(ranges.into_iter().flat_map(|(min, max)| [min, max]))?
+            }
+        };
+        Some(window_level_covering(range))
+    }
+
+    /// A short description of the series for the study browser
+    pub fn label(&self) -> String {
+        let mut label = series_label(
+            self.study_description.as_deref(),
+            self.series_description.as_deref(),
+            self.modality.as_deref(),
+            self.instances.len(),
+        );
+        let localizers = self
+            .instances
+            .iter()
+            .filter(|obj| is_localizer(obj))
+            .count();
+        if localizers > 0 {
+            label.push_str(&format!(
+                " ({} localizer{})",
+                localizers,
+                if localizers == 1 { "" } else { "s" }
+            ));
+        }
+        label
+    }
+
+    /// The instance to show first for the series:
+    /// the first one which is not a localizer if they are hidden,
+    /// or else the first one.
+    pub fn first_shown(&self, hide_localizers: bool) -> Option<&Rc<DefaultDicomObject>> {
+        self.instances
+            .iter()
+            .find(|obj| !hide_localizers || !is_localizer(obj))
+            .or_else(|| self.instances.first())
+    }
+}
+
+/// Whether the values of `ImageType` or `FrameType` mark a localizer
+/// (a scout or survey image used to plan the acquisition),
+/// in value 3 or beyond
+fn is_localizer_type(values: &[String]) -> bool {
+    values
+        .iter()
+        .skip(2)
+        .any(|value| value.trim_end_matches(|c| c == ' ' || c == '\0') == "LOCALIZER")
+}
+
+fn image_type_in(obj: &InMemDicomObject, tag: Tag) -> Option<Vec<String>> {
+    let elem = obj.element_opt(tag).ok()??;
+    Some(elem.to_multi_str().ok()?.to_vec())
+}
+
+/// Whether the instance is a localizer as per its `ImageType`
+pub fn is_localizer(obj: &InMemDicomObject) -> bool {
+    image_type_in(obj, tags::IMAGE_TYPE)
+        .map(|values| is_localizer_type(&values))
+        .unwrap_or(false)
+}
+
+/// Whether a frame of an enhanced multi-frame object is a localizer
+/// as per the `FrameType` of its functional groups
+pub fn is_localizer_frame(obj: &InMemDicomObject, frame: u32) -> bool {
+    [
+        tags::CT_IMAGE_FRAME_TYPE_SEQUENCE,
+        tags::MR_IMAGE_FRAME_TYPE_SEQUENCE,
+    ]
+    .into_iter()
+    .filter_map(|sequence| functional_group_of(obj, frame, sequence))
+    .filter_map(|group| image_type_in(group, tags::FRAME_TYPE))
+    .any(|values| is_localizer_type(&values))
+}
+
+/// The frame to go to from a frame, one step forward or backward,
+/// skipping hidden frames.
+/// Stays at the same frame if there is no other frame to show that way.
+pub fn step_frame(
+    frame: u32,
+    forward: bool,
+    number_of_frames: u32,
+    hidden: impl Fn(u32) -> bool,
+) -> u32 {
+    let mut next = frame;
+    loop {
+        next = match (forward, next) {
+            (true, next) if next + 1 < number_of_frames => next + 1,
+            (false, next) if next > 0 => next - 1,
+            _ => return frame,
+        };
+        if !hidden(next) {
+            return next;
+        }
+    }
+}
+
+fn series_label(
+    study_description: Option<&str>,
+    series_description: Option<&str>,
+    modality: Option<&str>,
+    count: usize,
+) -> String {
+    let mut label = String::new();
+    if let Some(modality) = modality {
+        label.push_str(modality);
+        label.push(' ');
+    }
+    label.push_str(series_description.unwrap_or("(no series description)"));
+    if let Some(study_description) = study_description {
+        label.push_str(" — ");
+        label.push_str(study_description);
+    }
+    label.push_str(&format!(
+        " ({} instance{})",
+        count,
+        if count == 1 { "" } else { "s" }
+    ));
+    label
+}
+
+/// Add an instance to the series it belongs to,
+/// creating a new series if necessary.
+///
+/// Series are identified by StudyInstanceUID and SeriesInstanceUID,
+/// and series of the same study are kept next to each other.
+/// Returns the index of the series.
+pub fn add_instance(series: &mut Vec<Series>, obj: Rc<DefaultDicomObject>) -> Result<usize> {
+    let study_instance_uid =
+        string_of(&obj, tags::STUDY_INSTANCE_UID).whatever_context("Missing StudyInstanceUID")?;
+    let series_instance_uid =
+        string_of(&obj, tags::SERIES_INSTANCE_UID).whatever_context("Missing SeriesInstanceUID")?;
+
+    if let Some(i) = series.iter().position(|s| {
+        s.study_instance_uid == study_instance_uid && s.series_instance_uid == series_instance_uid
+    }) {
+        series[i].push(obj);
+        return Ok(i);
+    }
+
+    let mut s = Series {
+        study_description: string_of(&obj, tags::STUDY_DESCRIPTION),
+        series_description: string_of(&obj, tags::SERIES_DESCRIPTION),
+        modality: string_of(&obj, tags::MODALITY),
+        frame_of_reference_uid: string_of(&obj, tags::FRAME_OF_REFERENCE_UID),
+        study_instance_uid,
+        series_instance_uid,
+        instances: Vec::new(),
+        declared_range: None,
+        ranges: RefCell::default(),
+    };
+    s.push(obj);
+
+    // keep the series of the same study together
+    let i = series
+        .iter()
+        .rposition(|other| other.study_instance_uid == s.study_instance_uid)
+        .map(|i| i + 1)
+        .unwrap_or(series.len());
+    series.insert(i, s);
+    Ok(i)
+}
+
+/// The series which the object belongs to, if it was loaded
+pub fn series_of<'a>(series: &'a [Series], obj: &DefaultDicomObject) -> Option<&'a Series> {
+    let series_instance_uid = string_of(obj, tags::SERIES_INSTANCE_UID)?;
+    series
+        .iter()
+        .find(|s| s.series_instance_uid == series_instance_uid)
+}
+
+/// The smallest and largest of the given values, if any
+fn range_of(values: impl IntoIterator<Item = f64>) -> Option<(f64, f64)> {
+    values.into_iter().fold(None, |range, x| match range {
+        Some((min, max)) => Some((f64::min(min, x), f64::max(max, x))),
+        None => Some((x, x)),
+    })
+}
+
+/// The window level spanning the given range of values
+pub fn window_level_covering((min, max): (f64, f64)) -> WindowLevel {
+    WindowLevel {
+        width: (max - min).max(1.),
+        center: (min + max) / 2.,
+    }
+}
+
+/// The range of stored values declared by a pair of attributes
+/// (smallest and largest), in modality units
+fn declared_range_of(obj: &DefaultDicomObject, smallest: Tag, largest: Tag) -> Option<(f64, f64)> {
+    let value = |tag| obj.element_opt(tag).ok()??.to_float64().ok();
+    let (smallest, largest) = (value(smallest)?, value(largest)?);
+    let modality = modality_of(obj).ok()?;
+    range_of([modality.apply(smallest), modality.apply(largest)])
+}
+
+/// The range of values of all frames of an instance in modality units,
+/// from SmallestImagePixelValue and LargestImagePixelValue if declared,
+/// or else from its pixel data
+fn instance_range_of(obj: &DefaultDicomObject) -> Option<(f64, f64)> {
+    declared_range_of(
+        obj,
+        tags::SMALLEST_IMAGE_PIXEL_VALUE,
+        tags::LARGEST_IMAGE_PIXEL_VALUE,
+    )
+    .or_else(|| {
+        let frames = number_of_frames_of(obj).ok()?;
+        let mut range = None;
+        for frame in 0..frames {
+            let samples = rescaled_samples_of(obj, frame).ok()?;
+            range = range_of(
+                range
+                    .into_iter()
+                    .flat_map(|(min, max)| [min, max])
+                    .chain(samples),
+            );
+        }
+        range
+    })
+}
+
+/// The maximum number of fully read instances kept in the cache
+pub const MAX_CACHED_INSTANCES: usize = 8;
+
+/// A fingerprint of the bytes of a file,
+/// to tell whether an instance was read from the same file
+pub fn fingerprint_of(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A cache of the most recently displayed instances,
+/// keyed by SOPInstanceUID,
+/// so that they are not read again from their files.
+///
+/// Each instance is kept with the fingerprint of the file it was read from,
+/// and is only used for a file with the same fingerprint.
+/// Instances are cloned on every hit,
+/// so they should be shared (as with `Rc`) rather than copied.
+#[derive(Debug)]
+pub struct InstanceCache<T> {
+    instances: HashMap<String, (u64, T)>,
+    /// the UIDs of the cached instances, least recently used first
+    order: VecDeque<String>,
+}
+
+impl<T> Default for InstanceCache<T> {
+    fn default() -> Self {
+        InstanceCache {
+            instances: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: Clone> InstanceCache<T> {
+    /// Fetch a cached instance read from a file with the given fingerprint,
+    /// marking it as recently used.
+    ///
+    /// An instance with the same UID read from another file is dropped.
+    pub fn get(&mut self, uid: &str, fingerprint: u64) -> Option<T> {
+        match self.instances.get(uid) {
+            Some((cached, instance)) if *cached == fingerprint => {
+                let instance = instance.clone();
+                self.touch(uid);
+                Some(instance)
+            }
+            Some(_) => {
+                gloo_console::warn!("Instance", uid, "was loaded from different files");
+                self.remove(uid);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Cache an instance read from a file with the given fingerprint,
+    /// evicting the least recently used instances if full
+    pub fn insert(&mut self, uid: String, fingerprint: u64, instance: T) {
+        self.instances.insert(uid.clone(), (fingerprint, instance));
+        self.touch(&uid);
+        while self.order.len() > MAX_CACHED_INSTANCES {
+            if let Some(old) = self.order.pop_front() {
+                self.instances.remove(&old);
+            }
+        }
+    }
+
+    /// Drop a cached instance, if any
+    pub fn remove(&mut self, uid: &str) {
+        self.instances.remove(uid);
+        self.order.retain(|other| other != uid);
+    }
+
+    fn touch(&mut self, uid: &str) {
+        self.order.retain(|other| other != uid);
+        self.order.push_back(uid.to_string());
+    }
+}
+
+/// A load of an instance to display,
+/// from when it starts until its file has been read
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LoadToken(u64);
+
+/// The generations of loads,
+/// so that a load which completes after a newer one has started
+/// does not replace what the newer one displays.
+#[derive(Debug, Default)]
+pub struct LoadGenerations {
+    latest: u64,
+}
+
+impl LoadGenerations {
+    /// Start a new load, superseding any load in progress
+    pub fn start(&mut self) -> LoadToken {
+        self.latest += 1;
+        LoadToken(self.latest)
+    }
+
+    /// Whether no newer load has started since the given one
+    pub fn is_current(&self, token: LoadToken) -> bool {
+        token.0 == self.latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn label_of_series() {
+        assert_eq!(
+            series_label(Some("CHEST"), Some("AXIAL 5mm"), Some("CT"), 42),
+            "CT AXIAL 5mm — CHEST (42 instances)"
+        );
+        assert_eq!(
+            series_label(None, None, None, 1),
+            "(no series description) (1 instance)"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn skip_localizers() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let image_type = |values: &[&str]| {
+            InMemDicomObject::from_element_iter([DataElement::new(
+                tags::IMAGE_TYPE,
+                VR::CS,
+                PrimitiveValue::Strs(values.iter().map(|v| v.to_string()).collect()),
+            )])
+        };
+        assert!(is_localizer(&image_type(&[
+            "ORIGINAL",
+            "PRIMARY",
+            "LOCALIZER"
+        ])));
+        assert!(!is_localizer(&image_type(&[
+            "ORIGINAL", "PRIMARY", "AXIAL"
+        ])));
+        assert!(!is_localizer(&InMemDicomObject::new_empty()));
+
+        // frames 1 and 2 are hidden
+        let hidden = |frame| frame == 1 || frame == 2;
+        assert_eq!(step_frame(0, true, 5, hidden), 3);
+        assert_eq!(step_frame(3, false, 5, hidden), 0);
+        assert_eq!(step_frame(4, true, 5, hidden), 4);
+        // nowhere else to go
+        assert_eq!(step_frame(0, true, 3, hidden), 0);
+        assert_eq!(step_frame(1, true, 5, |_| false), 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn window_level_covering_series() {
+        let range = range_of([-1000., 250., 3000., -24.]).unwrap();
+        assert_eq!(range, (-1000., 3000.));
+        let window_level = window_level_covering(range);
+        assert_eq!(window_level.width, 4000.);
+        assert_eq!(window_level.center, 1000.);
+
+        // a constant image still gets a usable window
+        assert_eq!(window_level_covering((5., 5.)).width, 1.);
+        assert_eq!(range_of([]), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn series_range_is_worked_out_when_asked() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let instance = |smallest: u16, largest: u16| {
+            let meta = dicom::object::meta::FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                .media_storage_sop_instance_uid("1.2.3.4")
+                .build()
+                .unwrap();
+            let mut obj = DefaultDicomObject::new_empty_with_meta(meta);
+            for elem in [
+                DataElement::new(
+                    tags::STUDY_INSTANCE_UID,
+                    VR::UI,
+                    PrimitiveValue::from("1.2"),
+                ),
+                DataElement::new(
+                    tags::SERIES_INSTANCE_UID,
+                    VR::UI,
+                    PrimitiveValue::from("1.2.3"),
+                ),
+                DataElement::new(
+                    tags::SMALLEST_IMAGE_PIXEL_VALUE,
+                    VR::US,
+                    PrimitiveValue::from(smallest),
+                ),
+                DataElement::new(
+                    tags::LARGEST_IMAGE_PIXEL_VALUE,
+                    VR::US,
+                    PrimitiveValue::from(largest),
+                ),
+            ] {
+                obj.put(elem);
+            }
+            Rc::new(obj)
+        };
+
+        let mut series = Vec::new();
+        add_instance(&mut series, instance(10, 100)).unwrap();
+        add_instance(&mut series, instance(0, 50)).unwrap();
+        // nothing is worked out when adding instances
+        assert!(series[0].ranges.borrow().is_empty());
+
+        let window_level = series[0].window_level().unwrap();
+        assert_eq!((window_level.width, window_level.center), (100., 50.));
+        assert_eq!(series[0].ranges.borrow().len(), 2);
+
+        // only the instances added since are worked out
+        add_instance(&mut series, instance(0, 200)).unwrap();
+        assert_eq!(series[0].window_level().unwrap().width, 200.);
+        assert_eq!(series[0].ranges.borrow().len(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn cache_instances_by_uid() {
+        let mut cache = InstanceCache::default();
+        let file = fingerprint_of(b"first file");
+        cache.insert("1.2.3".to_string(), file, 'a');
+        assert_eq!(cache.get("1.2.3", file), Some('a'));
+        assert_eq!(cache.get("1.2.4", file), None);
+
+        // the same UID from other bytes is not trusted
+        let other = fingerprint_of(b"other file");
+        assert_ne!(file, other);
+        assert_eq!(cache.get("1.2.3", other), None);
+        assert_eq!(cache.get("1.2.3", file), None);
+
+        // least recently used instances are evicted
+        for i in 0..=MAX_CACHED_INSTANCES {
+            cache.insert(i.to_string(), file, 'b');
+        }
+        assert_eq!(cache.get("0", file), None);
+        assert_eq!(cache.get("1", file), Some('b'));
+        // using an instance keeps it
+        cache.insert("new".to_string(), file, 'c');
+        assert_eq!(cache.get("1", file), Some('b'));
+        assert_eq!(cache.get("2", file), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn only_latest_load_wins() {
+        let mut loads = LoadGenerations::default();
+        let mut displayed = None;
+
+        // a large file is still being read when a small one is dropped
+        let large = loads.start();
+        let small = loads.start();
+        for (token, name) in [(small, "small"), (large, "large")] {
+            if loads.is_current(token) {
+                displayed = Some(name);
+            }
+        }
+        assert_eq!(displayed, Some("small"));
+
+        // a load started later supersedes it in turn
+        let next = loads.start();
+        assert!(!loads.is_current(small));
+        assert!(loads.is_current(next));
+    }
+}
@@ -0,0 +1,286 @@
+//! Tiled rendering of large images.
+//!
+//! Instead of windowing a whole frame on every render,
+//! large images are split into a grid of tiles,
+//! and only the tiles visible on the canvas are windowed and drawn.
+//! Windowed tiles are cached until the LUT, frame, or overlays change.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use dicom::object::DefaultDicomObject;
+use wasm_bindgen::{Clamped, JsValue};
+use web_sys::ImageData;
+
+use crate::display::DisplayOptions;
+use crate::imaging::{
+    convert_monochrome_region_to_y_values, image_dimensions_of, monochrome_of, Monochrome,
+    OutOfRange, Region,
+};
+use crate::overlay::{draw_overlays, OverlayLayer};
+use crate::parametric::has_float_pixel_data;
+
+/// The width and height of each tile in image pixels
+pub const TILE_SIZE: u32 = 512;
+
+/// The number of pixels per frame above which images are rendered in tiles
+pub const TILED_RENDERING_THRESHOLD: u64 = 4096 * 4096;
+
+/// The maximum number of windowed tiles kept in the cache
+/// (each tile takes up to 1 MiB)
+const MAX_CACHED_TILES: usize = 32;
+
+/// The region of the image covered by a tile of the grid,
+/// smaller than a full tile at the right and bottom edges
+pub fn tile_region((column, row): (u32, u32), (width, height): (u32, u32)) -> Region {
+    let x = column * TILE_SIZE;
+    let y = row * TILE_SIZE;
+    Region {
+        x,
+        y,
+        width: TILE_SIZE.min(width - x),
+        height: TILE_SIZE.min(height - y),
+    }
+}
+
+/// The tiles (column, row) of an image of the given size
+/// which intersect the visible rectangle,
+/// given by its top left and bottom right corners in image pixels.
+pub fn visible_tiles(
+    (width, height): (u32, u32),
+    (top_left, bottom_right): ((f64, f64), (f64, f64)),
+) -> Vec<(u32, u32)> {
+    // the first and last tile along an axis, if any is visible
+    let range = |start: f64, end: f64, size: u32| -> Option<(u32, u32)> {
+        if size == 0 || end <= 0. || start >= size as f64 || end <= start {
+            return None;
+        }
+        let last = (size - 1) / TILE_SIZE;
+        let first = (start.max(0.) as u32 / TILE_SIZE).min(last);
+        let end = ((end.min(size as f64).ceil() as u32 - 1) / TILE_SIZE).min(last);
+        Some((first, end))
+    };
+    match (
+        range(top_left.0, bottom_right.0, width),
+        range(top_left.1, bottom_right.1, height),
+    ) {
+        (Some((first_column, last_column)), Some((first_row, last_row))) => {
+            let columns = first_column..=last_column;
+            (first_row..=last_row)
+                .flat_map(|row| columns.clone().map(move |column| (column, row)))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The key of a cached tile
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+pub struct TileKey {
+    /// the tile column and row in the grid
+    pub tile: (u32, u32),
+    /// the generation of the windowed contents
+    pub generation: u64,
+}
+
+/// A cache of the most recently used windowed tiles
+#[derive(Debug)]
+pub struct TileCache<T> {
+    /// the fingerprint of what the cached tiles were windowed from
+    fingerprint: Option<u64>,
+    /// incremented whenever the fingerprint changes
+    generation: u64,
+    tiles: HashMap<TileKey, T>,
+    /// the keys of the cached tiles, least recently used first
+    order: VecDeque<TileKey>,
+}
+
+impl<T> Default for TileCache<T> {
+    fn default() -> Self {
+        TileCache {
+            fingerprint: None,
+            generation: 0,
+            tiles: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: Clone> TileCache<T> {
+    /// Start a new generation of tiles if the fingerprint
+    /// of what they are windowed from (image, frame, LUT, overlays)
+    /// has changed, dropping the tiles of the previous one.
+    ///
+    /// Returns the current generation.
+    pub fn sync(&mut self, fingerprint: u64) -> u64 {
+        if self.fingerprint != Some(fingerprint) {
+            self.fingerprint = Some(fingerprint);
+            self.generation += 1;
+            self.clear();
+        }
+        self.generation
+    }
+
+    /// Fetch a cached tile, marking it as recently used
+    pub fn get(&mut self, key: TileKey) -> Option<T> {
+        let tile = self.tiles.get(&key)?.clone();
+        if let Some(i) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(i);
+        }
+        self.order.push_back(key);
+        Some(tile)
+    }
+
+    /// Cache a tile, evicting the least recently used tiles if full
+    pub fn insert(&mut self, key: TileKey, tile: T) {
+        if self.tiles.insert(key, tile).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > MAX_CACHED_TILES {
+            if let Some(old) = self.order.pop_front() {
+                self.tiles.remove(&old);
+            }
+        }
+    }
+
+    /// Drop all cached tiles
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.order.clear();
+    }
+}
+
+/// A large monochrome image rendered in tiles
+pub struct TiledImage<'a> {
+    /// the object of the image
+    pub obj: &'a DefaultDicomObject,
+    /// the frame rendered
+    pub frame: u32,
+    /// the width and height of the image
+    pub size: (u32, u32),
+    /// how its stored values map to gray levels
+    pub monochrome: Monochrome,
+    /// the windowing lookup table
+    pub lut: &'a [u8],
+    /// how values beyond the lookup table are shown
+    pub out_of_range: OutOfRange,
+    /// the overlays painted onto the tiles
+    pub overlays: &'a [OverlayLayer],
+    /// how the windowed gray levels are shown
+    pub display: DisplayOptions,
+}
+
+impl TiledImage<'_> {
+    /// The size and kind of the object's image
+    /// if it is monochrome and large enough to be rendered in tiles.
+    pub fn tiling_of(obj: &DefaultDicomObject) -> Option<((u32, u32), Monochrome)> {
+        let (width, height) = image_dimensions_of(obj).ok()?;
+        if width as u64 * height as u64 <= TILED_RENDERING_THRESHOLD || has_float_pixel_data(obj) {
+            return None;
+        }
+        let photometric_interpretation = obj
+            .element(dicom::dictionary_std::tags::PHOTOMETRIC_INTERPRETATION)
+            .ok()?
+            .to_str()
+            .ok()?;
+        Some(((width, height), monochrome_of(&photometric_interpretation)?))
+    }
+
+    /// A fingerprint of everything the windowed tiles depend on
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.frame.hash(&mut hasher);
+        self.monochrome.hash(&mut hasher);
+        self.lut.hash(&mut hasher);
+        self.out_of_range.hash(&mut hasher);
+        self.display.hash(&mut hasher);
+        for layer in self.overlays {
+            (layer.plane.group, layer.visible, layer.color).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Window a region of the frame and paint its overlays
+    pub fn window(&self, region: Region) -> Result<ImageData, JsValue> {
+        let mut y_values = Vec::new();
+        convert_monochrome_region_to_y_values(
+            &mut y_values,
+            self.obj,
+            self.frame,
+            self.monochrome,
+            self.lut,
+            region,
+            self.out_of_range,
+        )?;
+        self.display.apply(&mut y_values);
+        draw_overlays(
+            &mut y_values,
+            self.obj,
+            self.frame,
+            self.size,
+            region,
+            self.overlays,
+        )?;
+        ImageData::new_with_u8_clamped_array_and_sh(Clamped(&y_values), region.width, region.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn find_visible_tiles() {
+        let size = (1200, 700);
+        // the whole image and more
+        assert_eq!(
+            visible_tiles(size, ((-100., -100.), (2000., 2000.))),
+            vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+        );
+        // zoomed into the middle
+        assert_eq!(
+            visible_tiles(size, ((600., 100.), (1000., 400.))),
+            vec![(1, 0)]
+        );
+        // panned out of view
+        assert!(visible_tiles(size, ((1300., 0.), (1500., 700.))).is_empty());
+
+        // the last tiles are clipped to the image
+        assert_eq!(
+            tile_region((2, 1), size),
+            Region {
+                x: 1024,
+                y: 512,
+                width: 176,
+                height: 188
+            }
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn cache_tiles_per_generation() {
+        let mut cache = TileCache::default();
+        let generation = cache.sync(1);
+        let key = |tile, generation| TileKey { tile, generation };
+        cache.insert(key((0, 0), generation), 'a');
+        assert_eq!(cache.get(key((0, 0), generation)), Some('a'));
+
+        // the same contents keep the tiles
+        assert_eq!(cache.sync(1), generation);
+        assert_eq!(cache.get(key((0, 0), generation)), Some('a'));
+
+        // new contents start over
+        let next = cache.sync(2);
+        assert_ne!(next, generation);
+        assert_eq!(cache.get(key((0, 0), next)), None);
+
+        // least recently used tiles are evicted
+        for i in 0..=MAX_CACHED_TILES as u32 {
+            cache.insert(key((i, 0), next), 'b');
+        }
+        assert_eq!(cache.get(key((0, 0), next)), None);
+        assert_eq!(cache.get(key((1, 0), next)), Some('b'));
+    }
+}
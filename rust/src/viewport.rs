@@ -0,0 +1,173 @@
+//! The zoom and pan of the displayed image,
+//! with inertial panning and eased zoom transitions.
+
+use wasm_bindgen::prelude::Closure;
+
+/// How long the mouse may rest before releasing a pan (ms)
+/// for the image to keep gliding
+pub const MOMENTUM_TIMEOUT: f64 = 50.;
+/// The nominal duration of an animation frame (ms)
+pub const FRAME_DURATION: f64 = 1000. / 60.;
+/// The fraction of the panning velocity kept after each nominal frame
+pub const PAN_FRICTION: f64 = 0.92;
+/// The panning velocity under which the image stops gliding (px/ms)
+pub const MIN_PAN_VELOCITY: f64 = 0.01;
+/// The fraction of the distance to the target zoom level
+/// left after each nominal frame
+pub const ZOOM_EASING: f64 = 0.7;
+
+/// The ongoing inertial panning and zoom transition of the viewport
+#[derive(Debug, Default)]
+pub struct ViewportMotion {
+    /// the panning velocity in canvas pixels per millisecond
+    pub velocity: (f64, f64),
+    /// the zoom level being eased towards, if any
+    pub zoom_target: Option<f64>,
+    /// the time stamp of the last animation frame (ms)
+    pub last_time: Option<f64>,
+    /// the handle of the requested animation frame, if animating
+    pub frame: Option<i32>,
+    /// the animation frame callback, created on first use
+    pub callback: Option<Closure<dyn FnMut(f64)>>,
+}
+
+impl ViewportMotion {
+    /// Estimate the panning velocity from a drag movement
+    /// taking `dt` milliseconds.
+    pub fn track_pan(&mut self, dx: f64, dy: f64, dt: f64) {
+        let dt = dt.max(1.);
+        // smooth out the jitter of individual mouse events
+        self.velocity = (
+            (self.velocity.0 + dx / dt) / 2.,
+            (self.velocity.1 + dy / dt) / 2.,
+        );
+    }
+
+    /// Advance the viewport by `dt` milliseconds.
+    /// Returns whether it is still moving.
+    pub fn step(&mut self, viewport: &mut Viewport, dt: f64) -> bool {
+        let frames = dt.max(0.) / FRAME_DURATION;
+
+        viewport.pan.0 += self.velocity.0 * dt;
+        viewport.pan.1 += self.velocity.1 * dt;
+        let friction = PAN_FRICTION.powf(frames);
+        self.velocity = (self.velocity.0 * friction, self.velocity.1 * friction);
+        if self.velocity.0.hypot(self.velocity.1) < MIN_PAN_VELOCITY {
+            self.velocity = (0., 0.);
+        }
+
+        if let Some(target) = self.zoom_target {
+            let zoom = target + (viewport.zoom - target) * ZOOM_EASING.powf(frames);
+            if (zoom / target - 1.).abs() < 1e-3 {
+                viewport.zoom = target;
+                self.zoom_target = None;
+            } else {
+                viewport.zoom = zoom;
+            }
+        }
+
+        self.velocity != (0., 0.) || self.zoom_target.is_some()
+    }
+}
+
+/// The zoom and pan applied by the user to the displayed image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    /// the zoom level relative to fitting the image to the canvas
+    pub zoom: f64,
+    /// the offset of the image from the center in canvas pixels
+    pub pan: (f64, f64),
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            zoom: 1.,
+            pan: (0., 0.),
+        }
+    }
+}
+
+impl Viewport {
+    /// The viewport at another zoom level,
+    /// panned so that the point at the given offset
+    /// from the center of the canvas stays in place
+    pub fn zoomed_about(self, zoom: f64, (x, y): (f64, f64)) -> Self {
+        let factor = zoom / self.zoom;
+        Viewport {
+            zoom,
+            pan: (x - (x - self.pan.0) * factor, y - (y - self.pan.1) * factor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn panning_velocity_decays() {
+        let mut motion = ViewportMotion {
+            velocity: (1., 0.),
+            ..Default::default()
+        };
+        let mut viewport = Viewport::default();
+
+        assert!(motion.step(&mut viewport, FRAME_DURATION));
+        assert!((viewport.pan.0 - FRAME_DURATION).abs() < 1e-9);
+        assert!((motion.velocity.0 - PAN_FRICTION).abs() < 1e-9);
+        assert_eq!(motion.velocity.1, 0.);
+
+        // the decay does not depend on the frame rate
+        let mut halves = ViewportMotion {
+            velocity: (1., 0.),
+            ..Default::default()
+        };
+        halves.step(&mut Viewport::default(), FRAME_DURATION / 2.);
+        halves.step(&mut Viewport::default(), FRAME_DURATION / 2.);
+        assert!((halves.velocity.0 - motion.velocity.0).abs() < 1e-9);
+
+        // the image eventually comes to a stop
+        let mut frames = 0;
+        while motion.step(&mut viewport, FRAME_DURATION) {
+            frames += 1;
+            assert!(frames < 1000, "the image should stop gliding");
+        }
+        assert_eq!(motion.velocity, (0., 0.));
+        let pan = viewport.pan;
+        assert!(!motion.step(&mut viewport, FRAME_DURATION));
+        assert_eq!(viewport.pan, pan);
+    }
+
+    #[wasm_bindgen_test]
+    fn zoom_eases_to_target() {
+        let mut motion = ViewportMotion {
+            zoom_target: Some(2.),
+            ..Default::default()
+        };
+        let mut viewport = Viewport::default();
+
+        assert!(motion.step(&mut viewport, FRAME_DURATION));
+        assert!((viewport.zoom - (2. - ZOOM_EASING)).abs() < 1e-9);
+
+        let mut frames = 0;
+        while motion.step(&mut viewport, FRAME_DURATION) {
+            frames += 1;
+            assert!(frames < 1000, "the zoom level should reach its target");
+        }
+        assert_eq!(viewport.zoom, 2.);
+        assert_eq!(motion.zoom_target, None);
+        assert_eq!(viewport.pan, (0., 0.));
+    }
+
+    #[wasm_bindgen_test]
+    fn pan_velocity_is_smoothed() {
+        let mut motion = ViewportMotion::default();
+        motion.track_pan(10., -4., 10.);
+        assert_eq!(motion.velocity, (0.5, -0.2));
+        motion.track_pan(10., -4., 10.);
+        assert!((motion.velocity.0 - 0.75).abs() < 1e-9);
+        assert!((motion.velocity.1 + 0.3).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,307 @@
+//! Waveforms such as ECGs, which are plotted channel by channel
+//! in place of an image.
+
+use dicom::core::{DicomValue, PrimitiveValue, Tag};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use snafu::prelude::*;
+
+use crate::imaging::{string_of, Result};
+
+/// A channel of a waveform
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    /// the label of the channel, or the meaning of its source, if any
+    pub label: Option<String>,
+    /// the samples in the units of the channel
+    pub samples: Vec<f64>,
+}
+
+/// The channels of the first multiplex group of a waveform
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    /// the number of samples per second
+    pub sampling_frequency: f64,
+    pub channels: Vec<Channel>,
+}
+
+impl Waveform {
+    /// The duration of the waveform in seconds
+    pub fn duration(&self) -> f64 {
+        let samples = self.channels.first().map_or(0, |c| c.samples.len());
+        samples as f64 / self.sampling_frequency
+    }
+}
+
+/// The common prefix of the UIDs of the waveform storage SOP classes
+const WAVEFORM_SOP_CLASS_PREFIX: &str = "1.2.840.10008.5.1.4.1.1.9.";
+
+/// Whether the object holds waveforms rather than an image:
+/// whether it is of a waveform storage SOP class,
+/// or else whether it has waveforms and no pixel data.
+///
+/// Images with waveforms of their own (such as ultrasound with ECG)
+/// are shown as images.
+pub fn is_waveform(obj: &InMemDicomObject) -> bool {
+    match string_of(obj, tags::SOP_CLASS_UID) {
+        Some(uid) => uid.starts_with(WAVEFORM_SOP_CLASS_PREFIX),
+        None => {
+            matches!(obj.element_opt(tags::WAVEFORM_SEQUENCE), Ok(Some(_)))
+                && matches!(obj.element_opt(tags::PIXEL_DATA), Ok(None))
+        }
+    }
+}
+
+fn number_of(obj: &InMemDicomObject, tag: Tag) -> Option<f64> {
+    obj.element_opt(tag).ok()??.to_float64().ok()
+}
+
+/// The label of a channel from its definition
+fn channel_label_of(definition: &InMemDicomObject) -> Option<String> {
+    string_of(definition, tags::CHANNEL_LABEL).or_else(|| {
+        let source = definition
+            .element_opt(tags::CHANNEL_SOURCE_SEQUENCE)
+            .ok()??
+            .items()?
+            .first()?;
+        string_of(source, tags::CODE_MEANING)
+    })
+}
+
+/// The raw samples of a multiplex group, channel after channel of each sample
+fn raw_samples_of(group: &InMemDicomObject) -> Result<Vec<f64>> {
+    let bits_allocated = group
+        .element(tags::WAVEFORM_BITS_ALLOCATED)
+        .whatever_context("Could not fetch WaveformBitsAllocated")?
+        .to_int::<u16>()
+        .whatever_context("WaveformBitsAllocated is not a number")?;
+    let interpretation = string_of(group, tags::WAVEFORM_SAMPLE_INTERPRETATION)
+        .whatever_context("Missing WaveformSampleInterpretation")?;
+    let data = group
+        .element(tags::WAVEFORM_DATA)
+        .whatever_context("Could not fetch WaveformData")?;
+    let bytes: Vec<u8> = match data.value() {
+        DicomValue::Primitive(PrimitiveValue::U8(bytes)) => bytes.to_vec(),
+        DicomValue::Primitive(PrimitiveValue::U16(words)) => {
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        }
+        DicomValue::Primitive(PrimitiveValue::I16(words)) => {
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        }
+        _ => whatever!("Could not read WaveformData as bytes"),
+    };
+
+    match (bits_allocated, interpretation.as_str()) {
+        (8, "SB") => Ok(bytes.iter().map(|&b| b as i8 as f64).collect()),
+        (8, "UB") => Ok(bytes.iter().map(|&b| b as f64).collect()),
+        (16, "SS") => Ok(bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f64)
+            .collect()),
+        (16, "US") => Ok(bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as f64)
+            .collect()),
+        (bits, interpretation) => whatever!(
+            "Unsupported waveform samples ({} bits, {}) :(",
+            bits,
+            interpretation
+        ),
+    }
+}
+
+/// Read the channels of the first multiplex group of a waveform object,
+/// in the units of each channel
+/// (scaled by `ChannelSensitivity` and its correction factor, if declared).
+pub fn waveform_of(obj: &InMemDicomObject) -> Result<Waveform> {
+    let group = obj
+        .element(tags::WAVEFORM_SEQUENCE)
+        .whatever_context("Could not fetch WaveformSequence")?
+        .items()
+        .and_then(|items| items.first())
+        .whatever_context("WaveformSequence is empty")?;
+
+    let number_of_channels = number_of(group, tags::NUMBER_OF_WAVEFORM_CHANNELS)
+        .whatever_context("Missing NumberOfWaveformChannels")?
+        as usize;
+    let number_of_samples = number_of(group, tags::NUMBER_OF_WAVEFORM_SAMPLES)
+        .whatever_context("Missing NumberOfWaveformSamples")? as usize;
+    let sampling_frequency =
+        number_of(group, tags::SAMPLING_FREQUENCY).whatever_context("Missing SamplingFrequency")?;
+    ensure_whatever!(
+        number_of_channels > 0 && sampling_frequency > 0.,
+        "The waveform has no channels or no sampling frequency"
+    );
+
+    let raw = raw_samples_of(group)?;
+    let total = number_of_channels
+        .checked_mul(number_of_samples)
+        .whatever_context("The waveform has too many samples")?;
+    ensure_whatever!(
+        raw.len() >= total,
+        "WaveformData is too short ({} samples) for {} channels of {} samples",
+        raw.len(),
+        number_of_channels,
+        number_of_samples
+    );
+
+    let definitions = group
+        .element_opt(tags::CHANNEL_DEFINITION_SEQUENCE)
+        .ok()
+        .flatten()
+        .and_then(|elem| elem.items())
+        .unwrap_or(&[]);
+
+    let channels = (0..number_of_channels)
+        .map(|c| {
+            let definition = definitions.get(c);
+            let value = |tag| definition.and_then(|definition| number_of(definition, tag));
+            let baseline = value(tags::CHANNEL_BASELINE).unwrap_or(0.);
+            let sensitivity = value(tags::CHANNEL_SENSITIVITY).unwrap_or(1.)
+                * value(tags::CHANNEL_SENSITIVITY_CORRECTION_FACTOR).unwrap_or(1.);
+            Channel {
+                label: definition.and_then(channel_label_of),
+                samples: (0..number_of_samples)
+                    .map(|s| (raw[s * number_of_channels + c] + baseline) * sensitivity)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(Waveform {
+        sampling_frequency,
+        channels,
+    })
+}
+
+/// The points of a line plot of samples
+/// spanning a rectangle (left, top, width, height),
+/// from the smallest value at the bottom to the largest at the top.
+///
+/// Constant samples are plotted across the middle.
+pub fn plot_points(
+    samples: &[f64],
+    (left, top, width, height): (f64, f64, f64, f64),
+) -> Vec<(f64, f64)> {
+    let (min, max) = samples
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &x| {
+            (min.min(x), max.max(x))
+        });
+    let step = width / (samples.len().max(2) - 1) as f64;
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let y = if max > min {
+                (x - min) / (max - min)
+            } else {
+                0.5
+            };
+            (left + i as f64 * step, top + height - y * height)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, Length, VR};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn read_waveform_channels() {
+        let definition = |label: &str, sensitivity: f64| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::CHANNEL_LABEL, VR::SH, PrimitiveValue::from(label)),
+                DataElement::new(
+                    tags::CHANNEL_SENSITIVITY,
+                    VR::DS,
+                    PrimitiveValue::from(sensitivity.to_string()),
+                ),
+            ])
+        };
+        let group = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::NUMBER_OF_WAVEFORM_CHANNELS,
+                VR::US,
+                PrimitiveValue::from(2_u16),
+            ),
+            DataElement::new(
+                tags::NUMBER_OF_WAVEFORM_SAMPLES,
+                VR::UL,
+                PrimitiveValue::from(3_u32),
+            ),
+            DataElement::new(
+                tags::SAMPLING_FREQUENCY,
+                VR::DS,
+                PrimitiveValue::from("500"),
+            ),
+            DataElement::new(
+                tags::CHANNEL_DEFINITION_SEQUENCE,
+                VR::SQ,
+                DicomValue::new_sequence(
+                    vec![definition("Lead I", 2.), definition("Lead II", 0.5)],
+                    Length::UNDEFINED,
+                ),
+            ),
+            DataElement::new(
+                tags::WAVEFORM_BITS_ALLOCATED,
+                VR::US,
+                PrimitiveValue::from(16_u16),
+            ),
+            DataElement::new(
+                tags::WAVEFORM_SAMPLE_INTERPRETATION,
+                VR::CS,
+                PrimitiveValue::from("SS"),
+            ),
+            // interleaved: (1, 10), (-2, 20), (3, -30)
+            DataElement::new(
+                tags::WAVEFORM_DATA,
+                VR::OW,
+                PrimitiveValue::I16([1, 10, -2, 20, 3, -30].into_iter().collect()),
+            ),
+        ]);
+        let obj = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::WAVEFORM_SEQUENCE,
+            VR::SQ,
+            DicomValue::new_sequence(vec![group], Length::UNDEFINED),
+        )]);
+
+        assert!(is_waveform(&obj));
+        assert!(!is_waveform(&InMemDicomObject::new_empty()));
+        // images with waveforms of their own are not
+        let mut image = obj.clone();
+        image.put(DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OB,
+            PrimitiveValue::from(vec![0_u8; 4]),
+        ));
+        assert!(!is_waveform(&image));
+        image.put(DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.840.10008.5.1.4.1.1.6.1\0"),
+        ));
+        assert!(!is_waveform(&image));
+        let mut ecg = obj.clone();
+        ecg.put(DataElement::new(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.840.10008.5.1.4.1.1.9.1.1\0"),
+        ));
+        assert!(is_waveform(&ecg));
+        let waveform = waveform_of(&obj).unwrap();
+        assert_eq!(waveform.sampling_frequency, 500.);
+        assert_eq!(waveform.duration(), 3. / 500.);
+        assert_eq!(waveform.channels.len(), 2);
+        assert_eq!(waveform.channels[0].label.as_deref(), Some("Lead I"));
+        assert_eq!(waveform.channels[0].samples, [2., -4., 6.]);
+        assert_eq!(waveform.channels[1].samples, [5., 10., -15.]);
+
+        let points = plot_points(&waveform.channels[0].samples, (10., 0., 100., 50.));
+        assert_eq!(points, [(10., 20.), (60., 50.), (110., 0.)]);
+        // constant samples across the middle
+        assert_eq!(plot_points(&[1., 1.], (0., 0., 10., 10.))[1], (10., 5.));
+    }
+}